@@ -0,0 +1,46 @@
+//! Compare `is_zero()` against the obvious implementation
+//!
+//! Run with `cargo bench --bench zero`
+
+use fs_sparse::is_zero;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+fn naive(buf: &[u8]) -> bool {
+    buf.iter().all(|&b| b == 0)
+}
+
+fn bench(name: &str, buf: &[u8], f: fn(&[u8]) -> bool) {
+    let mut iters = 0u64;
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(1) {
+        for _ in 0..16 {
+            black_box(f(black_box(buf)));
+        }
+        iters += 16;
+    }
+    let elapsed = start.elapsed();
+
+    let bytes = iters * buf.len() as u64;
+    println!(
+        "{:>8} {:>8} KiB: {:>10.1} MiB/s",
+        name,
+        buf.len() / 1024,
+        bytes as f64 / elapsed.as_secs_f64() / (1024. * 1024.)
+    );
+}
+
+fn main() {
+    for &size in &[4 * 1024, 64 * 1024, 1024 * 1024] {
+        let buf = vec![0u8; size];
+        bench("naive", &buf, naive);
+        bench("is_zero", &buf, is_zero);
+
+        // data at the very end is the worst case: everything has to be examined
+        let mut buf = buf;
+        *buf.last_mut().unwrap() = 1;
+        assert!(!is_zero(&buf));
+        bench("naive", &buf, naive);
+        bench("is_zero", &buf, is_zero);
+    }
+}
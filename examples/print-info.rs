@@ -10,11 +10,11 @@ mod macos {
 #[cfg(target_os = "macos")]
 use macos::*;
 
-#[cfg(target_os = "linux")]
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
 mod linux {
     pub use libc::{SEEK_DATA, SEEK_HOLE};
 }
-#[cfg(target_os = "linux")]
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
 use linux::*;
 
 #[cfg(unix)]
@@ -111,7 +111,7 @@ fn one_file(path: &Path) -> Result<(), Box<dyn std::error::Error>> {
                 break;
             }
             Err(e) => {
-                panic!(e);
+                panic!("{}", e);
             }
             Ok(off) => {
                 println!("{}", off);
@@ -138,7 +138,7 @@ fn cmd(args: pico_args::Arguments) -> Result<(), Box<dyn std::error::Error>> {
 
     println!("args: {:?}", input_files);
     for path in input_files {
-        one_file((&path).as_ref())?
+        one_file(path.as_ref())?
     }
 
     Ok(())
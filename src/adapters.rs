@@ -0,0 +1,371 @@
+//! Adapters for iterators over ranges
+
+use crate::{align_down, align_up, ItemKind, SparseRangeItem};
+use std::io;
+use std::iter::FusedIterator;
+
+/// Extra adapters for iterators over [`SparseRangeItem`]s, like [`SparseRangeIter`](crate::SparseRangeIter)
+///
+/// This is implemented for every iterator that yields `io::Result<SparseRangeItem>`, so adapters
+/// may be chained. Errors are passed through unchanged, and each adapter is fused if the iterator
+/// it wraps is.
+pub trait SparseRangeIterExt: Iterator<Item = io::Result<SparseRangeItem>> + Sized {
+    /// Only yield `Data` ranges
+    fn data_only(self) -> KindFilter<Self> {
+        KindFilter {
+            inner: self,
+            kind: ItemKind::Data,
+        }
+    }
+
+    /// Only yield `Hole` ranges
+    fn holes_only(self) -> KindFilter<Self> {
+        KindFilter {
+            inner: self,
+            kind: ItemKind::Hole,
+        }
+    }
+
+    /// Merge abutting ranges of the same kind into a single range
+    ///
+    /// Some filesystems report many small extents for a single contiguous region. This combines
+    /// them so every range yielded is followed by a range of a different kind (or a gap).
+    fn coalesce(self) -> Coalesce<Self> {
+        self.coalesce_gaps(0)
+    }
+
+    /// Like [`coalesce()`](Self::coalesce), but also merge `Data` ranges separated by a hole
+    /// smaller than `threshold` bytes (the hole becomes part of the merged `Data` range)
+    ///
+    /// Holes at the start or end of the file are left alone, no matter how small they are.
+    fn coalesce_gaps(self, threshold: u64) -> Coalesce<Self> {
+        Coalesce {
+            inner: self,
+            threshold,
+            ready: None,
+            pending: None,
+            held: None,
+        }
+    }
+
+    /// Treat holes smaller than `min` bytes as `Data`, merging them with their neighbors
+    ///
+    /// Unlike [`coalesce_gaps()`](Self::coalesce_gaps), this also applies to holes at the start
+    /// and end of the file. Useful when tracking a hole costs more than just handling the zeros
+    /// in it (for example, when sending a file over the network).
+    fn min_hole_size(self, min: u64) -> MinHoleSize<Self> {
+        MinHoleSize {
+            inner: SmallHolesAsData { inner: self, min }.coalesce(),
+        }
+    }
+
+    /// Shrink holes inward to multiples of `align`, growing `Data` outward to match
+    ///
+    /// Every hole yielded starts and ends on a multiple of `align`, so it can be punched out (or
+    /// skipped) a whole block at a time without losing any data, even when the filesystem reports
+    /// holes at a finer granularity, or `align` is larger than its block size (see
+    /// [`block_size()`](crate::block_size)). The parts of a hole that don't cover a whole aligned
+    /// block become `Data`, and holes that don't cover any become `Data` entirely. `Data` ranges
+    /// are merged with their neighbors as they grow. The end of the file is left where it is, so
+    /// the last range may not end on a multiple of `align`.
+    ///
+    /// # Panics
+    ///
+    /// If `align` is 0
+    fn aligned(self, align: u64) -> Aligned<Self> {
+        assert!(align > 0, "alignment must be non-zero");
+        Aligned {
+            inner: AlignHoles {
+                inner: self,
+                align,
+                queued: Vec::new(),
+            }
+            .coalesce(),
+        }
+    }
+
+    /// Split `Data` ranges into `(offset, len)` chunks of at most `max` bytes, skipping holes
+    ///
+    /// Handy for feeding fixed size buffers or multipart uploads. Every chunk except the last one
+    /// of each `Data` range is exactly `max` bytes long.
+    ///
+    /// # Panics
+    ///
+    /// If `max` is 0
+    fn data_chunks(self, max: u64) -> DataChunks<Self> {
+        assert!(max > 0, "chunk size must be non-zero");
+        DataChunks {
+            inner: self,
+            max,
+            current: None,
+        }
+    }
+}
+
+impl<I: Iterator<Item = io::Result<SparseRangeItem>>> SparseRangeIterExt for I {}
+
+/// Only yields ranges of a single kind. Errors are always passed through.
+///
+/// Created by [`SparseRangeIterExt::data_only()`] and [`SparseRangeIterExt::holes_only()`]
+#[derive(Debug, Clone)]
+pub struct KindFilter<I> {
+    inner: I,
+    kind: ItemKind,
+}
+
+impl<I: Iterator<Item = io::Result<SparseRangeItem>>> Iterator for KindFilter<I> {
+    type Item = io::Result<SparseRangeItem>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let kind = self.kind;
+        self.inner.find(|r| match r {
+            Ok(r) => r.kind == kind,
+            Err(_) => true,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
+    }
+}
+
+impl<I: FusedIterator<Item = io::Result<SparseRangeItem>>> FusedIterator for KindFilter<I> {}
+
+/// Merges abutting ranges of the same kind, and optionally small holes between data
+///
+/// Created by [`SparseRangeIterExt::coalesce()`] and [`SparseRangeIterExt::coalesce_gaps()`]
+#[derive(Debug, Clone)]
+pub struct Coalesce<I> {
+    inner: I,
+    threshold: u64,
+    /// Finished, to be returned before anything else
+    ready: Option<SparseRangeItem>,
+    /// The range we're currently growing
+    pending: Option<SparseRangeItem>,
+    /// A small hole following `pending` (which is `Data`) that we'll merge if `Data` follows it
+    held: Option<SparseRangeItem>,
+}
+
+impl<I: Iterator<Item = io::Result<SparseRangeItem>>> Iterator for Coalesce<I> {
+    type Item = io::Result<SparseRangeItem>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(r) = self.ready.take() {
+            return Some(Ok(r));
+        }
+
+        loop {
+            let r = match self.inner.next() {
+                Some(Ok(r)) => r,
+                Some(Err(e)) => {
+                    // we don't know where the ranges we're holding end anymore
+                    self.pending = None;
+                    self.held = None;
+                    return Some(Err(e));
+                }
+                None => {
+                    self.ready = self.held.take();
+                    return self.pending.take().map(Ok);
+                }
+            };
+
+            let mut p = match self.pending.take() {
+                Some(p) => p,
+                None => {
+                    self.pending = Some(r);
+                    continue;
+                }
+            };
+
+            if let Some(mut h) = self.held.take() {
+                if r.kind == ItemKind::Data && r.start == h.end {
+                    // data, small hole, data: merge all 3
+                    p.end = r.end;
+                    self.pending = Some(p);
+                    continue;
+                }
+
+                if r.kind == h.kind && r.start == h.end {
+                    // the hole continues, and may no longer be small enough to merge
+                    h.end = r.end;
+                    if h.end - h.start < self.threshold {
+                        self.pending = Some(p);
+                        self.held = Some(h);
+                        continue;
+                    }
+                    self.pending = Some(h);
+                } else {
+                    self.ready = Some(h);
+                    self.pending = Some(r);
+                }
+                return Some(Ok(p));
+            }
+
+            if r.kind == p.kind && r.start == p.end {
+                p.end = r.end;
+                self.pending = Some(p);
+                continue;
+            }
+
+            if p.kind == ItemKind::Data
+                && r.kind == ItemKind::Hole
+                && r.start == p.end
+                && r.end - r.start < self.threshold
+            {
+                self.pending = Some(p);
+                self.held = Some(r);
+                continue;
+            }
+
+            self.pending = Some(r);
+            return Some(Ok(p));
+        }
+    }
+}
+
+impl<I: FusedIterator<Item = io::Result<SparseRangeItem>>> FusedIterator for Coalesce<I> {}
+
+/// Treats small holes as data
+///
+/// Created by [`SparseRangeIterExt::min_hole_size()`]
+#[derive(Debug, Clone)]
+pub struct MinHoleSize<I> {
+    inner: Coalesce<SmallHolesAsData<I>>,
+}
+
+impl<I: Iterator<Item = io::Result<SparseRangeItem>>> Iterator for MinHoleSize<I> {
+    type Item = io::Result<SparseRangeItem>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl<I: FusedIterator<Item = io::Result<SparseRangeItem>>> FusedIterator for MinHoleSize<I> {}
+
+#[derive(Debug, Clone)]
+struct SmallHolesAsData<I> {
+    inner: I,
+    min: u64,
+}
+
+impl<I: Iterator<Item = io::Result<SparseRangeItem>>> Iterator for SmallHolesAsData<I> {
+    type Item = io::Result<SparseRangeItem>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let min = self.min;
+        self.inner.next().map(|r| {
+            r.map(|mut r| {
+                if r.kind == ItemKind::Hole && r.end - r.start < min {
+                    r.kind = ItemKind::Data;
+                }
+                r
+            })
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Aligns holes to a block size
+///
+/// Created by [`SparseRangeIterExt::aligned()`]
+#[derive(Debug, Clone)]
+pub struct Aligned<I> {
+    inner: Coalesce<AlignHoles<I>>,
+}
+
+impl<I: Iterator<Item = io::Result<SparseRangeItem>>> Iterator for Aligned<I> {
+    type Item = io::Result<SparseRangeItem>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl<I: FusedIterator<Item = io::Result<SparseRangeItem>>> FusedIterator for Aligned<I> {}
+
+#[derive(Debug, Clone)]
+struct AlignHoles<I> {
+    inner: I,
+    align: u64,
+    /// The rest of a split up hole, last first
+    queued: Vec<SparseRangeItem>,
+}
+
+impl<I: Iterator<Item = io::Result<SparseRangeItem>>> Iterator for AlignHoles<I> {
+    type Item = io::Result<SparseRangeItem>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(r) = self.queued.pop() {
+            return Some(Ok(r));
+        }
+
+        let mut r = match self.inner.next()? {
+            Ok(r) => r,
+            Err(e) => return Some(Err(e)),
+        };
+        if r.kind != ItemKind::Hole {
+            return Some(Ok(r));
+        }
+
+        let start = align_up(r.start, self.align);
+        let end = align_down(r.end, self.align);
+        if start >= end {
+            r.kind = ItemKind::Data;
+            return Some(Ok(r));
+        }
+
+        let piece = |kind, start, end| SparseRangeItem { kind, start, end };
+        if end < r.end {
+            self.queued.push(piece(ItemKind::Data, end, r.end));
+        }
+        self.queued.push(piece(ItemKind::Hole, start, end));
+        if r.start < start {
+            self.queued.push(piece(ItemKind::Data, r.start, start));
+        }
+        self.queued.pop().map(Ok)
+    }
+}
+
+/// Splits data ranges into bounded `(offset, len)` chunks
+///
+/// Created by [`SparseRangeIterExt::data_chunks()`]
+#[derive(Debug, Clone)]
+pub struct DataChunks<I> {
+    inner: I,
+    max: u64,
+    /// The part of the current `Data` range not yet returned, as `start..end`
+    current: Option<(u64, u64)>,
+}
+
+impl<I: Iterator<Item = io::Result<SparseRangeItem>>> Iterator for DataChunks<I> {
+    type Item = io::Result<(u64, u64)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((start, end)) = self.current {
+                let len = (end - start).min(self.max);
+                self.current = if start + len < end {
+                    Some((start + len, end))
+                } else {
+                    None
+                };
+                return Some(Ok((start, len)));
+            }
+
+            match self.inner.next()? {
+                Err(e) => return Some(Err(e)),
+                Ok(r) => {
+                    if r.kind == ItemKind::Data && r.start < r.end {
+                        self.current = Some((r.start, r.end));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<I: FusedIterator<Item = io::Result<SparseRangeItem>>> FusedIterator for DataChunks<I> {}
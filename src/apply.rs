@@ -0,0 +1,66 @@
+//! Restoring a file from a saved [`SparseMap`] and its data
+
+use crate::error::Result;
+use crate::unix::{pwrite_all, set_len};
+use crate::{punch_hole_or_zero, AsFile, ItemKind, ReadAt, SparseMap};
+use std::io::Read;
+
+/// Most bytes copied at once
+const CHUNK: u64 = 1024 * 1024;
+
+/// Make `dst` match `map`, with the data of each `Data` range read from the same offset in `src`
+///
+/// This is the restore half of a backup made with [`SparseMap::from_file()`]: `dst` is set to the
+/// length of the map, each Hole is punched out (or written with zeros, if the filesystem can't
+/// punch holes, see [`punch_hole_or_zero()`]), and each `Data` range is copied from `src`. `src`
+/// is only read where there is data, so it can be the original file, a dense copy of it, or
+/// anything else that can be read at any offset.
+///
+/// Returns the number of bytes of data written. The file's cursor is never used.
+///
+/// # Errors
+///
+/// An `UnexpectedEof` I/O error if `src` ends before the data does. `dst` holds whatever was
+/// written before the error.
+pub fn apply_map<S: ReadAt, F: AsFile>(map: &SparseMap, src: S, dst: F) -> Result<u64> {
+    apply(map, dst, |buf, offset| src.read_exact_at(buf, offset))
+}
+
+/// Like [`apply_map()`], but with the data read from `r` in order
+///
+/// `r` holds the contents of each `Data` range in `map`, one after another and nothing else, the
+/// way tar archives store sparse files (see [`tar`](crate::tar)). Exactly that much is read, so
+/// more may follow it in `r`.
+pub fn apply_map_from_reader<R: Read, F: AsFile>(map: &SparseMap, mut r: R, dst: F) -> Result<u64> {
+    apply(map, dst, |buf, _| r.read_exact(buf))
+}
+
+fn apply<F, D>(map: &SparseMap, dst: F, mut read: D) -> Result<u64>
+where
+    F: AsFile,
+    D: FnMut(&mut [u8], u64) -> std::io::Result<()>,
+{
+    let fd = dst.as_fd();
+    crate::set_sparse(fd, true)?;
+    set_len(fd, map.file_len())?;
+
+    let mut buf = Vec::new();
+    let mut written = 0;
+    for r in map {
+        if r.kind == ItemKind::Hole {
+            punch_hole_or_zero(fd, r.start, r.end - r.start)?;
+            continue;
+        }
+
+        let mut offset = r.start;
+        while offset < r.end {
+            buf.resize((r.end - offset).min(CHUNK) as usize, 0);
+            read(&mut buf, offset)?;
+            pwrite_all(fd, &buf, offset)?;
+            offset += buf.len() as u64;
+        }
+        written += r.end - r.start;
+    }
+
+    Ok(written)
+}
@@ -0,0 +1,239 @@
+//! Scanning from async code without blocking the executor
+//!
+//! Finding holes means blocking syscalls (`lseek()`, `ioctl()`, reads for the zero scanning
+//! backends), which stall an async runtime's worker threads if made directly from a task. An
+//! [`AsyncSparseIter`] instead runs an iterator on a thread of its own (or on a runtime's pool for
+//! blocking work, with [`AsyncSparseIter::spawn_with()`], or within tokio with the `tokio`
+//! feature), and hands its items over as they arrive, as a `Stream`. It doesn't depend on any
+//! particular runtime. [`AsyncSparseIter::next()`] waits for a single item without needing
+//! `StreamExt`.
+
+use crate::{AsFile, SparseIter, SparseRangeItem, SparseRangeIter};
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+/// Most items the scanning thread gets ahead of the consumer by
+const BUFFERED: usize = 64;
+
+struct State<T> {
+    items: VecDeque<io::Result<T>>,
+    /// The iterator is finished (or panicked)
+    done: bool,
+    /// The `AsyncSparseIter` was dropped, so the thread should stop
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    /// Signalled when there's room for more items, or the consumer is gone
+    space: Condvar,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Items from an iterator running on another thread, for async code
+///
+/// Created with [`AsyncSparseIter::new()`] from any iterator (a [`SparseIter`],
+/// [`SparseRangeIter`], [`SparseScan`](crate::SparseScan), ...), or [`scan_async()`]. The
+/// iterator runs on another thread, staying a few dozen items ahead, and stops when this is
+/// dropped. If it panics, this ends early. Items are taken with `Stream` (or
+/// [`AsyncSparseIter::next()`]).
+pub struct AsyncSparseIter<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> std::fmt::Debug for AsyncSparseIter<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncSparseIter").finish()
+    }
+}
+
+impl<T: Send + 'static> AsyncSparseIter<T> {
+    /// Run `iter` on another thread, making its items available to async code
+    ///
+    /// The iterator gets a new thread of its own, or with the `tokio` feature and within a tokio
+    /// runtime, runs on the runtime's pool for blocking work (with `spawn_blocking()`). If the
+    /// thread can't be created, the only item is the error from trying.
+    pub fn new<I>(iter: I) -> Self
+    where
+        I: Iterator<Item = io::Result<T>> + Send + 'static,
+    {
+        #[cfg(feature = "tokio")]
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            return Self::spawn_with(iter, |job| drop(runtime.spawn_blocking(job)));
+        }
+
+        let mut spawned = Ok(());
+        let this = Self::spawn_with(iter, |job| {
+            spawned = thread::Builder::new()
+                .name("fs-sparse scan".to_string())
+                .spawn(job)
+                .map(drop);
+        });
+        if let Err(e) = spawned {
+            let mut state = this.shared.lock();
+            state.items.push_back(Err(e));
+            state.done = true;
+        }
+        this
+    }
+
+    /// Run `iter` on a thread provided by `spawn`, such as an async runtime's pool for blocking
+    /// work
+    ///
+    /// `spawn` is called once, with a job that runs the iterator to completion. It must run the
+    /// job on some other thread, which the job blocks while waiting for items to be taken. For
+    /// example, with `smol` (or anything using the `blocking` crate):
+    ///
+    /// ```ignore
+    /// AsyncSparseIter::spawn_with(iter, |job| smol::unblock(job).detach())
+    /// ```
+    ///
+    /// and with `async-std` or `tokio` (which [`AsyncSparseIter::new()`] does with the `tokio`
+    /// feature):
+    ///
+    /// ```ignore
+    /// AsyncSparseIter::spawn_with(iter, |job| drop(async_std::task::spawn_blocking(job)))
+    /// AsyncSparseIter::spawn_with(iter, |job| drop(tokio::task::spawn_blocking(job)))
+    /// ```
+    ///
+    /// If the job is dropped without being run, this ends without any items.
+    pub fn spawn_with<I, S>(iter: I, spawn: S) -> Self
+    where
+        I: Iterator<Item = io::Result<T>> + Send + 'static,
+        S: FnOnce(Box<dyn FnOnce() + Send + 'static>),
+    {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                items: VecDeque::new(),
+                done: false,
+                closed: false,
+                waker: None,
+            }),
+            space: Condvar::new(),
+        });
+
+        let job = Job {
+            iter: Some(iter),
+            shared: shared.clone(),
+        };
+        spawn(Box::new(move || job.run()));
+        Self { shared }
+    }
+}
+
+impl<T> AsyncSparseIter<T> {
+    /// Wait for the next item, or `None` once the iterator is finished
+    ///
+    /// This is `StreamExt::next()`, for use without `futures`.
+    // named like `StreamExt::next()`, which this stands in for
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Next<'_, T> {
+        Next { iter: self }
+    }
+
+    /// Get the next item if it's ready, or arrange for `cx` to be woken when it is
+    ///
+    /// This is `Stream::poll_next()`, without needing to pin `self`.
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<io::Result<T>>> {
+        let mut state = self.shared.lock();
+        if let Some(item) = state.items.pop_front() {
+            self.shared.space.notify_one();
+            return Poll::Ready(Some(item));
+        }
+        if state.done {
+            return Poll::Ready(None);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> Stream for AsyncSparseIter<T> {
+    type Item = io::Result<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_next(cx)
+    }
+}
+
+impl<T> Drop for AsyncSparseIter<T> {
+    fn drop(&mut self) {
+        self.shared.lock().closed = true;
+        self.shared.space.notify_one();
+    }
+}
+
+/// The work of an `AsyncSparseIter`, run on another thread
+struct Job<T, I> {
+    iter: Option<I>,
+    shared: Arc<Shared<T>>,
+}
+
+impl<T, I: Iterator<Item = io::Result<T>>> Job<T, I> {
+    fn run(mut self) {
+        let iter = self.iter.take().unwrap();
+        let shared = &*self.shared;
+        for item in iter {
+            let mut state = shared.lock();
+            while state.items.len() >= BUFFERED && !state.closed {
+                state = shared
+                    .space
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+            if state.closed {
+                return;
+            }
+            state.items.push_back(item);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Marks the iterator as finished when the job is done: it finished, panicked, or was dropped
+/// without being run
+impl<T, I> Drop for Job<T, I> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.done = true;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Future returned by [`AsyncSparseIter::next()`]
+#[derive(Debug)]
+pub struct Next<'a, T> {
+    iter: &'a mut AsyncSparseIter<T>,
+}
+
+impl<T> Future for Next<'_, T> {
+    type Output = Option<io::Result<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.iter.poll_next(cx)
+    }
+}
+
+/// Find the ranges of Data and Holes in `file` without blocking the async executor
+///
+/// This is [`SparseRangeIter`] running on another thread (see [`AsyncSparseIter::new()`]). Use
+/// [`AsyncSparseIter::new()`] for other kinds of scan.
+pub fn scan_async<F: AsFile + Send + 'static>(file: F) -> AsyncSparseIter<SparseRangeItem> {
+    AsyncSparseIter::new(SparseRangeIter::from(SparseIter::from(file)))
+}
@@ -0,0 +1,73 @@
+//! The sparse attribute windows requires before a file may have holes
+
+use crate::AsFile;
+use std::io;
+use std::path::Path;
+
+/// Mark `file` as sparse (or not), so holes may be created in it
+///
+/// On windows, a file can only have holes if it has the sparse attribute
+/// (`FILE_ATTRIBUTE_SPARSE_FILE`), which this sets or clears with `FSCTL_SET_SPARSE`. Clearing it
+/// fills in every hole in the file, and fails on filesystems that don't support it (before
+/// windows 7 and server 2008 R2, it can't be cleared at all).
+///
+/// Unix filesystems don't have a sparse attribute: any file may have holes. There, this does
+/// nothing and always succeeds, so cross platform code can call it unconditionally before
+/// creating holes.
+pub fn set_sparse<F: AsFile>(file: F, sparse: bool) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let _ = (file, sparse);
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    {
+        crate::windows::set_sparse(file.as_handle(), sparse)
+    }
+}
+
+/// Does `file` have the sparse attribute?
+///
+/// On windows, files without the sparse attribute (`FILE_ATTRIBUTE_SPARSE_FILE`) never have
+/// holes: iterating over them reports the whole file as `Data`, and writing zeros to them always
+/// allocates space. Checking this before writing lets callers [`set_sparse()`] first.
+///
+/// Unix filesystems don't have a sparse attribute, so this always returns `true` there.
+pub fn is_marked_sparse<F: AsFile>(file: F) -> io::Result<bool> {
+    #[cfg(unix)]
+    {
+        let _ = file;
+        Ok(true)
+    }
+
+    #[cfg(windows)]
+    {
+        use winapi::um::winnt::FILE_ATTRIBUTE_SPARSE_FILE;
+
+        let info = crate::windows::basic_info(file.as_handle())?;
+        Ok(info.FileAttributes & FILE_ATTRIBUTE_SPARSE_FILE != 0)
+    }
+}
+
+/// Like [`is_marked_sparse()`], but for the file at `path`
+///
+/// This doesn't need to open the file for reading, just to look at its attributes. Symlinks are
+/// followed. On unix, this only checks that `path` exists.
+pub fn is_marked_sparse_path<P: AsRef<Path>>(path: P) -> io::Result<bool> {
+    let meta = std::fs::metadata(path)?;
+
+    #[cfg(unix)]
+    {
+        let _ = meta;
+        Ok(true)
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::MetadataExt;
+        use winapi::um::winnt::FILE_ATTRIBUTE_SPARSE_FILE;
+
+        Ok(meta.file_attributes() & FILE_ATTRIBUTE_SPARSE_FILE != 0)
+    }
+}
@@ -0,0 +1,126 @@
+//! The units a filesystem allocates storage in, and so can make holes in
+
+use crate::AsFile;
+use std::io;
+
+/// The smallest hole the filesystem holding `file` will report, or `None` if it can't report
+/// holes at all
+///
+/// Holes found by [`SparseIter`](crate::SparseIter) start and end on multiples of this (except at
+/// the end of the file), and punching a smaller or unaligned range can't free any storage. When
+/// this is `None`, a [`SparseIter`](crate::SparseIter) reports the whole file as `Data` (or
+/// fails, or falls back to reading it, depending on how it's set up).
+///
+///  - On macos, freebsd, and netbsd, this is `fpathconf(_PC_MIN_HOLE_SIZE)`, as the macos man
+///    page for `lseek()` suggests. macos reports 1 for filesystems that report holes without a
+///    minimum size.
+///  - linux has no such query. `SEEK_HOLE` is probed instead, and if it works, this is the
+///    file's `st_blksize`. Filesystems that don't really support `SEEK_HOLE` have it emulated
+///    by the kernel (reporting no holes), so they appear to support it here too.
+///  - On windows, this is the cluster size if the volume supports sparse files. NTFS can only
+///    free whole compression units (usually 16 clusters) of a sparse file.
+///  - openbsd can't report holes, so this is always `None` there.
+///
+/// On unix other than macos, freebsd, and netbsd this may move the file's cursor.
+pub fn min_hole_size<F: AsFile>(file: F) -> io::Result<Option<u64>> {
+    #[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "netbsd"))]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        // -1 is both an error and "no limit", so errno has to be cleared to tell them apart
+        #[cfg(any(target_os = "macos", target_os = "freebsd"))]
+        let errno = unsafe { libc::__error() };
+        #[cfg(target_os = "netbsd")]
+        let errno = unsafe { libc::__errno() };
+        unsafe { *errno = 0 };
+
+        let r = unsafe { libc::fpathconf(file.as_fd().as_raw_fd(), libc::_PC_MIN_HOLE_SIZE) };
+        if r > 0 {
+            return crate::error::checked_cast(r).map(Some);
+        }
+        match unsafe { *errno } {
+            0 | libc::EINVAL => Ok(None),
+            e => Err(io::Error::from_raw_os_error(e)),
+        }
+    }
+
+    #[cfg(all(
+        unix,
+        not(any(target_os = "macos", target_os = "freebsd", target_os = "netbsd"))
+    ))]
+    {
+        let fd = file.as_fd();
+        match crate::seek(fd, 0, crate::SEEK_HOLE) {
+            Ok(_) => {}
+            Err(e) if crate::is_unsupported(&e) => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        crate::error::checked_cast(crate::unix::stat(fd)?.st_blksize).map(Some)
+    }
+
+    #[cfg(windows)]
+    {
+        use winapi::um::winnt::FILE_SUPPORTS_SPARSE_FILES;
+
+        if crate::windows::volume_flags(file.as_handle())? & FILE_SUPPORTS_SPARSE_FILES == 0 {
+            return Ok(None);
+        }
+        block_size(file).map(Some)
+    }
+}
+
+/// The size of the blocks the filesystem holding `file` allocates storage in
+///
+/// Holes start and end on multiples of this, so ranges to punch out should be aligned to it (see
+/// [`align_up()`] and [`align_down()`]).
+///
+///  - On unix, this is the `f_frsize` from `fstatvfs()`, the filesystem's fundamental block size.
+///    This is the same as `f_bsize` on linux. On macos, `f_bsize` is instead the preferred size
+///    for I/O, which is often much larger.
+///  - On windows, this is the volume's cluster size.
+///
+/// This is not the `st_blksize` from `fstat()`, which is the preferred size for reads and writes.
+/// That is usually the same or larger, but on some filesystems it's smaller.
+pub fn block_size<F: AsFile>(file: F) -> io::Result<u64> {
+    #[cfg(unix)]
+    {
+        let st = crate::unix::statvfs(file.as_fd())?;
+
+        // these are 32 bits on some platforms
+        #[allow(clippy::useless_conversion)]
+        let (frsize, bsize) = (u64::from(st.f_frsize), u64::from(st.f_bsize));
+        // not every filesystem fills in f_frsize
+        match frsize {
+            0 => Ok(bsize),
+            size => Ok(size),
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        let info = crate::windows::compression_info(file.as_handle())?;
+        Ok(1 << info.ClusterShift)
+    }
+}
+
+/// Round `offset` down to a multiple of `align`
+///
+/// # Panics
+///
+/// If `align` is 0
+pub fn align_down(offset: u64, align: u64) -> u64 {
+    assert!(align > 0, "alignment must be non-zero");
+    offset - offset % align
+}
+
+/// Round `offset` up to a multiple of `align`
+///
+/// # Panics
+///
+/// If `align` is 0, or the result doesn't fit in a `u64`
+pub fn align_up(offset: u64, align: u64) -> u64 {
+    assert!(align > 0, "alignment must be non-zero");
+    offset
+        .checked_next_multiple_of(align)
+        .expect("aligned offset overflows u64")
+}
@@ -0,0 +1,241 @@
+//! Block maps for bmap-tools, so images can be flashed without writing their holes
+//!
+//! `bmaptool copy` writes only the blocks a `.bmap` file lists as mapped, and checks each range of
+//! them against a SHA-256 checksum. [`Bmap::builder()`] scans an image and records its mapped
+//! blocks, and [`Bmap::write_xml()`] writes them out in version 2.0 of the format, which
+//! `bmaptool` reads.
+//!
+//! A block is mapped if any of it is `Data`. Zeros that were written to an image (rather than left
+//! as holes) are `Data` too, so they get listed and flashed unless
+//! [`BmapBuilder::skip_zero_blocks()`] is used to read every mapped block and leave out the ones
+//! that are all zeros.
+
+use crate::sha256::Sha256;
+use crate::unix::{file_len, pread_full};
+use crate::{is_zero, AsFile, ItemKind, SparseIter, SparseRangeIter};
+use std::io::{self, Write};
+
+/// Most bytes read at once while checksumming
+const CHUNK: u64 = 1024 * 1024;
+
+/// Placeholder for the file's own checksum while computing it, as `bmaptool` expects
+const ZERO_CHECKSUM: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Configures how a [`Bmap`] is made
+///
+/// Created by [`Bmap::builder()`]. By default, blocks are 4096 bytes and any block with `Data` in
+/// it is mapped.
+#[derive(Debug, Clone)]
+pub struct BmapBuilder {
+    block_size: u64,
+    skip_zero_blocks: bool,
+}
+
+impl BmapBuilder {
+    /// Map blocks of `block_size` bytes
+    ///
+    /// # Panics
+    ///
+    /// If `block_size` is 0
+    pub fn block_size(mut self, block_size: u64) -> Self {
+        assert!(block_size != 0, "block size must be non-zero");
+        self.block_size = block_size;
+        self
+    }
+
+    /// Leave out blocks that are all zeros, even if they are `Data`
+    ///
+    /// This catches zeros that were written out instead of being left as holes (or a filesystem
+    /// that can't report holes at all), which otherwise get flashed for nothing.
+    pub fn skip_zero_blocks(mut self, skip: bool) -> Self {
+        self.skip_zero_blocks = skip;
+        self
+    }
+
+    /// Scan `file` and checksum its mapped blocks
+    ///
+    /// If the filesystem can't report holes, every block is mapped (see
+    /// [`SparseIter::fallback_to_data()`]). This never moves the file's cursor (unless the
+    /// `SparseIter` does, while looking for holes).
+    pub fn build<F: AsFile>(&self, file: F) -> io::Result<Bmap> {
+        let fd = file.as_fd();
+        let bs = self.block_size;
+        let image_size = file_len(fd)?;
+
+        // the first and last (inclusive) blocks of each run that has data in it
+        let mut runs: Vec<(u64, u64)> = Vec::new();
+        for r in SparseRangeIter::from(SparseIter::from(fd).fallback_to_data()) {
+            let r = r?;
+            if r.kind != ItemKind::Data {
+                continue;
+            }
+            let (first, last) = (r.start / bs, (r.end - 1) / bs);
+            match runs.last_mut() {
+                Some(prev) if first <= prev.1 + 1 => prev.1 = last,
+                _ => runs.push((first, last)),
+            }
+        }
+
+        let chunk_blocks = (CHUNK / bs).max(1);
+        let mut ranges = Vec::new();
+        let mut buf = Vec::new();
+        for (first, last) in runs {
+            let mut current: Option<(u64, Sha256)> = None;
+            let mut block = first;
+            while block <= last {
+                let offset = block * bs;
+                let blocks = (last + 1 - block).min(chunk_blocks);
+                buf.resize((blocks * bs).min(image_size - offset) as usize, 0);
+                if pread_full(fd, &mut buf, offset)? != buf.len() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "file was truncated while being mapped",
+                    ));
+                }
+
+                for (i, b) in buf.chunks(bs as usize).enumerate() {
+                    let index = block + i as u64;
+                    if self.skip_zero_blocks && is_zero(b) {
+                        if let Some((start, sha)) = current.take() {
+                            ranges.push(BmapRange::new(start, index - 1, sha));
+                        }
+                        continue;
+                    }
+                    current
+                        .get_or_insert_with(|| (index, Sha256::new()))
+                        .1
+                        .update(b);
+                }
+                block += blocks;
+            }
+            if let Some((start, sha)) = current {
+                ranges.push(BmapRange::new(start, last, sha));
+            }
+        }
+
+        Ok(Bmap {
+            image_size,
+            block_size: bs,
+            ranges,
+        })
+    }
+}
+
+/// A run of mapped blocks in a [`Bmap`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BmapRange {
+    /// Index of the first block in the run
+    pub first: u64,
+    /// Index of the last block in the run (inclusive, as bmap files write them)
+    pub last: u64,
+    /// SHA-256 of the blocks' contents, in lowercase hex
+    ///
+    /// If the run includes the end of the image, only the bytes up to the end are included.
+    pub sha256: String,
+}
+
+impl BmapRange {
+    fn new(first: u64, last: u64, sha: Sha256) -> Self {
+        Self {
+            first,
+            last,
+            sha256: sha.finish_hex(),
+        }
+    }
+}
+
+/// The mapped blocks of an image, as listed in a `.bmap` file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bmap {
+    image_size: u64,
+    block_size: u64,
+    ranges: Vec<BmapRange>,
+}
+
+impl Bmap {
+    /// Start configuring how to map an image
+    pub fn builder() -> BmapBuilder {
+        BmapBuilder {
+            block_size: 4096,
+            skip_zero_blocks: false,
+        }
+    }
+
+    /// The length of the image in bytes
+    pub fn image_size(&self) -> u64 {
+        self.image_size
+    }
+
+    /// The size of each block in bytes
+    pub fn block_size(&self) -> u64 {
+        self.block_size
+    }
+
+    /// The number of blocks in the image, counting a partial one at the end
+    pub fn blocks_count(&self) -> u64 {
+        self.image_size.div_ceil(self.block_size)
+    }
+
+    /// The number of blocks that are mapped
+    pub fn mapped_blocks_count(&self) -> u64 {
+        self.ranges.iter().map(|r| r.last - r.first + 1).sum()
+    }
+
+    /// The runs of mapped blocks, in order
+    pub fn ranges(&self) -> &[BmapRange] {
+        &self.ranges
+    }
+
+    /// Write this map to `w` as a version 2.0 `.bmap` file
+    ///
+    /// This includes the checksum of the file itself (`BmapFileChecksum`), computed the way
+    /// `bmaptool` checks it: over the whole file, with the checksum replaced by zeros.
+    pub fn write_xml<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let xml = self.xml(ZERO_CHECKSUM);
+        let mut sha = Sha256::new();
+        sha.update(xml.as_bytes());
+        w.write_all(self.xml(&sha.finish_hex()).as_bytes())
+    }
+
+    fn xml(&self, checksum: &str) -> String {
+        let mut s = String::new();
+        s.push_str("<?xml version=\"1.0\" ?>\n");
+        s.push_str("<bmap version=\"2.0\">\n");
+        s.push_str(&format!(
+            "    <ImageSize> {} </ImageSize>\n",
+            self.image_size
+        ));
+        s.push_str(&format!(
+            "    <BlockSize> {} </BlockSize>\n",
+            self.block_size
+        ));
+        s.push_str(&format!(
+            "    <BlocksCount> {} </BlocksCount>\n",
+            self.blocks_count()
+        ));
+        s.push_str(&format!(
+            "    <MappedBlocksCount> {} </MappedBlocksCount>\n",
+            self.mapped_blocks_count()
+        ));
+        s.push_str("    <ChecksumType> sha256 </ChecksumType>\n");
+        s.push_str(&format!(
+            "    <BmapFileChecksum> {} </BmapFileChecksum>\n",
+            checksum
+        ));
+        s.push_str("    <BlockMap>\n");
+        for r in &self.ranges {
+            let blocks = if r.first == r.last {
+                r.first.to_string()
+            } else {
+                format!("{}-{}", r.first, r.last)
+            };
+            s.push_str(&format!(
+                "        <Range chksum=\"{}\"> {} </Range>\n",
+                r.sha256, blocks
+            ));
+        }
+        s.push_str("    </BlockMap>\n");
+        s.push_str("</bmap>\n");
+        s
+    }
+}
@@ -0,0 +1,108 @@
+//! This crate's path-taking functions, with paths relative to a `cap_std::fs::Dir`
+
+use crate::error::Result;
+use crate::{clone_file, SparseMap};
+use cap_std::fs::{Dir, File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+#[cfg(unix)]
+use crate::Sparsified;
+
+/// This crate's path-taking functions as methods on a [`Dir`]
+///
+/// Each method is the function of a similar name ([`map_path()`](crate::map_path) for
+/// `dir.sparse_map(path)`), but resolves its paths within the directory, the way the rest of
+/// `cap_std` does: absolute paths, and `..` or symlinks leading out of the directory, are errors
+/// instead of escaping the sandbox. Files opened by path elsewhere in this crate are opened with
+/// the process's own access to the filesystem, so a sandboxed program should use these instead.
+///
+/// Files already open through `cap_std` (`cap_std::fs::File`) can be passed to anything taking
+/// an [`AsFile`](crate::AsFile) as they are.
+pub trait SparseDirExt {
+    /// Open the file at `path` and scan it into a [`SparseMap`] (see
+    /// [`map_path()`](crate::map_path))
+    fn sparse_map<P: AsRef<Path>>(&self, path: P) -> io::Result<SparseMap>;
+
+    /// Dig holes in the file at `path`, using the filesystem's block size (see
+    /// [`sparsify_path()`](crate::sparsify_path))
+    #[cfg(unix)]
+    fn sparsify<P: AsRef<Path>>(&self, path: P) -> Result<Sparsified>;
+
+    /// Copy the file at `src` to a new file at `dst` in `dst_dir`, leaving holes where `src` has
+    /// them (see [`copy_sparse_path()`](crate::copy_sparse_path))
+    ///
+    /// Unlike `copy_sparse_path()`, this never clones with `fclonefileat()` on macos, which
+    /// would resolve `dst` outside of `cap_std`.
+    #[cfg(unix)]
+    fn copy_sparse<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        src: P,
+        dst_dir: &Dir,
+        dst: Q,
+    ) -> io::Result<()>;
+
+    /// Clone the file at `src` to a new file at `dst` in `dst_dir` (see
+    /// [`clone_file_path()`](crate::clone_file_path))
+    ///
+    /// `dst` is created, and then [`clone_file()`] is used, on every platform: there's no
+    /// `fclonefileat()` on macos, so it always fails there with
+    /// [`Error::Unsupported`](crate::Error::Unsupported).
+    fn clone_file<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        src: P,
+        dst_dir: &Dir,
+        dst: Q,
+    ) -> Result<()>;
+}
+
+impl SparseDirExt for Dir {
+    fn sparse_map<P: AsRef<Path>>(&self, path: P) -> io::Result<SparseMap> {
+        SparseMap::from_file(self.open(path)?)
+    }
+
+    #[cfg(unix)]
+    fn sparsify<P: AsRef<Path>>(&self, path: P) -> Result<Sparsified> {
+        let file = self.open_with(path, OpenOptions::new().read(true).write(true))?;
+        crate::sparsify(&file, crate::block_size(&file)?)
+    }
+
+    #[cfg(unix)]
+    fn copy_sparse<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        src: P,
+        dst_dir: &Dir,
+        dst: Q,
+    ) -> io::Result<()> {
+        let src = self.open(src)?;
+        let dst = create_new(dst_dir, dst.as_ref())?;
+        dst.set_permissions(src.metadata()?.permissions())?;
+        crate::copy_sparse(&src, &dst)?;
+        Ok(())
+    }
+
+    fn clone_file<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        src: P,
+        dst_dir: &Dir,
+        dst: Q,
+    ) -> Result<()> {
+        let dst = dst.as_ref();
+        let src = self.open(src)?;
+        let dst_file = create_new(dst_dir, dst)?;
+        let r = clone_file(&src, &dst_file).and_then(|()| {
+            dst_file.set_permissions(src.metadata()?.permissions())?;
+            Ok(())
+        });
+        if r.is_err() {
+            drop(dst_file);
+            let _ = dst_dir.remove_file(dst);
+        }
+        r
+    }
+}
+
+/// Create a file at `path` in `dir` for writing, which must not already exist
+fn create_new(dir: &Dir, path: &Path) -> io::Result<File> {
+    dir.open_with(path, OpenOptions::new().write(true).create_new(true))
+}
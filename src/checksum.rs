@@ -0,0 +1,206 @@
+//! Checksumming each range of data while scanning
+//!
+//! [`ChecksummedRanges`] reads every `Data` range as it finds it, so a single pass gives both the
+//! layout of a file and a checksum of each piece of data in it. Comparing those with the ones
+//! from an earlier scan shows which ranges changed, and checking them against a copy verifies it
+//! without hashing its holes.
+//!
+//! The checksum is chosen with the [`Checksum`] trait: [`Crc32c`] is fast, [`Sha256`] is hard to
+//! fool, and other algorithms can be plugged in by implementing it.
+
+use crate::unix::pread_full;
+use crate::{AsFile, ItemKind, SparseIter, SparseRangeItem, SparseRangeIter};
+use std::io;
+use std::iter::FusedIterator;
+
+/// Most bytes read at once
+const CHUNK: u64 = 1024 * 1024;
+
+/// A checksum of one range of data at a time, for [`ChecksummedRanges`]
+pub trait Checksum {
+    /// The finished checksum
+    type Output;
+
+    /// Add `data` to the checksum
+    fn update(&mut self, data: &[u8]);
+
+    /// The checksum of everything passed to `update()`
+    fn finish(self) -> Self::Output;
+}
+
+/// CRC-32C (Castagnoli), as used by iSCSI, ext4, and btrfs
+#[derive(Debug, Clone)]
+pub struct Crc32c(u32);
+
+impl Crc32c {
+    /// Start a checksum of nothing
+    pub fn new() -> Self {
+        Crc32c(!0)
+    }
+}
+
+impl Default for Crc32c {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Lookup table for the reflected polynomial 0x82f63b78, one byte at a time
+const CRC32C_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x82f6_3b78
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+impl Checksum for Crc32c {
+    type Output = u32;
+
+    fn update(&mut self, data: &[u8]) {
+        let mut crc = self.0;
+        for &b in data {
+            crc = CRC32C_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8);
+        }
+        self.0 = crc;
+    }
+
+    fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+/// SHA-256
+#[derive(Clone)]
+pub struct Sha256(crate::sha256::Sha256);
+
+impl Sha256 {
+    /// Start a hash of nothing
+    pub fn new() -> Self {
+        Sha256(crate::sha256::Sha256::new())
+    }
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for Sha256 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sha256").finish()
+    }
+}
+
+impl Checksum for Sha256 {
+    type Output = [u8; 32];
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data)
+    }
+
+    fn finish(self) -> [u8; 32] {
+        self.0.finish()
+    }
+}
+
+/// A range from [`ChecksummedRanges`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksummedRange<O> {
+    /// Where the range is, and what kind it is
+    pub range: SparseRangeItem,
+    /// The checksum of the range's contents, for `Data`, or `None` for Holes
+    pub checksum: Option<O>,
+}
+
+/// Iterate over the ranges of Data and Holes in a file, with a checksum of each `Data` range
+///
+/// Every `Data` range is read in full before it's returned. If the filesystem can't report holes,
+/// the whole file is one `Data` range (see [`SparseIter::fallback_to_data()`]). If the file
+/// shrinks while being read, the checksum only covers what was still there. The file's cursor is
+/// never used. Like [`SparseRangeIter`], this stops after returning an error.
+#[derive(Debug)]
+pub struct ChecksummedRanges<F, M> {
+    ranges: SparseRangeIter<F>,
+    new_checksum: M,
+    buf: Vec<u8>,
+    failed: bool,
+}
+
+impl<F: AsFile, C: Checksum, M: FnMut() -> C> ChecksummedRanges<F, M> {
+    /// Scan `file`, starting a checksum for each `Data` range with `new_checksum`
+    ///
+    /// For example, `ChecksummedRanges::new(&file, Crc32c::new)`.
+    pub fn new(file: F, new_checksum: M) -> Self {
+        Self {
+            ranges: SparseRangeIter::from(SparseIter::from(file).fallback_to_data()),
+            new_checksum,
+            buf: Vec::new(),
+            failed: false,
+        }
+    }
+
+    fn checksum(&mut self, range: &SparseRangeItem) -> io::Result<C::Output> {
+        let fd = self.ranges.inner.get_ref().as_fd();
+        let mut checksum = (self.new_checksum)();
+        let mut offset = range.start;
+        while offset < range.end {
+            let want = (range.end - offset).min(CHUNK) as usize;
+            self.buf.resize(want, 0);
+            let n = pread_full(fd, &mut self.buf, offset)?;
+            checksum.update(&self.buf[..n]);
+            if n < want {
+                break;
+            }
+            offset += n as u64;
+        }
+        Ok(checksum.finish())
+    }
+}
+
+impl<F: AsFile, C: Checksum, M: FnMut() -> C> Iterator for ChecksummedRanges<F, M> {
+    type Item = io::Result<ChecksummedRange<C::Output>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+
+        let range = match self.ranges.next()? {
+            Ok(r) => r,
+            Err(e) => return Some(Err(e)),
+        };
+        if range.kind == ItemKind::Hole {
+            return Some(Ok(ChecksummedRange {
+                range,
+                checksum: None,
+            }));
+        }
+
+        match self.checksum(&range) {
+            Ok(checksum) => Some(Ok(ChecksummedRange {
+                range,
+                checksum: Some(checksum),
+            })),
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl<F: AsFile, C: Checksum, M: FnMut() -> C> FusedIterator for ChecksummedRanges<F, M> {}
@@ -0,0 +1,362 @@
+//! Cloning files: sharing storage between them instead of copying it
+
+use crate::error::{Error, Result};
+use crate::punch::check_range;
+use crate::{AsFile, ItemKind, SparseRangeItem};
+use std::borrow::Borrow;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Make `dst` a clone of `src`, sharing `src`'s storage instead of copying it
+///
+/// `dst` ends up with the same contents and length as `src`, with anything it held before
+/// discarded. Cloning takes about as long no matter how large the file is, and keeps every hole
+/// where it is. The files share storage until one of them is modified (only the modified blocks
+/// are copied then).
+///
+///  - On linux and android, this uses the `FICLONE` ioctl, which btrfs, xfs (when created with
+///    reflink support), and bcachefs support.
+///  - On windows, this uses `FSCTL_DUPLICATE_EXTENTS_TO_FILE`, which only ReFS supports. If `src`
+///    is marked sparse, `dst` is marked sparse too.
+///  - macos can only clone into a new file: use [`clone_file_path()`].
+///  - Everywhere else, this always fails with [`Error::Unsupported`].
+///
+/// Both files must be on the same filesystem. [`copy_sparse()`](crate::copy_sparse) works
+/// everywhere, and already shares storage where it can on linux.
+///
+/// # Errors
+///
+///  - [`Error::Unsupported`] if the platform or filesystem can't clone, or the files are on
+///    different filesystems. On linux `dst` is untouched in this case. On windows, it may have
+///    been emptied if the files are on different volumes.
+///  - [`Error::NotWritable`] if `dst` isn't open for writing
+pub fn clone_file<S: AsFile, D: AsFile>(src: S, dst: D) -> Result<()> {
+    // FICLONE leaves anything in `dst` past the end of `src` where it was
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let r = crate::linux::ficlone(src.as_fd(), dst.as_fd())
+        .and_then(|()| crate::unix::set_len(dst.as_fd(), crate::unix::file_len(src.as_fd())?));
+
+    #[cfg(windows)]
+    let r = duplicate_file(src.as_handle(), dst.as_handle());
+
+    #[cfg(not(any(windows, target_os = "linux", target_os = "android")))]
+    let r = {
+        let _ = (src, dst);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "cloning into an open file is not supported on this platform",
+        ))
+    };
+
+    r.map_err(classify)
+}
+
+/// Clone the file at `src` to a new file at `dst`, sharing `src`'s storage instead of copying it
+///
+/// `dst` must not already exist. It is created with the same permissions as `src`. If cloning
+/// fails, `dst` is removed again.
+///
+/// On macos, this uses `fclonefileat()`, which only APFS supports. Elsewhere, this creates `dst`
+/// and uses [`clone_file()`]. To clone within a capability sandbox, use
+/// `SparseDirExt::clone_file()` (with the `cap-std` feature) instead.
+///
+/// # Errors
+///
+///  - [`Error::Unsupported`] if the platform or filesystem can't clone, or the files are on
+///    different filesystems
+///  - [`Error::Io`] if `src` can't be opened, or `dst` can't be created
+pub fn clone_file_path<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> Result<()> {
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    let src = File::open(src)?;
+
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::unix::io::AsFd;
+
+        crate::macos::clone_file(src.as_fd(), dst).map_err(classify)
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        let dst_file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(dst)?;
+        let r = clone_file(&src, &dst_file).and_then(|()| {
+            dst_file.set_permissions(src.metadata()?.permissions())?;
+            Ok(())
+        });
+        if r.is_err() {
+            drop(dst_file);
+            let _ = std::fs::remove_file(dst);
+        }
+        r
+    }
+}
+
+/// Clone `len` bytes of `src` at `src_offset` into `dst` at `dst_offset`, sharing storage instead
+/// of copying
+///
+/// The rest of `dst` is left alone. `dst` is extended if the range ends past its end. This is
+/// the building block for updating part of a large file (a disk image, for example) without
+/// copying all of it: see [`clone_ranges()`] to clone the `Data` ranges of a
+/// [`SparseMap`](crate::SparseMap).
+///
+/// Both offsets, and `len`, must be multiples of the filesystem's block size, except that the
+/// range may end at the end of `src` part way through a block.
+///
+///  - On linux and android, this uses the `FICLONERANGE` ioctl.
+///  - On windows, this uses `FSCTL_DUPLICATE_EXTENTS_TO_FILE` (ReFS only).
+///  - Everywhere else, this always fails with [`Error::Unsupported`].
+///
+/// # Errors
+///
+///  - [`Error::Unsupported`] if the platform or filesystem can't clone, or the files are on
+///    different filesystems
+///  - [`Error::InvalidRange`] if either range isn't aligned to the block size, or ends past the
+///    largest offset a file may have. `offset` in the error is `src_offset`.
+///  - [`Error::NotWritable`] if `dst` isn't open for writing
+pub fn clone_range<S: AsFile, D: AsFile>(
+    src: S,
+    dst: D,
+    src_offset: u64,
+    dst_offset: u64,
+    len: u64,
+) -> Result<()> {
+    check_range(src_offset, len)?;
+    check_range(dst_offset, len).map_err(|_| Error::InvalidRange {
+        offset: src_offset,
+        len,
+    })?;
+    if len == 0 {
+        return Ok(());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let r = crate::linux::ficlonerange(src.as_fd(), dst.as_fd(), src_offset, dst_offset, len);
+
+    #[cfg(windows)]
+    let r = duplicate_range(
+        src.as_handle(),
+        dst.as_handle(),
+        src_offset,
+        dst_offset,
+        len,
+    );
+
+    #[cfg(not(any(windows, target_os = "linux", target_os = "android")))]
+    let r = {
+        let _ = (src, dst);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "cloning ranges is not supported on this platform",
+        ))
+    };
+
+    r.map_err(|e| classify_range(e, src_offset, len))
+}
+
+/// Clone each `Data` range in `ranges` from `src` into `dst`, at the same offset
+///
+/// `ranges` is anything yielding [`SparseRangeItem`]s (or references to them), like a
+/// [`SparseMap`](crate::SparseMap) of `src`. `Hole` ranges are skipped, so the parts of `dst`
+/// they cover are left alone. Each range is cloned with [`clone_range()`], and the same
+/// requirements apply.
+///
+/// Returns the number of bytes cloned. If an error is returned, the ranges before the one that
+/// failed have already been cloned.
+pub fn clone_ranges<S, D, I>(src: S, dst: D, ranges: I) -> Result<u64>
+where
+    S: AsFile,
+    D: AsFile,
+    I: IntoIterator,
+    I::Item: Borrow<SparseRangeItem>,
+{
+    let mut cloned = 0;
+    for r in ranges {
+        let r = r.borrow();
+        if r.kind == ItemKind::Data && r.start < r.end {
+            clone_range(&src, &dst, r.start, r.start, r.end - r.start)?;
+            cloned += r.end - r.start;
+        }
+    }
+    Ok(cloned)
+}
+
+/// What [`dedupe_range()`] found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deduped {
+    /// The ranges were identical, and now share storage
+    Shared,
+    /// The ranges differ. Parts before the first difference may share storage now.
+    Differs,
+}
+
+/// If `len` bytes of `src` at `src_offset` are identical to those of `dst` at `dst_offset`, make
+/// them share storage
+///
+/// This is how deduplication tools reclaim space: find ranges that are probably identical (by
+/// comparing checksums, for example), and let the filesystem compare them and share one copy.
+/// Unlike [`clone_range()`], the contents of `dst` never change, so this is safe even if the
+/// files are modified in the meantime. `dst` only needs to be open for writing if the caller
+/// doesn't own it.
+///
+/// The same alignment rules as [`clone_range()`] apply.
+///
+///  - On linux and android, this uses the `FIDEDUPERANGE` ioctl (btrfs, xfs with reflink
+///    support, bcachefs).
+///  - Everywhere else, this always fails with [`Error::Unsupported`].
+///
+/// # Errors
+///
+///  - [`Error::Unsupported`] if the platform or filesystem can't deduplicate, or the files are on
+///    different filesystems
+///  - [`Error::InvalidRange`] if either range isn't aligned to the block size, or runs past the
+///    end of its file. `offset` in the error is `src_offset`.
+pub fn dedupe_range<S: AsFile, D: AsFile>(
+    src: S,
+    dst: D,
+    src_offset: u64,
+    dst_offset: u64,
+    len: u64,
+) -> Result<Deduped> {
+    check_range(src_offset, len)?;
+    check_range(dst_offset, len).map_err(|_| Error::InvalidRange {
+        offset: src_offset,
+        len,
+    })?;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        let mut done = 0;
+        while done < len {
+            match crate::linux::fideduperange(
+                src.as_fd(),
+                dst.as_fd(),
+                src_offset + done,
+                dst_offset + done,
+                len - done,
+            ) {
+                Ok(Some(0)) => break,
+                Ok(Some(n)) => done += n,
+                Ok(None) => return Ok(Deduped::Differs),
+                Err(e) => return Err(classify_range(e, src_offset, len)),
+            }
+        }
+        Ok(Deduped::Shared)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    {
+        let _ = (src, dst);
+        Err(classify(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "deduplication is not supported on this platform",
+        )))
+    }
+}
+
+/// Classify an error from cloning, treating files on different filesystems as unsupported
+fn classify(source: io::Error) -> Error {
+    #[cfg(unix)]
+    let cross_device = source.raw_os_error() == Some(libc::EXDEV);
+    #[cfg(windows)]
+    let cross_device =
+        source.raw_os_error() == Some(winapi::shared::winerror::ERROR_NOT_SAME_DEVICE as i32);
+
+    if cross_device {
+        return Error::Unsupported { source };
+    }
+    Error::from_errno(source, 0, 0)
+}
+
+/// Like [`classify()`], but for operations on a range, where invalid arguments mean the range
+/// isn't aligned
+fn classify_range(source: io::Error, offset: u64, len: u64) -> Error {
+    #[cfg(unix)]
+    let invalid = source.raw_os_error() == Some(libc::EINVAL);
+    #[cfg(windows)]
+    let invalid =
+        source.raw_os_error() == Some(winapi::shared::winerror::ERROR_INVALID_PARAMETER as i32);
+
+    if invalid {
+        return Error::InvalidRange { offset, len };
+    }
+    classify(source)
+}
+
+/// Clone all of `src` into `dst` one chunk at a time with `FSCTL_DUPLICATE_EXTENTS_TO_FILE`
+#[cfg(windows)]
+fn duplicate_file(
+    src: std::os::windows::io::BorrowedHandle<'_>,
+    dst: std::os::windows::io::BorrowedHandle<'_>,
+) -> io::Result<()> {
+    use crate::windows;
+
+    // fails on anything but ReFS, before we've changed `dst`
+    let cluster = windows::cluster_size(src)?;
+    let info = windows::standard_info(src)?;
+    let len = windows::large_integer(&info.EndOfFile)?;
+
+    // the source and destination have to agree on whether they're sparse
+    if crate::is_marked_sparse(src)? {
+        windows::set_sparse(dst, true)?;
+    }
+    windows::set_len(dst, 0)?;
+    windows::set_len(dst, len)?;
+
+    duplicate_chunks(src, dst, 0, 0, len, cluster, len)
+}
+
+/// Clone part of `src` into `dst` with `FSCTL_DUPLICATE_EXTENTS_TO_FILE`, extending `dst` if needed
+#[cfg(windows)]
+fn duplicate_range(
+    src: std::os::windows::io::BorrowedHandle<'_>,
+    dst: std::os::windows::io::BorrowedHandle<'_>,
+    src_offset: u64,
+    dst_offset: u64,
+    len: u64,
+) -> io::Result<()> {
+    use crate::windows;
+
+    let cluster = windows::cluster_size(src)?;
+    let info = windows::standard_info(src)?;
+    let src_len = windows::large_integer(&info.EndOfFile)?;
+    let info = windows::standard_info(dst)?;
+    let dst_len = windows::large_integer(&info.EndOfFile)?;
+
+    if dst_len < dst_offset + len {
+        windows::set_len(dst, dst_offset + len)?;
+    }
+    duplicate_chunks(src, dst, src_offset, dst_offset, len, cluster, src_len)
+}
+
+/// Clone `len` bytes in chunks small enough for `FSCTL_DUPLICATE_EXTENTS_TO_FILE`
+#[cfg(windows)]
+fn duplicate_chunks(
+    src: std::os::windows::io::BorrowedHandle<'_>,
+    dst: std::os::windows::io::BorrowedHandle<'_>,
+    src_offset: u64,
+    dst_offset: u64,
+    len: u64,
+    cluster: u64,
+    src_len: u64,
+) -> io::Result<()> {
+    // Each chunk has to be a whole number of clusters (a chunk ending at the end of `src` may run
+    // past it) and smaller than 4 GiB.
+    let max = u64::from(u32::MAX) / cluster * cluster;
+    let mut done = 0;
+    while done < len {
+        let n = (len - done).min(max);
+        let aligned = if src_offset + done + n >= src_len {
+            n.div_ceil(cluster) * cluster
+        } else {
+            n
+        };
+        crate::windows::duplicate_extents(src, dst, src_offset + done, dst_offset + done, aligned)?;
+        done += n;
+    }
+
+    Ok(())
+}
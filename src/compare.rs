@@ -0,0 +1,101 @@
+//! Comparing the contents of two files without reading their holes
+
+use crate::unix::{pread_full, BorrowedFd};
+use crate::{AsFile, ItemKind, SparseIter, SparseRangeItem, SparseRangeIter};
+use std::io;
+use std::ops::Range;
+
+/// Most bytes read from each file at once
+const CHUNK: u64 = 1024 * 1024;
+
+/// Bytes compared in one go before looking for the exact bytes that differ
+const STRIDE: usize = 4096;
+
+/// Find the byte ranges where the contents of `a` and `b` differ
+///
+/// Holes read as zeros, so only the parts where at least one file has `Data` are read: where
+/// both have holes, they're the same without looking. Where one has a hole, the other's data is
+/// checked for zeros. If the filesystem can't report holes, all of that file is read (see
+/// [`SparseIter::fallback_to_data()`]).
+///
+/// Returns the differing ranges in order, with adjacent ones merged. If one file is longer, the
+/// part past the end of the shorter one is included as a single range, whatever it holds.
+/// This never moves either file's cursor (unless the [`SparseIter`] does, while looking for
+/// holes).
+pub fn compare_sparse<A: AsFile, B: AsFile>(a: A, b: B) -> io::Result<Vec<Range<u64>>> {
+    let (a, b) = (a.as_fd(), b.as_fd());
+    let a_ranges = data_ranges(a)?;
+    let b_ranges = data_ranges(b)?;
+    let a_len = a_ranges.last().map(|r| r.end).unwrap_or(0);
+    let b_len = b_ranges.last().map(|r| r.end).unwrap_or(0);
+    let common = a_len.min(b_len);
+
+    let mut diffs: Vec<Range<u64>> = Vec::new();
+    let mut add = |start: u64, end: u64| match diffs.last_mut() {
+        Some(d) if d.end == start => d.end = end,
+        _ => diffs.push(start..end),
+    };
+
+    let (mut a_buf, mut b_buf) = (Vec::new(), Vec::new());
+    let (mut ai, mut bi) = (0, 0);
+    let mut pos = 0;
+    while pos < common {
+        while a_ranges[ai].end <= pos {
+            ai += 1;
+        }
+        while b_ranges[bi].end <= pos {
+            bi += 1;
+        }
+        let (ar, br) = (&a_ranges[ai], &b_ranges[bi]);
+        let next = ar.end.min(br.end);
+        if ar.kind == ItemKind::Hole && br.kind == ItemKind::Hole {
+            pos = next;
+            continue;
+        }
+
+        while pos < next {
+            let len = (next - pos).min(CHUNK) as usize;
+            read(a, ar.kind, &mut a_buf, len, pos)?;
+            read(b, br.kind, &mut b_buf, len, pos)?;
+
+            for (i, (x, y)) in a_buf.chunks(STRIDE).zip(b_buf.chunks(STRIDE)).enumerate() {
+                if x == y {
+                    continue;
+                }
+                let base = pos + (i * STRIDE) as u64;
+                for (j, (p, q)) in x.iter().zip(y).enumerate() {
+                    if p != q {
+                        add(base + j as u64, base + j as u64 + 1);
+                    }
+                }
+            }
+            pos += len as u64;
+        }
+    }
+
+    if a_len != b_len {
+        add(common, a_len.max(b_len));
+    }
+    Ok(diffs)
+}
+
+fn data_ranges(fd: BorrowedFd<'_>) -> io::Result<Vec<SparseRangeItem>> {
+    SparseRangeIter::from(SparseIter::from(fd).fallback_to_data()).collect()
+}
+
+/// Fill `buf` with `len` bytes of the file at `offset`, or zeros if that's a hole
+fn read(
+    fd: BorrowedFd<'_>,
+    kind: ItemKind,
+    buf: &mut Vec<u8>,
+    len: usize,
+    offset: u64,
+) -> io::Result<()> {
+    buf.clear();
+    buf.resize(len, 0);
+    if kind == ItemKind::Data {
+        // if the file shrank since it was scanned, what's missing compares as zeros
+        pread_full(fd, buf, offset)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,194 @@
+//! Copying files without filling in their holes
+
+use crate::unix::{file_len, pread, pwrite_all, set_len, BorrowedFd};
+use crate::{AsFile, Progress, ReadAt, SparseIter, SparseRangeIter, SparseRangeIterExt};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::Path;
+
+/// Size of the buffer data is copied through
+const BUF_SIZE: usize = 1024 * 1024;
+
+/// Copy the contents of `src` into `dst`, leaving holes where `src` has them
+///
+/// `dst` ends up the same length as `src`, with anything it held before discarded. Only the
+/// `Data` ranges of `src` are read and written; everything else becomes a hole in `dst`. If the
+/// filesystem `src` is on can't report holes, all of `src` is copied (see
+/// [`SparseIter::fallback_to_data()`]).
+///
+/// On linux and android, each `Data` range is copied with `copy_file_range()`, which keeps the
+/// data in the kernel (and shares storage between the files instead of copying it, on
+/// filesystems with reflinks like btrfs and xfs). Where that isn't supported (across filesystems
+/// before linux 5.3, or on older kernels), and on other platforms, data is read and written
+/// through a buffer instead.
+///
+/// Returns the number of bytes of data copied. Neither file's cursor is used or moved (`src`'s
+/// is put back after each probe for holes, see [`SparseIter::preserve_cursor()`]).
+pub fn copy_sparse<S: AsFile, D: AsFile>(src: S, dst: D) -> io::Result<u64> {
+    copy_sparse_with_progress(src, dst, &Progress::new())
+}
+
+/// Like [`copy_sparse()`], but report progress to (and stop early if cancelled by) `progress`
+///
+/// Progress is reported as the offset in `src` reached so far, out of its length. If the copy is
+/// cancelled, the error converts into [`Error::Cancelled`](crate::Error::Cancelled), and `dst` is
+/// left with only part of the data.
+pub fn copy_sparse_with_progress<S: AsFile, D: AsFile>(
+    src: S,
+    dst: D,
+    progress: &Progress,
+) -> io::Result<u64> {
+    let (src, dst) = (src.as_fd(), dst.as_fd());
+    let total = file_len(src)?;
+
+    // truncating first throws away whatever `dst` had allocated, leaving it one big hole
+    crate::set_sparse(dst, true)?;
+    set_len(dst, 0)?;
+    set_len(dst, total)?;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let mut kernel_copy = true;
+
+    let mut buf = Vec::new();
+    let mut copied = 0;
+    let iter = SparseIter::from(src).fallback_to_data().preserve_cursor();
+    for r in SparseRangeIter::from(iter).data_only() {
+        let r = r?;
+        progress.update(r.start, total)?;
+
+        #[cfg_attr(
+            not(any(target_os = "linux", target_os = "android")),
+            allow(unused_mut)
+        )]
+        let mut offset = r.start;
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        while kernel_copy && offset < r.end {
+            match crate::linux::copy_file_range(src, dst, offset, r.end - offset) {
+                // `src` was truncated out from under us, let the buffered copy find out where
+                Ok(0) => break,
+                Ok(n) => {
+                    offset += n;
+                    copied += n;
+                    progress.update(offset, total)?;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                // EINVAL is how some filesystems (and special files) say they don't support it
+                Err(ref e)
+                    if matches!(
+                        e.raw_os_error(),
+                        Some(libc::EXDEV)
+                            | Some(libc::ENOSYS)
+                            | Some(libc::EOPNOTSUPP)
+                            | Some(libc::EINVAL)
+                    ) =>
+                {
+                    kernel_copy = false
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        let read = |buf: &mut [u8], offset| pread(src, buf, offset);
+        copied += copy_buffered(read, dst, &mut buf, offset, r.end, progress, total)?;
+    }
+
+    progress.update(total, total)?;
+    Ok(copied)
+}
+
+/// Like [`copy_sparse()`], but with the data read from `data` instead of `src`
+///
+/// Holes are found in `src`, and each `Data` range is read from the same offsets in `data`: a
+/// copy of `src` somewhere faster, a cache, or anything else that can be read at any offset. The
+/// data is always copied through a buffer.
+///
+/// `dst` ends up the length of `src`. If `data` is shorter, the rest of `dst` reads as zeros.
+pub fn copy_sparse_with_data<S: AsFile, R: ReadAt, D: AsFile>(
+    src: S,
+    data: R,
+    dst: D,
+) -> io::Result<u64> {
+    let (src, dst) = (src.as_fd(), dst.as_fd());
+    let total = file_len(src)?;
+    crate::set_sparse(dst, true)?;
+    set_len(dst, 0)?;
+    set_len(dst, total)?;
+
+    let progress = Progress::new();
+    let mut buf = Vec::new();
+    let mut copied = 0;
+    let iter = SparseIter::from(src).fallback_to_data().preserve_cursor();
+    for r in SparseRangeIter::from(iter).data_only() {
+        let r = r?;
+        let read = |buf: &mut [u8], offset| data.read_at(buf, offset);
+        copied += copy_buffered(read, dst, &mut buf, r.start, r.end, &progress, total)?;
+    }
+    Ok(copied)
+}
+
+/// Copy `start..end` to `dst` by reading it into `buf` with `read` and writing it back out
+///
+/// Returns the number of bytes copied, which is less than asked for if the source is shorter.
+fn copy_buffered<R: Fn(&mut [u8], u64) -> io::Result<usize>>(
+    read: R,
+    dst: BorrowedFd<'_>,
+    buf: &mut Vec<u8>,
+    start: u64,
+    end: u64,
+    progress: &Progress,
+    total: u64,
+) -> io::Result<u64> {
+    if start < end && buf.is_empty() {
+        buf.resize(BUF_SIZE, 0);
+    }
+
+    let mut offset = start;
+    while offset < end {
+        let want = (end - offset).min(BUF_SIZE as u64) as usize;
+        let n = match read(&mut buf[..want], offset) {
+            // the source was truncated out from under us
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        pwrite_all(dst, &buf[..n], offset)?;
+        offset += n as u64;
+        progress.update(offset, total)?;
+    }
+
+    Ok(offset - start)
+}
+
+/// Copy the file at `src` to a new file at `dst`, leaving holes where `src` has them
+///
+/// `dst` must not already exist. It is created with the same permissions as `src`.
+///
+/// On macos, this first tries to clone `src` with `fclonefileat()`, which is nearly instant and
+/// shares storage with `src` until either file is modified. Cloning only works within a single
+/// APFS volume. Elsewhere (HFS+, or a `dst` on a different volume), it falls back to
+/// [`copy_sparse()`].
+///
+/// The paths are resolved with the process's own access to the filesystem. Inside a capability
+/// sandbox, use `SparseDirExt::copy_sparse()` (with the `cap-std` feature) instead.
+pub fn copy_sparse_path<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> io::Result<()> {
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    let src = File::open(src)?;
+
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::unix::io::AsFd;
+
+        match crate::macos::clone_file(src.as_fd(), dst) {
+            Ok(()) => return Ok(()),
+            Err(ref e) if matches!(e.raw_os_error(), Some(libc::ENOTSUP) | Some(libc::EXDEV)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    let dst = OpenOptions::new().write(true).create_new(true).open(dst)?;
+    dst.set_permissions(src.metadata()?.permissions())?;
+    copy_sparse(&src, &dst)?;
+    Ok(())
+}
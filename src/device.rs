@@ -0,0 +1,127 @@
+//! Block devices, which have a size but no holes for the filesystem to report
+
+use crate::unix::{stat, BorrowedFd, Stat};
+use crate::AsFile;
+use std::io;
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "freebsd"
+))]
+use std::os::unix::io::AsRawFd;
+
+/// `_IOR(0x12, 114, size_t)`
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const BLKGETSIZE64: libc::c_ulong =
+    0x8000_1272 | ((std::mem::size_of::<usize>() as libc::c_ulong) << 16);
+
+/// `_IOR('d', 24, uint32_t)`
+#[cfg(target_os = "macos")]
+const DKIOCGETBLOCKSIZE: libc::c_ulong = 0x4004_6418;
+
+/// `_IOR('d', 25, uint64_t)`
+#[cfg(target_os = "macos")]
+const DKIOCGETBLOCKCOUNT: libc::c_ulong = 0x4008_6419;
+
+/// `_IOR('d', 129, off_t)`
+#[cfg(target_os = "freebsd")]
+const DIOCGMEDIASIZE: libc::c_ulong = 0x4008_6481;
+
+/// Is `file` a block device (a disk, a partition, or something like them)?
+///
+/// On macos, the raw (character) disk devices (`/dev/rdisk*`) count too, and on freebsd, where
+/// disks are only character devices, those do. Other character devices (terminals, `/dev/null`)
+/// don't.
+pub fn is_block_device<F: AsFile>(file: F) -> io::Result<bool> {
+    let fd = file.as_fd();
+    Ok(device_size(fd, &stat(fd)?)?.is_some())
+}
+
+/// The size of `fd`, if it's a block device, given what `fstat()` said about it
+///
+/// Block devices report an `st_size` of 0, so their size has to be asked for: with
+/// `BLKGETSIZE64` on linux, `DKIOCGETBLOCKCOUNT` (times `DKIOCGETBLOCKSIZE`) on macos, and
+/// `DIOCGMEDIASIZE` on freebsd. Elsewhere, this seeks to the end of the device, moving its cursor.
+pub(crate) fn device_size(fd: BorrowedFd<'_>, st: &Stat) -> io::Result<Option<u64>> {
+    let kind = st.st_mode & libc::S_IFMT;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        if kind != libc::S_IFBLK {
+            return Ok(None);
+        }
+        let mut size: u64 = 0;
+        let r = unsafe { libc::ioctl(fd.as_raw_fd(), BLKGETSIZE64 as _, &mut size as *mut u64) };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Some(size))
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        if kind != libc::S_IFBLK && kind != libc::S_IFCHR {
+            return Ok(None);
+        }
+        let (mut block_size, mut blocks): (u32, u64) = (0, 0);
+        let raw = fd.as_raw_fd();
+        let r = unsafe { libc::ioctl(raw, DKIOCGETBLOCKSIZE, &mut block_size as *mut u32) };
+        if r < 0 {
+            return not_a_disk(kind);
+        }
+        let r = unsafe { libc::ioctl(raw, DKIOCGETBLOCKCOUNT, &mut blocks as *mut u64) };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        match blocks.checked_mul(u64::from(block_size)) {
+            Some(size) => Ok(Some(size)),
+            None => Err(crate::Error::OffsetOverflow.into()),
+        }
+    }
+
+    #[cfg(target_os = "freebsd")]
+    {
+        if kind != libc::S_IFBLK && kind != libc::S_IFCHR {
+            return Ok(None);
+        }
+        let mut size: libc::off_t = 0;
+        let r = unsafe {
+            libc::ioctl(
+                fd.as_raw_fd(),
+                DIOCGMEDIASIZE,
+                &mut size as *mut libc::off_t,
+            )
+        };
+        if r < 0 {
+            return not_a_disk(kind);
+        }
+        crate::error::checked_cast(size).map(Some)
+    }
+
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "freebsd"
+    )))]
+    {
+        if kind != libc::S_IFBLK {
+            return Ok(None);
+        }
+        // SEEK_END never returns ENXIO
+        Ok(Some(crate::seek(fd, 0, libc::SEEK_END)?.unwrap()))
+    }
+}
+
+/// A character device that didn't answer a disk ioctl isn't a disk, but a block device that
+/// didn't is broken
+#[cfg(any(target_os = "macos", target_os = "freebsd"))]
+fn not_a_disk(kind: libc::mode_t) -> io::Result<Option<u64>> {
+    let e = io::Error::last_os_error();
+    if kind == libc::S_IFCHR && matches!(e.raw_os_error(), Some(libc::ENOTTY) | Some(libc::EINVAL))
+    {
+        return Ok(None);
+    }
+    Err(e)
+}
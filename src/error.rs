@@ -0,0 +1,156 @@
+//! Errors from operations that modify files
+
+use snafu::Snafu;
+use std::convert::TryFrom;
+use std::io;
+
+/// Things that can go wrong creating holes (and otherwise changing how a file is stored)
+///
+/// Iteration still reports plain `io::Error`s. Every `Error` converts into an `io::Error` (the
+/// original one, where there was one), so `?` works in functions returning `io::Result`. Those
+/// `io::Error`s convert back into the `Error` they came from.
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub(crate)))]
+#[non_exhaustive]
+pub enum Error {
+    /// The platform, or the filesystem the file is on, can't do this
+    #[snafu(display("operation not supported by the filesystem: {}", source))]
+    Unsupported {
+        /// The error the OS reported
+        source: io::Error,
+    },
+
+    /// The range given is too large for the platform to describe
+    #[snafu(display("range of {} bytes at offset {} is out of bounds", len, offset))]
+    InvalidRange {
+        /// Start of the range
+        offset: u64,
+        /// Length of the range
+        len: u64,
+    },
+
+    /// An offset or length doesn't fit in the type it's needed in
+    ///
+    /// Either an offset is past the largest one the platform can describe (usually `i64::MAX`),
+    /// or the OS reported a size that can't be right (a negative one).
+    #[snafu(display("offset or length out of range"))]
+    OffsetOverflow,
+
+    /// The file isn't open for writing
+    #[snafu(display("file is not open for writing: {}", source))]
+    NotWritable {
+        /// The error the OS reported
+        source: io::Error,
+    },
+
+    /// The file is a pipe, socket, or character device, which can't be seeked in (and so has no
+    /// holes to find)
+    ///
+    /// This is what `lseek()` failing with `ESPIPE` is reported as, when the file's type explains
+    /// why.
+    #[snafu(display("not a seekable file (it's a pipe, socket, or character device)"))]
+    NotASeekableFile,
+
+    /// The operation was stopped with a [`CancelToken`](crate::CancelToken)
+    #[snafu(display("operation was cancelled"))]
+    Cancelled,
+
+    /// The file was modified while it was being scanned
+    ///
+    /// The ranges reported before this may not match the file anymore. See
+    /// [`SparseIter::detect_changes()`](crate::SparseIter::detect_changes).
+    #[snafu(display("file changed while it was being scanned"))]
+    FileChanged,
+
+    /// A hole the filesystem reported has bytes in it that aren't zero
+    ///
+    /// Only checked for when asked to, with
+    /// [`SparseScanBuilder::verify()`](crate::SparseScanBuilder::verify).
+    #[snafu(display("hole reported at offset {} has data in it", offset))]
+    HoleNotZero {
+        /// The first byte found that isn't zero
+        offset: u64,
+    },
+
+    /// Any other I/O error
+    #[snafu(display("{}", source))]
+    Io {
+        /// The error the OS reported
+        source: io::Error,
+    },
+}
+
+/// A `Result` with [`Error`] as the default error type
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        // undo `From<Error> for io::Error`, for errors that passed through an `io::Result`
+        if source.get_ref().is_some_and(|e| e.is::<Error>()) {
+            return *source.into_inner().unwrap().downcast::<Error>().unwrap();
+        }
+        Error::Io { source }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        let kind = match e {
+            Error::Io { source } => return source,
+            Error::Unsupported { .. } => io::ErrorKind::Unsupported,
+            Error::InvalidRange { .. } => io::ErrorKind::InvalidInput,
+            Error::OffsetOverflow => io::ErrorKind::InvalidInput,
+            Error::NotWritable { .. } => io::ErrorKind::PermissionDenied,
+            Error::NotASeekableFile => io::ErrorKind::NotSeekable,
+            Error::Cancelled => io::ErrorKind::Other,
+            Error::FileChanged => io::ErrorKind::Other,
+            Error::HoleNotZero { .. } => io::ErrorKind::InvalidData,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
+/// Convert an offset or length to another integer type, failing with [`Error::OffsetOverflow`]
+/// if it doesn't fit
+pub(crate) fn checked_cast<T, U: TryFrom<T>>(v: T) -> io::Result<U> {
+    U::try_from(v).map_err(|_| Error::OffsetOverflow.into())
+}
+
+/// Take the result of a `checked_*()` operation on offsets or lengths, failing with
+/// [`Error::OffsetOverflow`] if it overflowed
+pub(crate) fn checked<T>(v: Option<T>) -> io::Result<T> {
+    v.ok_or_else(|| Error::OffsetOverflow.into())
+}
+
+impl Error {
+    /// Classify an error from a call that modifies `len` bytes at `offset`
+    pub(crate) fn from_errno(source: io::Error, offset: u64, len: u64) -> Self {
+        #[cfg(unix)]
+        {
+            match source.raw_os_error() {
+                // ENOTSUP and EOPNOTSUPP are the same on linux, but not everywhere
+                Some(e) if e == libc::EOPNOTSUPP || e == libc::ENOTSUP || e == libc::ENOSYS => {
+                    return Error::Unsupported { source }
+                }
+                Some(libc::EFBIG) => return Error::InvalidRange { offset, len },
+                Some(libc::EBADF) => return Error::NotWritable { source },
+                _ => {}
+            }
+        }
+
+        #[cfg(windows)]
+        {
+            use winapi::shared::winerror::{ERROR_ACCESS_DENIED, ERROR_INVALID_FUNCTION};
+
+            // filesystems without sparse file support (FAT) reject the FSCTLs outright
+            match source.raw_os_error().map(|e| e as u32) {
+                Some(ERROR_INVALID_FUNCTION) => return Error::Unsupported { source },
+                Some(ERROR_ACCESS_DENIED) => return Error::NotWritable { source },
+                _ => {}
+            }
+            let _ = (offset, len);
+        }
+
+        Error::Io { source }
+    }
+}
@@ -0,0 +1,122 @@
+//! Methods on `File` for the free functions and iterators of this crate
+
+use crate::error::Result;
+use crate::{AllocatedSize, ItemKind, SparseIter, SparseMap, SparseRangeIter, SparseStats, Zeroed};
+use std::fs::File;
+use std::io;
+
+/// This crate's functions as methods on a [`File`]
+///
+/// Each method is the free function (or iterator constructor) of the same name, given `&self`,
+/// so `file.sparse_ranges()` is `SparseRangeIter::from(SparseIter::from(&file))` and
+/// `file.punch_hole(offset, len)` is [`punch_hole(&file, offset, len)`](crate::punch_hole).
+/// Methods whose names would be too vague on a `File` have `sparse_` in front of them.
+///
+/// Iterators borrow the file. To get one that owns it, or to change how it scans, use
+/// [`SparseIter`] directly.
+pub trait SparseFileExt {
+    /// Iterate over where Data and Holes start in this file (see [`SparseIter`])
+    fn sparse_items(&self) -> SparseIter<&File>;
+
+    /// Iterate over the ranges of Data and Holes in this file (see [`SparseRangeIter`])
+    fn sparse_ranges(&self) -> SparseRangeIter<&File>;
+
+    /// Scan this file into a [`SparseMap`] (see [`SparseMap::from_file()`])
+    fn sparse_map(&self) -> io::Result<SparseMap>;
+
+    /// How much of this file is data and how much is holes (see [`stats()`](crate::stats))
+    fn sparse_stats(&self) -> io::Result<SparseStats>;
+
+    /// What is at `offset` in this file (see [`kind_at()`](crate::kind_at))
+    fn kind_at(&self, offset: u64) -> io::Result<ItemKind>;
+
+    /// Where the next data at or after `offset` starts (see
+    /// [`next_data_from()`](crate::next_data_from))
+    fn next_data_from(&self, offset: u64) -> io::Result<Option<u64>>;
+
+    /// Where the next hole at or after `offset` starts (see
+    /// [`next_hole_from()`](crate::next_hole_from))
+    fn next_hole_from(&self, offset: u64) -> io::Result<Option<u64>>;
+
+    /// This file's length, and the storage allocated to it (see
+    /// [`allocated_size()`](crate::allocated_size))
+    fn allocated_size(&self) -> io::Result<AllocatedSize>;
+
+    /// Does this file take up less space than its length? (see [`is_sparse()`](crate::is_sparse))
+    fn is_sparse(&self) -> io::Result<bool>;
+
+    /// Turn `len` bytes at `offset` into a hole (see [`punch_hole()`](crate::punch_hole))
+    fn punch_hole(&self, offset: u64, len: u64) -> Result<()>;
+
+    /// Turn `len` bytes at `offset` into a hole, or write zeros over them if that isn't possible
+    /// (see [`punch_hole_or_zero()`](crate::punch_hole_or_zero))
+    fn punch_hole_or_zero(&self, offset: u64, len: u64) -> Result<Zeroed>;
+
+    /// Allocate storage for the first `len` bytes of this file (see
+    /// [`preallocate()`](crate::preallocate))
+    fn preallocate(&self, len: u64) -> Result<()>;
+
+    /// Mark or unmark this file as sparse (see [`set_sparse()`](crate::set_sparse))
+    fn set_sparse(&self, sparse: bool) -> io::Result<()>;
+
+    /// Is this file marked sparse? (see [`is_marked_sparse()`](crate::is_marked_sparse))
+    fn is_marked_sparse(&self) -> io::Result<bool>;
+}
+
+impl SparseFileExt for File {
+    fn sparse_items(&self) -> SparseIter<&File> {
+        SparseIter::from(self)
+    }
+
+    fn sparse_ranges(&self) -> SparseRangeIter<&File> {
+        SparseRangeIter::from(SparseIter::from(self))
+    }
+
+    fn sparse_map(&self) -> io::Result<SparseMap> {
+        SparseMap::from_file(self)
+    }
+
+    fn sparse_stats(&self) -> io::Result<SparseStats> {
+        crate::stats(self)
+    }
+
+    fn kind_at(&self, offset: u64) -> io::Result<ItemKind> {
+        crate::kind_at(self, offset)
+    }
+
+    fn next_data_from(&self, offset: u64) -> io::Result<Option<u64>> {
+        crate::next_data_from(self, offset)
+    }
+
+    fn next_hole_from(&self, offset: u64) -> io::Result<Option<u64>> {
+        crate::next_hole_from(self, offset)
+    }
+
+    fn allocated_size(&self) -> io::Result<AllocatedSize> {
+        crate::allocated_size(self)
+    }
+
+    fn is_sparse(&self) -> io::Result<bool> {
+        crate::is_sparse(self)
+    }
+
+    fn punch_hole(&self, offset: u64, len: u64) -> Result<()> {
+        crate::punch_hole(self, offset, len)
+    }
+
+    fn punch_hole_or_zero(&self, offset: u64, len: u64) -> Result<Zeroed> {
+        crate::punch_hole_or_zero(self, offset, len)
+    }
+
+    fn preallocate(&self, len: u64) -> Result<()> {
+        crate::preallocate(self, len)
+    }
+
+    fn set_sparse(&self, sparse: bool) -> io::Result<()> {
+        crate::set_sparse(self, sparse)
+    }
+
+    fn is_marked_sparse(&self) -> io::Result<bool> {
+        crate::is_marked_sparse(self)
+    }
+}
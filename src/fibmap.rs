@@ -0,0 +1,104 @@
+//! Find holes with linux's `FIBMAP` ioctl, one block at a time
+
+use crate::error::checked_cast;
+use crate::read_scan::{blocks_between, BlockScan};
+use crate::unix::{file_len, BorrowedFd};
+use crate::ItemKind;
+use std::convert::TryInto;
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+/// `_IO(0x00, 1)`: map a logical block number to a physical one
+const FIBMAP: libc::c_ulong = 1;
+/// `_IO(0x00, 2)`: get the filesystem's block size
+const FIGETBSZ: libc::c_ulong = 2;
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Fibmap {
+    /// The filesystem's block size and the length of the file, looked up on the first block
+    geometry: Option<(u64, u64)>,
+    pos: u64,
+}
+
+impl Fibmap {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn ioctl_int(fd: BorrowedFd<'_>, req: libc::c_ulong, arg: &mut libc::c_int) -> io::Result<()> {
+    let r = unsafe { libc::ioctl(fd.as_raw_fd(), req as _, arg as *mut libc::c_int) };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+impl BlockScan for Fibmap {
+    fn seek(&mut self, offset: u64) {
+        self.pos = offset;
+    }
+
+    fn pos(&self) -> u64 {
+        self.pos
+    }
+
+    /// Scanning stops at the length the file had when the first block was read
+    fn blocks_left(&self) -> Option<usize> {
+        let (block_size, len) = self.geometry?;
+        blocks_between(self.pos, len, block_size)
+    }
+
+    fn next_block(&mut self, fd: BorrowedFd<'_>) -> io::Result<Option<(ItemKind, u64)>> {
+        let (block_size, len) = match self.geometry {
+            Some(g) => g,
+            None => {
+                let mut bsz = 0;
+                ioctl_int(fd, FIGETBSZ, &mut bsz)?;
+                let g = (checked_cast(bsz)?, file_len(fd)?);
+                self.geometry = Some(g);
+                g
+            }
+        };
+
+        let start = self.pos;
+        if start >= len {
+            return Ok(None);
+        }
+
+        let index = start / block_size;
+        // FIBMAP takes (and returns) an `int`, so it can't describe blocks past 2^31
+        let mut block: libc::c_int = index.try_into().map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "block number too large for FIBMAP",
+            )
+        })?;
+        ioctl_int(fd, FIBMAP, &mut block)?;
+
+        self.pos = ((index + 1) * block_size).min(len);
+        let kind = if block == 0 {
+            ItemKind::Hole
+        } else {
+            ItemKind::Data
+        };
+        Ok(Some((kind, start)))
+    }
+}
+
+/// Can we use FIBMAP on `fd`? It needs both filesystem support and `CAP_SYS_RAWIO`.
+pub(crate) fn supported(fd: BorrowedFd<'_>) -> io::Result<bool> {
+    let mut block = 0;
+    match ioctl_int(fd, FIGETBSZ, &mut block).and_then(|_| {
+        block = 0;
+        ioctl_int(fd, FIBMAP, &mut block)
+    }) {
+        Ok(()) => Ok(true),
+        Err(ref e) if crate::unix::is_unsupported(e) => Ok(false),
+        Err(ref e) if matches!(e.raw_os_error(), Some(libc::EPERM) | Some(libc::ENOTTY)) => {
+            Ok(false)
+        }
+        Err(e) => Err(e),
+    }
+}
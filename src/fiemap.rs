@@ -0,0 +1,581 @@
+//! Extent information from linux's `FS_IOC_FIEMAP` ioctl
+//!
+//! Where `SEEK_DATA`/`SEEK_HOLE` only tell us where data is, FIEMAP reports each extent along with
+//! where it lives on disk and some flags describing it (is it preallocated, shared with another
+//! file, compressed, ...). Not every filesystem supports it (notably, tmpfs doesn't).
+
+use crate::read_scan::BlockScan;
+use crate::unix::BorrowedFd;
+use crate::{AsFile, Fragmentation, ItemKind};
+use std::fmt;
+use std::io::{self, Write};
+use std::iter::FusedIterator;
+use std::os::unix::io::AsRawFd;
+
+/// `_IOWR('f', 11, struct fiemap)`
+const FS_IOC_FIEMAP: libc::c_ulong = 0xc020_660b;
+
+const FIEMAP_FLAG_SYNC: u32 = 0x1;
+
+/// Number of extents to ask for in each ioctl
+const BATCH: usize = 64;
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct fiemap {
+    fm_start: u64,
+    fm_length: u64,
+    fm_flags: u32,
+    fm_mapped_extents: u32,
+    fm_extent_count: u32,
+    fm_reserved: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct fiemap_extent {
+    fe_logical: u64,
+    fe_physical: u64,
+    fe_length: u64,
+    fe_reserved64: [u64; 2],
+    fe_flags: u32,
+    fe_reserved: [u32; 3],
+}
+
+/// A `struct fiemap` followed by room for the extents the kernel fills in
+#[repr(C)]
+#[derive(Clone)]
+struct FiemapBuf {
+    hdr: fiemap,
+    extents: [fiemap_extent; BATCH],
+}
+
+/// Flags describing an [`Extent`] (the `FIEMAP_EXTENT_*` flags)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ExtentFlags(u32);
+
+impl ExtentFlags {
+    /// This is the last extent in the file
+    pub const LAST: Self = Self(0x1);
+    /// The location of the data is unknown (`physical` is meaningless)
+    pub const UNKNOWN: Self = Self(0x2);
+    /// Space for the data has not been allocated yet (delayed allocation). Implies `UNKNOWN`.
+    pub const DELALLOC: Self = Self(0x4);
+    /// The data is not stored as-is (compressed, for example)
+    pub const ENCODED: Self = Self(0x8);
+    /// The data is encrypted
+    pub const DATA_ENCRYPTED: Self = Self(0x80);
+    /// The extent's offsets are not aligned to the filesystem's block size
+    pub const NOT_ALIGNED: Self = Self(0x100);
+    /// The data is stored in a metadata block. Implies `NOT_ALIGNED`.
+    pub const DATA_INLINE: Self = Self(0x200);
+    /// The data is packed into a block with data from other files. Implies `NOT_ALIGNED`.
+    pub const DATA_TAIL: Self = Self(0x400);
+    /// Space is allocated, but nothing has been written to it (it reads as zeros). This is what
+    /// `fallocate()` leaves behind.
+    pub const UNWRITTEN: Self = Self(0x800);
+    /// The filesystem doesn't track extents, so this was built from individual blocks
+    pub const MERGED: Self = Self(0x1000);
+    /// The space is shared with other files (reflinks, snapshots, deduplication)
+    pub const SHARED: Self = Self(0x2000);
+
+    /// Construct from the raw `fe_flags` value
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    /// The raw `fe_flags` value
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Are all the flags in `other` also set in `self`?
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// The names of the flags that are set, as `filefrag -v` prints them
+    ///
+    /// Flags this crate doesn't know about are left out.
+    pub fn names(self) -> impl Iterator<Item = &'static str> {
+        FLAG_NAMES
+            .iter()
+            .filter(move |&&(flag, _)| self.contains(flag))
+            .map(|&(_, name)| name)
+    }
+}
+
+/// Each flag and its name, in the order `filefrag -v` prints them
+const FLAG_NAMES: [(ExtentFlags, &str); 11] = [
+    (ExtentFlags::LAST, "last"),
+    (ExtentFlags::UNKNOWN, "unknown_loc"),
+    (ExtentFlags::DELALLOC, "delalloc"),
+    (ExtentFlags::ENCODED, "encoded"),
+    (ExtentFlags::DATA_ENCRYPTED, "encrypted"),
+    (ExtentFlags::NOT_ALIGNED, "not_aligned"),
+    (ExtentFlags::DATA_INLINE, "inline"),
+    (ExtentFlags::DATA_TAIL, "tail_packed"),
+    (ExtentFlags::UNWRITTEN, "unwritten"),
+    (ExtentFlags::MERGED, "merged"),
+    (ExtentFlags::SHARED, "shared"),
+];
+
+/// The flags' names separated by commas, with any unknown flags in hex (as `filefrag -v` prints
+/// them)
+impl fmt::Display for ExtentFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut sep = "";
+        for name in self.names() {
+            write!(f, "{}{}", sep, name)?;
+            sep = ",";
+        }
+        let unknown = FLAG_NAMES
+            .iter()
+            .fold(self.0, |bits, (flag, _)| bits & !flag.0);
+        if unknown != 0 {
+            write!(f, "{}{:#010x}", sep, unknown)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::ops::BitOr for ExtentFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// A single extent of a file, as reported by FIEMAP
+///
+/// Extents only cover data (including unwritten, preallocated space). Anything between extents
+/// is a hole.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extent {
+    /// Byte offset in the file where this extent starts
+    pub logical: u64,
+    /// Byte offset on the underlying device where this extent starts. Meaningless if `flags`
+    /// contains [`ExtentFlags::UNKNOWN`].
+    pub physical: u64,
+    /// Length of this extent in bytes
+    pub length: u64,
+    /// Details about this extent
+    pub flags: ExtentFlags,
+}
+
+impl Extent {
+    /// The byte offset in the file 1 after this extent ends
+    pub fn end(&self) -> u64 {
+        self.logical + self.length
+    }
+}
+
+/// Iterate over the [`Extent`]s of a file using FIEMAP
+#[derive(Debug)]
+pub struct FiemapIter<F> {
+    file: F,
+    map: Fiemap,
+}
+
+impl<F: AsFile> From<F> for FiemapIter<F> {
+    fn from(file: F) -> Self {
+        Self::starting_at(file, 0)
+    }
+}
+
+impl<F: AsFile> FiemapIter<F> {
+    /// Iterate over the extents of `file` that end after `offset`
+    ///
+    /// The first extent may start before `offset`.
+    pub fn starting_at(file: F, offset: u64) -> Self {
+        Self {
+            file,
+            map: Fiemap::new(offset),
+        }
+    }
+
+    /// Have the kernel flush the file's dirty data before mapping it (`FIEMAP_FLAG_SYNC`)
+    ///
+    /// Without this, recently written data that hasn't been allocated yet shows up as
+    /// [`ExtentFlags::DELALLOC`] extents on some filesystems, or not at all on others.
+    pub fn sync(mut self) -> Self {
+        self.map.flags |= FIEMAP_FLAG_SYNC;
+        self
+    }
+
+    /// Get back the file this iterator was created from
+    pub fn into_inner(self) -> F {
+        self.file
+    }
+}
+
+impl<F: AsFile> Iterator for FiemapIter<F> {
+    type Item = io::Result<Extent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.map.next_extent(self.file.as_fd())
+    }
+
+    /// An upper bound once the first extent has been returned, so `collect()` allocates once.
+    /// There may be fewer if the file changes during iteration.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.map.remaining())
+    }
+}
+
+impl<F: AsFile> FusedIterator for FiemapIter<F> {}
+
+/// The state of a FIEMAP scan, independent of the file being scanned
+#[derive(Clone)]
+pub(crate) struct Fiemap {
+    buf: Box<FiemapBuf>,
+    /// Index of the next extent in `buf` to return
+    next: usize,
+    /// Where to ask the kernel for extents from next
+    pos: u64,
+    flags: u32,
+    done: bool,
+    /// How many extents are left to return, once they've been counted
+    remaining: Option<usize>,
+}
+
+impl std::fmt::Debug for Fiemap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Fiemap")
+            .field("pos", &self.pos)
+            .field("flags", &self.flags)
+            .field("done", &self.done)
+            .field("remaining", &self.remaining)
+            .finish()
+    }
+}
+
+impl Fiemap {
+    pub(crate) fn new(offset: u64) -> Self {
+        Self {
+            buf: Box::new(FiemapBuf {
+                hdr: fiemap::default(),
+                extents: [fiemap_extent::default(); BATCH],
+            }),
+            next: 0,
+            pos: offset,
+            flags: 0,
+            done: false,
+            remaining: None,
+        }
+    }
+
+    /// Start over from `offset`
+    pub(crate) fn seek(&mut self, offset: u64) {
+        self.pos = offset;
+        self.next = 0;
+        self.buf.hdr.fm_mapped_extents = 0;
+        self.done = false;
+        self.remaining = None;
+    }
+
+    /// How many more extents [`next_extent()`](Self::next_extent) will return, if known
+    ///
+    /// This is known after the first batch of extents is fetched, and is exact unless the file
+    /// changes during iteration.
+    pub(crate) fn remaining(&self) -> Option<usize> {
+        if self.done {
+            return Some(0);
+        }
+        self.remaining
+    }
+
+    /// After the first batch has been fetched, count the extents after it
+    ///
+    /// This is skipped when the first batch holds all of them, so only files with many extents
+    /// pay for the extra ioctl (which doesn't copy out the extents, so is cheaper than fetching
+    /// them).
+    fn count(&mut self, fd: BorrowedFd<'_>) -> io::Result<()> {
+        let mapped = self.buf.hdr.fm_mapped_extents as usize;
+        let last = match mapped.checked_sub(1) {
+            Some(i) => &self.buf.extents[i],
+            None => {
+                self.remaining = Some(0);
+                return Ok(());
+            }
+        };
+        if mapped < BATCH || ExtentFlags(last.fe_flags).contains(ExtentFlags::LAST) {
+            self.remaining = Some(mapped);
+            return Ok(());
+        }
+
+        // with no room for extents, the kernel just counts them
+        let start = last.fe_logical + last.fe_length;
+        let mut hdr = fiemap {
+            fm_start: start,
+            fm_length: u64::MAX - start,
+            fm_flags: self.flags,
+            ..fiemap::default()
+        };
+        let r = unsafe { libc::ioctl(fd.as_raw_fd(), FS_IOC_FIEMAP as _, &mut hdr as *mut fiemap) };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.remaining = Some(mapped + hdr.fm_mapped_extents as usize);
+        Ok(())
+    }
+
+    /// Ask the kernel for the next batch of extents
+    fn fill(&mut self, fd: BorrowedFd<'_>) -> io::Result<()> {
+        self.buf.hdr = fiemap {
+            fm_start: self.pos,
+            fm_length: u64::MAX - self.pos,
+            fm_flags: self.flags,
+            fm_mapped_extents: 0,
+            fm_extent_count: BATCH as u32,
+            fm_reserved: 0,
+        };
+        self.next = 0;
+        ioctl_fiemap(fd, &mut self.buf)
+    }
+
+    /// Get the next extent, fusing on errors
+    pub(crate) fn next_extent(&mut self, fd: BorrowedFd<'_>) -> Option<io::Result<Extent>> {
+        if self.done {
+            return None;
+        }
+
+        if self.next >= self.buf.hdr.fm_mapped_extents as usize {
+            let mut r = self.fill(fd);
+            if r.is_ok() && self.remaining.is_none() {
+                r = self.count(fd);
+            }
+            if let Err(e) = r {
+                self.done = true;
+                return Some(Err(e));
+            }
+
+            if self.buf.hdr.fm_mapped_extents == 0 {
+                self.done = true;
+                return None;
+            }
+        }
+
+        let e = &self.buf.extents[self.next];
+        self.next += 1;
+        let e = Extent {
+            logical: e.fe_logical,
+            physical: e.fe_physical,
+            length: e.fe_length,
+            flags: ExtentFlags(e.fe_flags),
+        };
+
+        self.pos = e.end();
+        self.remaining = self.remaining.map(|n| n.saturating_sub(1));
+        if e.flags.contains(ExtentFlags::LAST) {
+            self.done = true;
+        }
+        Some(Ok(e))
+    }
+}
+
+/// How many pieces the data of `file` is in on disk, and how large they are
+///
+/// Extents that continue on from the previous one on disk are counted together, as `filefrag`
+/// does, so a file written in one contiguous run counts as a single extent however the
+/// filesystem splits it up. Unwritten (preallocated) extents are counted along with the data.
+pub fn fragmentation<F: AsFile>(file: F) -> io::Result<Fragmentation> {
+    let mut frag = Fragmentation::default();
+    // the end of the extent being built up, in the file and on disk, and its length
+    let mut run: Option<(u64, u64, u64)> = None;
+    for e in FiemapIter::from(file) {
+        let e = e?;
+        run = match run {
+            Some((logical, physical, len)) if logical == e.logical && physical == e.physical => {
+                Some((e.end(), e.physical + e.length, len + e.length))
+            }
+            _ => {
+                if let Some((_, _, len)) = run {
+                    frag.add(len);
+                }
+                Some((e.end(), e.physical + e.length, e.length))
+            }
+        };
+    }
+    if let Some((_, _, len)) = run {
+        frag.add(len);
+    }
+    Ok(frag)
+}
+
+/// Write the extents of `file` as a table, like the one `filefrag -v` prints
+///
+/// Each row has the extent's range in the file and on the device, its length, where on the
+/// device it would have started if it continued on from the previous extent (only when it
+/// doesn't), and its flags. Offsets and lengths are in blocks of `block_size` bytes, rounding
+/// down (`filefrag -v` uses the filesystem's block size). As with `filefrag`, the extent that
+/// reaches the end of the file also gets an `eof` flag.
+///
+/// Returns the number of extents.
+///
+/// # Panics
+///
+/// If `block_size` is 0
+pub fn write_extent_table<F: AsFile, W: Write>(
+    file: F,
+    block_size: u64,
+    mut w: W,
+) -> io::Result<u64> {
+    assert!(block_size > 0, "block size must be non-zero");
+    let fd = file.as_fd();
+    let len = crate::unix::file_len(fd)?;
+
+    writeln!(
+        w,
+        "{:>4}: {:>19} {:>23} {:>7} {:>11} flags:",
+        "ext", "logical_offset:", "physical_offset:", "length:", "expected:"
+    )?;
+    let mut count = 0;
+    let mut expected: Option<u64> = None;
+    for e in FiemapIter::from(fd) {
+        let e = e?;
+        let blocks = e.length / block_size;
+        let last = |start: u64| (start + blocks).saturating_sub(1);
+        let (logical, physical) = (e.logical / block_size, e.physical / block_size);
+
+        let expected_col = match expected {
+            Some(p) if p != physical => p.to_string(),
+            _ => String::new(),
+        };
+        let mut flags = e.flags.to_string();
+        if e.end() >= len {
+            flags += if flags.is_empty() { "eof" } else { ",eof" };
+        }
+        let row = format!(
+            "{:>4}: {:>8}..{:>8}: {:>10}..{:>10}: {:>6}: {:>11} {}",
+            count,
+            logical,
+            last(logical),
+            physical,
+            last(physical),
+            blocks,
+            expected_col,
+            flags
+        );
+        writeln!(w, "{}", row.trim_end())?;
+
+        expected = Some(physical + blocks);
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Classifies a file for [`SparseIter`](crate::SparseIter) using FIEMAP
+///
+/// Each extent becomes a `Data` "block" (or a `Hole`, if it is unwritten) and each gap between
+/// extents a `Hole`, all clamped to the length of the file.
+#[derive(Debug, Clone)]
+pub(crate) struct FiemapScan {
+    map: Fiemap,
+    pos: u64,
+    len: Option<u64>,
+    /// An extent that starts after `pos`, to be returned once we've passed the hole before it
+    pending: Option<Extent>,
+}
+
+impl FiemapScan {
+    pub(crate) fn new() -> Self {
+        Self {
+            map: Fiemap::new(0),
+            pos: 0,
+            len: None,
+            pending: None,
+        }
+    }
+}
+
+impl BlockScan for FiemapScan {
+    fn seek(&mut self, offset: u64) {
+        self.map.seek(offset);
+        self.pos = offset;
+        self.pending = None;
+    }
+
+    fn pos(&self) -> u64 {
+        self.pos
+    }
+
+    fn blocks_left(&self) -> Option<usize> {
+        // a hole before each extent, and one after the last. The pending extent is already
+        // counted as returned by `map`
+        let extents = self.map.remaining()? + usize::from(self.pending.is_some());
+        Some(extents.saturating_mul(2).saturating_add(1))
+    }
+
+    fn next_block(&mut self, fd: BorrowedFd<'_>) -> io::Result<Option<(ItemKind, u64)>> {
+        let len = match self.len {
+            Some(len) => len,
+            None => {
+                let len = crate::unix::file_len(fd)?;
+                self.len = Some(len);
+                len
+            }
+        };
+
+        let start = self.pos;
+        if start >= len {
+            return Ok(None);
+        }
+
+        let extent = loop {
+            match self.pending.take() {
+                Some(e) => break Some(e),
+                None => match self.map.next_extent(fd) {
+                    None => break None,
+                    Some(Err(e)) => return Err(e),
+                    // the first extent may start (and end) before where we seeked to
+                    Some(Ok(e)) if e.end() <= start => {}
+                    Some(Ok(e)) => break Some(e),
+                },
+            }
+        };
+
+        let (kind, end) = match extent {
+            None => (ItemKind::Hole, len),
+            Some(e) if e.logical > start => {
+                let end = e.logical;
+                self.pending = Some(e);
+                (ItemKind::Hole, end)
+            }
+            Some(e) if e.flags.contains(ExtentFlags::UNWRITTEN) => (ItemKind::Hole, e.end()),
+            Some(e) => (ItemKind::Data, e.end()),
+        };
+
+        self.pos = end.min(len);
+        Ok(Some((kind, start)))
+    }
+}
+
+/// Does the filesystem `fd` is on support FIEMAP?
+pub(crate) fn supported(fd: BorrowedFd<'_>) -> io::Result<bool> {
+    // with no room for extents, the kernel just counts them
+    let mut hdr = fiemap {
+        fm_length: u64::MAX,
+        ..fiemap::default()
+    };
+    let r = unsafe { libc::ioctl(fd.as_raw_fd(), FS_IOC_FIEMAP as _, &mut hdr as *mut fiemap) };
+    if r < 0 {
+        let e = io::Error::last_os_error();
+        if crate::unix::is_unsupported(&e) || e.raw_os_error() == Some(libc::ENOTTY) {
+            return Ok(false);
+        }
+        return Err(e);
+    }
+
+    Ok(true)
+}
+
+fn ioctl_fiemap(fd: BorrowedFd<'_>, buf: &mut FiemapBuf) -> io::Result<()> {
+    let r = unsafe { libc::ioctl(fd.as_raw_fd(), FS_IOC_FIEMAP as _, buf as *mut FiemapBuf) };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use crate::unix::{to_off, BorrowedFd};
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+// FreeBSD numbers these the other way around from macos (matching solaris, where they came from).
+// Past the end of the file, both return ENXIO, as on linux.
+pub use libc::{SEEK_DATA, SEEK_HOLE};
+
+/// `posix_fallocate()` the first `len` bytes of the file
+pub(crate) fn posix_fallocate(fd: BorrowedFd<'_>, len: u64) -> io::Result<()> {
+    let r = unsafe { libc::posix_fallocate(fd.as_raw_fd(), 0, to_off(len)?) };
+    match r {
+        0 => Ok(()),
+        // ZFS (and other copy-on-write filesystems, where it can't work) say EINVAL
+        libc::EINVAL => Err(io::Error::from_raw_os_error(libc::EOPNOTSUPP)),
+        e => Err(io::Error::from_raw_os_error(e)),
+    }
+}
@@ -0,0 +1,50 @@
+//! Hashing the contents of a file without reading its holes
+
+use crate::unix::pread_full;
+use crate::{AsFile, ItemKind, SparseIter, SparseRangeIter};
+use std::io::{self, Write};
+
+/// Most bytes read or fed to the hasher at once
+const CHUNK: u64 = 1024 * 1024;
+
+/// Feed the contents of `file` to `hasher`, reading only its `Data`
+///
+/// Holes are fed in as zeros without being read, so the result is the same as hashing every byte
+/// of the file, but only the data has to come off the disk. Any hasher that implements `Write`
+/// works: the RustCrypto ones (`sha2::Sha256`, `blake2::Blake2b512`, ... any `digest::Digest`)
+/// do, so `hash_sparse(&file, &mut hasher)` followed by `hasher.finalize()` gives the file's
+/// digest. Hashing the zeros still takes time, just much less than reading them.
+///
+/// If the filesystem can't report holes, all of the file is read (see
+/// [`SparseIter::fallback_to_data()`]). Returns the number of bytes fed to `hasher`, which is the
+/// length of the file unless it shrinks while being read. This never moves the file's cursor
+/// (unless the `SparseIter` does, while looking for holes).
+pub fn hash_sparse<F: AsFile, W: Write + ?Sized>(file: F, hasher: &mut W) -> io::Result<u64> {
+    let fd = file.as_fd();
+    let zeros = vec![0u8; CHUNK as usize];
+    let mut buf = Vec::new();
+    let mut fed = 0;
+    for r in SparseRangeIter::from(SparseIter::from(fd).fallback_to_data()) {
+        let r = r?;
+        let mut offset = r.start;
+        while offset < r.end {
+            let want = (r.end - offset).min(CHUNK) as usize;
+            let n = if r.kind == ItemKind::Hole {
+                hasher.write_all(&zeros[..want])?;
+                want
+            } else {
+                buf.resize(want, 0);
+                let n = pread_full(fd, &mut buf, offset)?;
+                hasher.write_all(&buf[..n])?;
+                n
+            };
+            fed += n as u64;
+            if n < want {
+                // `file` was truncated out from under us
+                return Ok(fed);
+            }
+            offset += n as u64;
+        }
+    }
+    Ok(fed)
+}
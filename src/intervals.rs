@@ -0,0 +1,77 @@
+//! Set operations on lists of byte ranges
+//!
+//! These treat a list of ranges as the set of offsets in them, so the lists given may be in any
+//! order, overlap, and contain empty ranges. The lists returned are always in order, with no
+//! empty, overlapping, or adjacent ranges. [`SparseMap`](crate::SparseMap) uses these for the
+//! same operations on the data in maps.
+
+use std::ops::Range;
+
+/// The offsets in either `a` or `b`
+pub fn union(a: &[Range<u64>], b: &[Range<u64>]) -> Vec<Range<u64>> {
+    normalize(a.iter().chain(b).cloned().collect())
+}
+
+/// The offsets in both `a` and `b`
+pub fn intersect(a: &[Range<u64>], b: &[Range<u64>]) -> Vec<Range<u64>> {
+    let (a, b) = (normalize(a.to_vec()), normalize(b.to_vec()));
+    let mut r = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        let start = a[i].start.max(b[j].start);
+        let end = a[i].end.min(b[j].end);
+        if start < end {
+            r.push(start..end);
+        }
+        // whichever ends first can't overlap anything else in the other list
+        if a[i].end < b[j].end {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    r
+}
+
+/// The offsets in `a` but not in `b`
+pub fn subtract(a: &[Range<u64>], b: &[Range<u64>]) -> Vec<Range<u64>> {
+    let b = normalize(b.to_vec());
+    let mut r = Vec::new();
+    let mut j = 0;
+    for mut range in normalize(a.to_vec()) {
+        while j < b.len() && b[j].end <= range.start {
+            j += 1;
+        }
+        let mut k = j;
+        while k < b.len() && b[k].start < range.end {
+            if b[k].start > range.start {
+                r.push(range.start..b[k].start);
+            }
+            range.start = range.start.max(b[k].end);
+            k += 1;
+        }
+        if range.start < range.end {
+            r.push(range);
+        }
+    }
+    r
+}
+
+/// The offsets in `within` that aren't in `a`
+pub fn complement(a: &[Range<u64>], within: Range<u64>) -> Vec<Range<u64>> {
+    subtract(&[within], a)
+}
+
+/// Sort `ranges`, dropping empty ones and merging those that overlap or touch
+fn normalize(mut ranges: Vec<Range<u64>>) -> Vec<Range<u64>> {
+    ranges.retain(|r| r.start < r.end);
+    ranges.sort_unstable_by_key(|r| r.start);
+    let mut r: Vec<Range<u64>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match r.last_mut() {
+            Some(last) if last.end >= range.start => last.end = last.end.max(range.end),
+            _ => r.push(range),
+        }
+    }
+    r
+}
@@ -11,25 +11,38 @@
 //!
 //! Using `read_at()` type operations based on the offsets returned by `SparseIter` are the only
 //! portable option avaliable. Using `read()` or other file cursor adjusting functions durring
-//! iteration will expose platform dependent behavior.
+//! iteration will expose platform dependent behavior, unless the iterator was built with
+//! `SparseIter::preserve_cursor()`.
 //!
 //! Using any write may transform `Hole`s into `Data`, potentially after an iteration has already
 //! examined that range. In general, writes while iterating will cause iteration to have behavior
 //! that may silently change between `fs-sparse` releases and may differ between platforms and
-//! filesystems.
+//! filesystems. [`SparseIter::detect_changes()`] turns most such changes into an error instead.
 //!
 //! # Portability
 //!
 //!  - When using openzfs, you may need to set the zfs_dmu_offset_next_sync=1 option to get good
 //!    reporting for holes.
 //!    (see the [openzfs documentation](https://openzfs.github.io/openzfs-docs/Performance%20and%20Tuning/ZFS%20on%20Linux%20Module%20Parameters.html#zfs-dmu-offset-next-sync))
-//!  - When using MacOS APFS, starting a sparse iter won't report the item the cursor is in the
-//!    middle of (for details, see [this mailing list
-//!    post](https://lists.gnu.org/archive/html/bug-gnulib/2018-09/msg00054.html)). Always starting
-//!    iteration from the start of the file or from a previously returned Item offset should work.
-//!    Mixing iterations and reads may not work properly
+//!    Without it, files with unsynced changes are reported as having no holes.
+//!    [`SparseIter::sync_on_zfs()`] works around this by syncing such files first.
+//!  - MacOS APFS's `SEEK_DATA` skips over the data range it is asked about if the offset is in
+//!    the middle of it (for details, see [this mailing list
+//!    post](https://lists.gnu.org/archive/html/bug-gnulib/2018-09/msg00054.html)), and its
+//!    `SEEK_HOLE` sometimes reports a hole before the offset it was asked about. `SparseIter`
+//!    works around both, so iteration reports the same ranges as on Linux, but
+//!    [`next_data_from()`] and [`next_hole_from()`] return exactly what the filesystem says.
 //!  - On Windows, files must specifically be marked as sparse (they have a seperate mode). If
-//!    files are not sparse, this library indicates the entire file is one big `Data`.
+//!    files are not sparse, this library indicates the entire file is one big `Data`. Use
+//!    [`is_marked_sparse()`] to check for this, and [`set_sparse()`] to mark a file. Holes are
+//!    found with `FSCTL_QUERY_ALLOCATED_RANGES`, and the options that only make sense on unix
+//!    (`lock_shared()`, `sync_on_zfs()` and `read_scan_devices()`) aren't available.
+//!  - On Android, shared storage (`/sdcard`) is usually sdcardfs or FUSE, which report every file
+//!    as entirely `Data` or reject `SEEK_DATA` outright (see [`SparseIter::fallback_to_data()`]).
+//!    App-private storage is on the underlying filesystem (ext4 or f2fs) and reports holes.
+//!  - OpenBSD can't report holes at all. Iterating returns an error unless
+//!    [`SparseIter::fallback_to_data()`] or [`SparseIter::fallback_to_read_scan()`] is used (or a
+//!    [`SparseScan`] that includes [`ScanBackend::ReadScan`]).
 //!  - On some systems, if one or more bytes with value 0 are _written_ to a file, it may still be
 //!    considered a hole when read back. DO NOT assume that holes are locations that were never
 //!    written to (looking at you, bmap-tools). This behavior is visible in (at least) zfsonlinux
@@ -40,26 +53,155 @@
 //  - Linux has an implicit hole at the end of the file, iow: SEEK_HOLE will return the end of the
 //    file if no actual holes exist
 //    - FIEMAP?
+//  - FreeBSD: SEEK_DATA is 3 and SEEK_HOLE is 4 (the reverse of MacOS). UFS and ZFS both
+//    support them, and like Linux report an implicit hole at the end of the file.
+//  - NetBSD: same values as FreeBSD, but not exported by libc.
+//  - OpenBSD: no SEEK_DATA/SEEK_HOLE. lseek() rejects them with EINVAL, so every file looks like
+//    it's on a filesystem without hole support.
 //  - Solaris?
 //  - MacOS?
-//  - Windows: totally different api
+//  - Windows: totally different api. windows.rs emulates SEEK_DATA/SEEK_HOLE with
+//    FSCTL_QUERY_ALLOCATED_RANGES, which reports a file that isn't marked sparse as all data.
 #![warn(rust_2018_idioms, missing_debug_implementations, missing_docs)]
 
-use std::{fs, io};
+use std::io;
+use std::iter::FusedIterator;
 
 /// Iterate over the start of Data and Holes within a `File`
+///
+/// Items are returned in order of increasing offset. The first item is located at the offset
+/// iteration started at (0, unless [`SparseIter::starting_at()`] was used), and `Data` and `Hole`
+/// items alternate after that. The final item is always an [`ItemKind::End`] located at the
+/// length of the file, after which the iterator returns `None`. If iteration starts at or past
+/// the end of the file, the `End` is the only item.
+///
+/// If an error is returned, iteration stops there: every later call to `next()` returns `None`.
+///
+/// The `File` may be anything implementing [`AsFile`]. It may either be borrowed
+/// (`SparseIter<&File>`) or owned (`SparseIter<File>`). Owning it allows returning the iterator
+/// from the function that opened the file, or sending it to another thread.
+///
+/// # Threads
+///
+/// A `SparseIter` is `Send` whenever `F` is, so an iterator over a `File`, `&File`, or
+/// `Arc<File>` can be moved to another thread. Several iterators may scan the same open file at
+/// once, from any threads: each probe gets its answer from the one syscall that makes it
+/// (`lseek()` returns where it moved the cursor to, and read scans use `pread()`), so the file's
+/// shared cursor moving under an iterator doesn't change what it returns. The exceptions are
+/// [`preserve_cursor()`](Self::preserve_cursor) and [`restore_cursor()`](Self::restore_cursor),
+/// which are about the cursor, and so only work if nothing else uses it at the same time.
+///
+/// Cloning an iterator (when `F` is `Clone`, like `&File` and `Arc<File>`) gives a second one
+/// that continues from the same place, so a scan can be split: clone it, then
+/// [`skip_to()`](Self::skip_to) an offset further on in the clone, and have each handle its part.
 #[derive(Debug)]
-pub struct SparseIter<'a> {
-    file: &'a fs::File,
+pub struct SparseIter<F> {
+    /// Held while iterating, if `lock_shared` was used. This and `saved_cursor` come before
+    /// `file` so they're dropped (unlocking, and restoring the cursor) before the file is closed.
+    #[cfg(unix)]
+    lock: Option<SharedLock>,
+    #[cfg(unix)]
+    lock_shared: bool,
+    saved_cursor: Option<SavedCursor>,
+    restore_cursor: bool,
+    file: F,
+    state: State,
+    /// Whether `set_up()` has run. It only runs once, not again after `skip_to()`.
+    started: bool,
+    preserve_cursor: bool,
+    sync: bool,
+    #[cfg(unix)]
+    sync_on_zfs: bool,
+    warning: Option<ScanWarning>,
+    detect_changes: bool,
+    /// The file as it was when iteration started, if `detect_changes` is set
+    version: Option<FileVersion>,
+    position: ScanPosition,
+    backend: Backend,
+    fallback: Fallback,
+    /// The block size to read scan with, if the file turns out to be a block device
+    #[cfg(unix)]
+    device_scan: Option<u64>,
+    /// Where to stop, if before the end of the file
+    limit: u64,
+}
+
+/// How a [`SparseIter`] finds holes
+#[derive(Debug, Clone)]
+enum Backend {
+    /// `lseek(SEEK_DATA)` and `lseek(SEEK_HOLE)`
+    Seek,
+    /// Read the file, looking for blocks of zeros
+    ReadScan(ReadScan),
+    /// Ask for the location of every block with `FIBMAP`
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    Fibmap(fibmap::Fibmap),
+    /// Ask for the file's extents with `FS_IOC_FIEMAP`
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    Fiemap(fiemap::FiemapScan),
 }
 
-impl<'a> From<&'a fs::File> for SparseIter<'a> {
-    fn from(file: &'a fs::File) -> Self {
-        // TODO: consider seeking to the start of the file. Not doing this allows writing
-        // non-portable code.
-        //
-        // XXX: always need to allow non-portable escape hatches
-        Self { file } 
+/// What a [`SparseIter`] does when the filesystem can't tell us where holes are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fallback {
+    /// Return the error
+    Error,
+    /// Claim the whole file is `Data`
+    Data,
+    /// Switch to a read scan with the given block size
+    ReadScan(u64),
+}
+
+/// Where a [`SparseIter`] is, and what it needs to look for next
+#[derive(Debug, Clone, Copy)]
+enum State {
+    /// Nothing emitted yet, classify the given offset
+    Start(u64),
+    /// Emitted `Data` at the given offset, look for the next hole
+    Data(u64),
+    /// Emitted a `Hole` at the given offset, look for the next data
+    Hole(u64),
+    /// Out of Data and Holes, emit `End` next
+    End,
+    /// No more items
+    Done,
+}
+
+/// The clone holds no lock and restores no cursor: those stay with the original, which still
+/// releases and restores them when dropped. Its other settings, and where it is in the file, are
+/// copied.
+impl<F: Clone> Clone for SparseIter<F> {
+    fn clone(&self) -> Self {
+        Self {
+            #[cfg(unix)]
+            lock: None,
+            #[cfg(unix)]
+            lock_shared: false,
+            saved_cursor: None,
+            restore_cursor: false,
+            file: self.file.clone(),
+            state: self.state,
+            started: self.started,
+            preserve_cursor: self.preserve_cursor,
+            sync: self.sync,
+            #[cfg(unix)]
+            sync_on_zfs: self.sync_on_zfs,
+            warning: self.warning,
+            detect_changes: self.detect_changes,
+            version: self.version,
+            position: self.position,
+            backend: self.backend.clone(),
+            fallback: self.fallback,
+            #[cfg(unix)]
+            device_scan: self.device_scan,
+            limit: self.limit,
+        }
+    }
+}
+
+impl<F: AsFile> From<F> for SparseIter<F> {
+    fn from(file: F) -> Self {
+        Self::starting_at(file, 0)
     }
 }
 
@@ -82,40 +224,847 @@ impl<'a> From<&'a fs::File> for SparseIter<'a> {
 // should use fpathconf(_PC_MIN_HOLE_SIZE) or pathconf(_PC_MIN_HOLE_SIZE) to determine if a file
 // system supports SEEK_HOLE.  See pathconf(2).
 
-
-
-#[cfg(target_os = "linux")]
+#[cfg(any(target_os = "linux", target_os = "android"))]
 mod linux;
-#[cfg(target_os = "linux")]
+#[cfg(any(target_os = "linux", target_os = "android"))]
 use linux::*;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod fibmap;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub mod fiemap;
+#[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+mod uring;
 
 #[cfg(target_os = "macos")]
 mod macos;
 #[cfg(target_os = "macos")]
 use macos::*;
 
+#[cfg(target_os = "freebsd")]
+mod freebsd;
+#[cfg(target_os = "freebsd")]
+use freebsd::*;
+
+#[cfg(target_os = "netbsd")]
+mod netbsd;
+#[cfg(target_os = "netbsd")]
+use netbsd::*;
+
+#[cfg(target_os = "openbsd")]
+mod openbsd;
+#[cfg(target_os = "openbsd")]
+use openbsd::*;
+
+#[cfg(unix)]
+mod unix;
+#[cfg(unix)]
+use unix::*;
+
+#[cfg(windows)]
+mod windows;
+#[cfg(windows)]
+use windows::*;
+
+mod map;
+pub use map::{map_path, RangeDiff, SparseMap};
+
+pub mod intervals;
+
+pub mod adapters;
+pub use adapters::SparseRangeIterExt;
+
+mod read_scan;
+use read_scan::{BlockScan, ReadScan};
+
+#[cfg(feature = "mmap")]
+mod mapped;
+#[cfg(feature = "mmap")]
+pub use mapped::{MappedData, MappedFile};
+
+pub mod zero;
+pub use zero::{is_zero, Segmenter};
+
+mod probe;
+pub use probe::{kind_at, next_data_from, next_hole_from};
+
+#[cfg(unix)]
+mod rev;
+#[cfg(unix)]
+pub use rev::{rscan, RevRangeIter};
+
 #[cfg(unix)]
-use std::os::unix::io::AsRawFd;
+mod device;
+#[cfg(unix)]
+pub use device::is_block_device;
+
+#[cfg(unix)]
+mod zfs;
+#[cfg(unix)]
+pub use zfs::is_zfs;
+
+mod size;
+pub use size::{allocated_size, is_sparse, AllocatedSize};
+
+mod block;
+pub use block::{align_down, align_up, block_size, min_hole_size};
+
+mod stats;
+pub use stats::{stats, Fragmentation, SparseStats};
+
+mod attr;
+pub use attr::{is_marked_sparse, is_marked_sparse_path, set_sparse};
+
+pub mod error;
+pub use error::Error;
+
+mod progress;
+pub use progress::{CancelToken, Progress};
 
-impl<'a> Iterator for SparseIter<'a> {
+mod read_at;
+pub use read_at::ReadAt;
+
+mod punch;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use punch::zero_range;
+pub use punch::{punch_hole, punch_hole_or_zero, Zeroed};
+
+#[cfg(unix)]
+mod copy;
+#[cfg(unix)]
+pub use copy::{copy_sparse, copy_sparse_path, copy_sparse_with_data, copy_sparse_with_progress};
+
+mod clone;
+pub use clone::{clone_file, clone_file_path, clone_range, clone_ranges, dedupe_range, Deduped};
+
+mod prealloc;
+pub use prealloc::preallocate;
+#[cfg(target_os = "macos")]
+pub use prealloc::preallocate_contiguous;
+
+#[cfg(unix)]
+mod sparsify;
+#[cfg(unix)]
+pub use sparsify::{sparsify, sparsify_dry_run, sparsify_path, sparsify_with_progress, Sparsified};
+
+#[cfg(all(unix, feature = "parallel"))]
+mod parallel;
+#[cfg(all(unix, feature = "parallel"))]
+pub use parallel::{read_scan_parallel, sparsify_parallel};
+
+#[cfg(unix)]
+mod reader;
+#[cfg(unix)]
+pub use reader::{DataExtents, ExtentReader, ReadData, SameFile, SparseReader};
+
+#[cfg(unix)]
+mod stream;
+#[cfg(unix)]
+pub use stream::{read_sparse_stream, write_sparse_stream};
+
+pub mod tar;
+
+mod tree;
+pub use tree::{scan_tree, FileStats, TreeStats};
+
+#[cfg(unix)]
+mod hash;
+#[cfg(unix)]
+pub use hash::hash_sparse;
+
+#[cfg(unix)]
+mod verify;
+#[cfg(unix)]
+pub use verify::{verify_holes, HoleCheck};
+
+#[cfg(unix)]
+mod compare;
+#[cfg(unix)]
+pub use compare::compare_sparse;
+
+#[cfg(unix)]
+mod apply;
+#[cfg(unix)]
+pub use apply::{apply_map, apply_map_from_reader};
+
+#[cfg(unix)]
+pub mod bmap;
+#[cfg(unix)]
+pub mod checksum;
+#[cfg(unix)]
+mod sha256;
+
+#[cfg(unix)]
+mod writer;
+#[cfg(unix)]
+pub use writer::SparseWriter;
+
+#[cfg(feature = "async")]
+pub mod async_scan;
+#[cfg(feature = "async")]
+pub use async_scan::{scan_async, AsyncSparseIter};
+
+mod scan;
+pub use scan::{ScanBackend, SparseScan, SparseScanBuilder};
+
+mod ext;
+pub use ext::SparseFileExt;
+
+#[cfg(feature = "cap-std")]
+mod cap;
+#[cfg(feature = "cap-std")]
+pub use cap::SparseDirExt;
+
+/// Something we can look for holes in
+///
+/// This is anything that can give us a file descriptor (on unix) or a handle (on windows): a
+/// `File` (owned or borrowed), an `OwnedFd`, or types from other crates that wrap either.
+///
+/// Async runtimes' files are included: `tokio::fs::File` and `async_std::fs::File` can be passed
+/// as they are, without converting them to a `std::fs::File` first. Functions here block, so from
+/// async code, either hand the file over to [`scan_async()`](crate::scan_async) (with the `async`
+/// feature) or call them from the runtime's pool for blocking work.
+#[cfg(unix)]
+pub trait AsFile: std::os::unix::io::AsFd {}
+#[cfg(unix)]
+impl<T: std::os::unix::io::AsFd + ?Sized> AsFile for T {}
+
+/// Something we can look for holes in
+///
+/// This is anything that can give us a file descriptor (on unix) or a handle (on windows): a
+/// `File` (owned or borrowed), an `OwnedHandle`, or types from other crates that wrap either.
+///
+/// Async runtimes' files are included: `tokio::fs::File` and `async_std::fs::File` can be passed
+/// as they are, without converting them to a `std::fs::File` first. Functions here block, so from
+/// async code, either hand the file over to [`scan_async()`](crate::scan_async) (with the `async`
+/// feature) or call them from the runtime's pool for blocking work.
+#[cfg(windows)]
+pub trait AsFile: std::os::windows::io::AsHandle {}
+#[cfg(windows)]
+impl<T: std::os::windows::io::AsHandle + ?Sized> AsFile for T {}
+
+impl<F: AsFile> SparseIter<F> {
+    /// Iterate over `file` starting at byte `offset` instead of at the start of the file
+    ///
+    /// The first item returned is located at `offset` (unless `offset` is at or past the end of
+    /// the file, in which case only `End` is returned), and describes whatever `offset` happens
+    /// to be in.
+    pub fn starting_at(file: F, offset: u64) -> Self {
+        // NOTE: we never look at the file's cursor. Each probe is an absolute seek, so where the
+        // cursor was before we started doesn't matter.
+        Self {
+            #[cfg(unix)]
+            lock: None,
+            #[cfg(unix)]
+            lock_shared: false,
+            saved_cursor: None,
+            restore_cursor: false,
+            file,
+            state: State::Start(offset),
+            started: false,
+            preserve_cursor: false,
+            sync: false,
+            #[cfg(unix)]
+            sync_on_zfs: false,
+            warning: None,
+            detect_changes: false,
+            version: None,
+            position: ScanPosition {
+                offset,
+                finished: false,
+            },
+            backend: Backend::Seek,
+            fallback: Fallback::Error,
+            #[cfg(unix)]
+            device_scan: None,
+            limit: u64::MAX,
+        }
+    }
+
+    /// Iterate over only the bytes of `file` in `window`
+    ///
+    /// This is [`starting_at()`](Self::starting_at) the start of `window`,
+    /// [`ending_at()`](Self::ending_at) its end. If `window` ends before it starts, nothing is
+    /// returned, not even `End`.
+    pub fn window(file: F, window: std::ops::Range<u64>) -> Self {
+        Self::starting_at(file, window.start).ending_at(window.end)
+    }
+
+    /// Stop iterating at byte `offset`, as if the file ended there
+    ///
+    /// The `End` item is located at `offset` (or at the end of the file, if that comes first), so
+    /// the last range is cut short there. Nothing past `offset` is looked at, beyond what a single
+    /// probe finds: read scans stop reading at `offset`, instead of at the end of the block that
+    /// crosses it. This lets a file be split into windows that are each scanned on their own.
+    ///
+    /// If iteration starts (or [`skip_to()`](Self::skip_to)s) past `offset`, nothing is returned,
+    /// not even `End`.
+    pub fn ending_at(mut self, offset: u64) -> Self {
+        self.limit = offset;
+        self
+    }
+
+    /// Leave the file's cursor where it was before each probe
+    ///
+    /// Probing for holes moves the file's cursor on most platforms. This saves the cursor before
+    /// every probe and restores it afterwards, so `read()`s and `write()`s interleaved with
+    /// iteration happen where they would have if we hadn't iterated at all. This costs 2 extra
+    /// syscalls per probe.
+    ///
+    /// Note that the cursor is shared by every handle to the same open file (including `dup()`ed
+    /// descriptors and `try_clone()`ed `File`s), so this doesn't make it safe to iterate on one
+    /// thread while using the cursor from another.
+    pub fn preserve_cursor(mut self) -> Self {
+        self.preserve_cursor = true;
+        self
+    }
+
+    /// Put the file's cursor back where it was before iteration when the iterator is dropped
+    ///
+    /// This records the cursor before the first probe, and restores it when the iterator is
+    /// dropped (or [`into_inner()`](Self::into_inner) is called), so wrapping a `File` used
+    /// elsewhere doesn't leave it reading or writing from wherever the last probe left it. Unlike
+    /// [`preserve_cursor()`](Self::preserve_cursor), the cursor still moves during iteration, but
+    /// this only costs 2 syscalls in total.
+    ///
+    /// An error restoring the cursor is ignored.
+    pub fn restore_cursor(mut self) -> Self {
+        self.restore_cursor = true;
+        self
+    }
+
+    /// Flush the file's dirty data to disk (with `fdatasync()`) before the first probe
+    ///
+    /// Some filesystems (zfs, and ext4 in some configurations) report data that was recently
+    /// written but not yet allocated as a hole. Syncing first makes the report accurate at the
+    /// cost of waiting for the writes to finish.
+    ///
+    /// Only the file's data is flushed, so this works on files opened read only. It doesn't help
+    /// with writes made through other open files after the first probe.
+    #[doc(alias = "flush_before_scan")]
+    pub fn sync(mut self) -> Self {
+        self.sync = true;
+        self
+    }
+
+    /// Like [`sync()`](Self::sync), but only when the file is on ZFS and looks like it has holes
+    /// that aren't being reported
+    ///
+    /// Unless the `zfs_dmu_offset_next_sync` module parameter is set, ZFS reports a file with
+    /// unsynced changes as having no holes at all. This checks for that before the first probe
+    /// (see [`is_zfs()`]), and syncs the file only then. If the sync fails, iteration goes ahead
+    /// anyway, and [`warning()`](Self::warning) returns [`ScanWarning::ZfsUnsynced`].
+    ///
+    /// This only affects iteration with `SEEK_HOLE`, not the other backends.
+    #[cfg(unix)]
+    pub fn sync_on_zfs(mut self) -> Self {
+        self.sync_on_zfs = true;
+        self
+    }
+
+    /// Hold a shared advisory lock (`flock(LOCK_SH)`) on the file while iterating
+    ///
+    /// Before the first probe, this waits for any exclusive lock on the file to be released, then
+    /// takes a shared one, which holds off writers that take an exclusive lock before writing
+    /// until iteration is over. The lock is released after the last item (or an error), or when
+    /// the iterator is dropped. Writers that don't take locks aren't held off.
+    ///
+    /// `flock()` locks belong to the open file, not the process: if the file is already locked
+    /// through the same open file (the same `File`, or one `try_clone()`ed from it), that lock
+    /// is converted to a shared one, and released at the end. On NFS, `flock()` locks may be
+    /// emulated with `fcntl()` locks, or not supported at all (an error from the first call to
+    /// `next()`).
+    #[cfg(unix)]
+    pub fn lock_shared(mut self) -> Self {
+        self.lock_shared = true;
+        self
+    }
+
+    /// Fail with [`Error::FileChanged`] if the file is modified during iteration
+    ///
+    /// The file's length and change time (`st_ctime`) are recorded before the first probe, and
+    /// checked again after every probe, which costs an extra `fstat()` each. If they differ, the
+    /// item that probe found is replaced by the error (as an `io::Error`, which converts back
+    /// into the [`Error`]). Without this, a file that changes mid-scan is reported as some mix of
+    /// how it was before and after.
+    ///
+    /// Changes can still be missed: the change time has limited resolution (a few milliseconds,
+    /// on some filesystems), and writes through a memory mapping may not update it until later.
+    pub fn detect_changes(mut self) -> Self {
+        self.detect_changes = true;
+        self
+    }
+
+    /// If the filesystem can't report holes, treat the whole file as `Data` instead of erroring
+    ///
+    /// Some filesystems (and older kernels) reject `SEEK_DATA` and `SEEK_HOLE` outright (with
+    /// `EINVAL` or `ENOTSUP`). Normally that error is returned from the first call to `next()`.
+    /// With this enabled, iteration instead reports a single `Data` item covering the rest of the
+    /// file, which is always a correct (if pessimistic) answer. This matches what windows reports
+    /// for files that aren't marked sparse.
+    pub fn fallback_to_data(mut self) -> Self {
+        self.fallback = Fallback::Data;
+        self
+    }
+
+    /// If the filesystem can't report holes, find them with a read scan instead of erroring
+    ///
+    /// Like [`fallback_to_data()`](Self::fallback_to_data), but instead of giving up on finding
+    /// holes, switch to [`read_scan()`](Self::read_scan) with the given `block_size`.
+    ///
+    /// # Panics
+    ///
+    /// If `block_size` is 0
+    pub fn fallback_to_read_scan(mut self, block_size: u64) -> Self {
+        assert!(block_size > 0, "block size must be non-zero");
+        self.fallback = Fallback::ReadScan(block_size);
+        self
+    }
+
+    /// Find holes by reading the file and looking for blocks of zeros
+    ///
+    /// If the file is a block device, find holes with a [`read_scan()`](Self::read_scan) instead
+    ///
+    /// `SEEK_HOLE` on a block device either fails or reports the whole device as `Data`, so this
+    /// lets a disk imaging tool use one code path for images and devices: regular files are
+    /// scanned as they would be otherwise, and block devices (see [`is_block_device()`]) are read
+    /// `block_size` bytes at a time, with their all-zero blocks reported as holes. This takes an
+    /// extra `fstat()` before the first probe.
+    ///
+    /// # Panics
+    ///
+    /// If `block_size` is 0
+    #[cfg(unix)]
+    pub fn read_scan_devices(mut self, block_size: u64) -> Self {
+        assert!(block_size > 0, "block size must be non-zero");
+        self.device_scan = Some(block_size);
+        self
+    }
+
+    /// Instead of asking the filesystem where the holes are, read the entire file `block_size`
+    /// bytes at a time, reporting each block that is entirely zeros as a `Hole`. Blocks are
+    /// aligned to multiples of `block_size` (the first block is shorter if iteration starts at an
+    /// unaligned offset).
+    ///
+    /// This works on every filesystem, but the "holes" it finds are just zeroed blocks, which may
+    /// be allocated on disk. It is mostly useful for deciding where to _make_ holes, for example
+    /// when copying a dense file into a sparse one. On unix, reading never moves the file's cursor.
+    ///
+    /// # Panics
+    ///
+    /// If `block_size` is 0
+    pub fn read_scan(mut self, block_size: u64) -> Self {
+        self.backend = Backend::ReadScan(ReadScan::new(block_size));
+        self
+    }
+
+    /// Find holes by asking the filesystem where each block is stored, with the `FIBMAP` ioctl
+    ///
+    /// This is a last resort for old filesystems that support neither `SEEK_HOLE` nor FIEMAP. It
+    /// makes one ioctl per filesystem block, and requires the `CAP_SYS_RAWIO` capability (usually
+    /// meaning it only works as root). Without it, the first call to `next()` returns a
+    /// `PermissionDenied` error.
+    ///
+    /// Blocks without a location on disk are reported as holes. Note that some filesystems report
+    /// preallocated but unwritten blocks as data here.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn fibmap(mut self) -> Self {
+        self.backend = Backend::Fibmap(fibmap::Fibmap::new());
+        self
+    }
+
+    /// Find holes by asking the filesystem for the file's extents, with the `FS_IOC_FIEMAP` ioctl
+    ///
+    /// Some filesystems support FIEMAP but not `SEEK_HOLE` (and some, like tmpfs, the reverse).
+    /// Unlike `SEEK_HOLE`, preallocated but unwritten extents are reported as holes. Without
+    /// FIEMAP support, the first call to `next()` returns the filesystem's error (usually
+    /// `EOPNOTSUPP`).
+    ///
+    /// See the [`fiemap`] module to get at the extents themselves.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn fiemap(mut self) -> Self {
+        self.backend = Backend::Fiemap(fiemap::FiemapScan::new());
+        self
+    }
+
+    /// Like [`read_scan()`](Self::read_scan), but examine the file through a memory mapping
+    ///
+    /// This avoids copying the file's contents into a buffer, which is faster on some systems.
+    /// The file is mapped on the first call to `next()`, so data appended after that is not seen.
+    ///
+    /// Memory mapping changes how failures are reported: if the file is truncated while it is
+    /// being scanned, or reading it fails (an I/O error on the underlying device, a network
+    /// filesystem going away), the process receives `SIGBUS` instead of `next()` returning an
+    /// error. Only use this on files that won't change out from under you.
+    ///
+    /// # Panics
+    ///
+    /// If `block_size` is 0
+    #[cfg(feature = "mmap")]
+    pub fn mmap_scan(mut self, block_size: u64) -> Self {
+        self.backend = Backend::ReadScan(ReadScan::mmap(block_size));
+        self
+    }
+
+    /// Like [`read_scan()`](Self::read_scan), but read the blocks through io_uring
+    ///
+    /// Blocks are read ahead in batches (up to 64 blocks or 4 MiB at a time), each with a single
+    /// syscall instead of one per block, which makes a big difference with small blocks and fast
+    /// storage. Linux 5.6 or later is needed. Where io_uring isn't available (older kernels, or
+    /// disabled by `kernel.io_uring_disabled` or a seccomp filter), the first call to `next()`
+    /// returns the error from setting it up (`ENOSYS` or `EPERM`).
+    ///
+    /// # Panics
+    ///
+    /// If `block_size` is 0
+    #[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+    pub fn uring_scan(mut self, block_size: u64) -> Self {
+        self.backend = Backend::ReadScan(ReadScan::uring(block_size));
+        self
+    }
+
+    fn seek(&self, offset: u64, whence: i32) -> io::Result<Option<u64>> {
+        let fd = self.file.as_fd();
+        if !self.preserve_cursor {
+            return seek(fd, offset, whence);
+        }
+
+        // SEEK_CUR never returns ENXIO
+        let cursor = seek(fd, 0, libc::SEEK_CUR)?.unwrap();
+        let r = seek(fd, offset, whence);
+        seek(fd, cursor, libc::SEEK_SET)?;
+        r
+    }
+
+    /// Get back the file this iterator was created from
+    pub fn into_inner(self) -> F {
+        self.file
+    }
+
+    /// Get a reference to the file this iterator was created from
+    pub fn get_ref(&self) -> &F {
+        &self.file
+    }
+
+    /// Resume a scan from where [`position()`](Self::position) said an earlier one had got to
+    ///
+    /// This is [`starting_at()`](Self::starting_at) the position's offset, so the first item is
+    /// the last one the earlier scan returned (or `End` if it had finished). Nothing but the
+    /// offset is kept: options like [`sync()`](Self::sync) have to be set again, and if the file
+    /// changed in between, the rest of the scan describes it as it is now.
+    pub fn resume_at(file: F, position: ScanPosition) -> Self {
+        Self::starting_at(file, position.offset)
+    }
+
+    /// Continue iterating from `offset`, skipping whatever is between here and there
+    ///
+    /// The next item is located at `offset`, as if the iterator had been created with
+    /// [`starting_at()`](Self::starting_at), but without setting it up again (options, locks,
+    /// and the baseline for [`detect_changes()`](Self::detect_changes) are kept). Nothing is
+    /// probed until then. `offset` may also be before the last item returned, to go back.
+    ///
+    /// Does nothing once iteration is over (after `End` or an error).
+    pub fn skip_to(&mut self, offset: u64) {
+        if let State::Done = self.state {
+            return;
+        }
+        self.state = State::Start(offset);
+        self.position = ScanPosition {
+            offset,
+            finished: false,
+        };
+    }
+
+    /// How far the scan has got, to [`resume_at()`](Self::resume_at) later
+    ///
+    /// This is the offset of the last item returned (or where the scan was started, before the
+    /// first), and doesn't change when an error is returned. Resuming returns that item again,
+    /// so a range whose processing was interrupted is seen in full.
+    pub fn position(&self) -> ScanPosition {
+        self.position
+    }
+
+    /// Why the items returned so far might show fewer holes than the file really has, if there's
+    /// a reason to think so
+    ///
+    /// The items are still correct (a hole reported as `Data` reads back the same), just
+    /// pessimistic.
+    pub fn warning(&self) -> Option<ScanWarning> {
+        self.warning
+    }
+
+    fn item(&mut self, kind: ItemKind, offset: u64) -> Option<io::Result<SparseItem>> {
+        self.state = match kind {
+            ItemKind::Data => State::Data(offset),
+            ItemKind::Hole => State::Hole(offset),
+            ItemKind::End => State::Done,
+        };
+        Some(Ok(SparseItem { kind, offset }))
+    }
+
+    fn end(&mut self) -> Option<io::Result<SparseItem>> {
+        self.state = State::End;
+        match file_len(self.file.as_fd()) {
+            Ok(len) => self.item(ItemKind::End, len),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+impl<F: AsFile> Iterator for SparseIter<F> {
     type Item = io::Result<SparseItem>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            // TODO: use lseek64 on 32-bit platforms that have it for larger seeks
-            let off = unsafe { libc::lseek(self.file.as_raw_fd(), 0, SEEK_DATA) };
-            if off < 0 {
-                // error!
-                return Some(Err(io::Error::last_os_error()));
+        let mut r = self.probe();
+        if let Some(Ok(ref mut item)) = r {
+            if item.offset >= self.limit {
+                *item = SparseItem {
+                    kind: ItemKind::End,
+                    offset: self.limit,
+                };
+                self.state = State::Done;
+            }
+        }
+        if let (Some(Ok(_)), Some(version)) = (&r, self.version) {
+            match FileVersion::of(self.file.as_fd()) {
+                Ok(now) if now == version => {}
+                Ok(_) => r = Some(Err(Error::FileChanged.into())),
+                Err(e) => r = Some(Err(e)),
+            }
+        }
+        match r {
+            Some(Ok(ref item)) => {
+                self.position = ScanPosition {
+                    offset: item.offset,
+                    finished: item.kind == ItemKind::End,
+                }
+            }
+            // whatever went wrong is likely to go wrong again, don't let callers spin on it
+            Some(Err(_)) => self.state = State::Done,
+            None => {}
+        }
+        #[cfg(unix)]
+        if let State::Done = self.state {
+            self.lock = None;
+        }
+        r
+    }
+
+    /// Until iteration is over, there's always at least one more item: `End`, or an error. An
+    /// upper bound is known with `fiemap()`, from the number of extents the file has (counted
+    /// along with the first batch of them), and with `fibmap()` once the file's length has been
+    /// looked up (on the first block). Read scans have one with `ending_at()`, where they stop.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.state {
+            State::Done => (0, Some(0)),
+            State::End => (1, Some(1)),
+            _ => {
+                let blocks = match self.backend {
+                    Backend::Seek => None,
+                    Backend::ReadScan(ref scan) => scan.blocks_left(),
+                    #[cfg(any(target_os = "linux", target_os = "android"))]
+                    Backend::Fibmap(ref scan) => scan.blocks_left(),
+                    #[cfg(any(target_os = "linux", target_os = "android"))]
+                    Backend::Fiemap(ref scan) => scan.blocks_left(),
+                };
+                // each block is at most one item, then there's `End`
+                (1, blocks.and_then(|n| n.checked_add(1)))
+            }
+        }
+    }
+}
+
+impl<F: AsFile> FusedIterator for SparseIter<F> {}
+
+impl<F: AsFile> SparseIter<F> {
+    fn probe(&mut self) -> Option<io::Result<SparseItem>> {
+        if let State::Start(offset) = self.state {
+            // a window that ends before it starts is empty
+            if offset > self.limit {
+                self.state = State::Done;
+                return None;
+            }
+            if !self.started {
+                if let Err(e) = self.set_up(offset) {
+                    return Some(Err(e));
+                }
+                self.started = true;
+            }
+        }
+
+        if !matches!(self.backend, Backend::Seek) {
+            return self.probe_blocks();
+        }
+
+        match self.state {
+            State::Done => None,
+            #[cfg(target_os = "macos")]
+            State::Start(offset) if offset > 0 && self.starts_in_data(offset) => {
+                self.item(ItemKind::Data, offset)
             }
+            State::Start(offset) => match self.seek(offset, SEEK_DATA) {
+                Err(ref e) if self.fallback != Fallback::Error && is_unsupported(e) => {
+                    if let Fallback::ReadScan(block_size) = self.fallback {
+                        self.backend = Backend::ReadScan(ReadScan::new(block_size));
+                        return self.probe_blocks();
+                    }
+
+                    let len = match file_len(self.file.as_fd()) {
+                        Ok(len) => len,
+                        Err(e) => return Some(Err(e)),
+                    };
 
+                    if offset < len {
+                        let r = self.item(ItemKind::Data, offset);
+                        self.state = State::End;
+                        r
+                    } else {
+                        self.item(ItemKind::End, len)
+                    }
+                }
+                Err(e) => Some(Err(e)),
+                Ok(Some(data)) if data == offset => self.item(ItemKind::Data, offset),
+                Ok(Some(_)) => self.item(ItemKind::Hole, offset),
+                Ok(None) => {
+                    // No data at or after `offset`. Either we're at (or past) the end of the file,
+                    // or the rest of the file is one big hole.
+                    let len = match file_len(self.file.as_fd()) {
+                        Ok(len) => len,
+                        Err(e) => return Some(Err(e)),
+                    };
+
+                    if offset < len {
+                        let r = self.item(ItemKind::Hole, offset);
+                        self.state = State::End;
+                        r
+                    } else {
+                        self.item(ItemKind::End, len)
+                    }
+                }
+            },
+            State::Data(offset) => match self.seek(offset, SEEK_HOLE) {
+                Err(e) => Some(Err(e)),
+                Ok(Some(hole)) => {
+                    // every file has an implicit hole at its end. That isn't a real hole, it's
+                    // just the end of the last data range.
+                    let len = match file_len(self.file.as_fd()) {
+                        Ok(len) => len,
+                        Err(e) => return Some(Err(e)),
+                    };
+
+                    // APFS sometimes answers with an offset before the one we asked about (0, for a
+                    // file that is a single hole followed by data). All we know then is that
+                    // there's data here, so claim it runs to the end of the file.
+                    if hole > offset && hole < len {
+                        self.item(ItemKind::Hole, hole)
+                    } else {
+                        self.item(ItemKind::End, len)
+                    }
+                }
+                Ok(None) => self.end(),
+            },
+            State::Hole(offset) => match self.seek(offset, SEEK_DATA) {
+                Err(e) => Some(Err(e)),
+                Ok(Some(data)) => self.item(ItemKind::Data, data),
+                Ok(None) => self.end(),
+            },
+            State::End => self.end(),
+        }
+    }
+
+    /// Get ready to probe from `offset`, as the options ask, before the first probe
+    #[cfg_attr(windows, allow(unused_variables))]
+    fn set_up(&mut self, offset: u64) -> io::Result<()> {
+        if self.restore_cursor && self.saved_cursor.is_none() {
+            self.saved_cursor = Some(SavedCursor::new(self.file.as_fd())?);
+        }
+
+        #[cfg(unix)]
+        if self.lock_shared && self.lock.is_none() {
+            self.lock = Some(SharedLock::new(self.file.as_fd())?);
+        }
+
+        #[cfg(unix)]
+        if let Some(block_size) = self.device_scan.take() {
+            if is_block_device(self.file.as_fd())? {
+                self.backend = Backend::ReadScan(ReadScan::new(block_size));
+            }
+        }
 
+        if self.sync {
+            fdatasync(self.file.as_fd())?;
+        } else {
+            #[cfg(unix)]
+            if self.sync_on_zfs && matches!(self.backend, Backend::Seek) {
+                self.sync_if_zfs_hides_holes(offset)?;
+            }
+        }
+
+        if self.detect_changes {
+            self.version = Some(FileVersion::of(self.file.as_fd())?);
+        }
+        Ok(())
+    }
+
+    /// Is `offset` in the middle of a `Data` range?
+    ///
+    /// On APFS, `SEEK_DATA` from the middle of a data range skips to the start of the _next_ data
+    /// range, so starting iteration there would report the data `offset` is in as a hole.
+    /// `SEEK_HOLE` doesn't have that problem. If it fails, leave it to the usual `SEEK_DATA` probe
+    /// to report the error (or fall back).
+    #[cfg(target_os = "macos")]
+    fn starts_in_data(&self, offset: u64) -> bool {
+        match self.seek(offset, SEEK_HOLE) {
+            Ok(Some(hole)) => hole != offset,
+            _ => false,
+        }
+    }
+
+    /// Probe using one of the backends that examines the file a block at a time
+    fn probe_blocks(&mut self) -> Option<io::Result<SparseItem>> {
+        let scan: &mut dyn BlockScan = match self.backend {
+            Backend::ReadScan(ref mut scan) => scan,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Backend::Fibmap(ref mut scan) => scan,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Backend::Fiemap(ref mut scan) => scan,
+            Backend::Seek => unreachable!(),
+        };
+        scan.read_until(self.limit);
+
+        let current = match self.state {
+            State::Done => return None,
+            State::End => return self.end(),
+            State::Start(offset) => {
+                scan.seek(offset);
+                None
+            }
+            State::Data(_) => Some(ItemKind::Data),
+            State::Hole(_) => Some(ItemKind::Hole),
+        };
+
+        let limit = self.limit;
+        loop {
+            match scan.next_block(self.file.as_fd()) {
+                Err(e) => return Some(Err(e)),
+                // the file ends wherever we stopped being able to read it
+                Ok(None) if current.is_some() => {
+                    let len = scan.pos();
+                    return self.item(ItemKind::End, len);
+                }
+                Ok(None) => return self.end(),
+                Ok(Some((kind, offset))) if Some(kind) != current => {
+                    return self.item(kind, offset)
+                }
+                Ok(Some(_)) if scan.pos() >= limit => return self.item(ItemKind::End, limit),
+                Ok(Some(_)) => {}
+            }
         }
     }
 }
 
 /// Is this Data or a Hole?
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ItemKind {
     /// Represents actual bytes (as far as the file system knows)
     Data,
@@ -123,7 +1072,6 @@ pub enum ItemKind {
     /// The absense of bytes and taken to equal a zeroed area
     Hole,
 
-    /*
     /// We've reached the end of the file
     /// This is needed to communicate the total file length (and complete the range)
     ///
@@ -131,7 +1079,6 @@ pub enum ItemKind {
     /// `None`).
     /// [`SparseRangeIter`] will not return an Item with this kind.
     End,
-    */
 }
 
 /// The item we've observed in the file we're iterating over
@@ -140,7 +1087,8 @@ pub enum ItemKind {
 /// for ranges.
 ///
 /// To get ranges, use the `SparseRangeIter` adapter.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SparseItem {
     /// The kind of this point
     pub kind: ItemKind,
@@ -148,50 +1096,118 @@ pub struct SparseItem {
     pub offset: u64,
 }
 
+/// A reason a scan may have reported fewer holes than the file has
+///
+/// See [`SparseIter::warning()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ScanWarning {
+    /// The file is on ZFS, looked like it had unsynced changes, and couldn't be synced
+    ///
+    /// ZFS may have reported some (or all) of its holes as `Data`. See
+    /// [`SparseIter::sync_on_zfs()`].
+    ZfsUnsynced,
+}
+
+/// How far a scan has got, from [`SparseIter::position()`] or [`SparseRangeIter::position()`]
+///
+/// With the `serde` feature, this can be saved, so that a long scan interrupted by the process
+/// exiting can be picked up again with [`SparseIter::resume_at()`] instead of starting over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScanPosition {
+    /// The offset the scan resumes from
+    pub offset: u64,
+    /// Whether the scan had reached the end of the file (`offset` is then the file's length)
+    pub finished: bool,
+}
+
 /// Iterate over a file returning the ranges of Data and Holes that compose it.
-#[derive(Debug)]
-pub struct SparseRangeIter<'a> {
-    inner: SparseIter<'a>,
+///
+/// Like [`SparseIter`], this stops after returning an error, and is `Send` and `Clone` under
+/// the same conditions.
+#[derive(Debug, Clone)]
+pub struct SparseRangeIter<F> {
+    inner: SparseIter<F>,
     prev: Option<SparseItem>,
 }
 
-impl<'a> From<SparseIter<'a>> for SparseRangeIter<'a> {
-    fn from(inner: SparseIter<'a>) -> Self {
-        Self { inner, prev: None } 
+impl<F> From<SparseIter<F>> for SparseRangeIter<F> {
+    fn from(inner: SparseIter<F>) -> Self {
+        Self { inner, prev: None }
+    }
+}
+
+impl<F> SparseRangeIter<F> {
+    /// Why the ranges returned so far might show fewer holes than the file really has (see
+    /// [`SparseIter::warning()`])
+    pub fn warning(&self) -> Option<ScanWarning> {
+        self.inner.warning
+    }
+
+    /// How far the scan has got, to resume it later
+    ///
+    /// This is the end of the last range returned (or where the scan was started, before the
+    /// first), so `SparseRangeIter::from(SparseIter::resume_at(file, position))` returns the
+    /// ranges after it.
+    pub fn position(&self) -> ScanPosition {
+        match self.prev {
+            Some(ref prev) => ScanPosition {
+                offset: prev.offset,
+                finished: prev.kind == ItemKind::End,
+            },
+            None => self.inner.position,
+        }
+    }
+}
+
+impl<F: AsFile> SparseRangeIter<F> {
+    /// Continue iterating from `offset`, skipping whatever is between here and there
+    ///
+    /// The next range starts at `offset` (see [`SparseIter::skip_to()`]).
+    pub fn skip_to(&mut self, offset: u64) {
+        if let State::Done = self.inner.state {
+            return;
+        }
+        self.prev = None;
+        self.inner.skip_to(offset);
     }
 }
 
-impl<'a> Iterator for SparseRangeIter<'a> {
+impl<F: AsFile> Iterator for SparseRangeIter<F> {
     type Item = io::Result<SparseRangeItem>;
     fn next(&mut self) -> Option<Self::Item> {
-        /*
-        let v = match self.inner.next() {
-            Some(Err(e)) => {
-                // TODO: consider fusing on error
-                return Some(Err(e))
-            },
-            Some(Ok(v)) => Some(v),
-            None => None,
-        };
+        loop {
+            // `SparseIter` is fused on error, so we are too
+            let v = match self.inner.next()? {
+                Err(e) => return Some(Err(e)),
+                Ok(v) => v,
+            };
 
-        match self.prev {
-            None => {
-                self.prev = v;
-                None
-            }
-            Some(prev) => {
-                let r = Some(Ok(SparseRangeItem { kind: prev.kind, start: prev.offset, end: v.offset }));
-                self.prev = v;
-                r
+            // the first item only opens a range, and `End` closes the last one
+            let end = v.offset;
+            if let Some(prev) = self.prev.replace(v) {
+                return Some(Ok(SparseRangeItem {
+                    kind: prev.kind,
+                    start: prev.offset,
+                    end,
+                }));
             }
         }
-        */
-        unimplemented!()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // every item after the first closes a range (or is an error)
+        let (_, upper) = self.inner.size_hint();
+        (0, upper)
     }
 }
 
+impl<F: AsFile> FusedIterator for SparseRangeIter<F> {}
+
 /// A range from a [`SparseRangeIter`]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SparseRangeItem {
     /// The kind of this range
     pub kind: ItemKind,
@@ -1 +1,155 @@
-pub use libc::{SEEK_HOLE, SEEK_DATA};
+use crate::unix::{to_off, BorrowedFd};
+use std::io;
+use std::os::unix::io::AsRawFd;
+
+pub use libc::{SEEK_DATA, SEEK_HOLE};
+
+// see `unix.rs`
+#[cfg(any(target_env = "musl", target_env = "ohos"))]
+use libc::fallocate as fallocate_raw;
+#[cfg(not(any(target_env = "musl", target_env = "ohos")))]
+use libc::fallocate64 as fallocate_raw;
+
+/// `fallocate()` with the given `mode` flags
+pub(crate) fn fallocate(fd: BorrowedFd<'_>, mode: i32, offset: u64, len: u64) -> io::Result<()> {
+    let r = unsafe { fallocate_raw(fd.as_raw_fd(), mode, to_off(offset)?, to_off(len)?) };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// `copy_file_range()` up to `len` bytes from `src` at `offset` into `dst` at the same offset
+///
+/// Returns the number of bytes copied, which is 0 if `src` ends at or before `offset`.
+pub(crate) fn copy_file_range(
+    src: BorrowedFd<'_>,
+    dst: BorrowedFd<'_>,
+    offset: u64,
+    len: u64,
+) -> io::Result<u64> {
+    let mut off_in: i64 = to_off(offset)?;
+    let mut off_out = off_in;
+    let len = len.min(isize::MAX as u64) as usize;
+    // libc's wrapper needs glibc 2.27 (and doesn't exist on android), the syscall has been around
+    // since linux 4.5
+    let r = unsafe {
+        libc::syscall(
+            libc::SYS_copy_file_range,
+            src.as_raw_fd(),
+            &mut off_in as *mut i64,
+            dst.as_raw_fd(),
+            &mut off_out as *mut i64,
+            len,
+            0u32,
+        )
+    };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(r as u64)
+}
+
+/// `ioctl(FICLONE)`: replace the contents of `dst` with a clone of `src`
+pub(crate) fn ficlone(src: BorrowedFd<'_>, dst: BorrowedFd<'_>) -> io::Result<()> {
+    let r = unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE as _, src.as_raw_fd()) };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// `ioctl(FICLONERANGE)`: replace `len` bytes of `dst` at `dst_offset` with a clone of the bytes
+/// of `src` at `src_offset`
+pub(crate) fn ficlonerange(
+    src: BorrowedFd<'_>,
+    dst: BorrowedFd<'_>,
+    src_offset: u64,
+    dst_offset: u64,
+    len: u64,
+) -> io::Result<()> {
+    let range = libc::file_clone_range {
+        src_fd: src.as_raw_fd().into(),
+        src_offset,
+        src_length: len,
+        dest_offset: dst_offset,
+    };
+    let r = unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONERANGE as _, &range) };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// `_IOWR(0x94, 54, struct file_dedupe_range)`
+const FIDEDUPERANGE: libc::c_ulong = 0xc018_9436;
+
+const FILE_DEDUPE_RANGE_DIFFERS: i32 = 1;
+
+/// `struct file_dedupe_range`, with room for a single destination
+#[repr(C)]
+struct file_dedupe_range {
+    src_offset: u64,
+    src_length: u64,
+    dest_count: u16,
+    reserved1: u16,
+    reserved2: u32,
+    info: file_dedupe_range_info,
+}
+
+#[repr(C)]
+struct file_dedupe_range_info {
+    dest_fd: i64,
+    dest_offset: u64,
+    bytes_deduped: u64,
+    status: i32,
+    reserved: u32,
+}
+
+/// `ioctl(FIDEDUPERANGE)`: share `len` bytes of `src` at `src_offset` with `dst` at
+/// `dst_offset`, if their contents are the same
+///
+/// Returns the number of bytes deduplicated, which may be less than `len` (filesystems limit how
+/// much they compare at once), or `None` if the contents differ.
+pub(crate) fn fideduperange(
+    src: BorrowedFd<'_>,
+    dst: BorrowedFd<'_>,
+    src_offset: u64,
+    dst_offset: u64,
+    len: u64,
+) -> io::Result<Option<u64>> {
+    let mut range = file_dedupe_range {
+        src_offset,
+        src_length: len,
+        dest_count: 1,
+        reserved1: 0,
+        reserved2: 0,
+        info: file_dedupe_range_info {
+            dest_fd: dst.as_raw_fd().into(),
+            dest_offset: dst_offset,
+            bytes_deduped: 0,
+            status: 0,
+            reserved: 0,
+        },
+    };
+    let r = unsafe {
+        libc::ioctl(
+            src.as_raw_fd(),
+            FIDEDUPERANGE as _,
+            &mut range as *mut file_dedupe_range,
+        )
+    };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    match range.info.status {
+        FILE_DEDUPE_RANGE_DIFFERS => Ok(None),
+        s if s < 0 => Err(io::Error::from_raw_os_error(-s)),
+        _ => Ok(Some(range.info.bytes_deduped)),
+    }
+}
@@ -1,2 +1,95 @@
+use crate::error::{checked, checked_cast};
+use crate::unix::{file_len, set_len, stat, write_zeros, BorrowedFd};
+use crate::{align_down, align_up, block_size};
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
 pub const SEEK_HOLE: i32 = 3;
 pub const SEEK_DATA: i32 = 4;
+
+/// Punch a hole with `fcntl(F_PUNCHHOLE)`
+///
+/// APFS only deallocates whole blocks, and rejects ranges that aren't block aligned. Any partial
+/// blocks at either end of the range are zeroed with writes instead. Nothing past the end of the
+/// file is touched.
+pub(crate) fn punch_hole(fd: BorrowedFd<'_>, offset: u64, len: u64) -> io::Result<()> {
+    let end = (offset + len).min(file_len(fd)?);
+    if offset >= end {
+        return Ok(());
+    }
+
+    let block_size = block_size(fd)?;
+    let hole_start = align_up(offset, block_size);
+    let hole_end = align_down(end, block_size);
+    if hole_start >= hole_end {
+        return write_zeros(fd, offset, end - offset);
+    }
+
+    write_zeros(fd, offset, hole_start - offset)?;
+    write_zeros(fd, hole_end, end - hole_end)?;
+
+    let args = libc::fpunchhole_t {
+        fp_flags: 0,
+        reserved: 0,
+        fp_offset: hole_start as libc::off_t,
+        fp_length: (hole_end - hole_start) as libc::off_t,
+    };
+    let r = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_PUNCHHOLE, &args) };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Reserve space for the file to grow to `len` bytes with `fcntl(F_PREALLOCATE)`, then extend it
+/// to `len` if it's shorter
+///
+/// With `contiguous`, the space must be allocated in one piece. Otherwise, it may be spread
+/// across several extents.
+pub(crate) fn preallocate(fd: BorrowedFd<'_>, len: u64, contiguous: bool) -> io::Result<()> {
+    let st = stat(fd)?;
+    let allocated = checked(checked_cast::<_, u64>(st.st_blocks)?.checked_mul(512))?;
+    if len > allocated {
+        let mut flags = libc::F_ALLOCATEALL;
+        if contiguous {
+            flags |= libc::F_ALLOCATECONTIG;
+        }
+
+        // F_PEOFPOSMODE allocates after the last allocated block, so only ask for what's missing
+        let mut store = libc::fstore_t {
+            fst_flags: flags,
+            fst_posmode: libc::F_PEOFPOSMODE,
+            fst_offset: 0,
+            fst_length: checked_cast(len - allocated)?,
+            fst_bytesalloc: 0,
+        };
+        let r = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_PREALLOCATE, &mut store) };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    if checked_cast::<_, u64>(st.st_size)? < len {
+        set_len(fd, len)?;
+    }
+
+    Ok(())
+}
+
+/// Clone `src` to a new file at `dst` with `fclonefileat()`
+///
+/// Fails with `ENOTSUP` if the filesystem can't clone (HFS+), and `EXDEV` if `dst` is on a
+/// different volume.
+pub(crate) fn clone_file(src: BorrowedFd<'_>, dst: &Path) -> io::Result<()> {
+    let dst = CString::new(dst.as_os_str().as_bytes())?;
+    let r = unsafe { libc::fclonefileat(src.as_raw_fd(), libc::AT_FDCWD, dst.as_ptr(), 0) };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
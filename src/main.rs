@@ -1,3 +1,558 @@
+//! `fsparse`: look at and work with sparse files from the command line
+//!
+//! Built with the `cli` feature.
+//!
+//! # JSON output
+//!
+//! With `--json`, each command prints a single JSON value to stdout instead of text. Errors are
+//! still reported as text on stderr, with a non-zero exit status. Paths that aren't valid UTF-8
+//! have the invalid parts replaced with U+FFFD. Fields may be added, but existing ones won't
+//! change.
+//!
+//! `map` prints an array with an object for each file:
+//!
+//! ```text
+//! {"path": "f", "size": 4194304, "data": 1048576, "holes": 3145728, "allocated": 1048576,
+//!  "ranges": [{"kind": "Hole", "start": 0, "end": 1048576}, ...]}
+//! ```
+//!
+//! `kind` is `"Data"` or `"Hole"`, and the ranges cover the whole file in order. With
+//! `--verbose`, each object also has `extents`: an array of objects with the `logical` and
+//! `physical` offsets and `length` of each extent in bytes, and its `flags` (an array of the
+//! names `filefrag -v` uses).
+//!
+//! `copy` prints an object with `source` and `destination` paths, `cloned` (whether storage is
+//! shared instead of copied) and `copied` (bytes of data copied, 0 when cloned).
+//!
+//! `punch` prints an object with the `path` and the `ranges` punched, each an object with an
+//! `offset` and `length`.
+//!
+//! `dig` prints an array with an object for each file: its `path`, the `block_size` used,
+//! `dry_run`, bytes of data `scanned`, bytes `punched` (or that would have been, on a dry run),
+//! and bytes `reclaimed` according to the filesystem (`null` on a dry run).
+
+#![warn(rust_2018_idioms)]
+
+use fs_sparse::{allocated_size, ItemKind, SparseIter, SparseRangeItem, SparseRangeIter};
+use serde::Serialize;
+use std::fs::File;
+use std::path::Path;
+
+type Result<T, E = Box<dyn std::error::Error>> = std::result::Result<T, E>;
+
+fn usage(e: i32) -> ! {
+    let ename = std::env::args().next().unwrap();
+
+    eprintln!(
+        r"Look at and work with sparse files
+
+Usage: {0} <command> [options]
+
+Options:
+  --json            Print the results as JSON
+
+Commands:
+  map <file>...     List the data and holes in each file, with a summary of how sparse it is
+      -v, --verbose     Also list each extent, with where it is on disk and its flags, like
+                        `filefrag -v` (linux only, on filesystems that support FIEMAP)
+      --sync            Flush each file's unwritten changes to disk first, for filesystems
+                        that don't report recently written data accurately
+  copy <src> <dst>  Copy a file, keeping its holes
+      --reflink[=WHEN]  Share storage between the copies instead of copying data: `always`
+                        (fail if that can't be done), `auto` (the default: copy if it can't),
+                        or `never`. `--reflink` alone is `--reflink=always`.
+  punch <file> <offset>:<length>...
+                    Deallocate each range of the file, leaving holes. Sizes may end in K, M, G,
+                    or T (powers of 1024)
+  dig <file>...     Find blocks of zeros in each file and deallocate them, like
+                    `fallocate --dig-holes`
+      --block-size <size>  Size of the blocks to look at (default: the filesystem's)
+      --dry-run            Only report how much would be deallocated
+",
+        ename
+    );
+
+    std::process::exit(e)
+}
+
+/// Print `value` as JSON
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn path_string(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+/// Where a file has data and holes, and how much space is allocated to it
+#[derive(Serialize)]
+struct Layout {
+    path: String,
+    size: u64,
+    data: u64,
+    holes: u64,
+    allocated: u64,
+    ranges: Vec<SparseRangeItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    extents: Option<Vec<Extent>>,
+}
+
+/// A FIEMAP extent, for `map --verbose --json`
+#[derive(Serialize)]
+struct Extent {
+    logical: u64,
+    physical: u64,
+    length: u64,
+    flags: Vec<&'static str>,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn extents(file: &File) -> Result<Vec<Extent>> {
+    let mut extents = Vec::new();
+    for e in fs_sparse::fiemap::FiemapIter::from(file) {
+        let e = e?;
+        extents.push(Extent {
+            logical: e.logical,
+            physical: e.physical,
+            length: e.length,
+            flags: e.flags.names().collect(),
+        });
+    }
+    Ok(extents)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn extents(_file: &File) -> Result<Vec<Extent>> {
+    Err("listing extents is only supported on linux".into())
+}
+
+/// Print a `filefrag -v` style table of `file`'s extents
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn print_extents(file: &File) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let block_size = file.metadata()?.blksize();
+    println!("extents (in blocks of {} bytes):", block_size);
+    let stdout = std::io::stdout();
+    fs_sparse::fiemap::write_extent_table(file, block_size, stdout.lock())?;
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn print_extents(file: &File) -> Result<()> {
+    extents(file).map(drop)
+}
+
+impl Layout {
+    /// Look at the file at `path`, including its extents if `extents` is set, after syncing it
+    /// if `sync` is set
+    fn new(path: &Path, extents: bool, sync: bool) -> Result<Self> {
+        let file = File::open(path)?;
+        // a filesystem that can't report holes gets the same answer it would from `cp` or `tar`
+        let mut iter = SparseIter::from(&file).fallback_to_data();
+        if sync {
+            iter = iter.sync();
+        }
+        let ranges = SparseRangeIter::from(iter).collect::<std::io::Result<Vec<_>>>()?;
+        let size = allocated_size(&file)?;
+        let data = ranges
+            .iter()
+            .filter(|r| r.kind == ItemKind::Data)
+            .map(|r| r.end - r.start)
+            .sum();
+        Ok(Self {
+            path: path_string(path),
+            size: size.logical,
+            data,
+            holes: size.logical.saturating_sub(data),
+            allocated: size.allocated,
+            ranges,
+            extents: match extents {
+                true => Some(self::extents(&file)?),
+                false => None,
+            },
+        })
+    }
+
+    /// Share of the file that is holes, in percent
+    fn sparseness(&self) -> f64 {
+        match self.size {
+            0 => 0.0,
+            size => self.holes as f64 * 100.0 / size as f64,
+        }
+    }
+
+    fn print(&self) {
+        println!("{}:", self.path);
+        println!(
+            "{:>6}  {:4}  {:>16}  {:>16}  {:>16}",
+            "#", "kind", "start", "end", "length"
+        );
+        for (i, r) in self.ranges.iter().enumerate() {
+            println!(
+                "{:>6}  {:4}  {:>16}  {:>16}  {:>16}",
+                i,
+                kind_name(r.kind),
+                r.start,
+                r.end,
+                r.end - r.start
+            );
+        }
+        println!(
+            "size: {}  data: {}  holes: {} ({:.1}%)  allocated: {}",
+            self.size,
+            self.data,
+            self.holes,
+            self.sparseness(),
+            self.allocated
+        );
+    }
+}
+
+fn kind_name(kind: ItemKind) -> &'static str {
+    match kind {
+        ItemKind::Data => "data",
+        ItemKind::Hole => "hole",
+        _ => "?",
+    }
+}
+
+fn cmd_map(mut args: pico_args::Arguments, json: bool) -> Result<()> {
+    let verbose = args.contains(["-v", "--verbose"]);
+    let sync = args.contains("--sync");
+    let paths = args.free_os()?;
+    if paths.is_empty() {
+        return Err("map: no files given".into());
+    }
+
+    let mut layouts = Vec::new();
+    for (i, path) in paths.iter().enumerate() {
+        let path = Path::new(path);
+        let mut map = || -> Result<()> {
+            if json {
+                layouts.push(Layout::new(path, verbose, sync)?);
+                return Ok(());
+            }
+            if i > 0 {
+                println!();
+            }
+            Layout::new(path, false, sync)?.print();
+            if verbose {
+                print_extents(&File::open(path)?)?;
+            }
+            Ok(())
+        };
+        map().map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+
+    if json {
+        print_json(&layouts)?;
+    }
+    Ok(())
+}
+
+/// When to share storage between copies, as in GNU cp's `--reflink`
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reflink {
+    Always,
+    Auto,
+    Never,
+}
+
+#[cfg(unix)]
+impl std::str::FromStr for Reflink {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "always" => Ok(Reflink::Always),
+            "auto" => Ok(Reflink::Auto),
+            "never" => Ok(Reflink::Never),
+            _ => Err(format!("expected always, auto, or never, not {:?}", s)),
+        }
+    }
+}
+
+/// What `copy` did
+#[cfg(unix)]
+#[derive(Serialize)]
+struct Copied {
+    source: String,
+    destination: String,
+    cloned: bool,
+    copied: u64,
+}
+
+/// Copy `src` to `dst`, returning whether it was cloned and how many bytes were copied
+#[cfg(unix)]
+fn copy_file(src: &Path, dst: &Path, reflink: Reflink) -> Result<(bool, u64)> {
+    use fs_sparse::{clone_file, copy_sparse, copy_sparse_with_data, Error};
+    use std::fs::OpenOptions;
+    use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+
+    let src_file = File::open(src)?;
+
+    // macos can only clone into a new file
+    #[cfg(target_os = "macos")]
+    {
+        if reflink != Reflink::Never && !dst.exists() {
+            match fs_sparse::clone_file_path(src, dst) {
+                Ok(()) => return Ok((true, 0)),
+                Err(Error::Unsupported { .. }) if reflink == Reflink::Auto => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    let mode = src_file.metadata()?.permissions().mode();
+    let dst_file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .mode(mode)
+        .open(dst)?;
+
+    // not opened with `truncate(true)`, which would empty `src` too if they're the same file
+    let (a, b) = (src_file.metadata()?, dst_file.metadata()?);
+    if (a.dev(), a.ino()) == (b.dev(), b.ino()) {
+        return Err("they are the same file".into());
+    }
+    // like `cp`, start from an empty `dst`, so none of what it held before is left past the end
+    dst_file.set_len(0)?;
+
+    match reflink {
+        Reflink::Always => Ok(clone_file(&src_file, &dst_file).map(|()| (true, 0))?),
+        Reflink::Auto => match clone_file(&src_file, &dst_file) {
+            Ok(()) => Ok((true, 0)),
+            Err(Error::Unsupported { .. }) => Ok((false, copy_sparse(&src_file, &dst_file)?)),
+            Err(e) => Err(e.into()),
+        },
+        // `copy_sparse()` shares storage where the kernel's copy does, so read and write the
+        // data ourselves
+        Reflink::Never => Ok((
+            false,
+            copy_sparse_with_data(&src_file, &src_file, &dst_file)?,
+        )),
+    }
+}
+
+#[cfg(unix)]
+fn cmd_copy(mut args: pico_args::Arguments, json: bool) -> Result<()> {
+    // a bare `--reflink` first, so that it doesn't take the next argument as its value
+    let reflink = if args.contains("--reflink") {
+        Reflink::Always
+    } else {
+        args.opt_value_from_str("--reflink")?
+            .unwrap_or(Reflink::Auto)
+    };
+    let paths = args.free_os()?;
+    let (src, dst) = match paths.as_slice() {
+        [src, dst] => (Path::new(src), Path::new(dst)),
+        _ => return Err("copy: expected a source and a destination".into()),
+    };
+
+    let (cloned, copied) = copy_file(src, dst, reflink)
+        .map_err(|e| format!("copying {} to {}: {}", src.display(), dst.display(), e))?;
+    if json {
+        print_json(&Copied {
+            source: path_string(src),
+            destination: path_string(dst),
+            cloned,
+            copied,
+        })?;
+    }
+    Ok(())
+}
+
+/// Parse a size in bytes, which may end with a binary unit (`4K`, `1M`, ...)
+fn parse_size(s: &str) -> Result<u64, String> {
+    let (digits, shift) = match s.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => {
+            let shift = match c.to_ascii_uppercase() {
+                'K' => 10,
+                'M' => 20,
+                'G' => 30,
+                'T' => 40,
+                _ => return Err(format!("unknown unit in {:?}", s)),
+            };
+            (&s[..i], shift)
+        }
+        _ => (s, 0),
+    };
+    let n: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid size: {:?}", s))?;
+    n.checked_mul(1 << shift)
+        .ok_or_else(|| format!("size too large: {:?}", s))
+}
+
+/// Parse a range given as `<offset>:<length>`
+fn parse_range(s: &str) -> Result<(u64, u64), String> {
+    match s.split_once(':') {
+        Some((offset, len)) => Ok((parse_size(offset)?, parse_size(len)?)),
+        None => Err(format!("expected <offset>:<length>, not {:?}", s)),
+    }
+}
+
+/// What `punch` did
+#[derive(Serialize)]
+struct Punched {
+    path: String,
+    ranges: Vec<PunchedRange>,
+}
+
+#[derive(Serialize)]
+struct PunchedRange {
+    offset: u64,
+    length: u64,
+}
+
+fn cmd_punch(args: pico_args::Arguments, json: bool) -> Result<()> {
+    let mut free = args.free_os()?.into_iter();
+    let path = match free.next() {
+        Some(path) => path,
+        None => return Err("punch: no file given".into()),
+    };
+    let ranges = free
+        .map(|r| parse_range(&r.to_string_lossy()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if ranges.is_empty() {
+        return Err("punch: no ranges given".into());
+    }
+
+    let path = Path::new(&path);
+    let punch = || -> Result<()> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)?;
+        for &(offset, len) in &ranges {
+            fs_sparse::punch_hole(&file, offset, len)
+                .map_err(|e| format!("{}:{}: {}", offset, len, e))?;
+        }
+        Ok(())
+    };
+    punch().map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    if json {
+        print_json(&Punched {
+            path: path_string(path),
+            ranges: ranges
+                .into_iter()
+                .map(|(offset, length)| PunchedRange { offset, length })
+                .collect(),
+        })?;
+    }
+    Ok(())
+}
+
+/// What `dig` did (or would do) to a file
+#[cfg(unix)]
+#[derive(Serialize)]
+struct Dug {
+    path: String,
+    block_size: u64,
+    dry_run: bool,
+    scanned: u64,
+    punched: u64,
+    reclaimed: Option<u64>,
+}
+
+#[cfg(unix)]
+fn cmd_dig(mut args: pico_args::Arguments, json: bool) -> Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let block_size: Option<u64> = args.opt_value_from_fn("--block-size", parse_size)?;
+    let dry_run = args.contains("--dry-run");
+    let paths = args.free_os()?;
+    if paths.is_empty() {
+        return Err("dig: no files given".into());
+    }
+    if block_size == Some(0) {
+        return Err("dig: block size must be non-zero".into());
+    }
+
+    let mut dug = Vec::new();
+    for path in &paths {
+        let path = Path::new(path);
+        let dig = || -> Result<Dug> {
+            let file = std::fs::OpenOptions::new()
+                .read(true)
+                .write(!dry_run)
+                .open(path)?;
+            let block_size = match block_size {
+                Some(b) => b,
+                None => file.metadata()?.blksize(),
+            };
+
+            let s = if dry_run {
+                fs_sparse::sparsify_dry_run(&file, block_size)?
+            } else {
+                fs_sparse::sparsify(&file, block_size)?
+            };
+            Ok(Dug {
+                path: path_string(path),
+                block_size,
+                dry_run,
+                scanned: s.scanned,
+                punched: s.punched,
+                reclaimed: if dry_run { None } else { Some(s.reclaimed) },
+            })
+        };
+        let d = dig().map_err(|e| format!("{}: {}", path.display(), e))?;
+        if !json {
+            match d.reclaimed {
+                None => println!("{}: would punch {}", d.path, d.punched),
+                Some(reclaimed) => println!(
+                    "{}: scanned {}, punched {}, reclaimed {}",
+                    d.path, d.scanned, d.punched, reclaimed
+                ),
+            }
+        }
+        dug.push(d);
+    }
+
+    if json {
+        return print_json(&dug);
+    }
+    if dug.len() > 1 {
+        if dry_run {
+            let total: u64 = dug.iter().map(|d| d.punched).sum();
+            println!("total: would punch {}", total);
+        } else {
+            let total: u64 = dug.iter().filter_map(|d| d.reclaimed).sum();
+            println!("total: reclaimed {}", total);
+        }
+    }
+    Ok(())
+}
+
+fn cmd(mut args: pico_args::Arguments) -> Result<()> {
+    // before the command, so that `--json` may come first
+    let json = args.contains("--json");
+    match args.subcommand()?.as_deref() {
+        Some("map") => cmd_map(args, json),
+        Some("punch") => cmd_punch(args, json),
+        #[cfg(unix)]
+        Some("dig") => cmd_dig(args, json),
+        #[cfg(unix)]
+        Some("copy") => cmd_copy(args, json),
+        Some(other) => Err(format!("unknown command: {}", other).into()),
+        None => usage(1),
+    }
+}
+
+// NOTE: we don't use a `Result` return because it uses debug formatting and we want display
+// formatting for our errors.
 fn main() {
-    println!("Hello, world!");
+    let mut args = pico_args::Arguments::from_env();
+    if args.contains(["-h", "--help"]) {
+        usage(0)
+    }
+
+    if let Err(e) = cmd(args) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 }
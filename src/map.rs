@@ -0,0 +1,330 @@
+use crate::intervals;
+use crate::{AsFile, ItemKind, SparseIter, SparseRangeItem, SparseRangeIter, SparseStats};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::ops::{Index, Range};
+use std::path::Path;
+
+/// The complete layout of a file, collected from a single scan
+///
+/// The ranges are in order, don't overlap, and cover the entire file from offset 0 to the file's
+/// length (as observed durring the scan). Querying a `SparseMap` never touches the file again, so
+/// it won't notice if the file is later modified.
+///
+/// With the `serde` feature, a `SparseMap` serializes as a plain list of its ranges. Deserializing
+/// one doesn't check that the ranges follow the rules above.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct SparseMap {
+    ranges: Vec<SparseRangeItem>,
+}
+
+impl SparseMap {
+    /// Scan `file` and record every range of Data and Holes in it
+    pub fn from_file<F: AsFile>(file: F) -> io::Result<Self> {
+        let ranges = SparseRangeIter::from(SparseIter::from(file)).collect::<io::Result<_>>()?;
+        Ok(Self { ranges })
+    }
+
+    /// Build a map of a file `len` bytes long that has data only in `data`
+    ///
+    /// `data` holds `(offset, length)` pairs, which must be in order, not overlap, and end by
+    /// `len`. Empty ones are ignored.
+    pub(crate) fn from_data(data: &[(u64, u64)], len: u64) -> Self {
+        let mut ranges: Vec<SparseRangeItem> = Vec::new();
+        let mut push = |kind, start, end| match ranges.last_mut() {
+            Some(last) if last.kind == kind => last.end = end,
+            _ => ranges.push(SparseRangeItem { kind, start, end }),
+        };
+        let mut pos = 0;
+        for &(offset, size) in data.iter().filter(|&&(_, size)| size != 0) {
+            if offset > pos {
+                push(ItemKind::Hole, pos, offset);
+            }
+            push(ItemKind::Data, offset, offset + size);
+            pos = offset + size;
+        }
+        if len > pos {
+            push(ItemKind::Hole, pos, len);
+        }
+        Self { ranges }
+    }
+
+    /// Build a map of a file `len` bytes long that has data only in `data`
+    ///
+    /// `data` may be in any order and overlap (see [`intervals`](crate::intervals)). Any of it past
+    /// `len` is left out.
+    pub fn from_data_ranges(data: &[Range<u64>], len: u64) -> Self {
+        let data = intervals::intersect(data, std::slice::from_ref(&(0..len)));
+        let pairs: Vec<_> = data.iter().map(|r| (r.start, r.end - r.start)).collect();
+        Self::from_data(&pairs, len)
+    }
+
+    /// The Data ranges, in order
+    pub fn data_ranges(&self) -> Vec<Range<u64>> {
+        self.ranges
+            .iter()
+            .filter(|r| r.kind == ItemKind::Data)
+            .map(|r| r.start..r.end)
+            .collect()
+    }
+
+    /// Data wherever either map has data
+    ///
+    /// Like the other set operations, this treats each map as the set of its Data, and the part
+    /// of the shorter map past its end as a hole. The result is as long as the longer map.
+    pub fn union(&self, other: &SparseMap) -> SparseMap {
+        let data = intervals::union(&self.data_ranges(), &other.data_ranges());
+        self.combined(other, &data)
+    }
+
+    /// Data only where both maps have data
+    ///
+    /// [`from_data_ranges()`](Self::from_data_ranges) builds a map of a region of interest to
+    /// mask a map with.
+    pub fn intersect(&self, other: &SparseMap) -> SparseMap {
+        let data = intervals::intersect(&self.data_ranges(), &other.data_ranges());
+        self.combined(other, &data)
+    }
+
+    /// Data where this map has data and `other` has a hole (or has ended)
+    pub fn subtract(&self, other: &SparseMap) -> SparseMap {
+        let data = intervals::subtract(&self.data_ranges(), &other.data_ranges());
+        self.combined(other, &data)
+    }
+
+    /// Data where this map has holes, and holes where it has data
+    ///
+    /// The same as [`invert()`](Self::invert).
+    pub fn complement(&self) -> SparseMap {
+        self.invert()
+    }
+
+    /// Swap Data and Holes, across the whole length of the file
+    ///
+    /// This is the [`complement()`](Self::complement) of the map's Data. Combined with the other
+    /// set operations, it answers questions like "what has to be punched out of B to make its
+    /// holes match A's": `b.intersect(&a.invert())`, the data in B where A has holes (B's data
+    /// past the end of A isn't included).
+    pub fn invert(&self) -> SparseMap {
+        let ranges = self
+            .ranges
+            .iter()
+            .map(|r| SparseRangeItem {
+                kind: match r.kind {
+                    ItemKind::Data => ItemKind::Hole,
+                    _ => ItemKind::Data,
+                },
+                ..r.clone()
+            })
+            .collect();
+        Self { ranges }
+    }
+
+    /// A map as long as the longer of `self` and `other`, with data in `data`
+    fn combined(&self, other: &SparseMap, data: &[Range<u64>]) -> SparseMap {
+        Self::from_data_ranges(data, self.file_len().max(other.file_len()))
+    }
+
+    /// Iterate over the ranges in order
+    pub fn iter(&self) -> std::slice::Iter<'_, SparseRangeItem> {
+        self.ranges.iter()
+    }
+
+    /// The number of ranges
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// `true` if there are no ranges, ie: the file was empty
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Get the `index`th range
+    pub fn get(&self, index: usize) -> Option<&SparseRangeItem> {
+        self.ranges.get(index)
+    }
+
+    /// Find the range containing the byte at `offset`
+    ///
+    /// Returns `None` if `offset` is at or past the end of the file.
+    pub fn find(&self, offset: u64) -> Option<&SparseRangeItem> {
+        let i = self.ranges.partition_point(|r| r.end <= offset);
+        self.ranges.get(i)
+    }
+
+    /// The length of the file in bytes
+    pub fn file_len(&self) -> u64 {
+        self.ranges.last().map(|r| r.end).unwrap_or(0)
+    }
+
+    /// The total number of bytes in Data ranges
+    pub fn data_len(&self) -> u64 {
+        self.kind_len(ItemKind::Data)
+    }
+
+    /// The total number of bytes in Holes
+    pub fn hole_len(&self) -> u64 {
+        self.kind_len(ItemKind::Hole)
+    }
+
+    /// How much of the file is holes, as a fraction from 0 to 1 (0 for an empty file)
+    ///
+    /// `map.sparseness() > 0.9` is "is this file more than 90% holes?"
+    pub fn sparseness(&self) -> f64 {
+        self.fraction(self.hole_len())
+    }
+
+    /// How much of the file is data, as a fraction from 0 to 1 (0 for an empty file)
+    pub fn density(&self) -> f64 {
+        self.fraction(self.data_len())
+    }
+
+    /// The data and hole totals, as [`SparseStats`]
+    ///
+    /// The map doesn't record how much storage the file has, so `allocated_bytes` is `None`.
+    pub fn stats(&self) -> SparseStats {
+        SparseStats::from(self)
+    }
+
+    /// Find where `other` differs from this map
+    ///
+    /// Returns the ranges where the kind in `other` (`after`) isn't the kind in this map
+    /// (`before`), in order, with neighbours that changed the same way merged together. Where one
+    /// map is longer, the part past the end of the shorter one is included, with `None` for the
+    /// shorter map's kind.
+    pub fn diff(&self, other: &SparseMap) -> Vec<RangeDiff> {
+        let mut diffs: Vec<RangeDiff> = Vec::new();
+        let end = self.file_len().max(other.file_len());
+        let mut pos = 0;
+        while pos < end {
+            let (a, b) = (self.find(pos), other.find(pos));
+            // at least one of them reaches past `pos`
+            let next = a.iter().chain(&b).map(|r| r.end).min().unwrap();
+            let (before, after) = (a.map(|r| r.kind), b.map(|r| r.kind));
+            if before != after {
+                match diffs.last_mut() {
+                    Some(d) if d.end == pos && d.before == before && d.after == after => {
+                        d.end = next
+                    }
+                    _ => diffs.push(RangeDiff {
+                        start: pos,
+                        end: next,
+                        before,
+                        after,
+                    }),
+                }
+            }
+            pos = next;
+        }
+        diffs
+    }
+
+    /// Write the map to `w` in the JSON format of `qemu-img map --output=json`
+    ///
+    /// Tools that already parse qemu's output can read it unchanged. Each range is an object with
+    /// `start`, `length`, `zero`, and `data`, along with the fields qemu prints for a raw image
+    /// (`depth` 0, `present`, `compressed` false, and `offset`, which is the same as `start`).
+    /// Holes are `"zero": true, "data": false`, and Data is the opposite.
+    pub fn write_qemu_json<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let mut s = String::from("[");
+        for (i, r) in self.ranges.iter().enumerate() {
+            if i > 0 {
+                s.push_str(",\n");
+            }
+            let data = r.kind == ItemKind::Data;
+            s.push_str(&format!(
+                "{{ \"start\": {}, \"length\": {}, \"depth\": 0, \"present\": true, \
+                 \"zero\": {}, \"data\": {}, \"compressed\": false, \"offset\": {}}}",
+                r.start,
+                r.end - r.start,
+                !data,
+                data,
+                r.start
+            ));
+        }
+        s.push_str("]\n");
+        w.write_all(s.as_bytes())
+    }
+
+    fn fraction(&self, len: u64) -> f64 {
+        match self.file_len() {
+            0 => 0.0,
+            file_len => len as f64 / file_len as f64,
+        }
+    }
+
+    fn kind_len(&self, kind: ItemKind) -> u64 {
+        self.ranges
+            .iter()
+            .filter(|r| r.kind == kind)
+            .map(|r| r.end - r.start)
+            .sum()
+    }
+}
+
+/// Open the file at `path` and scan it into a [`SparseMap`]
+///
+/// The file is only opened for reading, which is all scanning needs on any platform. Symlinks are
+/// followed. See [`SparseMap::from_file()`] for how the scan is done.
+///
+/// On windows, the file is opened for synchronous I/O (scanning doesn't work through a handle
+/// opened with `FILE_FLAG_OVERLAPPED`), shared with anything else that has it open for reading,
+/// writing, or deleting, and with `FILE_FLAG_BACKUP_SEMANTICS`, so a backup tool holding
+/// `SeBackupPrivilege` can map files it otherwise couldn't read.
+pub fn map_path<P: AsRef<Path>>(path: P) -> io::Result<SparseMap> {
+    let mut options = OpenOptions::new();
+    options.read(true);
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        use winapi::um::winbase::FILE_FLAG_BACKUP_SEMANTICS;
+        use winapi::um::winnt::{FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE};
+
+        options
+            .share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE)
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS);
+    }
+    SparseMap::from_file(options.open(path)?)
+}
+
+/// A range that differs between two [`SparseMap`]s, from [`SparseMap::diff()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RangeDiff {
+    /// The byte offset where this range begins (including this offset)
+    pub start: u64,
+    /// The byte offset 1 after this range ends (ie: excluding this offset)
+    pub end: u64,
+    /// The kind of the range in the map `diff()` was called on, or `None` if it's past the end
+    pub before: Option<ItemKind>,
+    /// The kind of the range in the other map, or `None` if it's past the end
+    pub after: Option<ItemKind>,
+}
+
+impl Index<usize> for SparseMap {
+    type Output = SparseRangeItem;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.ranges[index]
+    }
+}
+
+impl<'a> IntoIterator for &'a SparseMap {
+    type Item = &'a SparseRangeItem;
+    type IntoIter = std::slice::Iter<'a, SparseRangeItem>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for SparseMap {
+    type Item = SparseRangeItem;
+    type IntoIter = std::vec::IntoIter<SparseRangeItem>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ranges.into_iter()
+    }
+}
@@ -0,0 +1,96 @@
+//! Memory mapping a file without touching its holes
+
+use crate::{AsFile, ItemKind, SparseMap};
+use std::io;
+use std::iter::FusedIterator;
+use std::ops::Range;
+
+/// A file mapped into memory, along with a [`SparseMap`] of where its data is
+///
+/// Reading a hole through a mapping faults in pages of zeros, one page at a time. Going through
+/// the whole mapping of a large, mostly empty file (a VM image, say) does that for every hole.
+/// [`MappedFile::data()`] instead hands out slices of only the `Data` ranges, so the holes are
+/// never touched.
+///
+/// The map is taken just before the file is mapped, and isn't updated. As with
+/// [`SparseIter::mmap_scan()`](crate::SparseIter::mmap_scan), if the file is truncated while it
+/// is mapped, or reading it fails, the process receives `SIGBUS` (or an exception, on windows)
+/// when that part of the mapping is read, and if it's modified, the slices change under you. Only
+/// use this on files that won't change.
+#[derive(Debug)]
+pub struct MappedFile {
+    /// `None` for an empty file, which can't be mapped
+    mmap: Option<memmap2::Mmap>,
+    map: SparseMap,
+}
+
+impl MappedFile {
+    /// Scan `file` for holes, then map all of it (read only)
+    pub fn new<F: AsFile>(file: F) -> io::Result<Self> {
+        let map = SparseMap::from_file(&file)?;
+        let mmap = if map.file_len() == 0 {
+            None
+        } else {
+            // Safety: the mapping is only ever read from. Truncation and modification are
+            // documented above.
+            #[cfg(unix)]
+            let mmap = unsafe { memmap2::Mmap::map(&file.as_fd())? };
+            #[cfg(windows)]
+            let mmap = unsafe { memmap2::Mmap::map(&file.as_handle())? };
+            Some(mmap)
+        };
+        Ok(Self { mmap, map })
+    }
+
+    /// Where the file had data and holes when it was mapped
+    pub fn map(&self) -> &SparseMap {
+        &self.map
+    }
+
+    /// The entire mapping, holes included
+    ///
+    /// Its length is the file's length when it was mapped, which may differ from
+    /// [`SparseMap::file_len()`] if the file changed between the scan and the mapping.
+    pub fn as_slice(&self) -> &[u8] {
+        self.mmap.as_deref().unwrap_or(&[])
+    }
+
+    /// Iterate over the `Data` ranges of the file, each with its contents
+    pub fn data(&self) -> MappedData<'_> {
+        MappedData {
+            ranges: self.map.iter(),
+            mapping: self.as_slice(),
+        }
+    }
+}
+
+/// Iterator over the `Data` ranges of a [`MappedFile`]
+///
+/// Created by [`MappedFile::data()`]. Each item is the contents of a range, and where it is in
+/// the file. Ranges that extend past the end of the mapping are cut short, and those entirely
+/// past it are skipped.
+#[derive(Debug, Clone)]
+pub struct MappedData<'a> {
+    ranges: std::slice::Iter<'a, crate::SparseRangeItem>,
+    mapping: &'a [u8],
+}
+
+impl<'a> Iterator for MappedData<'a> {
+    type Item = (&'a [u8], Range<u64>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let len = self.mapping.len() as u64;
+        for r in &mut self.ranges {
+            if r.kind != ItemKind::Data || r.start >= len {
+                continue;
+            }
+            // both are within the mapping, so they fit in a usize
+            let end = r.end.min(len);
+            let slice = &self.mapping[r.start as usize..end as usize];
+            return Some((slice, r.start..end));
+        }
+        None
+    }
+}
+
+impl FusedIterator for MappedData<'_> {}
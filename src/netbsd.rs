@@ -0,0 +1,3 @@
+// Not in the libc crate yet. NetBSD uses the same values as FreeBSD and solaris.
+pub const SEEK_DATA: i32 = 3;
+pub const SEEK_HOLE: i32 = 4;
@@ -0,0 +1,4 @@
+// OpenBSD doesn't support SEEK_DATA/SEEK_HOLE at all. Passing these makes `lseek()` fail with
+// EINVAL, which we treat like any other filesystem that can't report holes (see `Fallback`).
+pub const SEEK_DATA: i32 = 3;
+pub const SEEK_HOLE: i32 = 4;
@@ -0,0 +1,193 @@
+//! Scanning for zeros on several threads at once
+//!
+//! A read scan is a loop of `read()` and [`is_zero()`](crate::is_zero), one block at a time.
+//! Fast storage (NVMe, or a file already in the page cache) can deliver data faster than a single
+//! thread gets through that loop. The functions here split the file into segments, aligned to the
+//! block size, and scan them on a pool of threads, which give the same results as scanning on one.
+//!
+//! The pool is rayon's global pool, or one of a given number of threads created for the call and
+//! gone when it returns.
+
+use crate::error::Result;
+use crate::read_scan::{BlockScan, ReadScan};
+use crate::unix::{file_len, BorrowedFd};
+use crate::{
+    allocated_size, punch_hole, AsFile, ItemKind, SparseIter, SparseMap, SparseRangeIter,
+    SparseRangeIterExt, Sparsified,
+};
+use rayon::prelude::*;
+use std::io;
+use std::ops::Range;
+
+/// Bytes each thread scans at a time (rounded to the block size)
+const SEGMENT_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Like a [`read_scan()`](crate::SparseIter::read_scan) of all of `file`, collected into a
+/// [`SparseMap`], but using `threads` threads
+///
+/// With `threads` of 0, this runs on rayon's global pool (one thread per CPU, unless configured
+/// otherwise). Otherwise, a pool of `threads` threads is created for it.
+/// The file should not be modified while it is being scanned: each segment sees the file as it
+/// was when that segment was read.
+///
+/// # Panics
+///
+/// If `block_size` is 0
+pub fn read_scan_parallel<F: AsFile>(
+    file: F,
+    block_size: u64,
+    threads: usize,
+) -> io::Result<SparseMap> {
+    assert!(block_size > 0, "block size must be non-zero");
+    let fd = file.as_fd();
+    let len = file_len(fd)?;
+    let segments = split(0..len, block_size);
+
+    let results = for_each_segment(&segments, block_size, threads, |segment, scan| {
+        let mut data: Vec<(u64, u64)> = Vec::new();
+        let mut end = segment.start;
+        scan.seek(segment.start);
+        while scan.pos() < segment.end {
+            match scan.next_block(fd)? {
+                Some((ItemKind::Hole, _)) => {}
+                Some((_, start)) => match data.last_mut() {
+                    Some(last) if last.0 + last.1 == start => last.1 += scan.pos() - start,
+                    _ => data.push((start, scan.pos() - start)),
+                },
+                // truncated out from under us
+                None => break,
+            }
+            end = scan.pos();
+        }
+        Ok::<_, io::Error>((data, end))
+    })?;
+
+    // `SparseMap::from_data()` joins up runs of data that cross segments
+    let mut data = Vec::new();
+    let mut end = 0;
+    for (d, e) in results {
+        data.extend(d);
+        end = end.max(e);
+    }
+    Ok(SparseMap::from_data(&data, end))
+}
+
+/// Like [`sparsify()`](crate::sparsify), but reading (and punching holes) with `threads` threads
+///
+/// With `threads` of 0, this runs on rayon's global pool (one thread per CPU, unless configured
+/// otherwise). Otherwise, a pool of `threads` threads is created for it.
+/// Runs of zeroed blocks that cross from one segment into the next are punched out in two parts,
+/// which leaves the file the same as punching them all at once.
+///
+/// # Errors
+///
+/// The same as [`sparsify()`](crate::sparsify), or [`Error::Io`](crate::Error::Io) if the pool
+/// can't be created. After an error, threads stop at the end of the segment they are working on.
+///
+/// # Panics
+///
+/// If `block_size` is 0
+pub fn sparsify_parallel<F: AsFile>(
+    file: F,
+    block_size: u64,
+    threads: usize,
+) -> Result<Sparsified> {
+    assert!(block_size > 0, "block size must be non-zero");
+    let fd = file.as_fd();
+    let before = allocated_size(fd)?;
+
+    let mut segments = Vec::new();
+    for r in SparseRangeIter::from(SparseIter::from(fd).fallback_to_data()).data_only() {
+        let r = r?;
+        segments.extend(split(r.start..r.end, block_size));
+    }
+
+    let results = for_each_segment(&segments, block_size, threads, |segment, scan| {
+        let mut stats = Sparsified::default();
+        let mut zeros: Option<(u64, u64)> = None;
+        scan.seek(segment.start);
+        while scan.pos() < segment.end {
+            let start = match scan.next_block(fd)? {
+                Some((ItemKind::Hole, start)) => start,
+                Some((_, _)) => {
+                    if let Some((start, end)) = zeros.take() {
+                        stats.punched += punch(fd, start, end)?;
+                    }
+                    continue;
+                }
+                None => break,
+            };
+            zeros = match zeros {
+                Some((run, _)) => Some((run, scan.pos())),
+                None => Some((start, scan.pos())),
+            };
+        }
+        stats.scanned = scan.pos().min(segment.end) - segment.start;
+        if let Some((start, end)) = zeros {
+            stats.punched += punch(fd, start, end)?;
+        }
+        Ok::<_, crate::Error>(stats)
+    })?;
+
+    let mut stats = Sparsified::default();
+    for s in results {
+        stats.scanned += s.scanned;
+        stats.punched += s.punched;
+    }
+    stats.reclaimed = before
+        .allocated
+        .saturating_sub(allocated_size(fd)?.allocated);
+    Ok(stats)
+}
+
+/// Punch out `start..end`, returning its length
+fn punch(fd: BorrowedFd<'_>, start: u64, end: u64) -> Result<u64> {
+    punch_hole(fd, start, end - start).map(|()| end - start)
+}
+
+/// Split `range` into segments of about `SEGMENT_SIZE` bytes, each ending on a multiple of
+/// `block_size` (except the last)
+fn split(range: Range<u64>, block_size: u64) -> Vec<Range<u64>> {
+    let size = SEGMENT_SIZE.div_ceil(block_size) * block_size;
+    let mut segments = Vec::new();
+    let mut start = range.start;
+    while start < range.end {
+        let end = ((start / size + 1) * size).min(range.end);
+        segments.push(start..end);
+        start = end;
+    }
+    segments
+}
+
+/// Run `f` on each of `segments` across `threads` threads, returning the results in the order of
+/// the segments, or the first error
+fn for_each_segment<T, E, S>(
+    segments: &[Range<u64>],
+    block_size: u64,
+    threads: usize,
+    f: S,
+) -> std::result::Result<Vec<T>, E>
+where
+    T: Send,
+    E: Send + From<io::Error>,
+    S: Fn(Range<u64>, &mut ReadScan) -> std::result::Result<T, E> + Sync + Send,
+{
+    // each of the pool's threads reuses one `ReadScan` (and its buffer) for all its segments
+    let scan = || {
+        segments
+            .par_iter()
+            .map_init(
+                || ReadScan::new(block_size),
+                |scan, segment| f(segment.clone(), scan),
+            )
+            .collect()
+    };
+    match threads {
+        0 => scan(),
+        n => rayon::ThreadPoolBuilder::new()
+            .num_threads(n)
+            .build()
+            .map_err(io::Error::other)?
+            .install(scan),
+    }
+}
@@ -0,0 +1,100 @@
+//! Reserving space for a file before writing it
+
+use crate::error::{Error, Result};
+use crate::AsFile;
+use std::io;
+
+/// Allocate space for `file` to hold `len` bytes, extending it to `len` bytes if it is shorter
+///
+/// Writing into preallocated space can't fail for lack of room, and the allocation is more likely
+/// to be contiguous than one built up by many small writes. The file is never shortened. Space
+/// that is already allocated is kept. Preallocated space reads as zeros.
+///
+/// Note that preallocated ranges are not holes: [`SparseIter`](crate::SparseIter) usually reports
+/// them as `Data`.
+///
+///  - On linux and android, this uses `fallocate()` (with no flags).
+///  - On freebsd, this uses `posix_fallocate()`. ZFS doesn't support it.
+///  - On macos, this uses `fcntl(F_PREALLOCATE)`. It first asks for the space in a single
+///    contiguous piece, and if the filesystem can't find one, settles for space in several
+///    pieces. Use [`preallocate_contiguous()`] to insist on a single piece.
+///  - On windows, this sets the file's allocation size, then its length. This doesn't use
+///    `SetFileValidData()`, which needs special privileges and exposes whatever the disk held
+///    before, so the first write past the old end of the file still has to fill the space
+///    before it with zeros.
+///  - Everywhere else, this always fails with [`Error::Unsupported`].
+///
+/// # Errors
+///
+///  - [`Error::Unsupported`] if the platform or filesystem can't preallocate. Writing zeros
+///    instead would allocate the space on most filesystems, but not on ones that compress or
+///    deduplicate data.
+///  - [`Error::NotWritable`] if `file` isn't open for writing
+///  - [`Error::InvalidRange`] if `len` is larger than the largest offset a file may have
+///  - [`Error::Io`] if there isn't enough space, among other things
+pub fn preallocate<F: AsFile>(file: F, len: u64) -> Result<()> {
+    if len > i64::MAX as u64 {
+        return Err(Error::InvalidRange { offset: 0, len });
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let r = crate::linux::fallocate(file.as_fd(), 0, 0, len);
+
+    #[cfg(target_os = "freebsd")]
+    let r = crate::freebsd::posix_fallocate(file.as_fd(), len);
+
+    #[cfg(target_os = "macos")]
+    let r = {
+        let fd = file.as_fd();
+        match crate::macos::preallocate(fd, len, true) {
+            Err(ref e) if e.raw_os_error() == Some(libc::ENOSPC) => {
+                crate::macos::preallocate(fd, len, false)
+            }
+            r => r,
+        }
+    };
+
+    #[cfg(windows)]
+    let r = {
+        let handle = file.as_handle();
+        crate::windows::standard_info(handle).and_then(|info| {
+            let allocated = crate::windows::large_integer(&info.AllocationSize)?;
+            let file_len = crate::windows::large_integer(&info.EndOfFile)?;
+            if allocated < len {
+                crate::windows::set_allocation_size(handle, len)?;
+            }
+            if file_len < len {
+                crate::windows::set_len(handle, len)?;
+            }
+            Ok(())
+        })
+    };
+
+    #[cfg(not(any(
+        windows,
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "macos"
+    )))]
+    let r = {
+        let _ = file;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "preallocation is not supported on this platform",
+        ))
+    };
+
+    r.map_err(|e: io::Error| Error::from_errno(e, 0, len))
+}
+
+/// Like [`preallocate()`], but fail with `ENOSPC` unless the space can be allocated in a single
+/// contiguous piece
+#[cfg(target_os = "macos")]
+pub fn preallocate_contiguous<F: AsFile>(file: F, len: u64) -> Result<()> {
+    if len > i64::MAX as u64 {
+        return Err(Error::InvalidRange { offset: 0, len });
+    }
+
+    crate::macos::preallocate(file.as_fd(), len, true).map_err(|e| Error::from_errno(e, 0, len))
+}
@@ -0,0 +1,44 @@
+//! One-off questions about a single offset, without building an iterator
+
+use crate::{seek, SEEK_DATA, SEEK_HOLE};
+use crate::{AsFile, ItemKind};
+use std::io;
+
+#[cfg(windows)]
+use crate::AsFd;
+
+/// Is the byte at `offset` in `file` part of Data or a Hole?
+///
+/// This is a single probe. Like iteration, it may move the file's cursor.
+///
+/// Returns an error of kind `UnexpectedEof` if `offset` is at or past the end of the file.
+pub fn kind_at<F: AsFile>(file: F, offset: u64) -> io::Result<ItemKind> {
+    match seek(file.as_fd(), offset, SEEK_HOLE)? {
+        Some(hole) if hole == offset => Ok(ItemKind::Hole),
+        Some(_) => Ok(ItemKind::Data),
+        None => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "offset is at or past the end of the file",
+        )),
+    }
+}
+
+/// Find the start of the first Data at or after `offset`
+///
+/// Returns `None` if there is no more data in the file past `offset`, either because the rest of
+/// the file is a hole or because `offset` is at or past the end of the file.
+///
+/// This is a single probe. Like iteration, it may move the file's cursor.
+pub fn next_data_from<F: AsFile>(file: F, offset: u64) -> io::Result<Option<u64>> {
+    seek(file.as_fd(), offset, SEEK_DATA)
+}
+
+/// Find the start of the first Hole at or after `offset`
+///
+/// Every file has an implicit hole at its end, so if there are no real holes past `offset` this
+/// returns the length of the file. Returns `None` if `offset` is at or past the end of the file.
+///
+/// This is a single probe. Like iteration, it may move the file's cursor.
+pub fn next_hole_from<F: AsFile>(file: F, offset: u64) -> io::Result<Option<u64>> {
+    seek(file.as_fd(), offset, SEEK_HOLE)
+}
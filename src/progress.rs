@@ -0,0 +1,93 @@
+//! Reporting how far long operations have gotten, and stopping them early
+
+use crate::error::{Error, Result};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag that stops operations using it, from any thread
+///
+/// Clones share the same flag, so keep one and hand a clone to the [`Progress`] of the operation
+/// to cancel.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// A token that hasn't been cancelled yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop every operation using this token (or a clone of it)
+    ///
+    /// Operations notice the next time they report progress, and return [`Error::Cancelled`].
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Has [`cancel()`](Self::cancel) been called?
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Where a long operation reports its progress, and how it finds out it should stop
+///
+/// Operations that take one ([`copy_sparse_with_progress()`](crate::copy_sparse_with_progress),
+/// [`sparsify_with_progress()`](crate::sparsify_with_progress), and
+/// [`SparseScan`](crate::SparseScan)s built with
+/// [`SparseScanBuilder::progress()`](crate::SparseScanBuilder::progress)) report as they go: after
+/// each chunk copied, block examined, or range found. Each report first fails with
+/// [`Error::Cancelled`] if the [`CancelToken`] has been cancelled (without calling the callback),
+/// and otherwise calls the callback with the offset in the file reached so far and the length of
+/// the file. Holes are skipped over in a single step, so progress jumps forward across them.
+///
+/// The default reports to no one and can't be cancelled.
+#[derive(Clone, Default)]
+pub struct Progress {
+    callback: Option<Arc<dyn Fn(u64, u64) + Send + Sync>>,
+    cancel: Option<CancelToken>,
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Progress")
+            .field("callback", &self.callback.is_some())
+            .field("cancel", &self.cancel)
+            .finish()
+    }
+}
+
+impl Progress {
+    /// Report to no one, and never stop early
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `callback` with the bytes processed so far and the total, every time progress is made
+    ///
+    /// This is called often (up to once per block), so it should be quick.
+    pub fn on_progress<C: Fn(u64, u64) + Send + Sync + 'static>(mut self, callback: C) -> Self {
+        self.callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Stop with [`Error::Cancelled`] once `token` is cancelled
+    pub fn cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Report that `done` of `total` bytes have been processed, and check whether to stop
+    pub(crate) fn update(&self, done: u64, total: u64) -> Result<()> {
+        if let Some(ref cancel) = self.cancel {
+            if cancel.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+        }
+        if let Some(ref callback) = self.callback {
+            callback(done.min(total), total);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,171 @@
+//! Creating holes in existing files
+//!
+//! [`punch_hole()`] is available everywhere, but only does anything on platforms (and
+//! filesystems) that can deallocate part of a file. Elsewhere it fails with
+//! [`Error::Unsupported`], and [`punch_hole_or_zero()`] can be used to settle for writing zeros.
+
+use crate::error::{Error, Result};
+use crate::AsFile;
+use std::io;
+
+/// How [`punch_hole_or_zero()`] zeroed a range
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zeroed {
+    /// The range was deallocated, and is now a hole
+    Punched,
+    /// Hole punching isn't supported, so zeros were written over the range instead
+    Written,
+}
+
+/// Deallocate `len` bytes of `file` starting at `offset`, turning them into a hole
+///
+/// The range reads back as zeros afterwards. The length of the file never changes: any part of
+/// the range past the end of the file is ignored.
+///
+/// Filesystems only deallocate whole blocks (or clusters). Partial blocks at either end of the
+/// range are zeroed but stay allocated.
+///
+///  - On linux and android, this uses `fallocate(FALLOC_FL_PUNCH_HOLE | FALLOC_FL_KEEP_SIZE)`.
+///  - On windows, this uses `FSCTL_SET_ZERO_DATA`. Files must be marked sparse for it to
+///    deallocate anything (otherwise it just writes zeros), so the file is marked sparse first if
+///    it isn't already (see [`set_sparse()`](crate::set_sparse)).
+///  - On macos, this uses `fcntl(F_PUNCHHOLE)`, which only accepts block aligned ranges. The
+///    aligned part of the range is punched, and the partial blocks around it are overwritten with
+///    zeros.
+///  - Everywhere else, this always fails with [`Error::Unsupported`].
+///
+/// # Errors
+///
+///  - [`Error::Unsupported`] if the platform or filesystem can't punch holes. Nothing has been
+///    written in this case, so falling back to writing zeros is safe (see
+///    [`punch_hole_or_zero()`]).
+///  - [`Error::NotWritable`] if `file` isn't open for writing
+///  - [`Error::InvalidRange`] if the range ends past the largest offset a file may have
+pub fn punch_hole<F: AsFile>(file: F, offset: u64, len: u64) -> Result<()> {
+    let end = check_range(offset, len)?;
+    if len == 0 {
+        return Ok(());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let r = crate::linux::fallocate(
+        file.as_fd(),
+        libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+        offset,
+        end - offset,
+    );
+
+    #[cfg(windows)]
+    let r = {
+        let handle = file.as_handle();
+        crate::is_marked_sparse(handle)
+            .and_then(|sparse| {
+                if sparse {
+                    Ok(())
+                } else {
+                    crate::windows::set_sparse(handle, true)
+                }
+            })
+            .and_then(|_| crate::windows::set_zero_data(handle, offset, end))
+    };
+
+    #[cfg(target_os = "macos")]
+    let r = crate::macos::punch_hole(file.as_fd(), offset, end - offset);
+
+    #[cfg(not(any(
+        windows,
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos"
+    )))]
+    let r = {
+        let _ = (file, end);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "hole punching is not supported on this platform",
+        ))
+    };
+
+    r.map_err(|e| Error::from_errno(e, offset, len))
+}
+
+/// Like [`punch_hole()`], but if holes can't be punched in `file`, write zeros over the range
+///
+/// Either way, the range reads back as zeros afterwards and the length of the file is
+/// unchanged. Writing zeros allocates space for the whole range, so this is only a good idea when
+/// reading zeros back matters more than saving space.
+pub fn punch_hole_or_zero<F: AsFile>(file: F, offset: u64, len: u64) -> Result<Zeroed> {
+    match punch_hole(&file, offset, len) {
+        Ok(()) => return Ok(Zeroed::Punched),
+        Err(Error::Unsupported { .. }) => {}
+        Err(e) => return Err(e),
+    }
+
+    write_zeros(file, offset, len).map_err(|e| Error::from_errno(e, offset, len))?;
+    Ok(Zeroed::Written)
+}
+
+/// Write zeros over the part of `offset..offset + len` that is inside the file
+fn write_zeros<F: AsFile>(file: F, offset: u64, len: u64) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let fd = file.as_fd();
+        let end = (offset + len).min(crate::unix::file_len(fd)?);
+        if offset < end {
+            crate::unix::write_zeros(fd, offset, end - offset)?;
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        let handle = file.as_handle();
+        let info = crate::windows::standard_info(handle)?;
+        let file_len = crate::windows::large_integer(&info.EndOfFile)?;
+        let end = (offset + len).min(file_len);
+        if offset < end {
+            crate::windows::write_zeros(handle, offset, end - offset)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that `offset..offset + len` is a range a file could have, returning its end
+pub(crate) fn check_range(offset: u64, len: u64) -> Result<u64> {
+    match offset.checked_add(len) {
+        Some(end) if end <= i64::MAX as u64 => Ok(end),
+        _ => Err(Error::InvalidRange { offset, len }),
+    }
+}
+
+/// Zero `len` bytes of `file` starting at `offset`, without writing the zeros
+///
+/// This uses `fallocate(FALLOC_FL_ZERO_RANGE | FALLOC_FL_KEEP_SIZE)`. Unlike
+/// [`punch_hole()`], the range stays allocated (as unwritten extents where the filesystem
+/// supports them, which read as zeros), so writing to it later can't fail for lack of space.
+/// [`SparseIter`](crate::SparseIter) reports unwritten extents as `Data` on most filesystems.
+/// Partial blocks at either end of the range are zeroed by writing to them.
+///
+/// Like [`punch_hole()`], the length of the file never changes. Support depends on the
+/// filesystem (ext4, xfs, and f2fs support it; btrfs and tmpfs don't).
+///
+/// # Errors
+///
+///  - [`Error::Unsupported`] if the filesystem can't zero ranges
+///  - [`Error::NotWritable`] if `file` isn't open for writing
+///  - [`Error::InvalidRange`] if the range ends past the largest offset a file may have
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn zero_range<F: AsFile>(file: F, offset: u64, len: u64) -> Result<()> {
+    check_range(offset, len)?;
+    if len == 0 {
+        return Ok(());
+    }
+
+    crate::linux::fallocate(
+        file.as_fd(),
+        libc::FALLOC_FL_ZERO_RANGE | libc::FALLOC_FL_KEEP_SIZE,
+        offset,
+        len,
+    )
+    .map_err(|e| Error::from_errno(e, offset, len))
+}
@@ -0,0 +1,242 @@
+//! Find holes by reading the file and looking for blocks of zeros
+//!
+//! This works on any file that can be read, no matter what the filesystem supports, at the cost
+//! of reading the whole file. Like GNU tar's fallback detection, "holes" found this way are just
+//! blocks of zeros: they may or may not be holes as far as the filesystem is concerned.
+//!
+//! With the `mmap` feature, the file may instead be mapped into memory and examined there. With
+//! the `io-uring` feature on Linux, blocks may instead be read a batch at a time through io_uring.
+
+use crate::is_zero;
+use crate::ItemKind;
+use crate::{pread, BorrowedFd};
+use std::convert::TryFrom;
+use std::io;
+
+/// A backend that classifies a file one block at a time
+pub(crate) trait BlockScan {
+    /// Continue scanning from `offset`
+    fn seek(&mut self, offset: u64);
+
+    /// The offset the next block starts at
+    fn pos(&self) -> u64;
+
+    /// Classify the next block, which runs from the current position to the next block boundary
+    /// (or the end of the file)
+    ///
+    /// Returns the block's kind and its starting offset, or `None` at the end of the file
+    fn next_block(&mut self, fd: BorrowedFd<'_>) -> io::Result<Option<(ItemKind, u64)>>;
+
+    /// At most how many more blocks there are, if that's known
+    fn blocks_left(&self) -> Option<usize> {
+        None
+    }
+
+    /// Don't read the file past `end`, treating it as the end of the file
+    ///
+    /// Only backends that read the file's contents need to do anything about this.
+    fn read_until(&mut self, _end: u64) {}
+}
+
+/// The state of a read scan: where we are, and a buffer to read into
+#[derive(Debug)]
+pub(crate) struct ReadScan {
+    block_size: u64,
+    /// The next unread offset
+    pos: u64,
+    /// Where to stop reading, if before the end of the file
+    end: u64,
+    buf: Vec<u8>,
+    #[cfg_attr(not(any(feature = "mmap", feature = "io-uring")), allow(dead_code))]
+    source: Source,
+}
+
+/// Where a [`ReadScan`] gets the file's contents from
+#[derive(Debug)]
+enum Source {
+    /// `pread()` into `ReadScan::buf`
+    Read,
+    /// A mapping of the file, created on the first read
+    #[cfg(feature = "mmap")]
+    Mmap(Option<memmap2::Mmap>),
+    /// Batches of reads through io_uring
+    #[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+    Uring(Box<crate::uring::UringReader>),
+}
+
+/// A clone continues from the same place, but sets up its own mapping or ring (on its first read)
+impl Clone for ReadScan {
+    fn clone(&self) -> Self {
+        let source = match self.source {
+            Source::Read => Source::Read,
+            #[cfg(feature = "mmap")]
+            Source::Mmap(_) => Source::Mmap(None),
+            #[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+            Source::Uring(_) => {
+                Source::Uring(Box::new(crate::uring::UringReader::new(self.block_size)))
+            }
+        };
+        Self {
+            block_size: self.block_size,
+            pos: self.pos,
+            end: self.end,
+            buf: Vec::new(),
+            source,
+        }
+    }
+}
+
+impl ReadScan {
+    pub(crate) fn new(block_size: u64) -> Self {
+        assert!(block_size > 0, "block size must be non-zero");
+        Self {
+            block_size,
+            pos: 0,
+            end: u64::MAX,
+            buf: Vec::new(),
+            source: Source::Read,
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    pub(crate) fn mmap(block_size: u64) -> Self {
+        Self {
+            source: Source::Mmap(None),
+            ..Self::new(block_size)
+        }
+    }
+
+    #[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+    pub(crate) fn uring(block_size: u64) -> Self {
+        Self {
+            source: Source::Uring(Box::new(crate::uring::UringReader::new(block_size))),
+            ..Self::new(block_size)
+        }
+    }
+}
+
+impl BlockScan for ReadScan {
+    fn seek(&mut self, offset: u64) {
+        self.pos = offset;
+    }
+
+    fn pos(&self) -> u64 {
+        self.pos
+    }
+
+    fn read_until(&mut self, end: u64) {
+        self.end = end;
+    }
+
+    fn blocks_left(&self) -> Option<usize> {
+        // without an end, we read until the file does, however long it is by then
+        if self.end == u64::MAX {
+            return None;
+        }
+        blocks_between(self.pos, self.end, self.block_size)
+    }
+
+    fn next_block(&mut self, fd: BorrowedFd<'_>) -> io::Result<Option<(ItemKind, u64)>> {
+        let start = self.pos;
+        if start >= self.end {
+            return Ok(None);
+        }
+        let len = (self.block_size - start % self.block_size).min(self.end - start);
+
+        #[cfg(feature = "mmap")]
+        {
+            if let Source::Mmap(ref mut map) = self.source {
+                return next_mapped_block(map, fd, start, len, &mut self.pos);
+            }
+        }
+
+        #[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+        {
+            if let Source::Uring(ref mut reader) = self.source {
+                let block = reader.read(fd, start, len, self.end)?;
+                if block.is_empty() {
+                    return Ok(None);
+                }
+                self.pos += block.len() as u64;
+                let kind = if is_zero(block) {
+                    ItemKind::Hole
+                } else {
+                    ItemKind::Data
+                };
+                return Ok(Some((kind, start)));
+            }
+        }
+
+        // `len` is at most `block_size`, which must already fit in memory
+        self.buf.resize(len as usize, 0);
+
+        let mut filled = 0;
+        while filled < self.buf.len() {
+            match pread(fd, &mut self.buf[filled..], start + filled as u64) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        if filled == 0 {
+            return Ok(None);
+        }
+
+        self.pos += filled as u64;
+        let kind = if is_zero(&self.buf[..filled]) {
+            ItemKind::Hole
+        } else {
+            ItemKind::Data
+        };
+        Ok(Some((kind, start)))
+    }
+}
+
+/// How many blocks of `block_size` cover `start..end`, counting partial ones at either end, if
+/// that fits in a `usize`
+pub(crate) fn blocks_between(start: u64, end: u64, block_size: u64) -> Option<usize> {
+    if start >= end {
+        return Some(0);
+    }
+    let first = crate::align_down(start, block_size);
+    usize::try_from((end - first).div_ceil(block_size)).ok()
+}
+
+#[cfg(feature = "mmap")]
+fn next_mapped_block(
+    map: &mut Option<memmap2::Mmap>,
+    fd: BorrowedFd<'_>,
+    start: u64,
+    len: u64,
+    pos: &mut u64,
+) -> io::Result<Option<(ItemKind, u64)>> {
+    if map.is_none() {
+        if crate::file_len(fd)? == 0 {
+            // can't map an empty file
+            return Ok(None);
+        }
+
+        // Safety: the mapping is only ever read from. If the file is truncated while mapped,
+        // reading past the new end raises SIGBUS, which is documented on `mmap_scan()`.
+        *map = Some(unsafe { memmap2::Mmap::map(&fd)? });
+    }
+    let map = map.as_ref().unwrap();
+
+    let map_len = map.len() as u64;
+    if start >= map_len {
+        return Ok(None);
+    }
+
+    // both are within the mapping, so they fit in a usize
+    let end = (start + len).min(map_len);
+    let block = &map[start as usize..end as usize];
+    *pos = end;
+    let kind = if is_zero(block) {
+        ItemKind::Hole
+    } else {
+        ItemKind::Data
+    };
+    Ok(Some((kind, start)))
+}
@@ -0,0 +1,224 @@
+//! Reading a file without reading its holes
+
+use crate::unix::{pread, BorrowedFd};
+use crate::{AsFile, ItemKind, ReadAt, SparseIter, SparseRangeItem, SparseRangeIter};
+use std::io::{self, Read};
+use std::iter::FusedIterator;
+
+/// A [`Read`] over a whole file that only reads its `Data`, and makes up the zeros in its holes
+///
+/// This reads the same bytes as reading the file normally would, but never asks the filesystem
+/// for the contents of a hole. That saves the I/O (and, on some filesystems, the work of
+/// producing zeros) when streaming a sparse file into something that needs every byte, like a
+/// compressor or a socket.
+///
+/// Reading starts where the [`SparseIter`] it is created from starts (the start of the file for
+/// [`SparseReader::new()`]), uses positioned reads, and never moves the file's cursor (unless the
+/// `SparseIter` does, while looking for holes). It ends at the length the file had when the last
+/// hole was found: if the file is modified while being read, the result is a mix of old and new.
+///
+/// If finding holes fails, that error is returned from `read()` and every later `read()`
+/// returns 0, as at the end of the file.
+///
+/// The data normally comes from the file being scanned, but [`SparseReader::with_data()`] takes
+/// it from any [`ReadAt`] instead, such as a cache or a copy of the file elsewhere, while holes
+/// are still found in the file itself.
+#[derive(Debug)]
+pub struct SparseReader<F, D = SameFile> {
+    ranges: SparseRangeIter<F>,
+    data: D,
+    /// The range being read, if there is one
+    range: Option<SparseRangeItem>,
+    /// The offset the next read starts at
+    pos: u64,
+}
+
+/// The data of a [`SparseReader`] comes from the file it scans
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SameFile;
+
+/// Where a [`SparseReader`] gets data from: [`SameFile`], or any [`ReadAt`]
+pub trait ReadData<F> {
+    /// Read into `buf` from `offset` in the data for `file`
+    fn read_data(&self, file: &F, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+}
+
+impl<F: AsFile> ReadData<F> for SameFile {
+    fn read_data(&self, file: &F, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        pread(file.as_fd(), buf, offset)
+    }
+}
+
+impl<F, D: ReadAt> ReadData<F> for D {
+    fn read_data(&self, _file: &F, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.read_at(buf, offset)
+    }
+}
+
+impl<F: AsFile> SparseReader<F> {
+    /// Read all of `file`
+    ///
+    /// If the filesystem can't report holes, the whole file is read (see
+    /// [`SparseIter::fallback_to_data()`]).
+    pub fn new(file: F) -> Self {
+        SparseIter::from(file).fallback_to_data().into()
+    }
+}
+
+impl<F: AsFile, D: ReadAt> SparseReader<F, D> {
+    /// Find holes in the file `iter` is over, but read its data from the same offsets in `data`
+    pub fn with_data(iter: SparseIter<F>, data: D) -> Self {
+        Self {
+            ranges: iter.into(),
+            data,
+            range: None,
+            pos: 0,
+        }
+    }
+}
+
+impl<F, D> SparseReader<F, D> {
+    /// The offset the next byte read comes from
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+}
+
+impl<F: AsFile> From<SparseIter<F>> for SparseReader<F> {
+    /// Read the file `iter` is over, finding holes the way it does
+    fn from(iter: SparseIter<F>) -> Self {
+        Self {
+            ranges: iter.into(),
+            data: SameFile,
+            range: None,
+            pos: 0,
+        }
+    }
+}
+
+impl<F: AsFile, D: ReadData<F>> Read for SparseReader<F, D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let range = loop {
+            match self.range {
+                Some(ref r) if self.pos < r.end => break r.clone(),
+                _ => {}
+            }
+
+            match self.ranges.next() {
+                Some(r) => {
+                    let r = r?;
+                    self.pos = self.pos.max(r.start);
+                    self.range = Some(r);
+                }
+                None => return Ok(0),
+            }
+        };
+
+        let want = (range.end - self.pos).min(buf.len() as u64) as usize;
+        let n = match range.kind {
+            ItemKind::Data => {
+                // 0 means the data was truncated out from under us, so this is the end
+                self.data
+                    .read_data(self.ranges.inner.get_ref(), &mut buf[..want], self.pos)?
+            }
+            _ => {
+                buf[..want].fill(0);
+                want
+            }
+        };
+
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+/// Iterate over the `Data` ranges of a file, each with a [`Read`] over its contents
+///
+/// Each item is the offset the range starts at, and an [`ExtentReader`] that reads the range
+/// (and nothing else). Backup tools can use this to process only the parts of a file that hold
+/// data, recording where each one belongs. Holes are skipped entirely. If the filesystem can't
+/// report holes, the whole file is a single `Data` range (see
+/// [`SparseIter::fallback_to_data()`]).
+///
+/// The readers borrow the file, not the iterator, so they may be kept around (and read in any
+/// order) while iteration continues. Like [`SparseReader`], they use positioned reads.
+#[derive(Debug)]
+pub struct DataExtents<'a> {
+    ranges: SparseRangeIter<BorrowedFd<'a>>,
+}
+
+impl<'a> DataExtents<'a> {
+    /// Iterate over the `Data` ranges of `file`
+    pub fn new<F: AsFile + ?Sized>(file: &'a F) -> Self {
+        SparseIter::from(file.as_fd()).fallback_to_data().into()
+    }
+}
+
+impl<'a> From<SparseIter<BorrowedFd<'a>>> for DataExtents<'a> {
+    /// Iterate over the `Data` ranges `iter` finds
+    fn from(iter: SparseIter<BorrowedFd<'a>>) -> Self {
+        Self {
+            ranges: iter.into(),
+        }
+    }
+}
+
+impl<'a> Iterator for DataExtents<'a> {
+    type Item = io::Result<(u64, ExtentReader<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // `SparseRangeIter` is fused on error, so we are too
+            let r = match self.ranges.next()? {
+                Ok(r) => r,
+                Err(e) => return Some(Err(e)),
+            };
+            if r.kind == ItemKind::Data && r.start < r.end {
+                let reader = ExtentReader {
+                    fd: *self.ranges.inner.get_ref(),
+                    pos: r.start,
+                    end: r.end,
+                };
+                return Some(Ok((r.start, reader)));
+            }
+        }
+    }
+}
+
+impl FusedIterator for DataExtents<'_> {}
+
+/// Reads a single `Data` range of a file
+///
+/// Created by [`DataExtents`]. Reading stops at the end of the range, or earlier if the file
+/// has been truncated since the range was found.
+#[derive(Debug, Clone)]
+pub struct ExtentReader<'a> {
+    fd: BorrowedFd<'a>,
+    /// The offset the next read starts at
+    pos: u64,
+    end: u64,
+}
+
+impl ExtentReader<'_> {
+    /// The offset the next byte read comes from
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// How many bytes of the range are left to read
+    pub fn remaining(&self) -> u64 {
+        self.end - self.pos
+    }
+}
+
+impl Read for ExtentReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let want = self.remaining().min(buf.len() as u64) as usize;
+        if want == 0 {
+            return Ok(0);
+        }
+        let n = pread(self.fd, &mut buf[..want], self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
@@ -0,0 +1,124 @@
+//! Scanning a file backwards, from its end
+
+use crate::unix::file_len;
+use crate::{AsFile, SparseIter, SparseRangeItem, SparseRangeIter};
+use std::io;
+use std::iter::FusedIterator;
+
+/// The size of the first window scanned, at the end of the file
+const FIRST_WINDOW: u64 = 1024 * 1024;
+
+/// Windows double in size up to this, so finding data far from the end takes few probes without
+/// holding too many ranges at once
+const MAX_WINDOW: u64 = 1024 * 1024 * 1024;
+
+/// Iterate over the ranges of `file` from its end back to its start
+///
+/// The ranges are the same as a [`SparseRangeIter`]'s, in reverse order, so the first `Data`
+/// range returned ends at the highest offset holding data: `rscan(&file).data_only().next()`
+/// finds it without scanning everything before it, as is wanted when trimming trailing space.
+///
+/// `SEEK_DATA` and `SEEK_HOLE` only look forwards, so this scans windows of the file (starting at
+/// 1 MiB at the end of the file, and doubling) forwards, then returns their ranges backwards.
+/// Like a [`SparseIter`], this errors if the filesystem can't report holes, may move the file's
+/// cursor, and stops after returning an error. The file's length is read on the first call to
+/// `next()`, and changes after that aren't noticed.
+///
+/// Adapters that assume ranges are in order, like
+/// [`coalesce()`](crate::SparseRangeIterExt::coalesce), don't work on this.
+pub fn rscan<F: AsFile>(file: F) -> RevRangeIter<F> {
+    RevRangeIter {
+        file,
+        found: Vec::new(),
+        lo: None,
+        window: FIRST_WINDOW,
+        done: false,
+    }
+}
+
+/// Iterates over the ranges of a file from its end, returned by [`rscan()`]
+#[derive(Debug)]
+pub struct RevRangeIter<F> {
+    file: F,
+    /// Ranges scanned but not returned yet, in order: the next one returned is the last
+    ///
+    /// The first may continue before `lo`, so it's only returned once that's been scanned.
+    found: Vec<SparseRangeItem>,
+    /// Where the scanned part of the file starts, once the length is known
+    lo: Option<u64>,
+    window: u64,
+    done: bool,
+}
+
+impl<F: AsFile> RevRangeIter<F> {
+    /// Get back the file this iterator was created from
+    pub fn into_inner(self) -> F {
+        self.file
+    }
+
+    /// Scan the window before `lo`, adding its ranges before those already found
+    fn scan_window(&mut self, lo: u64) -> io::Result<()> {
+        let start = lo.saturating_sub(self.window);
+        self.window = (self.window * 2).min(MAX_WINDOW);
+
+        let mut window = Vec::new();
+        for r in SparseRangeIter::from(SparseIter::starting_at(self.file.as_fd(), start)) {
+            let mut r = r?;
+            if r.start >= lo {
+                break;
+            }
+            r.end = r.end.min(lo);
+            window.push(r);
+        }
+
+        // the range that was cut off at `lo` continues in this window
+        if let (Some(last), Some(first)) = (window.last(), self.found.first_mut()) {
+            if last.kind == first.kind {
+                first.start = last.start;
+                window.pop();
+            }
+        }
+        window.append(&mut self.found);
+        self.found = window;
+        self.lo = Some(start);
+        Ok(())
+    }
+}
+
+impl<F: AsFile> Iterator for RevRangeIter<F> {
+    type Item = io::Result<SparseRangeItem>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        loop {
+            let lo = match self.lo {
+                Some(lo) => lo,
+                None => match file_len(self.file.as_fd()) {
+                    Ok(len) => len,
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(e));
+                    }
+                },
+            };
+
+            if self.found.len() > 1 || (lo == 0 && !self.found.is_empty()) {
+                return self.found.pop().map(Ok);
+            }
+            if lo == 0 {
+                self.done = true;
+                return None;
+            }
+
+            if let Err(e) = self.scan_window(lo) {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+impl<F: AsFile> FusedIterator for RevRangeIter<F> {}
@@ -0,0 +1,351 @@
+//! Choosing how to find holes, instead of relying on the default
+//!
+//! [`SparseIter`] uses `SEEK_DATA`/`SEEK_HOLE` unless told otherwise, and only finds out whether
+//! that works on the first call to `next()`. [`SparseScan::builder()`] instead takes an ordered
+//! list of backends, checks which ones the file's filesystem supports up front, and uses the
+//! first that works. The chosen backend is available from [`SparseScan::backend()`], so the same
+//! builder behaves the same way on every machine that has the same filesystems.
+//!
+//! The builder also holds every other option for a scan (where it starts and ends, which holes it
+//! reports, whether they're checked), so new ones are added to it rather than as new ways to
+//! construct a scan.
+
+use crate::adapters::{Aligned, MinHoleSize, SparseRangeIterExt};
+use crate::{AsFile, Progress, SparseIter, SparseRangeItem, SparseRangeIter};
+use std::io;
+use std::iter::FusedIterator;
+
+#[cfg(unix)]
+use crate::verify::{HoleCheck, HoleVerifier};
+#[cfg(unix)]
+use crate::ItemKind;
+
+use crate::{file_len, is_unsupported, seek, BorrowedFd};
+
+#[cfg(windows)]
+use crate::AsFd;
+
+/// A way of finding holes, for [`SparseScanBuilder::backends()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanBackend {
+    /// `lseek(SEEK_DATA)` and `lseek(SEEK_HOLE)` (what [`SparseIter`] uses by default)
+    SeekHole,
+    /// The `FS_IOC_FIEMAP` ioctl (see [`SparseIter::fiemap()`])
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    Fiemap,
+    /// The `FIBMAP` ioctl (see [`SparseIter::fibmap()`])
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    Fibmap,
+    /// Read the file looking for zeroed blocks (see [`SparseIter::read_scan()`])
+    ///
+    /// This is always supported, so backends listed after it are never used.
+    ReadScan {
+        /// Size of the blocks to examine, which must be non-zero
+        block_size: u64,
+    },
+    /// Map the file and look for zeroed blocks (see [`SparseIter::mmap_scan()`])
+    ///
+    /// This is always supported, so backends listed after it are never used.
+    #[cfg(feature = "mmap")]
+    MmapScan {
+        /// Size of the blocks to examine, which must be non-zero
+        block_size: u64,
+    },
+    /// Read the file through io_uring looking for zeroed blocks (see [`SparseIter::uring_scan()`])
+    ///
+    /// This is supported wherever io_uring can be set up, and then backends listed after it are
+    /// never used.
+    #[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+    UringScan {
+        /// Size of the blocks to examine, which must be non-zero
+        block_size: u64,
+    },
+}
+
+impl ScanBackend {
+    /// Can this backend be used on `fd`?
+    ///
+    /// Errors that don't just mean "unsupported" are returned.
+    fn supported(self, fd: BorrowedFd<'_>, start: u64) -> io::Result<bool> {
+        match self {
+            ScanBackend::SeekHole => {
+                // SEEK_CUR never returns ENXIO
+                let cursor = seek(fd, 0, libc::SEEK_CUR)?.unwrap();
+                let r = seek(fd, start, crate::SEEK_DATA);
+                seek(fd, cursor, libc::SEEK_SET)?;
+                match r {
+                    Ok(_) => Ok(true),
+                    Err(ref e) if is_unsupported(e) => Ok(false),
+                    Err(e) => Err(e),
+                }
+            }
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            ScanBackend::Fiemap => crate::fiemap::supported(fd),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            ScanBackend::Fibmap => crate::fibmap::supported(fd),
+            ScanBackend::ReadScan { .. } => Ok(true),
+            #[cfg(feature = "mmap")]
+            ScanBackend::MmapScan { .. } => Ok(true),
+            #[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+            ScanBackend::UringScan { .. } => match crate::uring::Ring::new(1) {
+                Ok(_) => Ok(true),
+                Err(ref e) if is_unsupported(e) || e.raw_os_error() == Some(libc::EPERM) => {
+                    Ok(false)
+                }
+                Err(e) => Err(e),
+            },
+        }
+    }
+
+    /// The size of the blocks this backend examines, for those that read the file
+    fn block_size(self) -> Option<u64> {
+        match self {
+            ScanBackend::ReadScan { block_size } => Some(block_size),
+            #[cfg(feature = "mmap")]
+            ScanBackend::MmapScan { block_size } => Some(block_size),
+            #[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+            ScanBackend::UringScan { block_size } => Some(block_size),
+            _ => None,
+        }
+    }
+}
+
+/// Configures a [`SparseScan`]
+///
+/// Created by [`SparseScan::builder()`]. By default, only [`ScanBackend::SeekHole`] is tried, the
+/// file isn't synced, and the scan covers the whole file and reports every hole, unchecked.
+#[derive(Debug, Clone)]
+#[doc(alias = "SparseScanOptions")]
+pub struct SparseScanBuilder {
+    backends: Vec<ScanBackend>,
+    sync: bool,
+    min_hole_size: u64,
+    align: u64,
+    start: u64,
+    end: u64,
+    #[cfg(unix)]
+    verify: Option<HoleCheck>,
+    progress: Progress,
+}
+
+impl Default for SparseScanBuilder {
+    fn default() -> Self {
+        Self {
+            backends: vec![ScanBackend::SeekHole],
+            sync: false,
+            min_hole_size: 0,
+            align: 1,
+            start: 0,
+            end: u64::MAX,
+            #[cfg(unix)]
+            verify: None,
+            progress: Progress::new(),
+        }
+    }
+}
+
+impl SparseScanBuilder {
+    /// Try these backends, in order, using the first one the file supports
+    ///
+    /// # Panics
+    ///
+    /// If `backends` is empty, or a backend that reads the file ([`ScanBackend::ReadScan`] and the
+    /// like) has a `block_size` of 0
+    pub fn backends(mut self, backends: &[ScanBackend]) -> Self {
+        assert!(!backends.is_empty(), "at least one backend is required");
+        assert!(
+            backends.iter().all(|b| b.block_size() != Some(0)),
+            "block size must be non-zero"
+        );
+        self.backends = backends.to_vec();
+        self
+    }
+
+    /// Flush the file's dirty data before looking for holes (see [`SparseIter::sync()`])
+    #[doc(alias = "flush_before_scan")]
+    pub fn sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    /// Report holes smaller than `min` bytes as `Data` (see
+    /// [`SparseRangeIterExt::min_hole_size()`])
+    pub fn min_hole_size(mut self, min: u64) -> Self {
+        self.min_hole_size = min;
+        self
+    }
+
+    /// Shrink holes inward to multiples of `align` (see [`SparseRangeIterExt::aligned()`])
+    ///
+    /// Holes are aligned after small ones are dropped by [`min_hole_size()`](Self::min_hole_size),
+    /// so a hole that was large enough may be shrunk below that size (or to nothing) here.
+    ///
+    /// # Panics
+    ///
+    /// If `align` is 0
+    pub fn align(mut self, align: u64) -> Self {
+        assert!(align > 0, "alignment must be non-zero");
+        self.align = align;
+        self
+    }
+
+    /// Start scanning at byte `offset` (see [`SparseIter::starting_at()`])
+    pub fn start(mut self, offset: u64) -> Self {
+        self.start = offset;
+        self
+    }
+
+    /// Stop scanning at byte `offset` (see [`SparseIter::ending_at()`])
+    pub fn end(mut self, offset: u64) -> Self {
+        self.end = offset;
+        self
+    }
+
+    /// Read back each hole before reporting it, to check that it really is all zeros
+    ///
+    /// A hole with data in it makes the scan return an error that converts into
+    /// [`Error::HoleNotZero`](crate::Error::HoleNotZero), then stop. This is the check
+    /// [`verify_holes()`](crate::verify_holes) makes, done as the scan goes.
+    ///
+    /// # Panics
+    ///
+    /// If `check` is [`HoleCheck::Sample`] with a spacing of 0
+    #[cfg(unix)]
+    pub fn verify(mut self, check: HoleCheck) -> Self {
+        if let HoleCheck::Sample(every) = check {
+            assert!(every > 0, "sample spacing must be non-zero");
+        }
+        self.verify = Some(check);
+        self
+    }
+
+    /// Report progress to `progress` after each range, and stop if it is cancelled
+    ///
+    /// Progress is the end of the latest range, out of the length of the file when the scan
+    /// started. Once cancelled, the scan returns an error that converts into
+    /// [`Error::Cancelled`](crate::Error::Cancelled), then stops.
+    pub fn progress(mut self, progress: Progress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Pick a backend for `file` and start scanning it
+    ///
+    /// Returns an `Unsupported` error if none of the backends can be used on `file`, or whatever
+    /// error checking a backend ran into if it wasn't just a lack of support.
+    pub fn build<F: AsFile>(self, file: F) -> io::Result<SparseScan<F>> {
+        if self.sync {
+            crate::fdatasync(file.as_fd())?;
+        }
+
+        let mut chosen = None;
+        for &backend in &self.backends {
+            if backend.supported(file.as_fd(), self.start)? {
+                chosen = Some(backend);
+                break;
+            }
+        }
+        let backend = chosen.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "none of the requested backends are supported for this file",
+            )
+        })?;
+
+        let iter = SparseIter::window(file, self.start..self.end);
+        let iter = match backend {
+            ScanBackend::SeekHole => iter,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            ScanBackend::Fiemap => iter.fiemap(),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            ScanBackend::Fibmap => iter.fibmap(),
+            ScanBackend::ReadScan { block_size } => iter.read_scan(block_size),
+            #[cfg(feature = "mmap")]
+            ScanBackend::MmapScan { block_size } => iter.mmap_scan(block_size),
+            #[cfg(all(feature = "io-uring", any(target_os = "linux", target_os = "android")))]
+            ScanBackend::UringScan { block_size } => iter.uring_scan(block_size),
+        };
+
+        #[cfg(unix)]
+        let verify = match self.verify {
+            Some(check) => Some(HoleVerifier::new(iter.get_ref().as_fd(), check)?),
+            None => None,
+        };
+        Ok(SparseScan {
+            total: file_len(iter.get_ref().as_fd())?.min(self.end),
+            inner: SparseRangeIter::from(iter)
+                .min_hole_size(self.min_hole_size)
+                .aligned(self.align),
+            backend,
+            #[cfg(unix)]
+            verify,
+            progress: self.progress,
+            done: false,
+        })
+    }
+}
+
+/// Iterate over the ranges of Data and Holes in a file, using an explicitly chosen backend
+///
+/// Created with [`SparseScan::builder()`]. Like [`SparseRangeIter`], this stops after returning
+/// an error.
+#[derive(Debug)]
+pub struct SparseScan<F> {
+    inner: Aligned<MinHoleSize<SparseRangeIter<F>>>,
+    backend: ScanBackend,
+    #[cfg(unix)]
+    verify: Option<HoleVerifier>,
+    progress: Progress,
+    /// Length of the file when the scan started (or `end`, if that's smaller), for reporting
+    /// progress
+    total: u64,
+    /// Cancelled, or failed verification
+    done: bool,
+}
+
+impl SparseScan<()> {
+    /// Start configuring a scan
+    pub fn builder() -> SparseScanBuilder {
+        SparseScanBuilder::default()
+    }
+}
+
+impl<F> SparseScan<F> {
+    /// The backend this scan is using
+    pub fn backend(&self) -> ScanBackend {
+        self.backend
+    }
+}
+
+impl<F: AsFile> Iterator for SparseScan<F> {
+    type Item = io::Result<SparseRangeItem>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let r = match self.inner.next()? {
+            Ok(r) => r,
+            Err(e) => return Some(Err(e)),
+        };
+
+        #[cfg(unix)]
+        {
+            if let (ItemKind::Hole, Some(verify)) = (r.kind, &mut self.verify) {
+                if let Err(e) = verify.check(r.start..r.end) {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+
+        if let Err(e) = self.progress.update(r.end, self.total) {
+            self.done = true;
+            return Some(Err(e.into()));
+        }
+        Some(Ok(r))
+    }
+}
+
+impl<F: AsFile> FusedIterator for SparseScan<F> {}
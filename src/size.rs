@@ -0,0 +1,70 @@
+use crate::AsFile;
+use std::io;
+
+/// How large a file appears to be, and how much space it actually takes up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AllocatedSize {
+    /// The length of the file in bytes (what `metadata().len()` reports)
+    pub logical: u64,
+    /// The number of bytes the filesystem has allocated to store the file
+    ///
+    /// This can be smaller than `logical` (holes, compression) or larger (preallocation, block
+    /// rounding, metadata stored alongside the data).
+    pub allocated: u64,
+}
+
+/// Get the logical size of `file` along with the amount of storage allocated to it
+///
+/// On unix this uses `st_blocks` (which is in 512 byte units on every platform we support). On
+/// windows this is the `AllocationSize` from `FILE_STANDARD_INFO`.
+pub fn allocated_size<F: AsFile>(file: F) -> io::Result<AllocatedSize> {
+    #[cfg(unix)]
+    {
+        use crate::error::{checked, checked_cast};
+
+        let st = crate::unix::stat(file.as_fd())?;
+        let blocks: u64 = checked_cast(st.st_blocks)?;
+        Ok(AllocatedSize {
+            logical: checked_cast(st.st_size)?,
+            allocated: checked(blocks.checked_mul(512))?,
+        })
+    }
+
+    #[cfg(windows)]
+    {
+        let info = crate::windows::standard_info(file.as_handle())?;
+        Ok(AllocatedSize {
+            logical: crate::windows::large_integer(&info.EndOfFile)?,
+            allocated: crate::windows::large_integer(&info.AllocationSize)?,
+        })
+    }
+}
+
+/// Does `file` look like it has holes in it?
+///
+/// This is a cheap check (a single `fstat()`, or a single query on windows) meant for deciding
+/// whether a full scan is worthwhile. It is a heuristic, not a promise:
+///
+///  - On unix, a file is considered sparse if fewer bytes are allocated to it than its length.
+///    Filesystems that compress data (zfs, btrfs) make dense files look sparse, small files
+///    stored inline in metadata may look sparse, and some filesystems (zfs) don't update the
+///    allocation of recently written data until it is synced to disk.
+///  - Preallocated (`fallocate()`ed) ranges count as allocated, so a file can have holes in the
+///    sense that [`SparseIter`](crate::SparseIter) reports and still not be considered sparse
+///    here. Likewise, filesystems that allocate in large blocks can hide small holes.
+///  - On windows, this reports whether the file is marked sparse (`FILE_ATTRIBUTE_SPARSE_FILE`),
+///    whether or not it currently has any holes (the same as
+///    [`is_marked_sparse()`](crate::is_marked_sparse)). Files that are not marked sparse never
+///    have holes.
+pub fn is_sparse<F: AsFile>(file: F) -> io::Result<bool> {
+    #[cfg(unix)]
+    {
+        let s = allocated_size(file)?;
+        Ok(s.allocated < s.logical)
+    }
+
+    #[cfg(windows)]
+    {
+        crate::is_marked_sparse(file)
+    }
+}
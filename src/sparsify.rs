@@ -0,0 +1,156 @@
+//! Turning zeros already in a file into holes
+
+use crate::error::Result;
+use crate::read_scan::{BlockScan, ReadScan};
+use crate::{
+    allocated_size, block_size, punch_hole, AsFile, ItemKind, Progress, SparseIter,
+    SparseRangeIter, SparseRangeIterExt,
+};
+use std::fs::OpenOptions;
+use std::path::Path;
+
+/// What [`sparsify()`] did (or [`sparsify_dry_run()`] would do) to a file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Sparsified {
+    /// Bytes of `Data` read while looking for zeros
+    pub scanned: u64,
+    /// Bytes of zeroed blocks that were punched out (or would have been, on a dry run)
+    pub punched: u64,
+    /// How much less space is allocated to the file than before, according to
+    /// [`allocated_size()`](crate::allocated_size)
+    ///
+    /// This is usually `punched`, give or take metadata. It is smaller when some of the zeros
+    /// weren't allocated in the first place (compression, deduplication), and may be 0 on
+    /// filesystems that only update the allocation once the file is synced (zfs). It is always 0
+    /// on a dry run.
+    pub reclaimed: u64,
+}
+
+/// Dig holes in `file`: find blocks that are entirely zeros and punch them out
+///
+/// This is what `fallocate --dig-holes` does. Only the `Data` ranges of `file` are read (all of
+/// it if the filesystem can't report holes), `block_size` bytes at a time. Each block that is
+/// all zeros is deallocated with [`punch_hole()`](crate::punch_hole), with runs of them punched
+/// together. Blocks are aligned to multiples of `block_size`, which should be a multiple of the
+/// filesystem's block size: filesystems only deallocate whole blocks, and smaller zeroed blocks
+/// are just rewritten with zeros. The file's contents and length never change.
+///
+/// `file` must be open for both reading and writing. Preallocated ranges read as zeros, so they
+/// are punched out too.
+///
+/// # Errors
+///
+///  - [`Error::Unsupported`](crate::Error::Unsupported) if the platform or filesystem can't punch
+///    holes. Nothing has been changed in this case.
+///  - [`Error::NotWritable`](crate::Error::NotWritable) if `file` isn't open for writing
+///  - [`Error::Io`](crate::Error::Io) if reading fails. Holes punched before the error stay
+///    punched.
+///
+/// # Panics
+///
+/// If `block_size` is 0
+pub fn sparsify<F: AsFile>(file: F, block_size: u64) -> Result<Sparsified> {
+    sparsify_with_progress(file, block_size, &Progress::new())
+}
+
+/// Find out what [`sparsify()`] would do to `file`, without punching anything
+///
+/// The file is scanned exactly as `sparsify()` would scan it, and `punched` counts the zeroed
+/// blocks it would punch out. `file` only needs to be open for reading.
+///
+/// # Panics
+///
+/// If `block_size` is 0
+pub fn sparsify_dry_run<F: AsFile>(file: F, block_size: u64) -> Result<Sparsified> {
+    dig(file, block_size, &Progress::new(), true)
+}
+
+/// Dig holes in the file at `path`, using the filesystem's block size
+///
+/// The file is opened for reading and writing (which [`sparsify()`] needs) and closed again
+/// afterwards. Blocks are the size [`block_size()`](crate::block_size) reports, the smallest the
+/// filesystem can deallocate, so every zeroed block that can be punched out is. Symlinks are
+/// followed. Errors are those of [`sparsify()`], along with any from opening the file.
+pub fn sparsify_path<P: AsRef<Path>>(path: P) -> Result<Sparsified> {
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    sparsify(&file, block_size(&file)?)
+}
+
+/// Like [`sparsify()`], but report progress to (and stop early if cancelled by) `progress`
+///
+/// Progress is reported as the offset in `file` reached so far, out of its length. If this is
+/// cancelled, it fails with [`Error::Cancelled`](crate::Error::Cancelled). The holes punched
+/// before then stay punched.
+///
+/// # Panics
+///
+/// If `block_size` is 0
+pub fn sparsify_with_progress<F: AsFile>(
+    file: F,
+    block_size: u64,
+    progress: &Progress,
+) -> Result<Sparsified> {
+    dig(file, block_size, progress, false)
+}
+
+/// [`sparsify_with_progress()`], or with `dry_run`, only count what it would punch
+fn dig<F: AsFile>(
+    file: F,
+    block_size: u64,
+    progress: &Progress,
+    dry_run: bool,
+) -> Result<Sparsified> {
+    assert!(block_size > 0, "block size must be non-zero");
+    let fd = file.as_fd();
+    let before = allocated_size(fd)?;
+    let total = before.logical;
+
+    let mut stats = Sparsified::default();
+    let mut scan = ReadScan::new(block_size);
+    // Punching only ever affects ranges the iterator has already reported (or holes just past
+    // them), so it doesn't change what the iterator finds next.
+    for r in SparseRangeIter::from(SparseIter::from(fd).fallback_to_data()).data_only() {
+        let r = r?;
+        let mut zeros: Option<(u64, u64)> = None;
+        scan.seek(r.start);
+        while scan.pos() < r.end {
+            progress.update(scan.pos(), total)?;
+            let start = match scan.next_block(fd)? {
+                Some((ItemKind::Hole, start)) => start,
+                Some((_, _)) => {
+                    if let Some((start, end)) = zeros.take() {
+                        stats.punched += punch(fd, start, end, dry_run)?;
+                    }
+                    continue;
+                }
+                // truncated out from under us
+                None => break,
+            };
+            zeros = match zeros {
+                Some((run, _)) => Some((run, scan.pos())),
+                None => Some((start, scan.pos())),
+            };
+        }
+        stats.scanned += scan.pos().min(r.end) - r.start;
+        if let Some((start, end)) = zeros {
+            stats.punched += punch(fd, start, end, dry_run)?;
+        }
+    }
+
+    progress.update(total, total)?;
+    if dry_run {
+        return Ok(stats);
+    }
+    stats.reclaimed = before
+        .allocated
+        .saturating_sub(allocated_size(fd)?.allocated);
+    Ok(stats)
+}
+
+/// Punch out `start..end` (unless this is a dry run), returning its length
+fn punch<F: AsFile>(file: F, start: u64, end: u64, dry_run: bool) -> Result<u64> {
+    if !dry_run {
+        punch_hole(file, start, end - start)?;
+    }
+    Ok(end - start)
+}
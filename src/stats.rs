@@ -0,0 +1,133 @@
+//! How much space a file saves by being sparse
+
+use crate::{allocated_size, AsFile, ItemKind, SparseIter, SparseMap, SparseRangeIter};
+use std::io;
+
+/// How much of a file is data and how much is holes, and the storage allocated to it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SparseStats {
+    /// Bytes of the file that are data
+    pub data_bytes: u64,
+    /// Bytes of the file that are holes
+    pub hole_bytes: u64,
+    /// Storage allocated to the file, according to [`allocated_size()`](crate::allocated_size)
+    ///
+    /// `None` when computed from a [`SparseMap`], which doesn't record it.
+    pub allocated_bytes: Option<u64>,
+}
+
+impl SparseStats {
+    /// Scan `file`, and find out how much storage it has
+    ///
+    /// If the filesystem can't report holes, the file is counted as all data (see
+    /// [`SparseIter::fallback_to_data()`]).
+    pub fn from_file<F: AsFile>(file: F) -> io::Result<Self> {
+        let allocated = allocated_size(&file)?.allocated;
+        let mut stats = Self {
+            allocated_bytes: Some(allocated),
+            ..Self::default()
+        };
+        for r in SparseRangeIter::from(SparseIter::from(&file).fallback_to_data()) {
+            let r = r?;
+            stats.add(r.kind, r.end - r.start);
+        }
+        Ok(stats)
+    }
+
+    fn add(&mut self, kind: ItemKind, len: u64) {
+        match kind {
+            ItemKind::Hole => self.hole_bytes += len,
+            _ => self.data_bytes += len,
+        }
+    }
+
+    /// The length of the file
+    pub fn apparent_bytes(&self) -> u64 {
+        self.data_bytes + self.hole_bytes
+    }
+
+    /// How much of the file is holes, as a fraction from 0 to 1 (0 for an empty file)
+    #[doc(alias = "sparseness")]
+    pub fn ratio(&self) -> f64 {
+        match self.apparent_bytes() {
+            0 => 0.0,
+            len => self.hole_bytes as f64 / len as f64,
+        }
+    }
+
+    /// How much less storage the file takes up than its length, if the allocation is known
+    ///
+    /// This is 0 (not negative) when more is allocated than the file's length, as with
+    /// preallocated space past the end of the file.
+    pub fn saved_bytes(&self) -> Option<u64> {
+        self.allocated_bytes
+            .map(|a| self.apparent_bytes().saturating_sub(a))
+    }
+}
+
+impl From<&SparseMap> for SparseStats {
+    /// Count the data and holes in `map`, leaving `allocated_bytes` unknown
+    fn from(map: &SparseMap) -> Self {
+        let mut stats = Self::default();
+        for r in map {
+            stats.add(r.kind, r.end - r.start);
+        }
+        stats
+    }
+}
+
+/// How many pieces a file's data is in, and how large they are
+///
+/// From a [`SparseMap`], each `Data` range is a piece: this is how fragmented the file's layout
+/// is, which is what matters when copying or transferring it range by range. On linux,
+/// [`fiemap::fragmentation()`](crate::fiemap::fragmentation) instead counts where the data is
+/// split up on disk, which is what matters when deciding whether to rewrite it in one piece.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fragmentation {
+    /// The number of pieces (extents) the data is in
+    pub extents: u64,
+    /// Bytes of data in all the extents
+    pub data_bytes: u64,
+    /// The size of the largest extent, or 0 if there are none
+    pub largest: u64,
+    /// The size of the smallest extent, or 0 if there are none
+    pub smallest: u64,
+}
+
+impl Fragmentation {
+    /// Count one more extent of `len` bytes
+    pub(crate) fn add(&mut self, len: u64) {
+        self.smallest = match self.extents {
+            0 => len,
+            _ => self.smallest.min(len),
+        };
+        self.largest = self.largest.max(len);
+        self.extents += 1;
+        self.data_bytes += len;
+    }
+
+    /// The average size of an extent, or 0 if there are none
+    pub fn average(&self) -> u64 {
+        self.data_bytes.checked_div(self.extents).unwrap_or(0)
+    }
+}
+
+impl From<&SparseMap> for Fragmentation {
+    /// Count each of the `Data` ranges in `map` as an extent
+    fn from(map: &SparseMap) -> Self {
+        let mut frag = Self::default();
+        for r in map.iter().filter(|r| r.kind == ItemKind::Data) {
+            frag.add(r.end - r.start);
+        }
+        frag
+    }
+}
+
+/// How much of `file` is data and how much is holes, and the storage allocated to it
+///
+/// This is [`SparseStats::from_file()`].
+pub fn stats<F: AsFile>(file: F) -> io::Result<SparseStats> {
+    SparseStats::from_file(file)
+}
@@ -0,0 +1,156 @@
+//! Sending sparse files through pipes and sockets, holes included
+
+use crate::unix::{pread_full, pwrite_all, set_len};
+use crate::{AsFile, ItemKind, SparseIter, SparseRangeIter};
+use std::io::{self, Read, Write};
+
+/// Start of every stream, so garbage is rejected up front
+const MAGIC: &[u8; 8] = b"fsparse1";
+
+/// Record tags
+const DATA: u8 = b'D';
+const HOLE: u8 = b'H';
+const END: u8 = b'E';
+
+/// Largest `Data` record written, and the size of the buffer used to read and write them
+const CHUNK: usize = 1024 * 1024;
+
+/// Write the contents of `file` to `w` as a sparse stream, which [`read_sparse_stream()`] turns
+/// back into a file with the same holes
+///
+/// Only the `Data` ranges of `file` are read and sent, so a mostly empty disk image takes
+/// little time and bandwidth, and arrives with its holes intact (unlike `rsync --sparse`, which
+/// only guesses where holes were from runs of zeros). If the filesystem can't report holes, the
+/// whole file is sent as data (see [`SparseIter::fallback_to_data()`]).
+///
+/// The stream is the 8 bytes `fsparse1`, followed by records. Each record is a one byte tag and
+/// big endian `u64`s:
+///
+///  - `D`, offset, length, then `length` bytes of data
+///  - `H`, offset, length: a hole
+///  - `E`, length of the file: the end of the stream
+///
+/// Records are in order of increasing offset, and `Data` ranges are split into records of at most
+/// 1 MiB. Nothing is buffered: wrap `w` in a `BufWriter` if it is slow to write to in small
+/// pieces.
+///
+/// Returns the number of bytes of data written (not counting the headers). This never moves the
+/// file's cursor (unless the [`SparseIter`] does, while looking for holes).
+pub fn write_sparse_stream<F: AsFile, W: Write>(file: F, mut w: W) -> io::Result<u64> {
+    let fd = file.as_fd();
+    w.write_all(MAGIC)?;
+
+    let mut buf = Vec::new();
+    let mut sent = 0;
+    let mut len = 0;
+    for r in SparseRangeIter::from(SparseIter::from(fd).fallback_to_data()) {
+        let r = r?;
+        len = r.end;
+        if r.kind != ItemKind::Data {
+            write_record(&mut w, HOLE, &[r.start, r.end - r.start])?;
+            continue;
+        }
+
+        let mut offset = r.start;
+        while offset < r.end {
+            let want = (r.end - offset).min(CHUNK as u64) as usize;
+            buf.resize(want, 0);
+            let n = pread_full(fd, &mut buf, offset)?;
+            if n == 0 {
+                // `file` was truncated out from under us
+                len = offset;
+                break;
+            }
+
+            write_record(&mut w, DATA, &[offset, n as u64])?;
+            w.write_all(&buf[..n])?;
+            offset += n as u64;
+            sent += n as u64;
+        }
+    }
+
+    write_record(&mut w, END, &[len])?;
+    w.flush()?;
+    Ok(sent)
+}
+
+/// Read a stream written by [`write_sparse_stream()`] from `r` into `file`
+///
+/// `file` ends up with the same length and contents as the file the stream was written from,
+/// with anything it held before discarded. Only data is written, so `file` has holes wherever
+/// the original did (as long as its filesystem supports them). Reading stops at the end of the
+/// stream, so more may follow it in `r`.
+///
+/// Returns the number of bytes of data written. The file's cursor is never used.
+///
+/// # Errors
+///
+/// An error of kind `InvalidData` if `r` doesn't hold a sparse stream, and `UnexpectedEof` if it
+/// ends before the stream does. `file` holds whatever was received before the error.
+pub fn read_sparse_stream<R: Read, F: AsFile>(mut r: R, file: F) -> io::Result<u64> {
+    let fd = file.as_fd();
+
+    let mut magic = [0u8; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a sparse stream"));
+    }
+
+    // truncating first throws away whatever `file` had allocated, leaving it one big hole
+    crate::set_sparse(fd, true)?;
+    set_len(fd, 0)?;
+
+    let mut buf = Vec::new();
+    let mut received = 0;
+    loop {
+        let mut tag = [0u8; 1];
+        r.read_exact(&mut tag)?;
+        match tag[0] {
+            DATA => {
+                let (offset, len) = (read_u64(&mut r)?, read_u64(&mut r)?);
+                if !matches!(offset.checked_add(len), Some(end) if end <= i64::MAX as u64) {
+                    return Err(invalid("data record out of range"));
+                }
+
+                let mut done = 0;
+                while done < len {
+                    let n = (len - done).min(CHUNK as u64) as usize;
+                    buf.resize(n, 0);
+                    r.read_exact(&mut buf)?;
+                    pwrite_all(fd, &buf, offset + done)?;
+                    done += n as u64;
+                }
+                received += len;
+            }
+            HOLE => {
+                // `file` started out empty, so it's already a hole
+                read_u64(&mut r)?;
+                read_u64(&mut r)?;
+            }
+            END => {
+                set_len(fd, read_u64(&mut r)?)?;
+                return Ok(received);
+            }
+            _ => return Err(invalid("unknown record in sparse stream")),
+        }
+    }
+}
+
+fn write_record<W: Write>(w: &mut W, tag: u8, fields: &[u64]) -> io::Result<()> {
+    let mut record = Vec::with_capacity(1 + 8 * fields.len());
+    record.push(tag);
+    for f in fields {
+        record.extend_from_slice(&f.to_be_bytes());
+    }
+    w.write_all(&record)
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut b = [0u8; 8];
+    r.read_exact(&mut b)?;
+    Ok(u64::from_be_bytes(b))
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,206 @@
+//! GNU tar's description of sparse files, for archivers
+//!
+//! GNU tar stores only the data of a sparse file, along with a "sparse map" listing where that
+//! data goes: an `(offset, size)` pair for each data range, in order, and the real length of the
+//! file. When a file ends in a hole (or is all hole), the map ends with a pair of the file's length
+//! and 0, as GNU tar writes it.
+//!
+//! Two of its PAX formats are supported (see "Storing Sparse Files" in the GNU tar manual):
+//!
+//!  - 0.1 keeps the whole map in extended header records: [`pax_records_0_1()`] and
+//!    [`parse_pax_0_1()`].
+//!  - 1.0 keeps the version and real length in extended header records
+//!    ([`pax_records_1_0()`]), and the map at the start of the entry's data, padded to a multiple
+//!    of 512 bytes ([`map_block_1_0()`] and [`parse_map_1_0()`]). This is what GNU tar writes
+//!    by default.
+//!
+//! Only the map is handled here: the archiver still picks the entry's name (for 1.0, the real name
+//! goes in `GNU.sparse.name`), sets its size to the size of the data stored (plus the map block
+//! for 1.0), and writes the data of each range in order.
+
+use crate::{ItemKind, SparseMap};
+use std::io::{self, Read};
+
+/// Tar block size, which the 1.0 map is padded to
+const BLOCK: usize = 512;
+
+/// The `(offset, size)` pairs GNU tar records for `map`
+fn segments(map: &SparseMap) -> Vec<(u64, u64)> {
+    let mut v: Vec<_> = map
+        .iter()
+        .filter(|r| r.kind == ItemKind::Data)
+        .map(|r| (r.start, r.end - r.start))
+        .collect();
+    let len = map.file_len();
+    if v.last().map(|&(offset, size)| offset + size) != Some(len) {
+        v.push((len, 0));
+    }
+    v
+}
+
+/// Extended header records describing `map` in format 0.1
+///
+/// These are `GNU.sparse.size` (the real length of the file), `GNU.sparse.numblocks`, and
+/// `GNU.sparse.map` (the offsets and sizes, separated by commas), in that order.
+pub fn pax_records_0_1(map: &SparseMap) -> Vec<(&'static str, String)> {
+    let segments = segments(map);
+    let list = segments
+        .iter()
+        .map(|(offset, size)| format!("{},{}", offset, size))
+        .collect::<Vec<_>>()
+        .join(",");
+    vec![
+        ("GNU.sparse.size", map.file_len().to_string()),
+        ("GNU.sparse.numblocks", segments.len().to_string()),
+        ("GNU.sparse.map", list),
+    ]
+}
+
+/// Read a map in format 0.1 back out of an entry's extended header records
+///
+/// Records other than the three written by [`pax_records_0_1()`] are ignored.
+///
+/// # Errors
+///
+/// An error of kind `InvalidData` if any of those records are missing or malformed, or the map
+/// doesn't fit in the file.
+pub fn parse_pax_0_1<'a, I>(records: I) -> io::Result<SparseMap>
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let (mut len, mut count, mut list) = (None, None, None);
+    for (key, value) in records {
+        match key {
+            "GNU.sparse.size" => len = Some(number(value)?),
+            "GNU.sparse.numblocks" => count = Some(number(value)?),
+            "GNU.sparse.map" => list = Some(value),
+            _ => {}
+        }
+    }
+    let (len, count, list) = match (len, count, list) {
+        (Some(len), Some(count), Some(list)) => (len, count, list),
+        _ => return Err(invalid("missing GNU.sparse records")),
+    };
+
+    let numbers = if list.is_empty() {
+        Vec::new()
+    } else {
+        list.split(',')
+            .map(number)
+            .collect::<io::Result<Vec<_>>>()?
+    };
+    if numbers.len() as u64 != count.saturating_mul(2) {
+        return Err(invalid("GNU.sparse.map doesn't match GNU.sparse.numblocks"));
+    }
+    let segments: Vec<_> = numbers.chunks(2).map(|p| (p[0], p[1])).collect();
+    build(&segments, len)
+}
+
+/// Extended header records marking an entry as holding a map in format 1.0
+///
+/// These are `GNU.sparse.major`, `GNU.sparse.minor`, and `GNU.sparse.realsize` (the real length
+/// of the file). The map itself comes from [`map_block_1_0()`].
+pub fn pax_records_1_0(map: &SparseMap) -> Vec<(&'static str, String)> {
+    vec![
+        ("GNU.sparse.major", "1".to_string()),
+        ("GNU.sparse.minor", "0".to_string()),
+        ("GNU.sparse.realsize", map.file_len().to_string()),
+    ]
+}
+
+/// The map in format 1.0, to be written at the start of the entry's data
+///
+/// This is the number of pairs, then each offset and size, all in decimal and followed by a
+/// newline, padded with zeros to a multiple of 512 bytes.
+pub fn map_block_1_0(map: &SparseMap) -> Vec<u8> {
+    let segments = segments(map);
+    let mut block = format!("{}\n", segments.len());
+    for (offset, size) in segments {
+        block.push_str(&format!("{}\n{}\n", offset, size));
+    }
+
+    let mut block = block.into_bytes();
+    let padded = block.len().div_ceil(BLOCK) * BLOCK;
+    block.resize(padded, 0);
+    block
+}
+
+/// Read a map in format 1.0 from the start of an entry's data
+///
+/// `records` are the entry's extended header records, which must include those written by
+/// [`pax_records_1_0()`]. Exactly the blocks holding the map are read from `r`, leaving it at the
+/// file's data.
+///
+/// # Errors
+///
+/// An error of kind `InvalidData` if the records don't describe format 1.0, or the map is
+/// malformed or doesn't fit in the file, and `UnexpectedEof` if `r` ends inside the map.
+pub fn parse_map_1_0<'a, I, R>(records: I, mut r: R) -> io::Result<SparseMap>
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+    R: Read,
+{
+    let (mut major, mut minor, mut len) = (None, None, None);
+    for (key, value) in records {
+        match key {
+            "GNU.sparse.major" => major = Some(value),
+            "GNU.sparse.minor" => minor = Some(value),
+            "GNU.sparse.realsize" => len = Some(number(value)?),
+            _ => {}
+        }
+    }
+    let len = match (major, minor, len) {
+        (Some("1"), Some("0"), Some(len)) => len,
+        (Some(_), Some(_), Some(_)) => return Err(invalid("not GNU sparse format 1.0")),
+        _ => return Err(invalid("missing GNU.sparse records")),
+    };
+
+    // numbers may straddle blocks, so keep the unfinished end of each one for the next
+    let mut numbers = Vec::new();
+    let mut partial = Vec::new();
+    let mut block = [0u8; BLOCK];
+    loop {
+        r.read_exact(&mut block)?;
+        partial.extend_from_slice(&block);
+        while let Some(i) = partial.iter().position(|&b| b == b'\n') {
+            let line = std::str::from_utf8(&partial[..i])
+                .map_err(|_| invalid("malformed GNU sparse map"))?;
+            numbers.push(number(line)?);
+            partial.drain(..=i);
+
+            let count = numbers[0];
+            if numbers.len() as u64 > count.saturating_mul(2) {
+                let segments: Vec<_> = numbers[1..].chunks(2).map(|p| (p[0], p[1])).collect();
+                return build(&segments, len);
+            }
+        }
+        if partial.len() > 20 {
+            // longer than any u64
+            return Err(invalid("malformed GNU sparse map"));
+        }
+    }
+}
+
+/// Check `segments` fit in a file `len` bytes long, and turn them into a map
+fn build(segments: &[(u64, u64)], len: u64) -> io::Result<SparseMap> {
+    let mut pos = 0;
+    for &(offset, size) in segments {
+        let end = offset
+            .checked_add(size)
+            .ok_or_else(|| invalid("GNU sparse map out of range"))?;
+        if offset < pos || end > len {
+            return Err(invalid("GNU sparse map out of order or out of range"));
+        }
+        pos = end;
+    }
+    Ok(SparseMap::from_data(segments, len))
+}
+
+fn number(s: &str) -> io::Result<u64> {
+    s.parse()
+        .map_err(|_| invalid("malformed number in GNU sparse map"))
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
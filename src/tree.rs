@@ -0,0 +1,146 @@
+//! Reporting on how sparse the files in a directory tree are
+
+use crate::{allocated_size, ItemKind, SparseIter, SparseRangeIter};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How many files [`TreeStats::largest`] keeps
+const LARGEST: usize = 10;
+
+/// Totals for the regular files in a directory tree, from [`scan_tree()`]
+#[derive(Debug, Default)]
+pub struct TreeStats {
+    /// Regular files scanned
+    pub files: u64,
+    /// Sum of the files' lengths
+    pub apparent: u64,
+    /// Sum of the storage allocated to the files, according to
+    /// [`allocated_size()`](crate::allocated_size)
+    pub allocated: u64,
+    /// Bytes of the files that are holes
+    pub holes: u64,
+    /// The files with the most storage allocated to them, most first
+    ///
+    /// These are where [`sparsify()`](crate::sparsify) has the most it could reclaim. At most 10
+    /// are kept.
+    pub largest: Vec<FileStats>,
+    /// Files and directories that couldn't be read, and why
+    ///
+    /// These are skipped, and not counted in the totals.
+    pub errors: Vec<(PathBuf, io::Error)>,
+}
+
+/// How sparse a single file is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStats {
+    /// Where the file is
+    pub path: PathBuf,
+    /// The file's length
+    pub apparent: u64,
+    /// Storage allocated to the file
+    pub allocated: u64,
+    /// Bytes of the file that are holes
+    pub holes: u64,
+}
+
+impl TreeStats {
+    /// Bytes of the files that are data (not holes)
+    pub fn data(&self) -> u64 {
+        self.apparent.saturating_sub(self.holes)
+    }
+
+    fn add(&mut self, file: FileStats) {
+        self.files += 1;
+        self.apparent += file.apparent;
+        self.allocated += file.allocated;
+        self.holes += file.holes;
+
+        let at = self
+            .largest
+            .iter()
+            .position(|f| f.allocated < file.allocated)
+            .unwrap_or(self.largest.len());
+        if at < LARGEST {
+            self.largest.insert(at, file);
+            self.largest.truncate(LARGEST);
+        }
+    }
+}
+
+impl FileStats {
+    /// Scan the file at `path`
+    ///
+    /// If the filesystem can't report holes, the file is counted as all data (see
+    /// [`SparseIter::fallback_to_data()`]).
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let size = allocated_size(&file)?;
+
+        let mut holes = 0;
+        for r in SparseRangeIter::from(SparseIter::from(&file).fallback_to_data()) {
+            let r = r?;
+            if r.kind == ItemKind::Hole {
+                holes += r.end - r.start;
+            }
+        }
+
+        Ok(Self {
+            path: path.to_owned(),
+            apparent: size.logical,
+            allocated: size.allocated,
+            holes,
+        })
+    }
+}
+
+/// Scan every regular file under `path`, totalling up how sparse they are
+///
+/// Directories are walked recursively. Symbolic links are not followed, and anything other than
+/// regular files and directories is skipped. Files that can't be opened or scanned (and
+/// directories that can't be listed) are recorded in [`TreeStats::errors`] instead of stopping
+/// the walk. `path` may also be a single regular file.
+///
+/// # Errors
+///
+/// If `path` itself can't be examined
+pub fn scan_tree<P: AsRef<Path>>(path: P) -> io::Result<TreeStats> {
+    let path = path.as_ref();
+    let mut stats = TreeStats::default();
+    let file_type = fs::symlink_metadata(path)?.file_type();
+    if file_type.is_dir() {
+        walk(path, fs::read_dir(path)?, &mut stats);
+    } else if file_type.is_file() {
+        add_file(path, &mut stats);
+    }
+    Ok(stats)
+}
+
+/// Add up the files in `entries`, which lists the directory `dir`
+fn walk(dir: &Path, entries: fs::ReadDir, stats: &mut TreeStats) {
+    for entry in entries {
+        let entry = match entry {
+            Ok(e) => e,
+            // we don't know which entry this was, so blame the directory
+            Err(e) => return stats.errors.push((dir.to_owned(), e)),
+        };
+        let path = entry.path();
+        match entry.file_type() {
+            Ok(t) if t.is_dir() => match fs::read_dir(&path) {
+                Ok(entries) => walk(&path, entries, stats),
+                Err(e) => stats.errors.push((path, e)),
+            },
+            Ok(t) if t.is_file() => add_file(&path, stats),
+            Ok(_) => {}
+            Err(e) => stats.errors.push((path, e)),
+        }
+    }
+}
+
+fn add_file(path: &Path, stats: &mut TreeStats) {
+    match FileStats::from_path(path) {
+        Ok(file) => stats.add(file),
+        Err(e) => stats.errors.push((path.to_owned(), e)),
+    }
+}
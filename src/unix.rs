@@ -0,0 +1,326 @@
+use crate::error::{checked, checked_cast, Error};
+use std::io;
+use std::os::unix::io::AsRawFd;
+pub(crate) use std::os::unix::io::BorrowedFd;
+
+// 32-bit android, glibc, and uclibc have a 32-bit `off_t`, which can't describe offsets past
+// 2 GiB, and their `fstat()` fails with EOVERFLOW on files that large (as `fstatvfs()` does on
+// large filesystems). Their `*64` variants use
+// 64-bit offsets everywhere. musl's `off_t` is always 64 bits, as it is on the BSDs and macos.
+#[cfg(not(any(
+    target_os = "android",
+    all(
+        target_os = "linux",
+        not(any(target_env = "musl", target_env = "ohos"))
+    )
+)))]
+use libc::{fstat, fstatvfs, ftruncate, lseek, off_t, pread as pread_raw, pwrite as pwrite_raw};
+#[cfg(any(
+    target_os = "android",
+    all(
+        target_os = "linux",
+        not(any(target_env = "musl", target_env = "ohos"))
+    )
+))]
+use libc::{
+    fstat64 as fstat, fstatvfs64 as fstatvfs, ftruncate64 as ftruncate, lseek64 as lseek,
+    off64_t as off_t, pread64 as pread_raw, pwrite64 as pwrite_raw,
+};
+
+/// What [`stat()`] returns
+#[cfg(not(any(
+    target_os = "android",
+    all(
+        target_os = "linux",
+        not(any(target_env = "musl", target_env = "ohos"))
+    )
+)))]
+pub(crate) type Stat = libc::stat;
+/// What [`statvfs()`] returns
+#[cfg(not(any(
+    target_os = "android",
+    all(
+        target_os = "linux",
+        not(any(target_env = "musl", target_env = "ohos"))
+    )
+)))]
+pub(crate) type Statvfs = libc::statvfs;
+/// What [`stat()`] returns
+#[cfg(any(
+    target_os = "android",
+    all(
+        target_os = "linux",
+        not(any(target_env = "musl", target_env = "ohos"))
+    )
+))]
+pub(crate) type Stat = libc::stat64;
+/// What [`statvfs()`] returns
+#[cfg(any(
+    target_os = "android",
+    all(
+        target_os = "linux",
+        not(any(target_env = "musl", target_env = "ohos"))
+    )
+))]
+pub(crate) type Statvfs = libc::statvfs64;
+
+/// Convert a file offset for the OS, failing if it's too large
+pub(crate) fn to_off(offset: u64) -> io::Result<off_t> {
+    checked_cast(offset)
+}
+
+/// `lseek()` that maps `ENXIO` (no more data or holes at or after `offset`) to `None`
+pub(crate) fn seek(fd: BorrowedFd<'_>, offset: u64, whence: i32) -> io::Result<Option<u64>> {
+    let off = unsafe { lseek(fd.as_raw_fd(), to_off(offset)?, whence) };
+    if off < 0 {
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() == Some(libc::ENXIO) {
+            return Ok(None);
+        }
+        if e.raw_os_error() == Some(libc::ESPIPE) && is_stream(fd) {
+            return Err(Error::NotASeekableFile.into());
+        }
+        return Err(e);
+    }
+
+    checked_cast(off).map(Some)
+}
+
+/// Is `fd` a pipe, socket, or character device?
+fn is_stream(fd: BorrowedFd<'_>) -> bool {
+    match stat(fd) {
+        Ok(st) => matches!(
+            st.st_mode & libc::S_IFMT,
+            libc::S_IFIFO | libc::S_IFSOCK | libc::S_IFCHR
+        ),
+        Err(_) => false,
+    }
+}
+
+/// `fstat()` the file
+pub(crate) fn stat(fd: BorrowedFd<'_>) -> io::Result<Stat> {
+    let mut st = std::mem::MaybeUninit::<Stat>::uninit();
+    let r = unsafe { fstat(fd.as_raw_fd(), st.as_mut_ptr()) };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { st.assume_init() })
+}
+
+/// `fstatvfs()` the filesystem the file is on
+pub(crate) fn statvfs(fd: BorrowedFd<'_>) -> io::Result<Statvfs> {
+    let mut st = std::mem::MaybeUninit::<Statvfs>::uninit();
+    let r = unsafe { fstatvfs(fd.as_raw_fd(), st.as_mut_ptr()) };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { st.assume_init() })
+}
+
+/// The current length of the file, in bytes
+pub(crate) fn file_len(fd: BorrowedFd<'_>) -> io::Result<u64> {
+    let st = stat(fd)?;
+    match crate::device::device_size(fd, &st)? {
+        Some(size) => Ok(size),
+        None => checked_cast(st.st_size),
+    }
+}
+
+/// A shared `flock()` on a file, released when this is dropped
+///
+/// This only holds the fd's number, so it must be dropped before the fd is closed.
+#[derive(Debug)]
+pub(crate) struct SharedLock(std::os::unix::io::RawFd);
+
+impl SharedLock {
+    /// Wait for any exclusive lock on the file to be released, then take a shared one
+    pub(crate) fn new(fd: BorrowedFd<'_>) -> io::Result<Self> {
+        loop {
+            if unsafe { libc::flock(fd.as_raw_fd(), libc::LOCK_SH) } == 0 {
+                return Ok(Self(fd.as_raw_fd()));
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+    }
+}
+
+impl Drop for SharedLock {
+    fn drop(&mut self) {
+        unsafe { libc::flock(self.0, libc::LOCK_UN) };
+    }
+}
+
+/// Where a file's cursor was, put back when this is dropped
+///
+/// This only holds the fd's number, so it must be dropped before the fd is closed.
+#[derive(Debug)]
+pub(crate) struct SavedCursor {
+    fd: std::os::unix::io::RawFd,
+    cursor: u64,
+}
+
+impl SavedCursor {
+    pub(crate) fn new(fd: BorrowedFd<'_>) -> io::Result<Self> {
+        // SEEK_CUR never returns ENXIO
+        let cursor = seek(fd, 0, libc::SEEK_CUR)?.unwrap();
+        Ok(Self {
+            fd: fd.as_raw_fd(),
+            cursor,
+        })
+    }
+}
+
+impl Drop for SavedCursor {
+    fn drop(&mut self) {
+        let fd = unsafe { BorrowedFd::borrow_raw(self.fd) };
+        // there's nothing useful to do with an error from a destructor
+        let _ = seek(fd, self.cursor, libc::SEEK_SET);
+    }
+}
+
+/// What the file looked like at some point, to tell whether it has changed since
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileVersion {
+    len: u64,
+    /// `st_ctime`, as seconds and nanoseconds. Writes, truncation, and metadata changes all
+    /// update it.
+    changed: (i64, i64),
+}
+
+impl FileVersion {
+    pub(crate) fn of(fd: BorrowedFd<'_>) -> io::Result<Self> {
+        let st = stat(fd)?;
+        #[cfg(target_os = "netbsd")]
+        let nsec = st.st_ctimensec;
+        #[cfg(not(target_os = "netbsd"))]
+        let nsec = st.st_ctime_nsec;
+        // these are 32 bits on some platforms
+        #[allow(clippy::useless_conversion)]
+        let changed = (i64::from(st.st_ctime), i64::from(nsec));
+        Ok(Self {
+            len: checked_cast(st.st_size)?,
+            changed,
+        })
+    }
+}
+
+/// Does `e` indicate that the file or filesystem doesn't support `SEEK_DATA`/`SEEK_HOLE`?
+pub(crate) fn is_unsupported(e: &io::Error) -> bool {
+    match e.raw_os_error() {
+        Some(libc::EINVAL) | Some(libc::ENOTSUP) | Some(libc::ENOSYS) => true,
+        Some(e) => e == libc::EOPNOTSUPP,
+        None => false,
+    }
+}
+
+/// `pread()`: read into `buf` from `offset` without touching the file's cursor
+pub(crate) fn pread(fd: BorrowedFd<'_>, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    let r = unsafe {
+        pread_raw(
+            fd.as_raw_fd(),
+            buf.as_mut_ptr() as *mut libc::c_void,
+            buf.len(),
+            to_off(offset)?,
+        )
+    };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    checked_cast(r)
+}
+
+/// Fill as much of `buf` as the file has at `offset`
+pub(crate) fn pread_full(fd: BorrowedFd<'_>, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match pread(fd, &mut buf[filled..], offset + filled as u64) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// `pwrite()` all of `buf` at `offset`, without touching the file's cursor
+pub(crate) fn pwrite_all(fd: BorrowedFd<'_>, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+    while !buf.is_empty() {
+        let r = unsafe {
+            pwrite_raw(
+                fd.as_raw_fd(),
+                buf.as_ptr() as *const libc::c_void,
+                buf.len(),
+                to_off(offset)?,
+            )
+        };
+        if r < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+        if r == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        buf = &buf[r as usize..];
+        offset += r as u64;
+    }
+
+    Ok(())
+}
+
+/// Write `len` zero bytes at `offset`, without touching the file's cursor
+pub(crate) fn write_zeros(fd: BorrowedFd<'_>, mut offset: u64, len: u64) -> io::Result<()> {
+    let zeros = [0u8; 64 * 1024];
+    let end = checked(offset.checked_add(len))?;
+    while offset < end {
+        let n = (end - offset).min(zeros.len() as u64) as usize;
+        pwrite_all(fd, &zeros[..n], offset)?;
+        offset += n as u64;
+    }
+
+    Ok(())
+}
+
+/// `ftruncate()`: set the length of the file, filling any extension with a hole
+pub(crate) fn set_len(fd: BorrowedFd<'_>, len: u64) -> io::Result<()> {
+    let r = unsafe { ftruncate(fd.as_raw_fd(), to_off(len)?) };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Flush the file's data (but not necessarily its metadata) to disk
+pub(crate) fn fdatasync(fd: BorrowedFd<'_>) -> io::Result<()> {
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    ))]
+    let r = unsafe { libc::fdatasync(fd.as_raw_fd()) };
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "openbsd"
+    )))]
+    let r = unsafe { libc::fsync(fd.as_raw_fd()) };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
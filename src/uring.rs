@@ -0,0 +1,388 @@
+//! Reading many blocks per syscall with io_uring
+//!
+//! Only what [`ReadScan`](crate::read_scan::ReadScan) needs: a ring to submit a batch of reads
+//! and wait for all of them, with a single `io_uring_enter()` per batch instead of a `pread()` per
+//! block. The kernel interface is used directly (see `linux/io_uring.h`), as `libc` doesn't
+//! describe it.
+
+use crate::unix::{pread_full, BorrowedFd};
+use std::collections::VecDeque;
+use std::io;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd};
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Bytes read per batch, at most
+const BATCH_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Reads per batch, at most (and the size of the ring)
+const DEPTH: u32 = 64;
+
+const IORING_OFF_SQ_RING: libc::off_t = 0;
+const IORING_OFF_CQ_RING: libc::off_t = 0x800_0000;
+const IORING_OFF_SQES: libc::off_t = 0x1000_0000;
+const IORING_ENTER_GETEVENTS: u32 = 1;
+/// Linux 5.6
+const IORING_OP_READ: u8 = 22;
+
+#[repr(C)]
+#[derive(Default)]
+struct SqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqringOffsets,
+    cq_off: CqringOffsets,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    rw_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    splice_fd_in: i32,
+    addr3: u64,
+    pad: u64,
+}
+
+#[repr(C)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+/// A memory mapping of part of a ring
+struct Map {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Map {
+    fn new(fd: &OwnedFd, len: usize, offset: libc::off_t) -> io::Result<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd.as_raw_fd(),
+                offset,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+
+    /// The value at byte `offset` in the mapping, which the kernel may be changing
+    fn atomic(&self, offset: u32) -> &AtomicU32 {
+        unsafe { &*(self.ptr.add(offset as usize) as *const AtomicU32) }
+    }
+}
+
+impl Drop for Map {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+    }
+}
+
+/// An io_uring instance
+pub(crate) struct Ring {
+    sq: Map,
+    cq: Map,
+    sqes: Map,
+    params: Params,
+    // dropped last, after the mappings
+    fd: OwnedFd,
+}
+
+// The mappings are only used through `&mut Ring`, so moving it between threads is fine
+unsafe impl Send for Ring {}
+
+impl std::fmt::Debug for Ring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ring")
+            .field("fd", &self.fd)
+            .field("entries", &self.params.sq_entries)
+            .finish()
+    }
+}
+
+impl Ring {
+    /// Set up a ring with room for at least `entries` reads at once
+    ///
+    /// Fails with `ENOSYS` on kernels without io_uring, and `EPERM` where it has been disabled.
+    pub(crate) fn new(entries: u32) -> io::Result<Self> {
+        let mut params = Params::default();
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_setup,
+                entries,
+                &mut params as *mut Params,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len =
+            params.cq_off.cqes as usize + params.cq_entries as usize * std::mem::size_of::<Cqe>();
+        let sqes_len = params.sq_entries as usize * std::mem::size_of::<Sqe>();
+        Ok(Self {
+            sq: Map::new(&fd, sq_len, IORING_OFF_SQ_RING)?,
+            cq: Map::new(&fd, cq_len, IORING_OFF_CQ_RING)?,
+            sqes: Map::new(&fd, sqes_len, IORING_OFF_SQES)?,
+            params,
+            fd,
+        })
+    }
+
+    /// Read into each of `reads` (a buffer and an offset) from `fd`, returning the result of
+    /// each read in order: bytes read, or the error
+    ///
+    /// # Safety
+    ///
+    /// Every buffer must stay valid until this returns. If waiting for the reads fails, they may
+    /// still be in progress, so the buffers must then be leaked.
+    unsafe fn read_all(
+        &mut self,
+        fd: BorrowedFd<'_>,
+        reads: &mut [(&mut [u8], u64)],
+    ) -> io::Result<Vec<io::Result<usize>>> {
+        let n = reads.len() as u32;
+        assert!(n <= self.params.sq_entries);
+
+        let sq_off = &self.params.sq_off;
+        let mask = *(self.sq.ptr.add(sq_off.ring_mask as usize) as *const u32);
+        let array = self.sq.ptr.add(sq_off.array as usize) as *mut u32;
+        let sqes = self.sqes.ptr as *mut Sqe;
+        let mut tail = self.sq.atomic(sq_off.tail).load(Ordering::Relaxed);
+        for (i, (buf, offset)) in reads.iter_mut().enumerate() {
+            let index = tail & mask;
+            sqes.add(index as usize).write(Sqe {
+                opcode: IORING_OP_READ,
+                fd: fd.as_raw_fd(),
+                off: *offset,
+                addr: buf.as_mut_ptr() as u64,
+                len: buf.len() as u32,
+                user_data: i as u64,
+                ..Sqe::default()
+            });
+            array.add(index as usize).write(index);
+            tail = tail.wrapping_add(1);
+        }
+        self.sq.atomic(sq_off.tail).store(tail, Ordering::Release);
+
+        let mut results: Vec<_> = (0..n).map(|_| Ok(0)).collect();
+        let (mut to_submit, mut to_reap) = (n, n);
+        while to_reap > 0 {
+            if to_submit > 0 || !self.reap(&mut results, &mut to_reap) {
+                let r = libc::syscall(
+                    libc::SYS_io_uring_enter,
+                    self.fd.as_raw_fd(),
+                    to_submit,
+                    1,
+                    IORING_ENTER_GETEVENTS,
+                    ptr::null::<libc::sigset_t>(),
+                    0,
+                );
+                if r < 0 {
+                    let e = io::Error::last_os_error();
+                    match e.raw_os_error() {
+                        Some(libc::EINTR) | Some(libc::EAGAIN) | Some(libc::EBUSY) => continue,
+                        _ => return Err(e),
+                    }
+                }
+                to_submit -= r as u32;
+            }
+        }
+        Ok(results)
+    }
+
+    /// Collect finished reads into `results`, returning whether there were any
+    unsafe fn reap(&mut self, results: &mut [io::Result<usize>], to_reap: &mut u32) -> bool {
+        let cq_off = &self.params.cq_off;
+        let mask = *(self.cq.ptr.add(cq_off.ring_mask as usize) as *const u32);
+        let cqes = self.cq.ptr.add(cq_off.cqes as usize) as *const Cqe;
+        let mut head = self.cq.atomic(cq_off.head).load(Ordering::Relaxed);
+        let tail = self.cq.atomic(cq_off.tail).load(Ordering::Acquire);
+        if head == tail {
+            return false;
+        }
+
+        while head != tail {
+            let cqe = &*cqes.add((head & mask) as usize);
+            results[cqe.user_data as usize] = if cqe.res < 0 {
+                Err(io::Error::from_raw_os_error(-cqe.res))
+            } else {
+                Ok(cqe.res as usize)
+            };
+            *to_reap -= 1;
+            head = head.wrapping_add(1);
+        }
+        self.cq.atomic(cq_off.head).store(head, Ordering::Release);
+        true
+    }
+}
+
+/// Reads blocks of a file ahead, a batch at a time
+#[derive(Debug)]
+pub(crate) struct UringReader {
+    block_size: u64,
+    /// Created on the first read
+    ring: Option<Ring>,
+    buf: Vec<u8>,
+    /// Blocks read ahead: offset in the file, offset in `buf`, and length
+    blocks: VecDeque<(u64, usize, usize)>,
+}
+
+impl UringReader {
+    pub(crate) fn new(block_size: u64) -> Self {
+        Self {
+            block_size,
+            ring: None,
+            buf: Vec::new(),
+            blocks: VecDeque::new(),
+        }
+    }
+
+    /// The contents of the `len` bytes at `start`, which are empty at the end of the file
+    ///
+    /// Nothing past `end` is read ahead.
+    pub(crate) fn read(
+        &mut self,
+        fd: BorrowedFd<'_>,
+        start: u64,
+        len: u64,
+        end: u64,
+    ) -> io::Result<&[u8]> {
+        if self.blocks.front().map(|b| b.0) != Some(start) {
+            self.read_ahead(fd, start, len, end)?;
+        }
+        match self.blocks.pop_front() {
+            Some((_, at, len)) => Ok(&self.buf[at..at + len]),
+            None => Ok(&[]),
+        }
+    }
+
+    /// Replace the blocks read ahead with a batch starting with `len` bytes at `start`, and ending
+    /// by `end`
+    fn read_ahead(&mut self, fd: BorrowedFd<'_>, start: u64, len: u64, end: u64) -> io::Result<()> {
+        self.blocks.clear();
+        if self.ring.is_none() {
+            self.ring = Some(Ring::new(DEPTH)?);
+        }
+
+        // the first block may be short, to get to a block boundary, and so may the last, to stop
+        // at `end`
+        let max = (BATCH_BYTES / self.block_size).clamp(1, DEPTH as u64);
+        let after = end.saturating_sub(start + len);
+        let rest = after.saturating_add(self.block_size - 1) / self.block_size;
+        let count = (rest.min(max - 1) + 1) as usize;
+        let bs = self.block_size as usize;
+        // where each block starts in the file, and how long it is
+        let block = |i: usize| -> (u64, usize) {
+            if i == 0 {
+                (start, len as usize)
+            } else {
+                let offset = start + len + (i as u64 - 1) * bs as u64;
+                (offset, (end - offset).min(bs as u64) as usize)
+            }
+        };
+        self.buf.resize(count * bs, 0);
+        let mut reads: Vec<(&mut [u8], u64)> = self
+            .buf
+            .chunks_mut(bs)
+            .enumerate()
+            .map(|(i, b)| {
+                let (offset, len) = block(i);
+                (&mut b[..len], offset)
+            })
+            .collect();
+
+        let results = match unsafe { self.ring.as_mut().unwrap().read_all(fd, &mut reads) } {
+            Ok(r) => r,
+            Err(e) => {
+                // the kernel may still be writing to the buffer, and the ring may still have
+                // reads queued, so start over with new ones
+                std::mem::forget(std::mem::take(&mut self.buf));
+                self.ring = None;
+                return Err(e);
+            }
+        };
+
+        let mut offset = start;
+        for (i, r) in results.into_iter().enumerate() {
+            let want = block(i).1;
+            let mut got = match r {
+                Ok(n) => n,
+                // report it when this block is asked for
+                Err(e) if i == 0 => return Err(e),
+                Err(_) => break,
+            };
+            if got > 0 && got < want {
+                // short reads are allowed anywhere, so only the end of the file if it stays short
+                let at = i * bs + got;
+                got += pread_full(fd, &mut self.buf[at..i * bs + want], offset + got as u64)?;
+            }
+            if got == 0 {
+                break;
+            }
+            self.blocks.push_back((offset, i * bs, got));
+            if got < want {
+                break;
+            }
+            offset += got as u64;
+        }
+        Ok(())
+    }
+}
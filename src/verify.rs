@@ -0,0 +1,154 @@
+//! Checking that the holes a filesystem reports really read as zeros
+
+use crate::error::Error;
+use crate::unix::{pread_full, BorrowedFd};
+use crate::{AsFile, ItemKind, SparseIter, SparseRangeIter};
+use std::io;
+use std::ops::Range;
+use std::os::unix::io::{AsFd, OwnedFd};
+
+/// Most bytes read at once
+const CHUNK: u64 = 1024 * 1024;
+
+/// Bytes checked in one go before looking for the exact bytes that aren't zero, and the size of
+/// each sample
+const STRIDE: u64 = 4096;
+
+/// How much of each hole [`verify_holes()`] reads back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HoleCheck {
+    /// Read every byte of every hole
+    Full,
+    /// Read 4 KiB at the start of each hole, at every multiple of this many bytes into it, and at
+    /// its end
+    ///
+    /// A hole that's misreported is usually misreported as a whole (or in whole extents), so
+    /// sampling finds most of them while reading a small part of the file.
+    Sample(u64),
+}
+
+/// Read back the holes reported in `file`, and find any bytes in them that aren't zero
+///
+/// Hole reporting is up to the filesystem (see the crate docs), and one that reports data as a
+/// hole would have a copy or backup made with this crate silently lose it. This double checks
+/// the report, at the cost of reading the holes (or samples of them, see [`HoleCheck`]). Data is
+/// never read.
+///
+/// Returns the ranges of non-zero bytes found in holes, in order, with adjacent ones merged. An
+/// empty list means every byte checked was zero. If the filesystem can't report holes, there are
+/// none to check (see [`SparseIter::fallback_to_data()`]). This never moves the file's cursor
+/// (unless the `SparseIter` does, while looking for holes).
+///
+/// # Panics
+///
+/// If `check` is [`HoleCheck::Sample`] with a spacing of 0
+pub fn verify_holes<F: AsFile>(file: F, check: HoleCheck) -> io::Result<Vec<Range<u64>>> {
+    let fd = file.as_fd();
+    let mut found: Vec<Range<u64>> = Vec::new();
+    let mut buf = Vec::new();
+    for r in SparseRangeIter::from(SparseIter::from(fd).fallback_to_data()) {
+        let r = r?;
+        if r.kind != ItemKind::Hole {
+            continue;
+        }
+        check_hole(fd, r.start..r.end, check, &mut buf, &mut found)?;
+    }
+    Ok(found)
+}
+
+/// Checks holes one at a time as a scan finds them, for
+/// [`SparseScanBuilder::verify()`](crate::SparseScanBuilder::verify)
+#[derive(Debug)]
+pub(crate) struct HoleVerifier {
+    check: HoleCheck,
+    /// A descriptor of the file of its own, since the scan owns the file
+    fd: OwnedFd,
+    buf: Vec<u8>,
+}
+
+impl HoleVerifier {
+    pub(crate) fn new(fd: BorrowedFd<'_>, check: HoleCheck) -> io::Result<Self> {
+        Ok(Self {
+            check,
+            fd: fd.try_clone_to_owned()?,
+            buf: Vec::new(),
+        })
+    }
+
+    /// Fail with [`Error::HoleNotZero`] if the hole at `range` has data in it
+    pub(crate) fn check(&mut self, range: Range<u64>) -> io::Result<()> {
+        let mut found = Vec::new();
+        check_hole(
+            self.fd.as_fd(),
+            range,
+            self.check,
+            &mut self.buf,
+            &mut found,
+        )?;
+        match found.first() {
+            Some(bad) => Err(Error::HoleNotZero { offset: bad.start }.into()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Read back the hole at `range` (or samples of it), adding any bytes that aren't zero to `found`
+fn check_hole(
+    fd: BorrowedFd<'_>,
+    range: Range<u64>,
+    check: HoleCheck,
+    buf: &mut Vec<u8>,
+    found: &mut Vec<Range<u64>>,
+) -> io::Result<()> {
+    match check {
+        HoleCheck::Full => check_range(fd, range, buf, found),
+        HoleCheck::Sample(every) => {
+            assert!(every > 0, "sample spacing must be non-zero");
+            let (mut offset, mut checked) = (range.start, range.start);
+            while offset < range.end {
+                checked = (offset + STRIDE).min(range.end);
+                check_range(fd, offset..checked, buf, found)?;
+                offset = offset.saturating_add(every.max(STRIDE));
+            }
+            if checked < range.end {
+                let last = range.end.saturating_sub(STRIDE).max(checked);
+                check_range(fd, last..range.end, buf, found)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Read `range` of the file, adding any bytes that aren't zero to `found`
+fn check_range(
+    fd: BorrowedFd<'_>,
+    range: Range<u64>,
+    buf: &mut Vec<u8>,
+    found: &mut Vec<Range<u64>>,
+) -> io::Result<()> {
+    let mut pos = range.start;
+    while pos < range.end {
+        let want = (range.end - pos).min(CHUNK) as usize;
+        buf.resize(want, 0);
+        let n = pread_full(fd, buf, pos)?;
+        for (i, chunk) in buf[..n].chunks(STRIDE as usize).enumerate() {
+            if crate::is_zero(chunk) {
+                continue;
+            }
+            let base = pos + i as u64 * STRIDE;
+            for (j, _) in chunk.iter().enumerate().filter(|(_, &b)| b != 0) {
+                let at = base + j as u64;
+                match found.last_mut() {
+                    Some(f) if f.end == at => f.end = at + 1,
+                    _ => found.push(at..at + 1),
+                }
+            }
+        }
+        if n < want {
+            // the file was truncated since it was scanned
+            return Ok(());
+        }
+        pos += n as u64;
+    }
+    Ok(())
+}
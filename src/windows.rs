@@ -0,0 +1,590 @@
+use crate::error::{checked, checked_cast};
+use std::io;
+use std::os::windows::io::{AsHandle, AsRawHandle, BorrowedHandle};
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::ntdef::{BOOLEAN, LARGE_INTEGER};
+use winapi::shared::winerror::{
+    ERROR_ACCESS_DENIED, ERROR_HANDLE_EOF, ERROR_INVALID_FUNCTION, ERROR_INVALID_PARAMETER,
+    ERROR_MORE_DATA, ERROR_NOT_SUPPORTED,
+};
+use winapi::um::fileapi::{
+    FlushFileBuffers, GetVolumeInformationByHandleW, ReadFile, SetFileInformationByHandle,
+    SetFilePointerEx, WriteFile, FILE_ALLOCATION_INFO, FILE_BASIC_INFO, FILE_COMPRESSION_INFO,
+    FILE_END_OF_FILE_INFO, FILE_STANDARD_INFO,
+};
+use winapi::um::ioapiset::DeviceIoControl;
+use winapi::um::minwinbase::{
+    FileAllocationInfo, FileBasicInfo, FileCompressionInfo, FileEndOfFileInfo, FileStandardInfo,
+    FILE_INFO_BY_HANDLE_CLASS, OVERLAPPED,
+};
+use winapi::um::winbase::{GetFileInformationByHandleEx, FILE_BEGIN, FILE_CURRENT, FILE_END};
+use winapi::um::winioctl::{
+    FSCTL_GET_INTEGRITY_INFORMATION, FSCTL_QUERY_ALLOCATED_RANGES, FSCTL_SET_SPARSE,
+    FSCTL_SET_ZERO_DATA,
+};
+use winapi::um::winnt::HANDLE;
+
+/// A borrowed handle, under the name the code shared with unix uses for a borrowed file
+pub(crate) use std::os::windows::io::BorrowedHandle as BorrowedFd;
+
+/// `as_fd()` for anything with a handle, so the code shared with unix can borrow files the same way
+pub(crate) trait AsFd {
+    fn as_fd(&self) -> BorrowedHandle<'_>;
+}
+
+impl<T: AsHandle + ?Sized> AsFd for T {
+    fn as_fd(&self) -> BorrowedHandle<'_> {
+        self.as_handle()
+    }
+}
+
+// Windows has no `SEEK_DATA` or `SEEK_HOLE`. `seek()` answers them with
+// `FSCTL_QUERY_ALLOCATED_RANGES` instead, so they only need to be distinct from the other whences.
+// These are FreeBSD's values.
+pub(crate) const SEEK_DATA: i32 = 3;
+pub(crate) const SEEK_HOLE: i32 = 4;
+
+/// `GetFileInformationByHandleEx(FileStandardInfo)`, which has both the logical and allocated size
+pub(crate) fn standard_info(handle: BorrowedHandle<'_>) -> io::Result<FILE_STANDARD_INFO> {
+    let mut info = std::mem::MaybeUninit::<FILE_STANDARD_INFO>::uninit();
+    let r = unsafe {
+        GetFileInformationByHandleEx(
+            handle.as_raw_handle() as _,
+            FileStandardInfo,
+            info.as_mut_ptr() as _,
+            std::mem::size_of::<FILE_STANDARD_INFO>() as _,
+        )
+    };
+    if r == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { info.assume_init() })
+}
+
+/// A size from one of the `FILE_*_INFO`s, which can't be negative
+pub(crate) fn large_integer(v: &LARGE_INTEGER) -> io::Result<u64> {
+    checked_cast(unsafe { *v.QuadPart() })
+}
+
+/// `GetFileInformationByHandleEx(FileBasicInfo)`, which has the file's attributes
+pub(crate) fn basic_info(handle: BorrowedHandle<'_>) -> io::Result<FILE_BASIC_INFO> {
+    let mut info = std::mem::MaybeUninit::<FILE_BASIC_INFO>::uninit();
+    let r = unsafe {
+        GetFileInformationByHandleEx(
+            handle.as_raw_handle() as _,
+            FileBasicInfo,
+            info.as_mut_ptr() as _,
+            std::mem::size_of::<FILE_BASIC_INFO>() as _,
+        )
+    };
+    if r == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { info.assume_init() })
+}
+
+/// `GetFileInformationByHandleEx(FileCompressionInfo)`, which has the volume's cluster size
+///
+/// Filled in for uncompressed files too.
+pub(crate) fn compression_info(handle: BorrowedHandle<'_>) -> io::Result<FILE_COMPRESSION_INFO> {
+    let mut info = std::mem::MaybeUninit::<FILE_COMPRESSION_INFO>::uninit();
+    let r = unsafe {
+        GetFileInformationByHandleEx(
+            handle.as_raw_handle() as _,
+            FileCompressionInfo,
+            info.as_mut_ptr() as _,
+            std::mem::size_of::<FILE_COMPRESSION_INFO>() as _,
+        )
+    };
+    if r == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { info.assume_init() })
+}
+
+/// The `FILE_SUPPORTS_*` flags of the volume the file is on, from
+/// `GetVolumeInformationByHandleW()`
+pub(crate) fn volume_flags(handle: BorrowedHandle<'_>) -> io::Result<DWORD> {
+    let mut flags = 0;
+    let r = unsafe {
+        GetVolumeInformationByHandleW(
+            handle.as_raw_handle() as _,
+            std::ptr::null_mut(),
+            0,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            &mut flags,
+            std::ptr::null_mut(),
+            0,
+        )
+    };
+    if r == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(flags)
+}
+
+/// `FILE_SET_SPARSE_BUFFER`, the input to `FSCTL_SET_SPARSE`
+#[repr(C)]
+#[allow(non_snake_case)]
+struct FILE_SET_SPARSE_BUFFER {
+    SetSparse: BOOLEAN,
+}
+
+/// `FILE_ZERO_DATA_INFORMATION`, the input to `FSCTL_SET_ZERO_DATA`
+#[repr(C)]
+#[allow(non_snake_case)]
+struct FILE_ZERO_DATA_INFORMATION {
+    FileOffset: i64,
+    BeyondFinalZero: i64,
+}
+
+/// Issue a `DeviceIoControl()` that takes `input` and returns nothing
+fn fsctl_in<T>(handle: BorrowedHandle<'_>, code: DWORD, input: &T) -> io::Result<()> {
+    let mut returned = 0;
+    let r = unsafe {
+        DeviceIoControl(
+            handle.as_raw_handle() as _,
+            code,
+            input as *const T as _,
+            std::mem::size_of::<T>() as _,
+            std::ptr::null_mut(),
+            0,
+            &mut returned,
+            std::ptr::null_mut(),
+        )
+    };
+    if r == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// `FSCTL_SET_SPARSE`: set or clear the file's sparse attribute
+pub(crate) fn set_sparse(handle: BorrowedHandle<'_>, sparse: bool) -> io::Result<()> {
+    fsctl_in(
+        handle,
+        FSCTL_SET_SPARSE,
+        &FILE_SET_SPARSE_BUFFER {
+            SetSparse: sparse as BOOLEAN,
+        },
+    )
+}
+
+/// `FSCTL_SET_ZERO_DATA`: zero `start..end`, deallocating it if the file is sparse
+pub(crate) fn set_zero_data(handle: BorrowedHandle<'_>, start: u64, end: u64) -> io::Result<()> {
+    fsctl_in(
+        handle,
+        FSCTL_SET_ZERO_DATA,
+        &FILE_ZERO_DATA_INFORMATION {
+            FileOffset: checked_cast(start)?,
+            BeyondFinalZero: checked_cast(end)?,
+        },
+    )
+}
+
+/// Write `len` zero bytes at `offset` with `WriteFile()`, without touching the file's cursor
+///
+/// The handle must not have been opened for overlapped I/O.
+pub(crate) fn write_zeros(handle: BorrowedHandle<'_>, mut offset: u64, len: u64) -> io::Result<()> {
+    let zeros = [0u8; 64 * 1024];
+    let end = checked(offset.checked_add(len))?;
+    while offset < end {
+        let n = (end - offset).min(zeros.len() as u64) as DWORD;
+        let mut overlapped: OVERLAPPED = unsafe { std::mem::zeroed() };
+        unsafe {
+            let s = overlapped.u.s_mut();
+            s.Offset = offset as DWORD;
+            s.OffsetHigh = (offset >> 32) as DWORD;
+        }
+
+        let mut written = 0;
+        let r = unsafe {
+            WriteFile(
+                handle.as_raw_handle() as _,
+                zeros.as_ptr() as _,
+                n,
+                &mut written,
+                &mut overlapped,
+            )
+        };
+        if r == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if written == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        offset += u64::from(written);
+    }
+
+    Ok(())
+}
+
+/// `SetFileInformationByHandle()` with a `FILE_*_INFO` struct
+fn set_info<T>(
+    handle: BorrowedHandle<'_>,
+    class: FILE_INFO_BY_HANDLE_CLASS,
+    info: &mut T,
+) -> io::Result<()> {
+    let r = unsafe {
+        SetFileInformationByHandle(
+            handle.as_raw_handle() as _,
+            class,
+            info as *mut T as _,
+            std::mem::size_of::<T>() as _,
+        )
+    };
+    if r == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// Set how much space is allocated to the file (`FileAllocationInfo`)
+pub(crate) fn set_allocation_size(handle: BorrowedHandle<'_>, len: u64) -> io::Result<()> {
+    let mut info: FILE_ALLOCATION_INFO = unsafe { std::mem::zeroed() };
+    let len = checked_cast(len)?;
+    unsafe { *info.AllocationSize.QuadPart_mut() = len };
+    set_info(handle, FileAllocationInfo, &mut info)
+}
+
+/// Set the length of the file (`FileEndOfFileInfo`)
+pub(crate) fn set_len(handle: BorrowedHandle<'_>, len: u64) -> io::Result<()> {
+    let mut info: FILE_END_OF_FILE_INFO = unsafe { std::mem::zeroed() };
+    let len = checked_cast(len)?;
+    unsafe { *info.EndOfFile.QuadPart_mut() = len };
+    set_info(handle, FileEndOfFileInfo, &mut info)
+}
+
+/// `FSCTL_GET_INTEGRITY_INFORMATION_BUFFER`, the output of `FSCTL_GET_INTEGRITY_INFORMATION`
+#[repr(C)]
+#[allow(non_snake_case)]
+struct FSCTL_GET_INTEGRITY_INFORMATION_BUFFER {
+    ChecksumAlgorithm: u16,
+    Reserved: u16,
+    Flags: u32,
+    ChecksumChunkSizeInBytes: u32,
+    ClusterSizeInBytes: u32,
+}
+
+/// `DUPLICATE_EXTENTS_DATA`, the input to `FSCTL_DUPLICATE_EXTENTS_TO_FILE`
+#[repr(C)]
+#[allow(non_snake_case)]
+struct DUPLICATE_EXTENTS_DATA {
+    FileHandle: HANDLE,
+    SourceFileOffset: i64,
+    TargetFileOffset: i64,
+    ByteCount: i64,
+}
+
+/// Not in winapi: `CTL_CODE(FILE_DEVICE_FILE_SYSTEM, 209, METHOD_BUFFERED, FILE_WRITE_DATA)`
+const FSCTL_DUPLICATE_EXTENTS_TO_FILE: DWORD = 0x0009_8344;
+
+/// Issue a `DeviceIoControl()` that takes nothing and returns a `T`
+fn fsctl_out<T>(handle: BorrowedHandle<'_>, code: DWORD) -> io::Result<T> {
+    let mut out = std::mem::MaybeUninit::<T>::uninit();
+    let mut returned = 0;
+    let r = unsafe {
+        DeviceIoControl(
+            handle.as_raw_handle() as _,
+            code,
+            std::ptr::null_mut(),
+            0,
+            out.as_mut_ptr() as _,
+            std::mem::size_of::<T>() as _,
+            &mut returned,
+            std::ptr::null_mut(),
+        )
+    };
+    if r == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { out.assume_init() })
+}
+
+/// The filesystem's cluster size, from `FSCTL_GET_INTEGRITY_INFORMATION`
+///
+/// Only ReFS supports this, so it doubles as a check for whether extents can be duplicated.
+pub(crate) fn cluster_size(handle: BorrowedHandle<'_>) -> io::Result<u64> {
+    let info: FSCTL_GET_INTEGRITY_INFORMATION_BUFFER =
+        fsctl_out(handle, FSCTL_GET_INTEGRITY_INFORMATION)?;
+    Ok(u64::from(info.ClusterSizeInBytes))
+}
+
+/// `FSCTL_DUPLICATE_EXTENTS_TO_FILE`: clone `len` bytes of `src` at `src_offset` into `dst` at
+/// `dst_offset`
+///
+/// The offsets and `len` must be multiples of the cluster size, and `len` must be under 4 GiB.
+pub(crate) fn duplicate_extents(
+    src: BorrowedHandle<'_>,
+    dst: BorrowedHandle<'_>,
+    src_offset: u64,
+    dst_offset: u64,
+    len: u64,
+) -> io::Result<()> {
+    fsctl_in(
+        dst,
+        FSCTL_DUPLICATE_EXTENTS_TO_FILE,
+        &DUPLICATE_EXTENTS_DATA {
+            FileHandle: src.as_raw_handle() as HANDLE,
+            SourceFileOffset: checked_cast(src_offset)?,
+            TargetFileOffset: checked_cast(dst_offset)?,
+            ByteCount: checked_cast(len)?,
+        },
+    )
+}
+
+/// Like `lseek()`: move the file's cursor, returning where it ended up
+///
+/// `SEEK_DATA` and `SEEK_HOLE` are emulated (see [`next_data()`] and [`next_hole()`]), and don't
+/// move the cursor. Like `lseek()`, they return `None` (`ENXIO`) at or past the end of the file,
+/// and for `SEEK_DATA` when there's no data after `offset`.
+pub(crate) fn seek(
+    handle: BorrowedHandle<'_>,
+    offset: u64,
+    whence: i32,
+) -> io::Result<Option<u64>> {
+    let method = match whence {
+        SEEK_DATA => return next_data(handle, offset),
+        SEEK_HOLE => return next_hole(handle, offset),
+        libc::SEEK_SET => FILE_BEGIN,
+        libc::SEEK_CUR => FILE_CURRENT,
+        libc::SEEK_END => FILE_END,
+        _ => return Err(io::Error::from_raw_os_error(ERROR_INVALID_PARAMETER as i32)),
+    };
+
+    let mut distance: LARGE_INTEGER = unsafe { std::mem::zeroed() };
+    let mut new: LARGE_INTEGER = unsafe { std::mem::zeroed() };
+    unsafe { *distance.QuadPart_mut() = checked_cast(offset)? };
+    let r = unsafe { SetFilePointerEx(handle.as_raw_handle() as _, distance, &mut new, method) };
+    if r == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(Some(large_integer(&new)?))
+}
+
+/// `FILE_ALLOCATED_RANGE_BUFFER`, the input and (an array of them) the output of
+/// `FSCTL_QUERY_ALLOCATED_RANGES`
+#[repr(C)]
+#[allow(non_snake_case)]
+#[derive(Clone, Copy)]
+struct FILE_ALLOCATED_RANGE_BUFFER {
+    FileOffset: i64,
+    Length: i64,
+}
+
+/// `FSCTL_QUERY_ALLOCATED_RANGES`: the allocated ranges that overlap `start..end`, in order
+///
+/// At most `max` are returned, clipped to `start..end`. The second value is true if there were
+/// more.
+fn allocated_ranges(
+    handle: BorrowedHandle<'_>,
+    start: u64,
+    end: u64,
+    max: usize,
+) -> io::Result<(Vec<std::ops::Range<u64>>, bool)> {
+    let mut out = vec![
+        FILE_ALLOCATED_RANGE_BUFFER {
+            FileOffset: 0,
+            Length: 0,
+        };
+        max
+    ];
+    let query = FILE_ALLOCATED_RANGE_BUFFER {
+        FileOffset: checked_cast(start)?,
+        Length: checked_cast(end - start)?,
+    };
+    let mut returned = 0;
+    let r = unsafe {
+        DeviceIoControl(
+            handle.as_raw_handle() as _,
+            FSCTL_QUERY_ALLOCATED_RANGES,
+            &query as *const FILE_ALLOCATED_RANGE_BUFFER as _,
+            std::mem::size_of::<FILE_ALLOCATED_RANGE_BUFFER>() as _,
+            out.as_mut_ptr() as _,
+            std::mem::size_of_val(&out[..]) as _,
+            &mut returned,
+            std::ptr::null_mut(),
+        )
+    };
+    // with `ERROR_MORE_DATA`, `out` is still filled with as many ranges as fit
+    let more = r == 0 && {
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() != Some(ERROR_MORE_DATA as i32) {
+            return Err(e);
+        }
+        true
+    };
+
+    let n = returned as usize / std::mem::size_of::<FILE_ALLOCATED_RANGE_BUFFER>();
+    let ranges = out[..n]
+        .iter()
+        .map(|r| {
+            let offset: u64 = checked_cast(r.FileOffset)?;
+            let len: u64 = checked_cast(r.Length)?;
+            Ok(offset.max(start)..checked(offset.checked_add(len))?.min(end))
+        })
+        .collect::<io::Result<_>>()?;
+    Ok((ranges, more))
+}
+
+/// How many ranges to ask `FSCTL_QUERY_ALLOCATED_RANGES` for at once
+const RANGES_PER_QUERY: usize = 64;
+
+/// `SEEK_DATA`: where the first allocated range at or after `offset` starts
+///
+/// Files that aren't marked sparse are reported as a single allocated range, so they're all data.
+fn next_data(handle: BorrowedHandle<'_>, offset: u64) -> io::Result<Option<u64>> {
+    let len = file_len(handle)?;
+    if offset >= len {
+        return Ok(None);
+    }
+
+    let (ranges, _) = allocated_ranges(handle, offset, len, 1)?;
+    Ok(ranges.first().map(|r| r.start))
+}
+
+/// `SEEK_HOLE`: where the first unallocated range at or after `offset` starts, or the end of the
+/// file if there isn't one
+fn next_hole(handle: BorrowedHandle<'_>, offset: u64) -> io::Result<Option<u64>> {
+    let len = file_len(handle)?;
+    if offset >= len {
+        return Ok(None);
+    }
+
+    let mut pos = offset;
+    while pos < len {
+        let (ranges, more) = allocated_ranges(handle, pos, len, RANGES_PER_QUERY)?;
+        for r in &ranges {
+            if r.start > pos {
+                return Ok(Some(pos));
+            }
+            pos = pos.max(r.end);
+        }
+        if !more {
+            break;
+        }
+    }
+
+    Ok(Some(pos.min(len)))
+}
+
+/// The file's length, from `FileStandardInfo`
+pub(crate) fn file_len(handle: BorrowedHandle<'_>) -> io::Result<u64> {
+    large_integer(&standard_info(handle)?.EndOfFile)
+}
+
+/// Where a file's cursor was, put back when this is dropped
+///
+/// This only holds the handle's value, so it must be dropped before the handle is closed.
+#[derive(Debug)]
+pub(crate) struct SavedCursor {
+    handle: HANDLE,
+    cursor: u64,
+}
+
+// Like a unix `RawFd`, the handle is just a number here
+unsafe impl Send for SavedCursor {}
+unsafe impl Sync for SavedCursor {}
+
+impl SavedCursor {
+    pub(crate) fn new(handle: BorrowedHandle<'_>) -> io::Result<Self> {
+        // SEEK_CUR never returns `None`
+        let cursor = seek(handle, 0, libc::SEEK_CUR)?.unwrap();
+        Ok(Self {
+            handle: handle.as_raw_handle() as HANDLE,
+            cursor,
+        })
+    }
+}
+
+impl Drop for SavedCursor {
+    fn drop(&mut self) {
+        let handle = unsafe { BorrowedHandle::borrow_raw(self.handle as _) };
+        // there's nothing useful to do with an error from a destructor
+        let _ = seek(handle, self.cursor, libc::SEEK_SET);
+    }
+}
+
+/// What the file looked like at some point, to tell whether it has changed since
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileVersion {
+    len: u64,
+    /// `ChangeTime`, in 100ns intervals. Writes, truncation, and metadata changes all update it.
+    changed: i64,
+}
+
+impl FileVersion {
+    pub(crate) fn of(handle: BorrowedHandle<'_>) -> io::Result<Self> {
+        let info = basic_info(handle)?;
+        Ok(Self {
+            len: file_len(handle)?,
+            changed: unsafe { *info.ChangeTime.QuadPart() },
+        })
+    }
+}
+
+/// Does `e` indicate that the file or filesystem doesn't support `FSCTL_QUERY_ALLOCATED_RANGES`?
+pub(crate) fn is_unsupported(e: &io::Error) -> bool {
+    matches!(
+        e.raw_os_error().map(|e| e as DWORD),
+        Some(ERROR_INVALID_FUNCTION) | Some(ERROR_NOT_SUPPORTED)
+    )
+}
+
+/// Read into `buf` from `offset` with `ReadFile()`
+///
+/// The handle must not have been opened for overlapped I/O. Unlike `pread()`, this moves the
+/// file's cursor (to the end of what was read).
+pub(crate) fn pread(handle: BorrowedHandle<'_>, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    let mut overlapped: OVERLAPPED = unsafe { std::mem::zeroed() };
+    unsafe {
+        let s = overlapped.u.s_mut();
+        s.Offset = offset as DWORD;
+        s.OffsetHigh = (offset >> 32) as DWORD;
+    }
+
+    let mut read = 0;
+    let r = unsafe {
+        ReadFile(
+            handle.as_raw_handle() as _,
+            buf.as_mut_ptr() as _,
+            buf.len().min(DWORD::MAX as usize) as DWORD,
+            &mut read,
+            &mut overlapped,
+        )
+    };
+    if r == 0 {
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() == Some(ERROR_HANDLE_EOF as i32) {
+            return Ok(0);
+        }
+        return Err(e);
+    }
+
+    Ok(read as usize)
+}
+
+/// `FlushFileBuffers()`
+///
+/// That needs write access. Read only handles have nothing of their own to flush, and
+/// `FSCTL_QUERY_ALLOCATED_RANGES` doesn't depend on what has reached the disk, so for them this
+/// does nothing.
+pub(crate) fn fdatasync(handle: BorrowedHandle<'_>) -> io::Result<()> {
+    if unsafe { FlushFileBuffers(handle.as_raw_handle() as _) } == 0 {
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() != Some(ERROR_ACCESS_DENIED as i32) {
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,149 @@
+//! Writing a stream of bytes to a file, leaving holes where it is zero
+
+use crate::unix::{file_len, pwrite_all, set_len};
+use crate::{is_zero, punch_hole_or_zero, AsFile};
+use std::io::{self, Write};
+
+/// Size of the blocks checked for zeros, unless [`SparseWriter::block_size()`] is used
+const DEFAULT_BLOCK_SIZE: u64 = 4096;
+
+/// A [`Write`] that skips over blocks of zeros instead of writing them
+///
+/// Everything written is split into blocks (aligned to multiples of the block size, 4 KiB unless
+/// [`block_size()`](Self::block_size) is used). Blocks that aren't entirely zeros are written to
+/// the file. Blocks of zeros are not: past the end of the file they are skipped, leaving holes,
+/// and over existing parts of the file they are punched out (or, where holes can't be punched,
+/// written after all; see [`punch_hole_or_zero()`](crate::punch_hole_or_zero)). This produces a
+/// sparse file from a dense stream, for example when decompressing a disk image.
+///
+/// Writes are buffered until a block is complete. [`flush()`](Write::flush) writes out any
+/// partial block, and extends the file if it ended in skipped zeros. [`finish()`](Self::finish)
+/// does the same, returning the file. Dropping a `SparseWriter` also flushes it, ignoring any
+/// errors.
+///
+/// Writing starts at offset 0 (or the offset given to [`starting_at()`](Self::starting_at)) and
+/// uses positioned writes: the file's cursor is never used or moved. The file is never shortened,
+/// so anything already past the last byte written is left alone.
+#[derive(Debug)]
+pub struct SparseWriter<F: AsFile> {
+    /// `None` only once `finish()` has taken it
+    file: Option<F>,
+    block_size: u64,
+    /// The offset `buf` starts at
+    pos: u64,
+    /// Bytes of the block containing `pos`, not yet written
+    buf: Vec<u8>,
+    /// Where the run of zeros that ends at `pos` started, if there is one
+    zeros: Option<u64>,
+    /// The length of the file: zeros past this can be skipped
+    len: u64,
+}
+
+impl<F: AsFile> SparseWriter<F> {
+    /// Write to `file` from the start
+    pub fn new(file: F) -> io::Result<Self> {
+        Self::starting_at(file, 0)
+    }
+
+    /// Write to `file` starting at byte `offset`
+    pub fn starting_at(file: F, offset: u64) -> io::Result<Self> {
+        let len = file_len(file.as_fd())?;
+        Ok(Self {
+            file: Some(file),
+            block_size: DEFAULT_BLOCK_SIZE,
+            pos: offset,
+            buf: Vec::new(),
+            zeros: None,
+            len,
+        })
+    }
+
+    /// Check for zeros in blocks of `block_size` bytes
+    ///
+    /// Smaller blocks find more zeros, but filesystems can't make holes smaller than their own
+    /// block size, so this should be a multiple of it.
+    ///
+    /// # Panics
+    ///
+    /// If `block_size` is 0
+    pub fn block_size(mut self, block_size: u64) -> Self {
+        assert!(block_size > 0, "block size must be non-zero");
+        self.block_size = block_size;
+        self
+    }
+
+    /// The offset the next byte written will go to
+    pub fn position(&self) -> u64 {
+        self.pos + self.buf.len() as u64
+    }
+
+    /// Flush everything written, and return the file
+    pub fn finish(mut self) -> io::Result<F> {
+        self.flush()?;
+        Ok(self.file.take().unwrap())
+    }
+
+    /// Write out (or skip) the buffered bytes, which are all or the end of a block
+    fn write_buf(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+
+        let end = self.position();
+        if is_zero(&self.buf) {
+            self.zeros.get_or_insert(self.pos);
+        } else {
+            self.write_zeros()?;
+            pwrite_all(self.file.as_ref().unwrap().as_fd(), &self.buf, self.pos)?;
+            self.len = self.len.max(end);
+        }
+        self.pos = end;
+        self.buf.clear();
+        Ok(())
+    }
+
+    /// Make the pending run of zeros read as zeros
+    fn write_zeros(&mut self) -> io::Result<()> {
+        if let Some(start) = self.zeros.take() {
+            // nothing to do past the end of the file, it's already a hole
+            let end = self.pos.min(self.len);
+            if start < end {
+                punch_hole_or_zero(self.file.as_ref().unwrap(), start, end - start)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<F: AsFile> Write for SparseWriter<F> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        // a full block is only written out on the next write, so that if writing it fails, none
+        // of `data` has been taken
+        let room = self.block_size - self.position() % self.block_size;
+        if room == self.block_size {
+            self.write_buf()?;
+        }
+
+        let n = data.len().min(room as usize);
+        self.buf.extend_from_slice(&data[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_buf()?;
+        self.write_zeros()?;
+        if self.pos > self.len {
+            set_len(self.file.as_ref().unwrap().as_fd(), self.pos)?;
+            self.len = self.pos;
+        }
+        Ok(())
+    }
+}
+
+impl<F: AsFile> Drop for SparseWriter<F> {
+    fn drop(&mut self) {
+        if self.file.is_some() {
+            let _ = self.flush();
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Finding runs of zeros in buffers
+//!
+//! Checking for zeros is most of the work when scanning file contents, so [`is_zero()`] uses SIMD
+//! instructions when the CPU has them (detected at runtime). [`Segmenter`] builds on it to split
+//! a stream of bytes into runs of data and zeros.
+
+use crate::{ItemKind, SparseRangeItem};
+
+/// Is every byte in `buf` zero?
+pub fn is_zero(buf: &[u8]) -> bool {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return unsafe { x86::is_zero_avx2(buf) };
+        }
+        if is_x86_feature_detected!("sse2") {
+            return unsafe { x86::is_zero_sse2(buf) };
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            return unsafe { aarch64::is_zero_neon(buf) };
+        }
+    }
+
+    is_zero_generic(buf)
+}
+
+/// Compare a word at a time, for CPUs we don't have anything better for
+fn is_zero_generic(buf: &[u8]) -> bool {
+    let (head, body, tail) = unsafe { buf.align_to::<u64>() };
+    head.iter().all(|&b| b == 0) && body.iter().all(|&w| w == 0) && tail.iter().all(|&b| b == 0)
+}
+
+/// Splits a stream of bytes into runs of data and zeros, without a file
+///
+/// This is the classification a [`read_scan()`](crate::SparseIter::read_scan) does, for bytes
+/// that arrive a piece at a time (off the network, out of a decompressor) and haven't been
+/// written anywhere yet: the stream is split into blocks of `block_size` bytes (the last may be
+/// shorter), and each block that is entirely zeros is a `Hole`. Adjacent blocks of the same kind
+/// make up a single run.
+///
+/// Pieces of any size can be [`feed()`](Self::feed)ed, and blocks may be split between them.
+/// A run is only known to be over once a block of the other kind is seen, so each call returns
+/// the runs that ended, and [`finish()`](Self::finish) returns the rest.
+#[derive(Debug, Clone)]
+pub struct Segmenter {
+    block_size: u64,
+    /// Where the current block starts
+    pos: u64,
+    /// How much of the current block has been fed
+    fill: u64,
+    /// Whether any of the current block isn't zero
+    data: bool,
+    /// The run the blocks before the current one are part of
+    run: Option<SparseRangeItem>,
+}
+
+impl Segmenter {
+    /// Split a stream into blocks of `block_size` bytes
+    ///
+    /// # Panics
+    ///
+    /// If `block_size` is 0
+    pub fn new(block_size: u64) -> Self {
+        assert!(block_size > 0, "block size must be non-zero");
+        Self {
+            block_size,
+            pos: 0,
+            fill: 0,
+            data: false,
+            run: None,
+        }
+    }
+
+    /// Classify the next piece of the stream, returning the runs that ended in it
+    pub fn feed(&mut self, mut buf: &[u8]) -> Vec<SparseRangeItem> {
+        let mut ended = Vec::new();
+        while !buf.is_empty() {
+            // `block_size - fill` may not fit in a usize, but `buf.len()` does
+            let n = (self.block_size - self.fill).min(buf.len() as u64) as usize;
+            let (block, rest) = buf.split_at(n);
+            if !self.data && !is_zero(block) {
+                self.data = true;
+            }
+            self.fill += n as u64;
+            buf = rest;
+
+            if self.fill == self.block_size {
+                self.end_block(&mut ended);
+            }
+        }
+        ended
+    }
+
+    /// How many bytes have been fed so far
+    pub fn offset(&self) -> u64 {
+        self.pos + self.fill
+    }
+
+    /// End the stream, returning the runs that hadn't ended yet (up to 2, or none for an empty
+    /// stream)
+    pub fn finish(mut self) -> Vec<SparseRangeItem> {
+        let mut ended = Vec::new();
+        if self.fill > 0 {
+            self.end_block(&mut ended);
+        }
+        ended.extend(self.run.take());
+        ended
+    }
+
+    fn end_block(&mut self, ended: &mut Vec<SparseRangeItem>) {
+        let kind = if self.data {
+            ItemKind::Data
+        } else {
+            ItemKind::Hole
+        };
+        let end = self.pos + self.fill;
+        match self.run {
+            Some(ref mut run) if run.kind == kind => run.end = end,
+            _ => {
+                let start = self.pos;
+                ended.extend(self.run.replace(SparseRangeItem { kind, start, end }));
+            }
+        }
+        self.pos = end;
+        self.fill = 0;
+        self.data = false;
+    }
+}
+
+// Each SIMD version ORs together 4 vectors worth of bytes at a time and only then checks the
+// result, which keeps the (relatively expensive) test off the critical path while still bailing
+// out early on data.
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn is_zero_avx2(buf: &[u8]) -> bool {
+        const STEP: usize = 4 * 32;
+        let mut chunks = buf.chunks_exact(STEP);
+        for c in &mut chunks {
+            let p = c.as_ptr() as *const __m256i;
+            let a = _mm256_or_si256(_mm256_loadu_si256(p), _mm256_loadu_si256(p.add(1)));
+            let b = _mm256_or_si256(_mm256_loadu_si256(p.add(2)), _mm256_loadu_si256(p.add(3)));
+            let v = _mm256_or_si256(a, b);
+            if _mm256_testz_si256(v, v) == 0 {
+                return false;
+            }
+        }
+
+        super::is_zero_generic(chunks.remainder())
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn is_zero_sse2(buf: &[u8]) -> bool {
+        const STEP: usize = 4 * 16;
+        let mut chunks = buf.chunks_exact(STEP);
+        for c in &mut chunks {
+            let p = c.as_ptr() as *const __m128i;
+            let a = _mm_or_si128(_mm_loadu_si128(p), _mm_loadu_si128(p.add(1)));
+            let b = _mm_or_si128(_mm_loadu_si128(p.add(2)), _mm_loadu_si128(p.add(3)));
+            let v = _mm_or_si128(a, b);
+            // sse2 has no `ptest`, compare against zero and check every byte matched
+            if _mm_movemask_epi8(_mm_cmpeq_epi8(v, _mm_setzero_si128())) != 0xffff {
+                return false;
+            }
+        }
+
+        super::is_zero_generic(chunks.remainder())
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod aarch64 {
+    use std::arch::aarch64::*;
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn is_zero_neon(buf: &[u8]) -> bool {
+        const STEP: usize = 4 * 16;
+        let mut chunks = buf.chunks_exact(STEP);
+        for c in &mut chunks {
+            let p = c.as_ptr();
+            let a = vorrq_u8(vld1q_u8(p), vld1q_u8(p.add(16)));
+            let b = vorrq_u8(vld1q_u8(p.add(32)), vld1q_u8(p.add(48)));
+            if vmaxvq_u8(vorrq_u8(a, b)) != 0 {
+                return false;
+            }
+        }
+
+        super::is_zero_generic(chunks.remainder())
+    }
+}
@@ -0,0 +1,117 @@
+//! Working around openzfs not reporting holes in files with unsynced changes
+//!
+//! With the `zfs_dmu_offset_next_sync` module parameter turned off (it was off by default before
+//! openzfs 2.1.5, and still is on some systems), ZFS answers `SEEK_HOLE` for a file with dirty
+//! data as if it had no holes at all. That's correct, but pessimistic. Syncing the file first
+//! gets the real answer.
+
+use crate::error::{checked, checked_cast};
+use crate::unix::{fdatasync, file_len, stat};
+use crate::{AsFile, ScanWarning, SparseIter, SEEK_HOLE};
+use std::io;
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "freebsd"
+))]
+use std::os::unix::io::{AsRawFd, BorrowedFd};
+
+// 32-bit glibc's `fstatfs()` fails with EOVERFLOW on large filesystems (see `unix.rs`)
+#[cfg(any(
+    target_os = "macos",
+    target_os = "freebsd",
+    all(target_os = "linux", any(target_env = "musl", target_env = "ohos"))
+))]
+use libc::{fstatfs as statfs_raw, statfs as Statfs};
+#[cfg(any(
+    target_os = "android",
+    all(
+        target_os = "linux",
+        not(any(target_env = "musl", target_env = "ohos"))
+    )
+))]
+use libc::{fstatfs64 as statfs_raw, statfs64 as Statfs};
+
+/// `ZFS_SUPER_MAGIC`, the `f_type` openzfs reports on linux
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const ZFS_SUPER_MAGIC: u64 = 0x2fc1_2fc1;
+
+/// Is `file` on a ZFS filesystem?
+///
+/// This is detected on linux, android, macos, and freebsd (with `fstatfs()`). Everywhere else,
+/// this is always `false`.
+pub fn is_zfs<F: AsFile>(file: F) -> io::Result<bool> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        // the type of f_type differs between architectures and C libraries
+        #[allow(clippy::unnecessary_cast)]
+        let fs_type = fstatfs(file.as_fd())?.f_type as u64;
+        Ok(fs_type == ZFS_SUPER_MAGIC)
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "freebsd"))]
+    {
+        let st = fstatfs(file.as_fd())?;
+        let name = st.f_fstypename.iter().take_while(|&&c| c != 0);
+        Ok(name.map(|&c| c as u8).eq(b"zfs".iter().copied()))
+    }
+
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "freebsd"
+    )))]
+    {
+        let _ = file;
+        Ok(false)
+    }
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "freebsd"
+))]
+fn fstatfs(fd: BorrowedFd<'_>) -> io::Result<Statfs> {
+    let mut st = std::mem::MaybeUninit::<Statfs>::uninit();
+    let r = unsafe { statfs_raw(fd.as_raw_fd(), st.as_mut_ptr()) };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(unsafe { st.assume_init() })
+}
+
+impl<F: AsFile> SparseIter<F> {
+    /// If the file is on ZFS, and it looks like ZFS is hiding holes past `offset` because the
+    /// file has unsynced changes, sync it
+    ///
+    /// ZFS reports no holes at all in a file with dirty data, so the hint is that `SEEK_HOLE`
+    /// finds none, even though less storage is allocated than the file's length. That's also true
+    /// of compressed files without holes, and syncing them is harmless. If syncing fails, the
+    /// scan goes ahead with a warning.
+    pub(crate) fn sync_if_zfs_hides_holes(&mut self, offset: u64) -> io::Result<()> {
+        let fd = self.file.as_fd();
+        if !is_zfs(fd)? {
+            return Ok(());
+        }
+
+        let len = file_len(fd)?;
+        match self.seek(offset, SEEK_HOLE)? {
+            Some(hole) if hole >= len => {}
+            _ => return Ok(()),
+        }
+        let blocks: u64 = checked_cast(stat(fd)?.st_blocks)?;
+        if checked(blocks.checked_mul(512))? >= len {
+            return Ok(());
+        }
+
+        if fdatasync(fd).is_err() {
+            self.warning = Some(ScanWarning::ZfsUnsynced);
+        }
+        Ok(())
+    }
+}
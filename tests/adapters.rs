@@ -0,0 +1,199 @@
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{ItemKind, SparseIter, SparseRangeItem, SparseRangeIter, SparseRangeIterExt};
+
+fn collect<I: Iterator<Item = std::io::Result<fs_sparse::SparseRangeItem>>>(
+    i: I,
+) -> Vec<(ItemKind, u64, u64)> {
+    i.map(|r| r.unwrap())
+        .map(|r| (r.kind, r.start / UNIT, r.end / UNIT))
+        .collect()
+}
+
+use ItemKind::{Data, Hole};
+
+#[test]
+fn only() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 7, &[1, 2, 5]);
+        let ranges = || SparseRangeIter::from(SparseIter::from(&f));
+
+        assert_eq!(
+            collect(ranges().data_only()),
+            vec![(Data, 1, 3), (Data, 5, 6)],
+            "{}",
+            dir.display()
+        );
+        assert_eq!(
+            collect(ranges().holes_only()),
+            vec![(Hole, 0, 1), (Hole, 3, 5), (Hole, 6, 7)],
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+fn synthetic(r: &[(ItemKind, u64, u64)]) -> impl Iterator<Item = std::io::Result<SparseRangeItem>> {
+    r.iter()
+        .map(|&(kind, start, end)| {
+            Ok(SparseRangeItem {
+                kind,
+                start: start * UNIT,
+                end: end * UNIT,
+            })
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+#[test]
+fn coalesce() {
+    let r = [
+        (Data, 0, 1),
+        (Data, 1, 2),
+        (Hole, 2, 3),
+        (Hole, 3, 4),
+        (Data, 4, 5),
+    ];
+    assert_eq!(
+        collect(synthetic(&r).coalesce()),
+        vec![(Data, 0, 2), (Hole, 2, 4), (Data, 4, 5)]
+    );
+
+    // ranges with gaps between them aren't merged
+    let r = [(Data, 0, 1), (Data, 2, 3)];
+    assert_eq!(collect(synthetic(&r).coalesce()), r.to_vec());
+}
+
+#[test]
+fn coalesce_gaps() {
+    let r = [
+        (Hole, 0, 1),
+        (Data, 1, 2),
+        (Hole, 2, 3),
+        (Data, 3, 4),
+        (Hole, 4, 6),
+        (Data, 6, 7),
+        (Hole, 7, 8),
+    ];
+    assert_eq!(
+        collect(synthetic(&r).coalesce_gaps(2 * UNIT)),
+        vec![
+            (Hole, 0, 1),
+            (Data, 1, 4),
+            (Hole, 4, 6),
+            (Data, 6, 7),
+            (Hole, 7, 8)
+        ]
+    );
+    assert_eq!(
+        collect(synthetic(&r).coalesce_gaps(2 * UNIT + 1)),
+        vec![(Hole, 0, 1), (Data, 1, 7), (Hole, 7, 8)]
+    );
+
+    // a small hole that grows too large isn't merged
+    let r = [(Data, 0, 1), (Hole, 1, 2), (Hole, 2, 4), (Data, 4, 5)];
+    assert_eq!(
+        collect(synthetic(&r).coalesce_gaps(2 * UNIT)),
+        vec![(Data, 0, 1), (Hole, 1, 4), (Data, 4, 5)]
+    );
+
+    // a small hole followed by a gap isn't merged
+    let r = [(Data, 0, 1), (Hole, 1, 2), (Data, 3, 4)];
+    assert_eq!(collect(synthetic(&r).coalesce_gaps(2 * UNIT)), r.to_vec());
+}
+
+#[test]
+fn coalesce_file() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 7, &[1, 2, 5]);
+        let r = SparseRangeIter::from(SparseIter::from(&f)).coalesce_gaps(3 * UNIT);
+        assert_eq!(
+            collect(r),
+            vec![(Hole, 0, 1), (Data, 1, 6), (Hole, 6, 7)],
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn min_hole_size() {
+    let r = [
+        (Hole, 0, 1),
+        (Data, 1, 2),
+        (Hole, 2, 3),
+        (Data, 3, 4),
+        (Hole, 4, 6),
+        (Data, 6, 7),
+        (Hole, 7, 8),
+    ];
+    assert_eq!(
+        collect(synthetic(&r).min_hole_size(2 * UNIT)),
+        vec![(Data, 0, 4), (Hole, 4, 6), (Data, 6, 8)]
+    );
+    assert_eq!(collect(synthetic(&r).min_hole_size(UNIT)), r.to_vec());
+    assert_eq!(
+        collect(synthetic(&r).min_hole_size(u64::MAX)),
+        vec![(Data, 0, 8)]
+    );
+}
+
+#[test]
+fn aligned() {
+    let r = [
+        (Hole, 0, 3),
+        (Data, 3, 4),
+        (Hole, 4, 5),
+        (Data, 5, 6),
+        (Hole, 6, 11),
+        (Data, 11, 12),
+        (Hole, 12, 13),
+    ];
+    assert_eq!(
+        collect(synthetic(&r).aligned(2 * UNIT)),
+        vec![(Hole, 0, 2), (Data, 2, 6), (Hole, 6, 10), (Data, 10, 13)]
+    );
+    assert_eq!(collect(synthetic(&r).aligned(UNIT)), r.to_vec());
+    assert_eq!(
+        collect(synthetic(&r).aligned(16 * UNIT)),
+        vec![(Data, 0, 13)]
+    );
+}
+
+#[test]
+fn aligned_file() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 8, &[2, 5]);
+        let r = SparseRangeIter::from(SparseIter::from(&f)).aligned(4 * UNIT);
+        assert_eq!(collect(r), vec![(Data, 0, 8)], "{}", dir.display());
+        let r = SparseRangeIter::from(SparseIter::from(&f)).aligned(2 * UNIT);
+        assert_eq!(
+            collect(r),
+            vec![(Hole, 0, 2), (Data, 2, 6), (Hole, 6, 8)],
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn data_chunks() {
+    let r = [(Hole, 0, 1), (Data, 1, 2), (Hole, 2, 3), (Data, 3, 6)];
+    let c: Vec<_> = synthetic(&r)
+        .data_chunks(2 * UNIT)
+        .map(|c| c.unwrap())
+        .collect();
+    assert_eq!(
+        c,
+        vec![(UNIT, UNIT), (3 * UNIT, 2 * UNIT), (5 * UNIT, UNIT)]
+    );
+
+    let c: Vec<_> = synthetic(&r)
+        .data_chunks(1)
+        .take(3)
+        .map(|c| c.unwrap())
+        .collect();
+    assert_eq!(c, vec![(UNIT, 1), (UNIT + 1, 1), (UNIT + 2, 1)]);
+}
@@ -0,0 +1,54 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{contents, dirs, ranges, sparse_file, UNIT};
+use fs_sparse::{apply_map, apply_map_from_reader, Error, SparseMap};
+
+#[test]
+fn from_read_at() {
+    for dir in dirs() {
+        let (_t, src) = sparse_file(&dir, 6, &[1, 2, 4]);
+        let map = SparseMap::from_file(&src).unwrap();
+        // `dst` starts out longer, and with data where `src` has holes
+        let (_t, dst) = sparse_file(&dir, 8, &[0, 5, 7]);
+
+        assert_eq!(apply_map(&map, &src, &dst).unwrap(), 3 * UNIT);
+        assert_eq!(ranges(&dst), ranges(&src), "{}", dir.display());
+        assert_eq!(contents(&dst), contents(&src), "{}", dir.display());
+    }
+}
+
+#[test]
+fn from_reader() {
+    for dir in dirs() {
+        let (_t, src) = sparse_file(&dir, 5, &[0, 3]);
+        let map = SparseMap::from_file(&src).unwrap();
+        let (_t, dst) = sparse_file(&dir, 0, &[]);
+
+        // only the data, then something else
+        let mut packed = vec![0xffu8; 2 * UNIT as usize];
+        packed.extend_from_slice(b"more");
+        let mut r = &packed[..];
+
+        assert_eq!(apply_map_from_reader(&map, &mut r, &dst).unwrap(), 2 * UNIT);
+        assert_eq!(r, b"more");
+        assert_eq!(ranges(&dst), ranges(&src), "{}", dir.display());
+        assert_eq!(contents(&dst), contents(&src), "{}", dir.display());
+    }
+}
+
+#[test]
+fn short_source() {
+    let (_t, src) = sparse_file(&std::env::temp_dir(), 3, &[2]);
+    let map = SparseMap::from_file(&src).unwrap();
+    let (_t, dst) = sparse_file(&std::env::temp_dir(), 0, &[]);
+
+    let short = vec![0xffu8; 2 * UNIT as usize + 10];
+    match apply_map(&map, &short, &dst) {
+        Err(Error::Io { source }) => {
+            assert_eq!(source.kind(), std::io::ErrorKind::UnexpectedEof)
+        }
+        r => panic!("{:?}", r),
+    }
+}
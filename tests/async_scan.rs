@@ -0,0 +1,149 @@
+#![cfg(all(unix, feature = "async"))]
+
+mod common;
+
+use common::{dirs, sparse_file};
+use fs_sparse::{scan_async, AsyncSparseIter, SparseIter, SparseRangeIter};
+use std::future::Future;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake};
+use std::thread::{self, Thread};
+
+/// Just enough of an executor to run one future on this thread
+fn block_on<F: Future>(f: F) -> F::Output {
+    struct Unpark(Thread);
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut f = Box::pin(f);
+    loop {
+        match f.as_mut().poll(&mut cx) {
+            Poll::Ready(v) => return v,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[test]
+fn ranges() {
+    for dir in dirs() {
+        let (_t, file) = sparse_file(&dir, 9, &[1, 2, 5, 7]);
+        let expected: Vec<_> = SparseRangeIter::from(SparseIter::from(&file))
+            .map(|r| r.unwrap())
+            .collect();
+
+        let mut iter = scan_async(file);
+        let got = block_on(async {
+            let mut v = Vec::new();
+            while let Some(r) = iter.next().await {
+                v.push(r.unwrap());
+            }
+            v
+        });
+        assert_eq!(got, expected, "{}", dir.display());
+    }
+}
+
+#[test]
+fn many_items_and_early_drop() {
+    // more items than are buffered, so the thread has to wait for us
+    let items = (0..1000u64).map(Ok);
+    let mut iter = AsyncSparseIter::new(items);
+    let got = block_on(async {
+        let mut v = Vec::new();
+        while let Some(i) = iter.next().await {
+            v.push(i.unwrap());
+        }
+        v
+    });
+    assert_eq!(got, (0..1000).collect::<Vec<_>>());
+
+    // dropping part way through stops the thread instead of leaving it blocked
+    let mut iter = AsyncSparseIter::new((0..).map(Ok::<u64, _>));
+    assert_eq!(block_on(iter.next()).unwrap().unwrap(), 0);
+    drop(iter);
+}
+
+#[test]
+fn spawn_with() {
+    // a stand-in for a runtime's pool of threads for blocking work
+    let mut handle = None;
+    let mut iter = AsyncSparseIter::spawn_with((0..100u64).map(Ok), |job| {
+        handle = Some(thread::spawn(job));
+    });
+    let got = block_on(async {
+        let mut v = Vec::new();
+        while let Some(i) = iter.next().await {
+            v.push(i.unwrap());
+        }
+        v
+    });
+    assert_eq!(got, (0..100).collect::<Vec<_>>());
+    handle.unwrap().join().unwrap();
+
+    // a job that never runs ends the iterator instead of leaving it waiting
+    let mut iter = AsyncSparseIter::spawn_with((0..10u64).map(Ok), drop);
+    assert!(block_on(iter.next()).is_none());
+}
+
+/// Like `tokio::fs::File`: not a `std::fs::File`, but it has a file descriptor
+struct RuntimeFile(std::fs::File);
+
+impl std::os::unix::io::AsFd for RuntimeFile {
+    fn as_fd(&self) -> std::os::unix::io::BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+#[test]
+fn runtime_file() {
+    let (_t, file) = sparse_file(&std::env::temp_dir(), 3, &[1]);
+    let expected: Vec<_> = SparseRangeIter::from(SparseIter::from(&file))
+        .map(|r| r.unwrap())
+        .collect();
+
+    let file = RuntimeFile(file);
+    assert!(fs_sparse::is_sparse(&file).unwrap());
+    let mut iter = scan_async(file);
+    let got = block_on(async {
+        let mut v = Vec::new();
+        while let Some(r) = iter.next().await {
+            v.push(r.unwrap());
+        }
+        v
+    });
+    assert_eq!(got, expected);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn tokio_stream() {
+    use futures_core::Stream;
+    use std::pin::Pin;
+
+    let (_t, file) = sparse_file(&std::env::temp_dir(), 6, &[0, 3, 4]);
+    let expected: Vec<_> = SparseRangeIter::from(SparseIter::from(&file))
+        .map(|r| r.unwrap())
+        .collect();
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .max_blocking_threads(1)
+        .build()
+        .unwrap();
+    let got = runtime.block_on(async {
+        // runs on the runtime's only thread for blocking work
+        let mut iter = scan_async(tokio::fs::File::from_std(file));
+        let mut v = Vec::new();
+        while let Some(r) = std::future::poll_fn(|cx| Pin::new(&mut iter).poll_next(cx)).await {
+            v.push(r.unwrap());
+        }
+        v
+    });
+    assert_eq!(got, expected);
+}
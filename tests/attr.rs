@@ -0,0 +1,37 @@
+mod common;
+
+use common::{dirs, UNIT};
+use fs_sparse::{
+    is_marked_sparse, is_marked_sparse_path, set_sparse, ItemKind, SparseIter, SparseRangeIter,
+};
+
+#[test]
+fn set_sparse_then_extend() {
+    for dir in dirs() {
+        let f = tempfile::tempfile_in(&dir).unwrap();
+        set_sparse(&f, true).unwrap();
+        f.set_len(4 * UNIT).unwrap();
+
+        let r: Vec<_> = SparseRangeIter::from(SparseIter::from(&f))
+            .map(|r| r.unwrap())
+            .map(|r| (r.kind, r.start, r.end))
+            .collect();
+        assert_eq!(r, vec![(ItemKind::Hole, 0, 4 * UNIT)], "{}", dir.display());
+
+        set_sparse(&f, false).unwrap();
+    }
+}
+
+#[test]
+fn marked_sparse() {
+    let t = tempfile::NamedTempFile::new().unwrap();
+    set_sparse(t.as_file(), true).unwrap();
+    assert!(is_marked_sparse(t.as_file()).unwrap());
+    assert!(is_marked_sparse_path(t.path()).unwrap());
+
+    let missing = t.path().with_extension("missing");
+    assert_eq!(
+        is_marked_sparse_path(&missing).unwrap_err().kind(),
+        std::io::ErrorKind::NotFound
+    );
+}
@@ -0,0 +1,73 @@
+use fs_sparse::{ItemKind, SparseIter, SparseRangeItem, SparseRangeIter};
+use std::path::Path;
+use std::process::Command;
+
+// [ENXIO] The whence argument is SEEK_HOLE or SEEK_DATA, and offset is
+// greater or equal to the file size; or the whence argument is SEEK_DATA
+// and the offset falls within the final hole of the file.
+
+// https://www.systutorials.com/how-to-efficiently-archive-a-very-large-sparse-file/
+// notes a potential difference in behavior between a "all hole" and "hole with 1 data" file.
+
+const SEEK: u64 = 10 * 1024 * 1024 * 1024;
+
+fn dd_ct(path: &Path, ct: u64, seek: u64) {
+    let s = Command::new("dd")
+        .args([
+            "if=/dev/zero",
+            &format!("of={}", path.display()),
+            "bs=1",
+            &format!("count={}", ct),
+            &format!("seek={}", seek),
+        ])
+        .status()
+        .expect("dd failed to execute");
+    assert!(s.success());
+}
+
+fn ranges(path: &Path) -> Vec<SparseRangeItem> {
+    let file = std::fs::File::open(path).unwrap();
+    SparseRangeIter::from(SparseIter::from(&file))
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[test]
+fn dd_ct_0() {
+    let tmpfile = tempfile::NamedTempFile::new().unwrap();
+    dd_ct(tmpfile.path(), 0, SEEK);
+
+    // macos/apfs: using SEEK_DATA returns ENXIO
+    assert_eq!(
+        ranges(tmpfile.path()),
+        vec![SparseRangeItem {
+            kind: ItemKind::Hole,
+            start: 0,
+            end: SEEK
+        }]
+    );
+}
+
+#[test]
+fn dd_ct_1() {
+    let tmpfile = tempfile::NamedTempFile::new().unwrap();
+    dd_ct(tmpfile.path(), 1, SEEK);
+
+    // macos/apfs: using SEEK_DATA returns valid offset (of 10G), but SEEK_HOLE then returns 0
+    // (instead of 10G + 1).
+    assert_eq!(
+        ranges(tmpfile.path()),
+        vec![
+            SparseRangeItem {
+                kind: ItemKind::Hole,
+                start: 0,
+                end: SEEK
+            },
+            SparseRangeItem {
+                kind: ItemKind::Data,
+                start: SEEK,
+                end: SEEK + 1
+            },
+        ]
+    );
+}
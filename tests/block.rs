@@ -0,0 +1,67 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{
+    align_down, align_up, block_size, min_hole_size, punch_hole, ItemKind, SparseIter,
+    SparseRangeIter,
+};
+
+#[test]
+fn holes_are_aligned_to_min_hole_size() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 8, &[1, 5]);
+        let min = min_hole_size(&f)
+            .unwrap()
+            .unwrap_or_else(|| panic!("{}: can't report holes", dir.display()));
+        assert!(min > 0, "{}", dir.display());
+
+        let len = f.metadata().unwrap().len();
+        for r in SparseRangeIter::from(SparseIter::from(&f)) {
+            let r = r.unwrap();
+            if r.kind == ItemKind::Hole {
+                assert_eq!(r.start % min, 0, "{}: {:?}", dir.display(), r);
+                assert!(
+                    r.end == len || r.end % min == 0,
+                    "{}: {:?}",
+                    dir.display(),
+                    r
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn aligned_punch_frees_blocks() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 1, &[0]);
+        let bs = block_size(&f).unwrap();
+        assert!(bs.is_power_of_two(), "{}: {}", dir.display(), bs);
+
+        // an unaligned range, shrunk to the blocks entirely inside it
+        let start = align_up(bs / 2, bs);
+        let end = align_down(UNIT - bs / 2, bs);
+        punch_hole(&f, start, end - start).unwrap();
+
+        let holes: Vec<_> = SparseRangeIter::from(SparseIter::from(&f))
+            .map(|r| r.unwrap())
+            .filter(|r| r.kind == ItemKind::Hole)
+            .map(|r| r.start..r.end)
+            .collect();
+        assert_eq!(holes, vec![start..end], "{}", dir.display());
+    }
+}
+
+#[test]
+fn align() {
+    assert_eq!(align_down(0, 4096), 0);
+    assert_eq!(align_down(4095, 4096), 0);
+    assert_eq!(align_down(4096, 4096), 4096);
+    assert_eq!(align_up(0, 4096), 0);
+    assert_eq!(align_up(1, 4096), 4096);
+    assert_eq!(align_up(4096, 4096), 4096);
+    assert_eq!(align_up(7, 3), 9);
+    assert_eq!(align_down(7, 3), 6);
+}
@@ -0,0 +1,75 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::bmap::{Bmap, BmapRange};
+use std::os::unix::fs::FileExt;
+
+const FF_1: &str = "f5fb04aa5b882706b9309e885f19477261336ef76a150c3b4d3489dfac3953ec";
+const FF_2: &str = "4bda3a28f4ffe603c0ec1258c0034d65a1a0d35ab7bd523a834608adabf03cc5";
+const FF_1_ZERO_1: &str = "556cade4811c0ace8fa77c75b4b065802da982316359c7a1b12080e79d3bd639";
+
+fn range(first: u64, last: u64, sha256: &str) -> BmapRange {
+    BmapRange {
+        first,
+        last,
+        sha256: sha256.to_string(),
+    }
+}
+
+#[test]
+fn mapped_blocks() {
+    for dir in dirs() {
+        let (_t, file) = sparse_file(&dir, 6, &[1, 2, 4]);
+        let bmap = Bmap::builder().block_size(UNIT).build(&file).unwrap();
+
+        assert_eq!(bmap.image_size(), 6 * UNIT);
+        assert_eq!(bmap.blocks_count(), 6);
+        assert_eq!(bmap.mapped_blocks_count(), 3, "{}", dir.display());
+        assert_eq!(bmap.ranges(), &[range(1, 2, FF_2), range(4, 4, FF_1)]);
+    }
+}
+
+#[test]
+fn skip_zero_blocks() {
+    for dir in dirs() {
+        let (_t, file) = sparse_file(&dir, 4, &[1]);
+        // written zeros are data, not a hole
+        file.write_all_at(&vec![0u8; UNIT as usize], 2 * UNIT)
+            .unwrap();
+
+        let bmap = Bmap::builder().block_size(UNIT).build(&file).unwrap();
+        assert_eq!(
+            bmap.ranges(),
+            &[range(1, 2, FF_1_ZERO_1)],
+            "{}",
+            dir.display()
+        );
+
+        let bmap = Bmap::builder()
+            .block_size(UNIT)
+            .skip_zero_blocks(true)
+            .build(&file)
+            .unwrap();
+        assert_eq!(bmap.ranges(), &[range(1, 1, FF_1)], "{}", dir.display());
+    }
+}
+
+#[test]
+fn xml() {
+    let (_t, file) = sparse_file(&std::env::temp_dir(), 5, &[0, 3]);
+    let bmap = Bmap::builder().block_size(UNIT).build(&file).unwrap();
+    let mut out = Vec::new();
+    bmap.write_xml(&mut out).unwrap();
+    let xml = String::from_utf8(out).unwrap();
+
+    assert!(xml.starts_with("<?xml version=\"1.0\" ?>\n<bmap version=\"2.0\">\n"));
+    assert!(xml.contains(&format!("<ImageSize> {} </ImageSize>", 5 * UNIT)));
+    assert!(xml.contains(&format!("<BlockSize> {} </BlockSize>", UNIT)));
+    assert!(xml.contains("<BlocksCount> 5 </BlocksCount>"));
+    assert!(xml.contains("<MappedBlocksCount> 2 </MappedBlocksCount>"));
+    assert!(xml.contains(&format!("<Range chksum=\"{}\"> 0 </Range>", FF_1)));
+    assert!(xml.contains(&format!("<Range chksum=\"{}\"> 3 </Range>", FF_1)));
+    assert!(xml.ends_with("</BlockMap>\n</bmap>\n"));
+}
@@ -0,0 +1,99 @@
+#![cfg(all(unix, feature = "cap-std"))]
+
+mod common;
+
+use cap_std::ambient_authority;
+use cap_std::fs::Dir;
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{map_path, Error, SparseDirExt};
+use std::os::unix::fs::FileExt;
+
+fn open(dir: &std::path::Path) -> Dir {
+    Dir::open_ambient_dir(dir, ambient_authority()).unwrap()
+}
+
+#[test]
+fn map() {
+    for dir in dirs() {
+        let (t, _f) = sparse_file(&dir, 4, &[1, 3]);
+        let name = t.path().file_name().unwrap();
+        let map = open(&dir).sparse_map(name).unwrap();
+        assert_eq!(map, map_path(t.path()).unwrap(), "{}", dir.display());
+    }
+}
+
+#[test]
+fn stays_in_dir() {
+    let outer = tempfile::tempdir().unwrap();
+    let inner = outer.path().join("inner");
+    std::fs::create_dir(&inner).unwrap();
+    let (t, _f) = sparse_file(outer.path(), 2, &[0]);
+    let name = t.path().file_name().unwrap();
+
+    let dir = open(&inner);
+    let up = std::path::Path::new("..").join(name);
+    assert!(dir.sparse_map(up).is_err());
+    assert!(dir.sparse_map(t.path()).is_err());
+    assert!(dir.copy_sparse(t.path(), &dir, "copy").is_err());
+    assert!(!inner.join("copy").exists());
+}
+
+#[test]
+fn sparsify() {
+    for dir in dirs() {
+        let (t, f) = sparse_file(&dir, 3, &[0, 1, 2]);
+        f.write_all_at(&vec![0u8; UNIT as usize], UNIT).unwrap();
+        let name = t.path().file_name().unwrap();
+        let s = match open(&dir).sparsify(name) {
+            Ok(s) => s,
+            Err(Error::Unsupported { .. }) => continue,
+            Err(e) => panic!("{}: {}", dir.display(), e),
+        };
+        assert_eq!(s.punched, UNIT, "{}", dir.display());
+    }
+}
+
+#[test]
+fn copy() {
+    for dir in dirs() {
+        let (t, _f) = sparse_file(&dir, 4, &[0, 2]);
+        let name = t.path().file_name().unwrap();
+        let dst = tempfile::tempdir_in(&dir).unwrap();
+        let dst_dir = open(dst.path());
+
+        open(&dir).copy_sparse(name, &dst_dir, "copy").unwrap();
+        let copy = dst.path().join("copy");
+        assert_eq!(
+            map_path(&copy).unwrap(),
+            map_path(t.path()).unwrap(),
+            "{}",
+            dir.display()
+        );
+
+        // `dst` must be new
+        assert!(open(&dir).copy_sparse(name, &dst_dir, "copy").is_err());
+    }
+}
+
+#[test]
+fn clone() {
+    for dir in dirs() {
+        let (t, _f) = sparse_file(&dir, 4, &[1, 3]);
+        let name = t.path().file_name().unwrap();
+        let dst = tempfile::tempdir_in(&dir).unwrap();
+        let dst_dir = open(dst.path());
+
+        match open(&dir).clone_file(name, &dst_dir, "clone") {
+            Ok(()) => assert_eq!(
+                map_path(dst.path().join("clone")).unwrap(),
+                map_path(t.path()).unwrap(),
+                "{}",
+                dir.display()
+            ),
+            Err(Error::Unsupported { .. }) => {
+                assert!(!dst.path().join("clone").exists(), "{}", dir.display())
+            }
+            Err(e) => panic!("{}: {}", dir.display(), e),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::checksum::{Checksum, ChecksummedRanges, Crc32c, Sha256};
+use fs_sparse::ItemKind;
+
+fn crc(data: &[u8]) -> u32 {
+    let mut c = Crc32c::new();
+    c.update(data);
+    c.finish()
+}
+
+#[test]
+fn check_values() {
+    assert_eq!(crc(b"123456789"), 0xe306_9283);
+    assert_eq!(crc(b""), 0);
+
+    let mut s = Sha256::new();
+    s.update(b"abc");
+    let hex: String = s.finish().iter().map(|b| format!("{:02x}", b)).collect();
+    assert_eq!(
+        hex,
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+}
+
+#[test]
+fn ranges() {
+    for dir in dirs() {
+        let (_t, file) = sparse_file(&dir, 6, &[1, 2, 4]);
+        let got: Vec<_> = ChecksummedRanges::new(&file, Crc32c::new)
+            .map(|r| r.unwrap())
+            .map(|r| {
+                (
+                    r.range.kind,
+                    r.range.start / UNIT,
+                    r.range.end / UNIT,
+                    r.checksum,
+                )
+            })
+            .collect();
+
+        let one = crc(&vec![0xff; UNIT as usize]);
+        let two = crc(&vec![0xff; 2 * UNIT as usize]);
+        assert_eq!(
+            got,
+            vec![
+                (ItemKind::Hole, 0, 1, None),
+                (ItemKind::Data, 1, 3, Some(two)),
+                (ItemKind::Hole, 3, 4, None),
+                (ItemKind::Data, 4, 5, Some(one)),
+                (ItemKind::Hole, 5, 6, None),
+            ],
+            "{}",
+            dir.display()
+        );
+    }
+}
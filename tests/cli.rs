@@ -0,0 +1,327 @@
+#![cfg(all(unix, feature = "cli"))]
+
+mod common;
+
+use assert_cmd::prelude::*;
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{ItemKind, SparseMap};
+use predicates::prelude::*;
+use std::os::unix::fs::FileExt;
+use std::process::Command;
+
+fn fsparse() -> Command {
+    Command::cargo_bin("fsparse").unwrap()
+}
+
+#[test]
+fn map() {
+    let (t, _f) = sparse_file(&std::env::temp_dir(), 4, &[1]);
+    let data = format!("     1  data  {:>16}  {:>16}  {:>16}", UNIT, 2 * UNIT, UNIT);
+    let summary = format!(
+        "size: {}  data: {}  holes: {} (75.0%)",
+        4 * UNIT,
+        UNIT,
+        3 * UNIT
+    );
+    fsparse()
+        .arg("map")
+        .arg(t.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(data).and(predicate::str::contains(summary)));
+}
+
+#[test]
+fn map_sync() {
+    for dir in dirs() {
+        let (t, _f) = sparse_file(&dir, 4, &[1]);
+        let data = format!("     1  data  {:>16}  {:>16}  {:>16}", UNIT, 2 * UNIT, UNIT);
+        fsparse()
+            .args(["map", "--sync"])
+            .arg(t.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(data));
+    }
+}
+
+#[test]
+fn errors() {
+    fsparse()
+        .args(["map", "/nonexistent"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::starts_with("/nonexistent: "));
+    fsparse().arg("map").assert().failure();
+    fsparse().arg("frob").assert().failure();
+}
+
+#[test]
+fn copy() {
+    for dir in dirs() {
+        let (src, _f) = sparse_file(&dir, 4, &[1, 3]);
+        for reflink in &["--reflink=never", "--reflink=auto"] {
+            let dst = dir.join(format!("fsparse-copy-{}", std::process::id()));
+            fsparse()
+                .arg("copy")
+                .arg(reflink)
+                .arg(src.path())
+                .arg(&dst)
+                .assert()
+                .success();
+            let got = std::fs::read(&dst).unwrap();
+            let map = SparseMap::from_file(std::fs::File::open(&dst).unwrap()).unwrap();
+            assert_eq!(map.data_len(), 2 * UNIT, "{}: {}", dir.display(), reflink);
+            std::fs::remove_file(&dst).unwrap();
+            assert!(got == std::fs::read(src.path()).unwrap(), "{}", reflink);
+        }
+
+        // only works on filesystems that can clone
+        let dst = tempfile::NamedTempFile::new_in(&dir).unwrap();
+        let out = fsparse()
+            .args(["copy", "--reflink"])
+            .arg(src.path())
+            .arg(dst.path())
+            .output()
+            .unwrap();
+        if out.status.success() {
+            assert!(std::fs::read(dst.path()).unwrap() == std::fs::read(src.path()).unwrap());
+        } else {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            assert!(stderr.contains("not supported"), "{}", stderr);
+        }
+    }
+}
+
+#[test]
+fn copy_over_longer() {
+    for dir in dirs() {
+        let (src, _f) = sparse_file(&dir, 2, &[1]);
+        for reflink in &["--reflink=never", "--reflink=auto"] {
+            let (dst, _d) = sparse_file(&dir, 5, &[0, 4]);
+            fsparse()
+                .arg("copy")
+                .arg(reflink)
+                .arg(src.path())
+                .arg(dst.path())
+                .assert()
+                .success();
+            assert!(
+                std::fs::read(dst.path()).unwrap() == std::fs::read(src.path()).unwrap(),
+                "{}: {}",
+                dir.display(),
+                reflink
+            );
+        }
+    }
+}
+
+#[test]
+fn copy_errors() {
+    let (src, _f) = sparse_file(&std::env::temp_dir(), 1, &[0]);
+    fsparse()
+        .arg("copy")
+        .arg(src.path())
+        .arg(src.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("same file"));
+    assert_eq!(std::fs::metadata(src.path()).unwrap().len(), UNIT);
+
+    fsparse()
+        .args(["copy", "--reflink=sometimes", "a", "b"])
+        .assert()
+        .failure();
+    fsparse().args(["copy", "a"]).assert().failure();
+}
+
+#[test]
+fn punch() {
+    for dir in dirs() {
+        let (t, f) = sparse_file(&dir, 4, &[0, 1, 2, 3]);
+        let out = fsparse()
+            .arg("punch")
+            .arg(t.path())
+            .args(["1M:1M", "3145728:1024K"])
+            .output()
+            .unwrap();
+        if !out.status.success() {
+            // the filesystem can't punch holes
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            assert!(stderr.contains("not supported"), "{}", stderr);
+            continue;
+        }
+
+        let map = SparseMap::from_file(&f).unwrap();
+        assert_eq!(map.data_len(), 2 * UNIT, "{}", dir.display());
+        assert_eq!(map.file_len(), 4 * UNIT);
+        assert_eq!(map.find(UNIT).unwrap().kind, ItemKind::Hole);
+        assert_eq!(map.find(3 * UNIT).unwrap().kind, ItemKind::Hole);
+    }
+}
+
+#[test]
+fn punch_errors() {
+    let (t, _f) = sparse_file(&std::env::temp_dir(), 1, &[0]);
+    for range in &["1M", "1X:1", "x:1", "0:99999999999T"] {
+        fsparse()
+            .arg("punch")
+            .arg(t.path())
+            .arg(range)
+            .assert()
+            .failure();
+    }
+    fsparse().arg("punch").arg(t.path()).assert().failure();
+    fsparse().arg("punch").assert().failure();
+}
+
+#[test]
+fn dig() {
+    for dir in dirs() {
+        let (t, f) = sparse_file(&dir, 4, &[0, 1, 2, 3]);
+        f.write_all_at(&vec![0u8; 2 * UNIT as usize], UNIT).unwrap();
+
+        fsparse()
+            .args(["dig", "--dry-run", "--block-size=64K"])
+            .arg(t.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::ends_with(format!(
+                ": would punch {}\n",
+                2 * UNIT
+            )));
+        assert_eq!(SparseMap::from_file(&f).unwrap().data_len(), 4 * UNIT);
+
+        let out = fsparse().arg("dig").arg(t.path()).output().unwrap();
+        if !out.status.success() {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            assert!(stderr.contains("not supported"), "{}", stderr);
+            continue;
+        }
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        assert!(
+            stdout.contains(&format!("scanned {}, punched {}", 4 * UNIT, 2 * UNIT)),
+            "{}",
+            stdout
+        );
+        assert_eq!(SparseMap::from_file(&f).unwrap().data_len(), 2 * UNIT);
+    }
+}
+
+fn json(cmd: &mut Command) -> serde_json::Value {
+    let out = cmd.arg("--json").output().unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    serde_json::from_slice(&out.stdout).unwrap()
+}
+
+#[test]
+fn json_output() {
+    let dir = std::env::temp_dir();
+    let (t, _f) = sparse_file(&dir, 4, &[1]);
+    let path = t.path().to_str().unwrap();
+
+    let map = json(fsparse().args(["map", path]));
+    assert_eq!(
+        map,
+        serde_json::json!([{
+            "path": path,
+            "size": 4 * UNIT,
+            "data": UNIT,
+            "holes": 3 * UNIT,
+            "allocated": map[0]["allocated"],
+            "ranges": [
+                {"kind": "Hole", "start": 0, "end": UNIT},
+                {"kind": "Data", "start": UNIT, "end": 2 * UNIT},
+                {"kind": "Hole", "start": 2 * UNIT, "end": 4 * UNIT},
+            ],
+        }])
+    );
+
+    let dug = json(fsparse().args(["dig", "--dry-run", "--block-size=4K", path]));
+    assert_eq!(
+        dug,
+        serde_json::json!([{
+            "path": path,
+            "block_size": 4096,
+            "dry_run": true,
+            "scanned": UNIT,
+            "punched": 0,
+            "reclaimed": null,
+        }])
+    );
+
+    let dst = dir.join(format!("fsparse-json-{}", std::process::id()));
+    let copied = json(fsparse().args(["copy", "--reflink=never", path]).arg(&dst));
+    std::fs::remove_file(&dst).unwrap();
+    assert_eq!(
+        copied,
+        serde_json::json!({
+            "source": path,
+            "destination": dst.to_str().unwrap(),
+            "cloned": false,
+            "copied": UNIT,
+        })
+    );
+
+    let out = fsparse()
+        .args(["--json", "punch", path, "4K:4K"])
+        .output()
+        .unwrap();
+    if out.status.success() {
+        let punched: serde_json::Value = serde_json::from_slice(&out.stdout).unwrap();
+        assert_eq!(
+            punched,
+            serde_json::json!({
+                "path": path,
+                "ranges": [{"offset": 4096, "length": 4096}],
+            })
+        );
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn map_verbose() {
+    for dir in dirs() {
+        let (t, f) = sparse_file(&dir, 4, &[1]);
+        f.sync_all().unwrap();
+        let out = fsparse()
+            .args(["map", "-v"])
+            .arg(t.path())
+            .output()
+            .unwrap();
+        if !out.status.success() {
+            // no FIEMAP on this filesystem
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            assert!(stderr.contains("not supported"), "{}", stderr);
+            continue;
+        }
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        assert!(stdout.contains("physical_offset:"), "{}", stdout);
+        assert!(stdout.ends_with(" last\n"), "{}", stdout);
+
+        let map = json(fsparse().args(["map", "--verbose"]).arg(t.path()));
+        let extents = map[0]["extents"].as_array().unwrap();
+        assert_eq!(extents[0]["logical"], UNIT);
+        assert_eq!(
+            extents.last().unwrap()["flags"],
+            serde_json::json!(["last"])
+        );
+    }
+}
+
+#[test]
+fn map_pipe() {
+    let out = fsparse()
+        .args(["map", "/dev/stdin"])
+        .stdin(std::process::Stdio::piped())
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("not a seekable file"), "{}", stderr);
+}
@@ -0,0 +1,120 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, ranges, sparse_file, UNIT};
+use fs_sparse::{
+    clone_file, clone_file_path, clone_range, clone_ranges, dedupe_range, Deduped, Error, ItemKind,
+    SparseMap,
+};
+
+#[test]
+fn clone() {
+    for dir in dirs() {
+        let (_t, src) = sparse_file(&dir, 4, &[1, 3]);
+        let (_t, dst) = sparse_file(&dir, 6, &[0]);
+        match clone_file(&src, &dst) {
+            Ok(()) => {}
+            Err(Error::Unsupported { .. }) => {
+                // nothing changed
+                assert_eq!(dst.metadata().unwrap().len(), 6 * UNIT, "{}", dir.display());
+                continue;
+            }
+            Err(e) => panic!("{}: {}", dir.display(), e),
+        }
+        assert_eq!(ranges(&dst), ranges(&src), "{}", dir.display());
+    }
+}
+
+#[test]
+fn clone_into_longer() {
+    for dir in dirs() {
+        let (_t, src) = sparse_file(&dir, 2, &[0, 1]);
+        let (_t, dst) = sparse_file(&dir, 5, &[0, 1, 2, 3, 4]);
+        match clone_file(&src, &dst) {
+            Ok(()) => {}
+            Err(Error::Unsupported { .. }) => continue,
+            Err(e) => panic!("{}: {}", dir.display(), e),
+        }
+        assert_eq!(dst.metadata().unwrap().len(), 2 * UNIT, "{}", dir.display());
+        assert_eq!(
+            SparseMap::from_file(&dst).unwrap(),
+            SparseMap::from_file(&src).unwrap(),
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn clone_path() {
+    for dir in dirs() {
+        let (src_t, src) = sparse_file(&dir, 4, &[1]);
+        let d = tempfile::tempdir_in(&dir).unwrap();
+        let dst_path = d.path().join("clone");
+
+        match clone_file_path(src_t.path(), &dst_path) {
+            Ok(()) => {}
+            Err(Error::Unsupported { .. }) => {
+                // doesn't leave an empty file behind
+                assert!(!dst_path.exists(), "{}", dir.display());
+                continue;
+            }
+            Err(e) => panic!("{}: {}", dir.display(), e),
+        }
+        let dst = std::fs::File::open(&dst_path).unwrap();
+        assert_eq!(ranges(&dst), ranges(&src), "{}", dir.display());
+    }
+}
+
+#[test]
+fn clone_data_ranges() {
+    for dir in dirs() {
+        let (_t, src) = sparse_file(&dir, 4, &[1, 3]);
+        // `dst` has data where `src` has holes, which must survive
+        let (_t, dst) = sparse_file(&dir, 4, &[0, 2]);
+        let map = SparseMap::from_file(&src).unwrap();
+        match clone_ranges(&src, &dst, &map) {
+            Ok(n) => assert_eq!(n, 2 * UNIT, "{}", dir.display()),
+            Err(Error::Unsupported { .. }) => continue,
+            Err(e) => panic!("{}: {}", dir.display(), e),
+        }
+        assert_eq!(
+            ranges(&dst),
+            vec![(ItemKind::Data, 0, 4)],
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn clone_range_errors() {
+    let (_t, src) = sparse_file(&dirs()[0], 1, &[0]);
+    let (_t, dst) = sparse_file(&dirs()[0], 0, &[]);
+    match clone_range(&src, &dst, 0, u64::MAX, 2) {
+        Err(Error::InvalidRange { offset: 0, len: 2 }) => {}
+        r => panic!("{:?}", r),
+    }
+    // nothing to do
+    clone_range(&src, &dst, 0, 0, 0).unwrap();
+}
+
+#[test]
+fn dedupe() {
+    for dir in dirs() {
+        let (_t, a) = sparse_file(&dir, 2, &[0, 1]);
+        let (_t, b) = sparse_file(&dir, 2, &[0]);
+        match dedupe_range(&a, &b, 0, 0, UNIT) {
+            Ok(d) => assert_eq!(d, Deduped::Shared, "{}", dir.display()),
+            Err(Error::Unsupported { .. }) => continue,
+            Err(e) => panic!("{}: {}", dir.display(), e),
+        }
+        assert_eq!(
+            dedupe_range(&a, &b, UNIT, UNIT, UNIT).unwrap(),
+            Deduped::Differs,
+            "{}",
+            dir.display()
+        );
+    }
+}
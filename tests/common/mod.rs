@@ -0,0 +1,74 @@
+#![allow(dead_code)]
+
+use fs_sparse::{ItemKind, SparseIter, SparseRangeIter};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Granularity of the layouts we build. Large enough that every filesystem we test on can
+/// represent each piece as its own extent.
+pub const UNIT: u64 = 1024 * 1024;
+
+/// Where to put test files: the default temporary directory (often ext4 or similar), and tmpfs
+/// when it's available
+pub fn dirs() -> Vec<PathBuf> {
+    let mut d = vec![std::env::temp_dir()];
+    if Path::new("/dev/shm").is_dir() {
+        d.push(PathBuf::from("/dev/shm"));
+    }
+    d
+}
+
+/// Create a file in `dir` that is `len` units long, with the units at each of `data` filled
+/// with non-zero bytes
+pub fn sparse_file(dir: &Path, len: u64, data: &[u64]) -> (tempfile::NamedTempFile, File) {
+    let tmp = tempfile::NamedTempFile::new_in(dir).unwrap();
+    let file = tmp.reopen().unwrap();
+    // files on windows only have holes once they're marked sparse
+    #[cfg(windows)]
+    fs_sparse::set_sparse(&file, true).unwrap();
+    file.set_len(len * UNIT).unwrap();
+    let buf = vec![0xffu8; UNIT as usize];
+    for d in data {
+        write_all_at(&file, &buf, d * UNIT);
+    }
+    (tmp, file)
+}
+
+/// The Data and Hole ranges in `file`, in units
+pub fn ranges(file: &File) -> Vec<(ItemKind, u64, u64)> {
+    SparseRangeIter::from(SparseIter::from(file))
+        .map(|r| r.unwrap())
+        .map(|r| (r.kind, r.start / UNIT, r.end / UNIT))
+        .collect()
+}
+
+/// Everything in `file` from its cursor on
+pub fn contents(mut file: &File) -> Vec<u8> {
+    let mut v = Vec::new();
+    file.read_to_end(&mut v).unwrap();
+    v
+}
+
+/// Write all of `buf` at `offset`, without needing the unix-only `FileExt::write_all_at()`
+pub fn write_all_at(file: &File, mut buf: &[u8], mut offset: u64) {
+    while !buf.is_empty() {
+        #[cfg(unix)]
+        let n = std::os::unix::fs::FileExt::write_at(file, buf, offset).unwrap();
+        #[cfg(windows)]
+        let n = std::os::windows::fs::FileExt::seek_write(file, buf, offset).unwrap();
+        assert!(n > 0, "wrote nothing");
+        buf = &buf[n..];
+        offset += n as u64;
+    }
+}
+
+/// The read and write ends of a pipe: `File`s that can't be seeked
+#[cfg(unix)]
+pub fn pipe() -> (File, File) {
+    use std::os::unix::io::FromRawFd;
+
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) }
+}
@@ -0,0 +1,38 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::compare_sparse;
+use std::os::unix::fs::FileExt;
+
+#[test]
+fn same() {
+    for dir in dirs() {
+        let (_t, a) = sparse_file(&dir, 6, &[1, 4]);
+        let (_t, b) = sparse_file(&dir, 6, &[1, 4]);
+        assert_eq!(compare_sparse(&a, &b).unwrap(), vec![]);
+
+        // written zeros are the same as a hole
+        b.write_all_at(&vec![0u8; UNIT as usize], 2 * UNIT).unwrap();
+        assert_eq!(compare_sparse(&a, &b).unwrap(), vec![]);
+    }
+}
+
+#[test]
+fn differences() {
+    for dir in dirs() {
+        let (_t, a) = sparse_file(&dir, 4, &[0, 2]);
+        let (_t, b) = sparse_file(&dir, 6, &[0, 3]);
+        // bytes that differ inside data on both sides
+        b.write_all_at(&[1, 2], 10).unwrap();
+        b.write_all_at(&[3], 20).unwrap();
+
+        assert_eq!(
+            compare_sparse(&a, &b).unwrap(),
+            vec![10..12, 20..21, 2 * UNIT..6 * UNIT],
+            "{}",
+            dir.display()
+        );
+    }
+}
@@ -0,0 +1,99 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{contents, dirs, ranges, sparse_file, UNIT};
+use fs_sparse::{copy_sparse, copy_sparse_path, copy_sparse_with_data};
+
+#[test]
+fn copy() {
+    for dir in dirs() {
+        let (_t, src) = sparse_file(&dir, 7, &[1, 2, 5]);
+        // `dst` starts out longer, and with data where `src` has holes
+        let (_t, dst) = sparse_file(&dir, 9, &[0, 8]);
+
+        assert_eq!(copy_sparse(&src, &dst).unwrap(), 3 * UNIT);
+        assert_eq!(ranges(&dst), ranges(&src), "{}", dir.display());
+        assert_eq!(contents(&dst), contents(&src), "{}", dir.display());
+    }
+}
+
+#[test]
+fn copy_keeps_cursors() {
+    use std::io::{Seek, SeekFrom};
+
+    for dir in dirs() {
+        let (_t, mut src) = sparse_file(&dir, 5, &[1, 3]);
+        let (_t, mut dst) = sparse_file(&dir, 2, &[0]);
+        src.seek(SeekFrom::Start(7)).unwrap();
+        dst.seek(SeekFrom::Start(11)).unwrap();
+
+        copy_sparse(&src, &dst).unwrap();
+        assert_eq!(src.stream_position().unwrap(), 7, "{}", dir.display());
+        assert_eq!(dst.stream_position().unwrap(), 11, "{}", dir.display());
+    }
+}
+
+#[test]
+fn copy_empty() {
+    for dir in dirs() {
+        let (_t, src) = sparse_file(&dir, 0, &[]);
+        let (_t, dst) = sparse_file(&dir, 2, &[0]);
+        assert_eq!(copy_sparse(&src, &dst).unwrap(), 0);
+        assert_eq!(dst.metadata().unwrap().len(), 0, "{}", dir.display());
+    }
+}
+
+#[test]
+fn copy_path() {
+    for dir in dirs() {
+        let (src_t, src) = sparse_file(&dir, 4, &[1]);
+        let d = tempfile::tempdir_in(&dir).unwrap();
+        let dst_path = d.path().join("copy");
+
+        copy_sparse_path(src_t.path(), &dst_path).unwrap();
+        let dst = std::fs::File::open(&dst_path).unwrap();
+        assert_eq!(ranges(&dst), ranges(&src), "{}", dir.display());
+        assert_eq!(contents(&dst), contents(&src), "{}", dir.display());
+
+        // never overwrites
+        let e = copy_sparse_path(src_t.path(), &dst_path).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::AlreadyExists);
+    }
+}
+
+#[test]
+fn copy_across_filesystems() {
+    // copying between filesystems can't be done in the kernel everywhere
+    for src_dir in dirs() {
+        for dst_dir in dirs() {
+            let (_t, src) = sparse_file(&src_dir, 5, &[0, 3]);
+            let (_t, dst) = sparse_file(&dst_dir, 0, &[]);
+
+            assert_eq!(copy_sparse(&src, &dst).unwrap(), 2 * UNIT);
+            assert_eq!(ranges(&dst), ranges(&src), "{}", dst_dir.display());
+            assert_eq!(contents(&dst), contents(&src), "{}", dst_dir.display());
+        }
+    }
+}
+
+#[test]
+fn copy_with_data() {
+    for dir in dirs() {
+        let (_t, src) = sparse_file(&dir, 4, &[0, 2]);
+        let (_t, dst) = sparse_file(&dir, 0, &[]);
+        // only 2.5 units of data, so the second range is cut short
+        let data = vec![0x11u8; 5 * UNIT as usize / 2];
+
+        assert_eq!(
+            copy_sparse_with_data(&src, &data, &dst).unwrap(),
+            3 * UNIT / 2
+        );
+        assert_eq!(dst.metadata().unwrap().len(), 4 * UNIT);
+
+        let mut want = vec![0u8; 4 * UNIT as usize];
+        want[..UNIT as usize].fill(0x11);
+        want[2 * UNIT as usize..5 * UNIT as usize / 2].fill(0x11);
+        assert!(contents(&dst) == want, "{}", dir.display());
+    }
+}
@@ -0,0 +1,56 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{sparse_file, UNIT};
+use fs_sparse::{is_block_device, ItemKind, SparseIter, SparseRangeIter};
+use std::fs::File;
+
+#[test]
+fn not_devices() {
+    let (_t, f) = sparse_file(&std::env::temp_dir(), 4, &[1]);
+    assert!(!is_block_device(&f).unwrap());
+    assert!(!is_block_device(File::open("/dev/null").unwrap()).unwrap());
+
+    // regular files are scanned as usual
+    let r: Vec<_> = SparseRangeIter::from(SparseIter::from(&f).read_scan_devices(4096))
+        .map(|r| r.unwrap())
+        .map(|r| (r.kind, r.start / UNIT, r.end / UNIT))
+        .collect();
+    assert_eq!(
+        r,
+        vec![
+            (ItemKind::Hole, 0, 1),
+            (ItemKind::Data, 1, 2),
+            (ItemKind::Hole, 2, 4)
+        ]
+    );
+}
+
+/// Scan the last few blocks of whatever block devices we can open
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn devices() {
+    for name in &["vda", "vdb", "sda", "nvme0n1", "loop0"] {
+        let dev = match File::open(format!("/dev/{}", name)) {
+            Ok(f) => f,
+            Err(_) => continue,
+        };
+        assert!(is_block_device(&dev).unwrap(), "{}", name);
+
+        // the kernel's idea of the size, in 512 byte sectors
+        let sectors = std::fs::read_to_string(format!("/sys/class/block/{}/size", name)).unwrap();
+        let size = sectors.trim().parse::<u64>().unwrap() * 512;
+
+        let start = size.saturating_sub(16 * 4096);
+        let items: Vec<_> = SparseIter::starting_at(&dev, start)
+            .read_scan_devices(4096)
+            .map(|i| i.unwrap())
+            .collect();
+        let end = items.last().unwrap();
+        assert_eq!((end.kind, end.offset), (ItemKind::End, size), "{}", name);
+        if size > 0 {
+            assert_eq!(items[0].offset, start, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::pipe;
+use fs_sparse::SparseIter;
+use std::fs::File;
+
+#[test]
+fn fallback_to_data() {
+    // procfs doesn't support SEEK_DATA
+    let f = match File::open("/proc/self/status") {
+        Ok(f) => f,
+        Err(_) => return,
+    };
+    assert!(SparseIter::from(&f).next().unwrap().is_err());
+
+    let items: Vec<_> = SparseIter::from(&f)
+        .fallback_to_data()
+        .map(|i| i.unwrap().kind)
+        .collect();
+    assert_eq!(items, vec![fs_sparse::ItemKind::End]);
+
+    // non-seekable is a different problem, and still an error
+    let (r, _w) = pipe();
+    assert!(SparseIter::from(&r)
+        .fallback_to_data()
+        .next()
+        .unwrap()
+        .is_err());
+}
+
+#[test]
+fn not_seekable() {
+    use std::os::unix::net::UnixStream;
+
+    let (r, _w) = pipe();
+    let (s, _t) = UnixStream::pair().unwrap();
+    for e in [
+        SparseIter::from(&r).next().unwrap().unwrap_err(),
+        SparseIter::from(&s).next().unwrap().unwrap_err(),
+        fs_sparse::next_data_from(&r, 0).unwrap_err(),
+    ] {
+        assert_eq!(e.kind(), std::io::ErrorKind::NotSeekable);
+        assert!(matches!(
+            fs_sparse::Error::from(e),
+            fs_sparse::Error::NotASeekableFile
+        ));
+    }
+}
+
+#[test]
+fn offset_overflow() {
+    let f = tempfile::tempfile().unwrap();
+    f.set_len(1).unwrap();
+
+    let e = fs_sparse::kind_at(&f, u64::MAX).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(matches!(
+        fs_sparse::Error::from(e),
+        fs_sparse::Error::OffsetOverflow
+    ));
+
+    let mut i = SparseIter::starting_at(&f, i64::MAX as u64 + 1);
+    assert!(i.next().unwrap().is_err());
+    assert!(i.next().is_none());
+}
+
+#[test]
+fn detect_changes() {
+    let f = tempfile::tempfile().unwrap();
+    f.set_len(4096).unwrap();
+
+    let mut i = SparseIter::from(&f).detect_changes();
+    assert!(i.next().unwrap().is_ok());
+    f.set_len(8192).unwrap();
+    let e = i.next().unwrap().unwrap_err();
+    assert!(matches!(
+        fs_sparse::Error::from(e),
+        fs_sparse::Error::FileChanged
+    ));
+    assert!(i.next().is_none());
+
+    // without changes, the whole file is scanned
+    let items: Vec<_> = SparseIter::from(&f)
+        .detect_changes()
+        .map(|i| i.unwrap().kind)
+        .collect();
+    assert_eq!(items.last(), Some(&fs_sparse::ItemKind::End));
+}
+
+#[test]
+fn detect_changes_across_skip_to() {
+    let f = tempfile::tempfile().unwrap();
+    f.set_len(4096).unwrap();
+
+    let mut i = SparseIter::from(&f).detect_changes();
+    assert!(i.next().unwrap().is_ok());
+    f.set_len(8192).unwrap();
+    // skipping keeps the baseline from before the change
+    i.skip_to(0);
+    let e = i.next().unwrap().unwrap_err();
+    assert!(matches!(
+        fs_sparse::Error::from(e),
+        fs_sparse::Error::FileChanged
+    ));
+}
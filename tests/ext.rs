@@ -0,0 +1,48 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{
+    allocated_size, ItemKind, SparseFileExt, SparseIter, SparseMap, SparseRangeIter, Zeroed,
+};
+
+#[test]
+fn same_as_functions() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 5, &[1, 3]);
+        let ranges: Vec<_> = f.sparse_ranges().map(|r| r.unwrap()).collect();
+        let expected: Vec<_> = SparseRangeIter::from(SparseIter::from(&f))
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(ranges, expected, "{}", dir.display());
+        assert_eq!(f.sparse_items().count(), SparseIter::from(&f).count());
+        assert_eq!(f.sparse_map().unwrap(), SparseMap::from_file(&f).unwrap());
+        assert_eq!(f.sparse_stats().unwrap().data_bytes, 2 * UNIT);
+
+        assert_eq!(f.kind_at(0).unwrap(), ItemKind::Hole);
+        assert_eq!(f.kind_at(UNIT).unwrap(), ItemKind::Data);
+        assert_eq!(f.next_data_from(0).unwrap(), Some(UNIT));
+        assert_eq!(f.next_hole_from(UNIT).unwrap(), Some(2 * UNIT));
+
+        assert_eq!(f.allocated_size().unwrap(), allocated_size(&f).unwrap());
+        assert!(f.is_sparse().unwrap(), "{}", dir.display());
+    }
+}
+
+#[test]
+fn punch() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 3, &[0, 1, 2]);
+        if f.punch_hole_or_zero(UNIT, UNIT).unwrap() == Zeroed::Written {
+            continue;
+        }
+        let kinds: Vec<_> = f.sparse_ranges().map(|r| r.unwrap().kind).collect();
+        assert_eq!(
+            kinds,
+            vec![ItemKind::Data, ItemKind::Hole, ItemKind::Data],
+            "{}",
+            dir.display()
+        );
+    }
+}
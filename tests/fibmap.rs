@@ -0,0 +1,32 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+mod common;
+
+use common::{dirs, sparse_file};
+use fs_sparse::{SparseIter, SparseRangeIter};
+
+#[test]
+fn fibmap() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 7, &[1, 2, 5]);
+        f.sync_all().unwrap();
+
+        let r: Result<Vec<_>, _> = SparseRangeIter::from(SparseIter::from(&f).fibmap()).collect();
+        let r = match r {
+            Ok(r) => r,
+            // needs CAP_SYS_RAWIO, and filesystem support
+            Err(e)
+                if e.kind() == std::io::ErrorKind::PermissionDenied
+                    || e.raw_os_error() == Some(libc::EINVAL) =>
+            {
+                continue
+            }
+            Err(e) => panic!("{}: {}", dir.display(), e),
+        };
+
+        let expected: Vec<_> = SparseRangeIter::from(SparseIter::from(&f))
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(r, expected, "{}", dir.display());
+    }
+}
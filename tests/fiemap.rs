@@ -0,0 +1,251 @@
+#![cfg(any(target_os = "linux", target_os = "android"))]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::fiemap::{write_extent_table, Extent, ExtentFlags, FiemapIter};
+use fs_sparse::{ItemKind, SparseIter, SparseRangeIter};
+use std::os::unix::io::AsRawFd;
+
+/// Collect extents, or `None` if the filesystem doesn't support FIEMAP
+fn extents(f: &std::fs::File) -> Option<Vec<Extent>> {
+    match FiemapIter::from(f).collect::<Result<Vec<_>, _>>() {
+        Ok(e) => Some(e),
+        Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => None,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+/// Merge abutting extents into `(start, end)` ranges
+fn merged(extents: &[Extent]) -> Vec<(u64, u64)> {
+    let mut r: Vec<(u64, u64)> = Vec::new();
+    for e in extents {
+        match r.last_mut() {
+            Some(l) if l.1 == e.logical => l.1 = e.end(),
+            _ => r.push((e.logical, e.end())),
+        }
+    }
+    r
+}
+
+#[test]
+fn extents_match_data() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 7, &[1, 2, 5]);
+        f.sync_all().unwrap();
+        let e = match extents(&f) {
+            Some(e) => e,
+            None => continue,
+        };
+
+        assert_eq!(
+            merged(&e),
+            vec![(UNIT, 3 * UNIT), (5 * UNIT, 6 * UNIT)],
+            "{}",
+            dir.display()
+        );
+        assert!(e.last().unwrap().flags.contains(ExtentFlags::LAST));
+    }
+}
+
+#[test]
+fn unwritten() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[]);
+        let r = unsafe { libc::fallocate(f.as_raw_fd(), 0, 0, UNIT as libc::off_t) };
+        if r != 0 {
+            continue;
+        }
+        let e = match extents(&f) {
+            Some(e) => e,
+            None => continue,
+        };
+
+        assert!(!e.is_empty(), "{}", dir.display());
+        assert!(e.iter().all(|e| e.flags.contains(ExtentFlags::UNWRITTEN)));
+    }
+}
+
+#[test]
+fn sync() {
+    for dir in dirs() {
+        // no sync_all() here, FIEMAP_FLAG_SYNC has to take care of it
+        let (_t, f) = sparse_file(&dir, 7, &[1, 2, 5]);
+        let e = match FiemapIter::from(&f).sync().collect::<Result<Vec<_>, _>>() {
+            Ok(e) => e,
+            Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => continue,
+            Err(e) => panic!("{}", e),
+        };
+
+        assert_eq!(merged(&e), vec![(UNIT, 3 * UNIT), (5 * UNIT, 6 * UNIT)]);
+        assert!(e.iter().all(|e| !e.flags.contains(ExtentFlags::DELALLOC)));
+    }
+}
+
+#[test]
+fn backend() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 7, &[1, 2, 5]);
+        f.sync_all().unwrap();
+
+        let r: Result<Vec<_>, _> = SparseRangeIter::from(SparseIter::from(&f).fiemap()).collect();
+        let r = match r {
+            Ok(r) => r,
+            Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => continue,
+            Err(e) => panic!("{}: {}", dir.display(), e),
+        };
+
+        let expected: Vec<_> = SparseRangeIter::from(SparseIter::from(&f))
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(r, expected, "{}", dir.display());
+
+        let r: Vec<_> = SparseRangeIter::from(SparseIter::starting_at(&f, 2 * UNIT + 10).fiemap())
+            .map(|r| r.unwrap())
+            .map(|r| (r.kind, r.start, r.end))
+            .collect();
+        assert_eq!(
+            r,
+            vec![
+                (ItemKind::Data, 2 * UNIT + 10, 3 * UNIT),
+                (ItemKind::Hole, 3 * UNIT, 5 * UNIT),
+                (ItemKind::Data, 5 * UNIT, 6 * UNIT),
+                (ItemKind::Hole, 6 * UNIT, 7 * UNIT)
+            ],
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn backend_unwritten_is_hole() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[3]);
+        let r = unsafe { libc::fallocate(f.as_raw_fd(), 0, 0, UNIT as libc::off_t) };
+        if r != 0 {
+            continue;
+        }
+        f.sync_all().unwrap();
+
+        let r: Result<Vec<_>, _> = SparseRangeIter::from(SparseIter::from(&f).fiemap()).collect();
+        let r = match r {
+            Ok(r) => r,
+            Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => continue,
+            Err(e) => panic!("{}: {}", dir.display(), e),
+        };
+        let r: Vec<_> = r.into_iter().map(|r| (r.kind, r.start, r.end)).collect();
+        assert_eq!(
+            r,
+            vec![
+                (ItemKind::Hole, 0, 3 * UNIT),
+                (ItemKind::Data, 3 * UNIT, 4 * UNIT)
+            ],
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn flag_names() {
+    let flags = ExtentFlags::LAST | ExtentFlags::UNWRITTEN;
+    assert_eq!(flags.names().collect::<Vec<_>>(), vec!["last", "unwritten"]);
+    assert_eq!(flags.to_string(), "last,unwritten");
+    assert_eq!(ExtentFlags::default().to_string(), "");
+    assert_eq!(
+        (ExtentFlags::SHARED | ExtentFlags::from_bits(0x10_0000)).to_string(),
+        "shared,0x00100000"
+    );
+}
+
+#[test]
+fn extent_table() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[1, 3]);
+        f.sync_all().unwrap();
+        let e = match extents(&f) {
+            Some(e) => e,
+            None => continue,
+        };
+
+        let mut out = Vec::new();
+        let count = write_extent_table(&f, 4096, &mut out).unwrap();
+        assert_eq!(count, e.len() as u64);
+
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(
+            lines[0],
+            " ext:     logical_offset:        physical_offset: length:   expected: flags:"
+        );
+        assert_eq!(lines.len(), e.len() + 1);
+
+        let first = &e[0];
+        let start = first.logical / 4096;
+        let end = start + first.length / 4096 - 1;
+        assert!(
+            lines[1].starts_with(&format!("   0: {:>8}..{:>8}:", start, end)),
+            "{}",
+            lines[1]
+        );
+        assert!(lines.last().unwrap().ends_with("last,eof"), "{}", out);
+    }
+}
+
+#[test]
+fn fragmentation() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 6, &[0, 2, 3]);
+        f.sync_all().unwrap();
+        let e = match extents(&f) {
+            Some(e) => e,
+            None => continue,
+        };
+
+        let frag = fs_sparse::fiemap::fragmentation(&f).unwrap();
+        assert_eq!(frag.data_bytes, 3 * UNIT, "{}", dir.display());
+        // at least one extent for each separate range, and no more than the kernel reported
+        assert!(
+            frag.extents >= 2 && frag.extents as usize <= e.len(),
+            "{:?}",
+            frag
+        );
+        assert!(frag.smallest <= UNIT, "{:?}", frag);
+        assert!(frag.largest <= 2 * UNIT);
+    }
+}
+
+#[test]
+fn size_hint() {
+    use std::os::unix::fs::FileExt;
+
+    for dir in dirs() {
+        // more extents than are fetched in one ioctl
+        let (_t, f) = sparse_file(&dir, 2, &[]);
+        for i in 0..200 {
+            f.write_all_at(&[1; 4096], i * 8192).unwrap();
+        }
+        f.sync_all().unwrap();
+        let e = match extents(&f) {
+            Some(e) => e,
+            None => continue,
+        };
+
+        let mut iter = FiemapIter::from(&f);
+        assert_eq!(iter.size_hint(), (0, None));
+        iter.next().unwrap().unwrap();
+        let left = e.len() - 1;
+        assert_eq!(iter.size_hint(), (0, Some(left)), "{}", dir.display());
+        assert_eq!(iter.count(), left);
+
+        let mut iter = SparseIter::from(&f).fiemap();
+        assert_eq!(iter.size_hint(), (1, None));
+        iter.next().unwrap().unwrap();
+        let (lower, upper) = iter.size_hint();
+        let upper = upper.unwrap();
+        let rest = iter.count();
+        assert!(lower <= rest && rest <= upper, "{:?}", (lower, rest, upper));
+        assert!(upper <= 2 * e.len() + 2, "{} {}", upper, e.len());
+    }
+}
@@ -0,0 +1,26 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::pipe;
+use fs_sparse::{SparseIter, SparseRangeIter, SparseRangeIterExt};
+
+#[test]
+fn fuse_on_error() {
+    let (r, _w) = pipe();
+
+    let mut i = SparseIter::from(&r);
+    assert!(i.next().unwrap().is_err());
+    assert!(i.next().is_none());
+    assert!(i.next().is_none());
+
+    let mut i = SparseRangeIter::from(SparseIter::from(&r));
+    assert!(i.next().unwrap().is_err());
+    assert!(i.next().is_none());
+
+    let mut i = SparseRangeIter::from(SparseIter::from(&r))
+        .coalesce()
+        .data_only();
+    assert!(i.next().unwrap().is_err());
+    assert!(i.next().is_none());
+}
@@ -0,0 +1,25 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::hash_sparse;
+
+#[test]
+fn feeds_dense_contents() {
+    for dir in dirs() {
+        for &(len, data) in &[
+            (5, &[1, 3][..]),
+            (3, &[][..]),
+            (2, &[0, 1][..]),
+            (0, &[][..]),
+        ] {
+            let (t, file) = sparse_file(&dir, len, data);
+
+            // a `Vec` sees exactly what a hasher would
+            let mut fed = Vec::new();
+            assert_eq!(hash_sparse(&file, &mut fed).unwrap(), len * UNIT);
+            assert!(fed == std::fs::read(t.path()).unwrap(), "{}", dir.display());
+        }
+    }
+}
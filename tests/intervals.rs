@@ -0,0 +1,37 @@
+// a list of one range is what's meant, not a mistake for a list of the numbers in it
+#![allow(clippy::single_range_in_vec_init)]
+
+use fs_sparse::intervals::{complement, intersect, subtract, union};
+
+#[test]
+fn operations() {
+    let a = [0..10, 20..30, 40..50];
+    let b = [5..25, 45..60];
+
+    assert_eq!(union(&a, &b), vec![0..30, 40..60]);
+    assert_eq!(intersect(&a, &b), vec![5..10, 20..25, 45..50]);
+    assert_eq!(subtract(&a, &b), vec![0..5, 25..30, 40..45]);
+    assert_eq!(subtract(&b, &a), vec![10..20, 50..60]);
+    assert_eq!(complement(&a, 0..60), vec![10..20, 30..40, 50..60]);
+    assert_eq!(complement(&a, 5..45), vec![10..20, 30..40]);
+    assert_eq!(complement(&[], 3..7), vec![3..7]);
+}
+
+#[test]
+fn messy_input() {
+    // out of order, overlapping, adjacent, and empty ranges
+    let a = [20..30, 0..5, 3..10, 10..12, 7..7];
+    assert_eq!(union(&a, &[]), vec![0..12, 20..30]);
+    assert_eq!(intersect(&a, &[11..21, 0..1]), vec![0..1, 11..12, 20..21]);
+    assert_eq!(subtract(&a, &[1..2, 4..25]), vec![0..1, 2..4, 25..30]);
+    assert_eq!(subtract(&a, &[0..100]), vec![]);
+    assert_eq!(intersect(&a, &[]), vec![]);
+}
+
+#[test]
+fn one_range_spans_many() {
+    let a = [0..2, 4..6, 8..10];
+    assert_eq!(subtract(&[0..10], &a), vec![2..4, 6..8]);
+    assert_eq!(intersect(&[1..9], &a), vec![1..2, 4..6, 8..9]);
+    assert_eq!(subtract(&a, &[1..9]), vec![0..1, 9..10]);
+}
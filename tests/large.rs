@@ -0,0 +1,58 @@
+#![cfg(unix)]
+
+//! Files past 4 GiB, which need 64-bit offsets on every platform (including 32-bit linux, where
+//! `off_t` is 32 bits unless the `*64` calls are used)
+
+mod common;
+
+use common::{dirs, UNIT};
+use fs_sparse::{
+    allocated_size, kind_at, next_data_from, punch_hole, ItemKind, SparseIter, SparseRangeIter,
+};
+use std::os::unix::fs::FileExt;
+
+const GIB: u64 = 1024 * 1024 * 1024;
+
+/// Where the data is: past both 2 GiB and 4 GiB
+const AT: u64 = 5 * GIB;
+
+#[test]
+fn data_past_4_gib() {
+    for dir in dirs() {
+        let t = tempfile::NamedTempFile::new_in(&dir).unwrap();
+        let f = t.as_file();
+        f.set_len(AT + 2 * UNIT).unwrap();
+        f.write_all_at(&vec![0xff; 2 * UNIT as usize], AT).unwrap();
+
+        let size = allocated_size(f).unwrap();
+        assert_eq!(size.logical, AT + 2 * UNIT, "{}", dir.display());
+        assert!(size.allocated < GIB, "{}: {:?}", dir.display(), size);
+
+        assert_eq!(next_data_from(f, 0).unwrap(), Some(AT), "{}", dir.display());
+        assert_eq!(
+            kind_at(f, AT + UNIT).unwrap(),
+            ItemKind::Data,
+            "{}",
+            dir.display()
+        );
+
+        punch_hole(f, AT, UNIT).unwrap();
+        let ranges: Vec<_> = SparseRangeIter::from(SparseIter::from(f))
+            .map(|r| r.unwrap())
+            .map(|r| (r.kind, r.start, r.end))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                (ItemKind::Hole, 0, AT + UNIT),
+                (ItemKind::Data, AT + UNIT, AT + 2 * UNIT)
+            ],
+            "{}",
+            dir.display()
+        );
+
+        let mut buf = [0u8; 4];
+        f.read_exact_at(&mut buf, AT + UNIT).unwrap();
+        assert_eq!(buf, [0xff; 4], "{}", dir.display());
+    }
+}
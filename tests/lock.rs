@@ -0,0 +1,57 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, sparse_file};
+use fs_sparse::{ItemKind, SparseIter};
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+
+/// Can an exclusive lock be taken on `file` without waiting?
+fn can_lock_exclusive(file: &File) -> bool {
+    let fd = file.as_raw_fd();
+    if unsafe { libc::flock(fd, libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        unsafe { libc::flock(fd, libc::LOCK_UN) };
+        true
+    } else {
+        false
+    }
+}
+
+#[test]
+fn lock_shared() {
+    for dir in dirs() {
+        let (tmp, f) = sparse_file(&dir, 4, &[1]);
+        // a separate open file, as another writer would have
+        let writer = tmp.reopen().unwrap();
+
+        let mut i = SparseIter::from(&f).lock_shared();
+        assert!(can_lock_exclusive(&writer));
+        assert!(i.next().unwrap().is_ok());
+        assert!(!can_lock_exclusive(&writer));
+        for item in &mut i {
+            item.unwrap();
+        }
+        assert!(can_lock_exclusive(&writer));
+
+        // dropping the iterator part way through also unlocks
+        let mut i = SparseIter::from(&f).lock_shared();
+        assert!(i.next().unwrap().is_ok());
+        assert!(!can_lock_exclusive(&writer));
+        drop(i);
+        assert!(can_lock_exclusive(&writer));
+    }
+}
+
+#[test]
+fn lock_shared_owned() {
+    let (tmp, f) = sparse_file(&std::env::temp_dir(), 2, &[0]);
+    let writer = tmp.reopen().unwrap();
+
+    let mut i = SparseIter::from(f).lock_shared();
+    assert_eq!(i.next().unwrap().unwrap().kind, ItemKind::Data);
+    assert!(!can_lock_exclusive(&writer));
+    let f = i.into_inner();
+    assert!(can_lock_exclusive(&writer));
+    drop(f);
+}
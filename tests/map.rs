@@ -0,0 +1,174 @@
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{map_path, ItemKind, RangeDiff, SparseMap, SparseRangeItem};
+
+#[test]
+fn from_file() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 7, &[1, 2, 5]);
+        let map = SparseMap::from_file(&f).unwrap();
+
+        assert_eq!(map.len(), 5, "{}", dir.display());
+        assert_eq!(map.file_len(), 7 * UNIT);
+        assert_eq!(map.data_len(), 3 * UNIT);
+        assert_eq!(map.hole_len(), 4 * UNIT);
+        assert_eq!(
+            map[1],
+            SparseRangeItem {
+                kind: ItemKind::Data,
+                start: UNIT,
+                end: 3 * UNIT
+            }
+        );
+        assert_eq!(map.get(5), None);
+        assert_eq!(map.find(0), map.get(0));
+        assert_eq!(map.find(3 * UNIT - 1), map.get(1));
+        assert_eq!(map.find(3 * UNIT), map.get(2));
+        assert_eq!(map.find(7 * UNIT), None);
+        assert_eq!(map.iter().count(), 5);
+    }
+}
+
+#[test]
+fn from_path() {
+    for dir in dirs() {
+        let (t, f) = sparse_file(&dir, 7, &[1, 2, 5]);
+        assert_eq!(
+            map_path(t.path()).unwrap(),
+            SparseMap::from_file(&f).unwrap(),
+            "{}",
+            dir.display()
+        );
+    }
+    let e = map_path(std::env::temp_dir().join("fs-sparse-does-not-exist")).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn empty() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 0, &[]);
+        let map = SparseMap::from_file(&f).unwrap();
+        assert!(map.is_empty());
+        assert_eq!(map.file_len(), 0);
+        assert_eq!(map.find(0), None);
+    }
+}
+
+#[test]
+fn qemu_json() {
+    let (_t, f) = sparse_file(&std::env::temp_dir(), 3, &[1]);
+    let map = SparseMap::from_file(&f).unwrap();
+    let mut out = Vec::new();
+    map.write_qemu_json(&mut out).unwrap();
+
+    let u = UNIT;
+    let expected = format!(
+        "[{{ \"start\": 0, \"length\": {u}, \"depth\": 0, \"present\": true, \"zero\": true, \
+         \"data\": false, \"compressed\": false, \"offset\": 0}},\n\
+         {{ \"start\": {u}, \"length\": {u}, \"depth\": 0, \"present\": true, \"zero\": false, \
+         \"data\": true, \"compressed\": false, \"offset\": {u}}},\n\
+         {{ \"start\": {u2}, \"length\": {u}, \"depth\": 0, \"present\": true, \"zero\": true, \
+         \"data\": false, \"compressed\": false, \"offset\": {u2}}}]\n",
+        u = u,
+        u2 = 2 * u
+    );
+    assert_eq!(String::from_utf8(out).unwrap(), expected);
+
+    let mut out = Vec::new();
+    SparseMap::default().write_qemu_json(&mut out).unwrap();
+    assert_eq!(out, b"[]\n");
+}
+
+#[test]
+fn diff() {
+    for dir in dirs() {
+        let (_t, a) = sparse_file(&dir, 6, &[0, 1, 3]);
+        let (_t, b) = sparse_file(&dir, 8, &[0, 2, 3, 7]);
+        let a = SparseMap::from_file(&a).unwrap();
+        let b = SparseMap::from_file(&b).unwrap();
+
+        let d = |start, end, before, after| RangeDiff {
+            start: start * UNIT,
+            end: end * UNIT,
+            before,
+            after,
+        };
+        let (data, hole) = (Some(ItemKind::Data), Some(ItemKind::Hole));
+        assert_eq!(
+            a.diff(&b),
+            vec![
+                d(1, 2, data, hole),
+                d(2, 3, hole, data),
+                d(6, 7, None, hole),
+                d(7, 8, None, data),
+            ],
+            "{}",
+            dir.display()
+        );
+
+        let reverse = b.diff(&a);
+        assert_eq!(reverse[3], d(7, 8, data, None));
+        assert!(a.diff(&a).is_empty());
+    }
+}
+
+#[test]
+#[allow(clippy::single_range_in_vec_init)]
+fn set_operations() {
+    // data in units 0, 1, 3 of 6, and 0, 2, 3, 7 of 8
+    let a = SparseMap::from_data_ranges(&[0..2, 3..4], 6);
+    let b = SparseMap::from_data_ranges(&[0..1, 2..4, 7..8], 8);
+    assert_eq!(a.file_len(), 6);
+    assert_eq!(a.len(), 4);
+
+    let check = |map: SparseMap, data: Vec<std::ops::Range<u64>>, len| {
+        assert_eq!(map.data_ranges(), data);
+        assert_eq!(map.file_len(), len);
+        // the ranges still cover the file, alternating between kinds
+        assert_eq!(map.data_len() + map.hole_len(), len);
+        let kinds: Vec<_> = map.iter().map(|r| r.kind).collect();
+        assert!(kinds.windows(2).all(|k| k[0] != k[1]), "{:?}", kinds);
+    };
+    check(a.union(&b), vec![0..4, 7..8], 8);
+    check(a.intersect(&b), vec![0..1, 3..4], 8);
+    check(a.subtract(&b), vec![1..2], 8);
+    check(b.subtract(&a), vec![2..3, 7..8], 8);
+    check(a.invert(), vec![2..3, 4..6], 6);
+    check(SparseMap::default().invert(), vec![], 0);
+    assert_eq!(a.invert().invert(), a);
+    assert_eq!(a.complement(), a.invert());
+
+    // what to punch out of b to make its holes match a's
+    check(b.intersect(&a.invert()), vec![2..3], 8);
+
+    // masking by a region of interest
+    let roi = SparseMap::from_data_ranges(&[1..5], 5);
+    check(b.intersect(&roi), vec![2..4], 8);
+}
+
+#[test]
+#[allow(clippy::single_range_in_vec_init)]
+fn from_data_ranges() {
+    // out of order, overlapping, empty, and past the end
+    let map = SparseMap::from_data_ranges(&[5..9, 2..2, 0..1, 1..3, 8..20], 10);
+    assert_eq!(map.data_ranges(), vec![0..3, 5..10]);
+    assert_eq!(map.file_len(), 10);
+    assert_eq!(map.hole_len(), 2);
+}
+
+#[test]
+fn sparseness() {
+    let map = SparseMap::from_data_ranges(&[0..1, 5..6], 10);
+    assert_eq!(map.sparseness(), 0.8);
+    assert_eq!(map.density(), 0.2);
+    let stats = map.stats();
+    assert_eq!((stats.data_bytes, stats.hole_bytes), (2, 8));
+    assert_eq!(stats.allocated_bytes, None);
+    assert_eq!(stats.ratio(), map.sparseness());
+
+    let empty = SparseMap::default();
+    assert_eq!((empty.sparseness(), empty.density()), (0.0, 0.0));
+    assert_eq!(SparseMap::from_data_ranges(&[], 4).sparseness(), 1.0);
+}
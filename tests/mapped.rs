@@ -0,0 +1,34 @@
+#![cfg(all(unix, feature = "mmap"))]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::MappedFile;
+
+#[test]
+fn data() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 6, &[1, 2, 4]);
+        let mapped = MappedFile::new(&f).unwrap();
+        assert_eq!(mapped.as_slice().len() as u64, 6 * UNIT);
+        assert_eq!(mapped.map().data_len(), 3 * UNIT, "{}", dir.display());
+
+        let data: Vec<_> = mapped.data().collect();
+        let ranges: Vec<_> = data.iter().map(|(_, r)| r.clone()).collect();
+        assert_eq!(ranges, vec![UNIT..3 * UNIT, 4 * UNIT..5 * UNIT]);
+        for (slice, range) in data {
+            assert_eq!(slice.len() as u64, range.end - range.start);
+            assert!(slice.iter().all(|&b| b == 0xff));
+        }
+    }
+}
+
+#[test]
+fn empty() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 0, &[]);
+        let mapped = MappedFile::new(&f).unwrap();
+        assert!(mapped.as_slice().is_empty());
+        assert_eq!(mapped.data().count(), 0);
+    }
+}
@@ -0,0 +1,79 @@
+#![cfg(all(unix, feature = "parallel"))]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{
+    read_scan_parallel, sparsify_parallel, Error, ItemKind, SparseIter, SparseRangeItem,
+    SparseRangeIter,
+};
+use std::os::unix::fs::FileExt;
+
+fn ranges(iter: SparseIter<&std::fs::File>) -> Vec<SparseRangeItem> {
+    SparseRangeIter::from(iter).map(|r| r.unwrap()).collect()
+}
+
+#[test]
+fn read_scan() {
+    for dir in dirs() {
+        // data on both sides of the 16 MiB segment boundaries
+        let (_t, f) = sparse_file(&dir, 40, &[0, 15, 16, 17, 20, 31, 32, 39]);
+        for threads in [0, 1, 3] {
+            let map = read_scan_parallel(&f, 4096, threads).unwrap();
+            assert_eq!(
+                map.iter().cloned().collect::<Vec<_>>(),
+                ranges(SparseIter::from(&f).read_scan(4096)),
+                "{}",
+                dir.display()
+            );
+        }
+
+        // blocks that don't divide the segment size
+        let map = read_scan_parallel(&f, 3 * 4096, 4).unwrap();
+        assert_eq!(
+            map.iter().cloned().collect::<Vec<_>>(),
+            ranges(SparseIter::from(&f).read_scan(3 * 4096))
+        );
+    }
+
+    let (_t, f) = sparse_file(&std::env::temp_dir(), 0, &[]);
+    assert!(read_scan_parallel(&f, 4096, 2).unwrap().is_empty());
+}
+
+#[test]
+fn sparsify() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 40, &(0..40).collect::<Vec<_>>());
+        // zeros across the boundaries at 16 and 32 MiB
+        f.write_all_at(&vec![0u8; 10 * UNIT as usize], 10 * UNIT)
+            .unwrap();
+        f.write_all_at(&vec![0u8; 2 * UNIT as usize], 31 * UNIT)
+            .unwrap();
+
+        let s = match sparsify_parallel(&f, 4096, 4) {
+            Ok(s) => s,
+            Err(Error::Unsupported { .. }) => continue,
+            Err(e) => panic!("{}: {}", dir.display(), e),
+        };
+        assert_eq!(s.scanned, 40 * UNIT, "{}", dir.display());
+        assert_eq!(s.punched, 12 * UNIT, "{}", dir.display());
+        assert_eq!(f.metadata().unwrap().len(), 40 * UNIT);
+
+        let got: Vec<_> = ranges(SparseIter::from(&f))
+            .into_iter()
+            .map(|r| (r.kind, r.start, r.end))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                (ItemKind::Data, 0, 10 * UNIT),
+                (ItemKind::Hole, 10 * UNIT, 20 * UNIT),
+                (ItemKind::Data, 20 * UNIT, 31 * UNIT),
+                (ItemKind::Hole, 31 * UNIT, 33 * UNIT),
+                (ItemKind::Data, 33 * UNIT, 40 * UNIT),
+            ],
+            "{}",
+            dir.display()
+        );
+    }
+}
@@ -0,0 +1,61 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{allocated_size, preallocate, Error};
+
+/// Did preallocating work? `false` if the platform or filesystem doesn't support it
+fn preallocated(r: fs_sparse::error::Result<()>) -> bool {
+    match r {
+        Ok(()) => true,
+        Err(Error::Unsupported { .. }) => false,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[test]
+fn grows_file() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 0, &[]);
+        if !preallocated(preallocate(&f, 4 * UNIT)) {
+            continue;
+        }
+
+        let s = allocated_size(&f).unwrap();
+        assert_eq!(s.logical, 4 * UNIT, "{}", dir.display());
+        assert!(s.allocated >= 4 * UNIT, "{}: {:?}", dir.display(), s);
+    }
+}
+
+#[test]
+fn never_shrinks() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[0]);
+        if !preallocated(preallocate(&f, UNIT)) {
+            continue;
+        }
+        assert_eq!(f.metadata().unwrap().len(), 4 * UNIT, "{}", dir.display());
+    }
+}
+
+#[cfg(target_os = "macos")]
+#[test]
+fn contiguous_never_shrinks() {
+    use fs_sparse::preallocate_contiguous;
+
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[0]);
+        preallocate_contiguous(&f, UNIT).unwrap();
+        assert_eq!(f.metadata().unwrap().len(), 4 * UNIT, "{}", dir.display());
+    }
+}
+
+#[test]
+fn too_large() {
+    let (_t, f) = sparse_file(&dirs()[0], 0, &[]);
+    match preallocate(&f, u64::MAX) {
+        Err(Error::InvalidRange { offset: 0, len }) => assert_eq!(len, u64::MAX),
+        r => panic!("{:?}", r),
+    }
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{kind_at, next_data_from, next_hole_from, ItemKind};
+
+#[test]
+fn kinds() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[1]);
+
+        assert_eq!(kind_at(&f, 0).unwrap(), ItemKind::Hole, "{}", dir.display());
+        assert_eq!(kind_at(&f, UNIT - 1).unwrap(), ItemKind::Hole);
+        assert_eq!(kind_at(&f, UNIT).unwrap(), ItemKind::Data);
+        assert_eq!(kind_at(&f, 2 * UNIT - 1).unwrap(), ItemKind::Data);
+        assert_eq!(kind_at(&f, 2 * UNIT).unwrap(), ItemKind::Hole);
+        assert_eq!(kind_at(&f, 4 * UNIT - 1).unwrap(), ItemKind::Hole);
+        assert_eq!(
+            kind_at(&f, 4 * UNIT).unwrap_err().kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+    }
+}
+
+#[test]
+fn next_from() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[1]);
+
+        assert_eq!(
+            next_data_from(&f, 0).unwrap(),
+            Some(UNIT),
+            "{}",
+            dir.display()
+        );
+        assert_eq!(next_data_from(&f, UNIT + 1).unwrap(), Some(UNIT + 1));
+        assert_eq!(next_data_from(&f, 2 * UNIT).unwrap(), None);
+        assert_eq!(next_data_from(&f, 4 * UNIT).unwrap(), None);
+
+        assert_eq!(next_hole_from(&f, 0).unwrap(), Some(0));
+        assert_eq!(next_hole_from(&f, UNIT).unwrap(), Some(2 * UNIT));
+        assert_eq!(next_hole_from(&f, 3 * UNIT).unwrap(), Some(3 * UNIT));
+        assert_eq!(next_hole_from(&f, 4 * UNIT).unwrap(), None);
+    }
+}
+
+#[test]
+fn next_hole_at_end() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 2, &[0, 1]);
+        assert_eq!(
+            next_hole_from(&f, 0).unwrap(),
+            Some(2 * UNIT),
+            "{}",
+            dir.display()
+        );
+    }
+}
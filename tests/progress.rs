@@ -0,0 +1,89 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{
+    copy_sparse_with_progress, sparsify_with_progress, CancelToken, Error, Progress, ScanBackend,
+    SparseScan,
+};
+use std::sync::{Arc, Mutex};
+
+/// Every `(done, total)` reported
+type Seen = Arc<Mutex<Vec<(u64, u64)>>>;
+
+/// A `Progress` that records every report
+fn recorder() -> (Progress, Seen) {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let s = seen.clone();
+    let p = Progress::new().on_progress(move |done, total| s.lock().unwrap().push((done, total)));
+    (p, seen)
+}
+
+fn cancelled() -> Progress {
+    let token = CancelToken::new();
+    token.cancel();
+    Progress::new().cancel_token(token)
+}
+
+#[test]
+fn copy() {
+    for dir in dirs() {
+        let (_t, src) = sparse_file(&dir, 4, &[1, 3]);
+        let (_t, dst) = sparse_file(&dir, 0, &[]);
+        let (p, seen) = recorder();
+        copy_sparse_with_progress(&src, &dst, &p).unwrap();
+
+        let seen = seen.lock().unwrap();
+        assert!(seen.windows(2).all(|w| w[0].0 <= w[1].0), "{:?}", seen);
+        assert!(seen.iter().all(|&(_, total)| total == 4 * UNIT));
+        assert_eq!(seen.last(), Some(&(4 * UNIT, 4 * UNIT)));
+    }
+}
+
+#[test]
+fn copy_cancelled() {
+    let (_t, src) = sparse_file(&dirs()[0], 2, &[0]);
+    let (_t, dst) = sparse_file(&dirs()[0], 0, &[]);
+    let e = copy_sparse_with_progress(&src, &dst, &cancelled()).unwrap_err();
+    assert!(matches!(Error::from(e), Error::Cancelled));
+}
+
+#[test]
+fn sparsify() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 2, &[0]);
+        let (p, seen) = recorder();
+        sparsify_with_progress(&f, 4096, &p).unwrap();
+        assert_eq!(seen.lock().unwrap().last(), Some(&(2 * UNIT, 2 * UNIT)));
+
+        assert!(matches!(
+            sparsify_with_progress(&f, 4096, &cancelled()),
+            Err(Error::Cancelled)
+        ));
+    }
+}
+
+#[test]
+fn scan() {
+    let (_t, f) = sparse_file(&dirs()[0], 3, &[1]);
+    let (p, seen) = recorder();
+    let n = SparseScan::builder()
+        .backends(&[ScanBackend::ReadScan { block_size: 4096 }])
+        .progress(p)
+        .build(&f)
+        .unwrap()
+        .collect::<std::io::Result<Vec<_>>>()
+        .unwrap()
+        .len();
+    assert_eq!(seen.lock().unwrap().len(), n);
+    assert_eq!(seen.lock().unwrap().last(), Some(&(3 * UNIT, 3 * UNIT)));
+
+    let mut scan = SparseScan::builder()
+        .progress(cancelled())
+        .build(&f)
+        .unwrap();
+    let e = scan.next().unwrap().unwrap_err();
+    assert!(matches!(Error::from(e), Error::Cancelled));
+    assert!(scan.next().is_none());
+}
@@ -0,0 +1,139 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, ranges, sparse_file, UNIT};
+use fs_sparse::{punch_hole, punch_hole_or_zero, Error, ItemKind, Zeroed};
+use std::os::unix::fs::FileExt;
+
+/// Did punching work? `false` if the platform or filesystem doesn't support it
+fn punched(r: fs_sparse::error::Result<()>) -> bool {
+    match r {
+        Ok(()) => true,
+        Err(Error::Unsupported { .. }) => false,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[test]
+fn punch() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[0, 1, 2, 3]);
+        if !punched(punch_hole(&f, UNIT, 2 * UNIT)) {
+            continue;
+        }
+        assert_eq!(
+            ranges(&f),
+            vec![
+                (ItemKind::Data, 0, 1),
+                (ItemKind::Hole, 1, 3),
+                (ItemKind::Data, 3, 4)
+            ],
+            "{}",
+            dir.display()
+        );
+        assert_eq!(f.metadata().unwrap().len(), 4 * UNIT);
+    }
+}
+
+#[test]
+fn unaligned() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 2, &[0, 1]);
+        if !punched(punch_hole(&f, 10, UNIT)) {
+            continue;
+        }
+
+        let mut buf = vec![0u8; 2 * UNIT as usize];
+        f.read_exact_at(&mut buf, 0).unwrap();
+        assert!(buf[..10].iter().all(|&b| b == 0xff));
+        assert!(buf[10..UNIT as usize + 10].iter().all(|&b| b == 0));
+        assert!(buf[UNIT as usize + 10..].iter().all(|&b| b == 0xff));
+    }
+}
+
+#[test]
+fn past_end() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 2, &[0, 1]);
+        if !punched(punch_hole(&f, UNIT, 10 * UNIT)) {
+            continue;
+        }
+        assert_eq!(f.metadata().unwrap().len(), 2 * UNIT, "{}", dir.display());
+        assert_eq!(
+            ranges(&f),
+            vec![(ItemKind::Data, 0, 1), (ItemKind::Hole, 1, 2)],
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn errors() {
+    for dir in dirs() {
+        let (t, _f) = sparse_file(&dir, 2, &[0, 1]);
+        let ro = std::fs::File::open(t.path()).unwrap();
+        match punch_hole(&ro, 0, UNIT) {
+            Err(Error::NotWritable { .. }) => {}
+            Err(Error::Unsupported { .. }) => continue,
+            r => panic!("{}: {:?}", dir.display(), r),
+        }
+
+        match punch_hole(&ro, u64::MAX, 1) {
+            Err(Error::InvalidRange { offset, len }) => {
+                assert_eq!((offset, len), (u64::MAX, 1))
+            }
+            r => panic!("{}: {:?}", dir.display(), r),
+        }
+
+        let e: std::io::Error = punch_hole(&ro, 0, UNIT).unwrap_err().into();
+        assert_eq!(e.kind(), std::io::ErrorKind::PermissionDenied);
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn zero_range() {
+    use fs_sparse::zero_range;
+
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[0, 1, 2, 3]);
+        match zero_range(&f, 10, 2 * UNIT) {
+            Ok(()) => {}
+            // tmpfs and btrfs don't support it
+            Err(Error::Unsupported { .. }) => continue,
+            Err(e) => panic!("{}: {}", dir.display(), e),
+        }
+
+        let mut buf = vec![0u8; 4 * UNIT as usize];
+        f.read_exact_at(&mut buf, 0).unwrap();
+        assert!(buf[..10].iter().all(|&b| b == 0xff));
+        assert!(buf[10..2 * UNIT as usize + 10].iter().all(|&b| b == 0));
+        assert!(buf[2 * UNIT as usize + 10..].iter().all(|&b| b == 0xff));
+        assert_eq!(f.metadata().unwrap().len(), 4 * UNIT, "{}", dir.display());
+    }
+}
+
+#[test]
+fn or_zero() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 2, &[0, 1]);
+        let how = punch_hole_or_zero(&f, 10, 4 * UNIT).unwrap();
+
+        let mut buf = vec![0u8; 2 * UNIT as usize];
+        f.read_exact_at(&mut buf, 0).unwrap();
+        assert!(buf[..10].iter().all(|&b| b == 0xff));
+        assert!(buf[10..].iter().all(|&b| b == 0));
+        assert_eq!(f.metadata().unwrap().len(), 2 * UNIT, "{}", dir.display());
+
+        // where punching is supported at all, whether it worked depends on the filesystem
+        if !cfg!(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "macos"
+        )) {
+            assert_eq!(how, Zeroed::Written, "{}", dir.display());
+        }
+    }
+}
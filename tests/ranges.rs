@@ -0,0 +1,438 @@
+mod common;
+
+use common::{dirs, ranges, sparse_file, write_all_at, UNIT};
+use fs_sparse::{ItemKind, SparseIter, SparseRangeItem, SparseRangeIter};
+
+use ItemKind::{Data, End, Hole};
+
+#[test]
+fn empty() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 0, &[]);
+        assert_eq!(ranges(&f), vec![], "{}", dir.display());
+    }
+}
+
+#[test]
+fn all_hole() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[]);
+        assert_eq!(ranges(&f), vec![(Hole, 0, 4)], "{}", dir.display());
+    }
+}
+
+#[test]
+fn all_data() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 2, &[0, 1]);
+        assert_eq!(ranges(&f), vec![(Data, 0, 2)], "{}", dir.display());
+    }
+}
+
+#[test]
+fn trailing_hole() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[0]);
+        assert_eq!(
+            ranges(&f),
+            vec![(Data, 0, 1), (Hole, 1, 4)],
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn leading_hole() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[3]);
+        assert_eq!(
+            ranges(&f),
+            vec![(Hole, 0, 3), (Data, 3, 4)],
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn alternating() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 7, &[1, 2, 5]);
+        assert_eq!(
+            ranges(&f),
+            vec![
+                (Hole, 0, 1),
+                (Data, 1, 3),
+                (Hole, 3, 5),
+                (Data, 5, 6),
+                (Hole, 6, 7)
+            ],
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn starting_at() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 7, &[1, 2, 5]);
+        let r = |offset| -> Vec<_> {
+            SparseRangeIter::from(SparseIter::starting_at(&f, offset))
+                .map(|r| r.unwrap())
+                .map(|r| (r.kind, r.start, r.end))
+                .collect()
+        };
+
+        assert_eq!(
+            r(2 * UNIT + 10),
+            vec![
+                (Data, 2 * UNIT + 10, 3 * UNIT),
+                (Hole, 3 * UNIT, 5 * UNIT),
+                (Data, 5 * UNIT, 6 * UNIT),
+                (Hole, 6 * UNIT, 7 * UNIT)
+            ],
+            "{}",
+            dir.display()
+        );
+        assert_eq!(
+            r(6 * UNIT + 1),
+            vec![(Hole, 6 * UNIT + 1, 7 * UNIT)],
+            "{}",
+            dir.display()
+        );
+        assert_eq!(r(7 * UNIT), vec![], "{}", dir.display());
+        assert_eq!(r(8 * UNIT), vec![], "{}", dir.display());
+    }
+}
+
+fn open_ranges(path: &std::path::Path) -> SparseRangeIter<std::fs::File> {
+    SparseRangeIter::from(SparseIter::from(std::fs::File::open(path).unwrap()))
+}
+
+#[test]
+fn owned() {
+    for dir in dirs() {
+        let (t, _f) = sparse_file(&dir, 4, &[1]);
+        let iter = open_ranges(t.path());
+        let r = std::thread::spawn(move || {
+            iter.map(|r| r.unwrap())
+                .map(|r| (r.kind, r.start / UNIT, r.end / UNIT))
+                .collect::<Vec<_>>()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(
+            r,
+            vec![(Hole, 0, 1), (Data, 1, 2), (Hole, 2, 4)],
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn owned_fd() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[1]);
+        let fd = std::os::unix::io::OwnedFd::from(f.try_clone().unwrap());
+        let r: Vec<_> = SparseRangeIter::from(SparseIter::from(fd))
+            .map(|r| r.unwrap())
+            .map(|r| (r.kind, r.start / UNIT, r.end / UNIT))
+            .collect();
+        assert_eq!(r, ranges(&f), "{}", dir.display());
+    }
+}
+
+#[test]
+fn preserve_cursor() {
+    use std::io::{Read, Seek, SeekFrom};
+
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[1]);
+        (&f).seek(SeekFrom::Start(UNIT + 1)).unwrap();
+
+        let mut iter = SparseIter::from(&f).preserve_cursor();
+        assert_eq!(iter.next().unwrap().unwrap().kind, Hole);
+        assert_eq!(iter.next().unwrap().unwrap().kind, Data);
+
+        let mut b = [0u8; 1];
+        (&f).read_exact(&mut b).unwrap();
+        assert_eq!(b, [0xff], "{}", dir.display());
+        assert_eq!(
+            (&f).stream_position().unwrap(),
+            UNIT + 2,
+            "{}",
+            dir.display()
+        );
+
+        assert_eq!(iter.next().unwrap().unwrap().kind, Hole);
+        assert_eq!(iter.next().unwrap().unwrap().kind, End);
+        assert!(iter.next().is_none());
+        assert_eq!(
+            (&f).stream_position().unwrap(),
+            UNIT + 2,
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn restore_cursor() {
+    use std::io::{Seek, SeekFrom};
+
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[1]);
+        (&f).seek(SeekFrom::Start(UNIT + 1)).unwrap();
+
+        let mut iter = SparseIter::from(&f).restore_cursor();
+        assert_eq!(iter.next().unwrap().unwrap().kind, Hole);
+        assert_eq!(iter.next().unwrap().unwrap().kind, Data);
+        drop(iter);
+        assert_eq!(
+            (&f).stream_position().unwrap(),
+            UNIT + 1,
+            "{}",
+            dir.display()
+        );
+
+        let iter = SparseIter::from(&f).restore_cursor();
+        assert_eq!(iter.count(), 4);
+        assert_eq!(
+            (&f).stream_position().unwrap(),
+            UNIT + 1,
+            "{}",
+            dir.display()
+        );
+
+        // moving the cursor between creating the iterator and iterating is kept
+        let mut iter = SparseIter::from(&f).restore_cursor();
+        (&f).seek(SeekFrom::Start(3)).unwrap();
+        iter.next().unwrap().unwrap();
+        let mut f = iter.into_inner();
+        assert_eq!(f.stream_position().unwrap(), 3, "{}", dir.display());
+    }
+}
+
+#[test]
+fn resume() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 6, &[1, 2, 4]);
+        let all = ranges(&f);
+
+        // resume after every number of ranges, including none and all of them
+        for n in 0..=all.len() {
+            let mut iter = SparseRangeIter::from(SparseIter::from(&f));
+            let mut r: Vec<_> = (&mut iter)
+                .take(n)
+                .map(|r| r.unwrap())
+                .map(|r| (r.kind, r.start / UNIT, r.end / UNIT))
+                .collect();
+            let pos = iter.position();
+            assert_eq!(pos.finished, n == all.len(), "{}", dir.display());
+
+            r.extend(
+                SparseRangeIter::from(SparseIter::resume_at(&f, pos))
+                    .map(|r| r.unwrap())
+                    .map(|r| (r.kind, r.start / UNIT, r.end / UNIT)),
+            );
+            assert_eq!(r, all, "{} {}", n, dir.display());
+        }
+
+        // a resumed SparseIter returns the last item again
+        let mut iter = SparseIter::from(&f);
+        assert_eq!(iter.position().offset, 0);
+        iter.next().unwrap().unwrap();
+        iter.next().unwrap().unwrap();
+        let pos = iter.position();
+        assert_eq!((pos.offset, pos.finished), (UNIT, false));
+        let rest = points(SparseIter::resume_at(&f, pos));
+        assert_eq!(
+            rest,
+            vec![(Data, 1), (Hole, 3), (Data, 4), (Hole, 5), (End, 6)]
+        );
+
+        let mut iter = SparseIter::from(&f);
+        for i in &mut iter {
+            i.unwrap();
+        }
+        let pos = iter.position();
+        assert_eq!((pos.offset, pos.finished), (6 * UNIT, true));
+        assert_eq!(points(SparseIter::resume_at(&f, pos)), vec![(End, 6)]);
+    }
+}
+
+#[test]
+fn skip_to() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 8, &[1, 2, 5]);
+
+        let mut iter = SparseIter::from(&f);
+        assert_eq!(iter.next().unwrap().unwrap().kind, Hole);
+        iter.skip_to(2 * UNIT + 1);
+        assert_eq!(
+            points(iter),
+            vec![(Data, 2), (Hole, 3), (Data, 5), (Hole, 6), (End, 8)]
+        );
+
+        let mut iter = SparseRangeIter::from(SparseIter::from(&f));
+        assert_eq!(iter.next().unwrap().unwrap().kind, Hole);
+        iter.skip_to(4 * UNIT);
+        let r: Vec<_> = iter
+            .map(|r| r.unwrap())
+            .map(|r| (r.kind, r.start / UNIT, r.end / UNIT))
+            .collect();
+        assert_eq!(r, vec![(Hole, 4, 5), (Data, 5, 6), (Hole, 6, 8)]);
+
+        // going back, and past the end
+        let mut iter = SparseIter::from(&f);
+        iter.nth(3).unwrap().unwrap();
+        iter.skip_to(0);
+        assert_eq!(iter.next().unwrap().unwrap().offset, 0);
+        iter.skip_to(100 * UNIT);
+        assert_eq!(points(iter), vec![(End, 8)]);
+
+        // nothing happens once iteration is over
+        let mut iter = SparseIter::from(&f);
+        for i in &mut iter {
+            i.unwrap();
+        }
+        iter.skip_to(0);
+        assert!(iter.next().is_none());
+    }
+}
+
+fn points(iter: SparseIter<&std::fs::File>) -> Vec<(ItemKind, u64)> {
+    iter.map(|i| i.unwrap())
+        .map(|i| (i.kind, i.offset / UNIT))
+        .collect()
+}
+
+#[test]
+fn end() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[1]);
+        assert_eq!(
+            points(SparseIter::from(&f)),
+            vec![(Hole, 0), (Data, 1), (Hole, 2), (End, 4)],
+            "{}",
+            dir.display()
+        );
+        assert_eq!(
+            points(SparseIter::starting_at(&f, 2 * UNIT)),
+            vec![(Hole, 2), (End, 4)]
+        );
+        assert_eq!(
+            points(SparseIter::starting_at(&f, 5 * UNIT)),
+            vec![(End, 4)]
+        );
+
+        let (_t, f) = sparse_file(&dir, 2, &[0, 1]);
+        assert_eq!(
+            points(SparseIter::from(&f)),
+            vec![(Data, 0), (End, 2)],
+            "{}",
+            dir.display()
+        );
+
+        let (_t, f) = sparse_file(&dir, 0, &[]);
+        assert_eq!(
+            points(SparseIter::from(&f)),
+            vec![(End, 0)],
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn sync() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[1]);
+        assert_eq!(
+            SparseRangeIter::from(SparseIter::from(&f).sync())
+                .map(|r| r.unwrap())
+                .map(|r| (r.kind, r.start / UNIT, r.end / UNIT))
+                .collect::<Vec<_>>(),
+            vec![(Hole, 0, 1), (Data, 1, 2), (Hole, 2, 4)],
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn window_stops_reading_at_end() {
+    for dir in dirs() {
+        // zeros in the first half of the block, data in the second
+        let (_t, f) = sparse_file(&dir, 1, &[0]);
+        write_all_at(&f, &vec![0u8; UNIT as usize / 2], 0);
+        let items: Vec<_> = SparseIter::window(&f, 0..UNIT / 2)
+            .read_scan(UNIT)
+            .map(|i| i.unwrap())
+            .map(|i| (i.kind, i.offset))
+            .collect();
+        assert_eq!(items, vec![(Hole, 0), (End, UNIT / 2)], "{}", dir.display());
+    }
+}
+
+#[test]
+fn window() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 8, &[1, 2, 5]);
+        for iter in &[
+            SparseIter::window(&f, UNIT..6 * UNIT),
+            SparseIter::window(&f, UNIT..6 * UNIT).read_scan(4096),
+        ] {
+            assert_eq!(
+                points(iter.clone()),
+                vec![(Data, 1), (Hole, 3), (Data, 5), (End, 6)],
+                "{}",
+                dir.display()
+            );
+        }
+        assert_eq!(
+            points(SparseIter::window(&f, 2 * UNIT..4 * UNIT)),
+            vec![(Data, 2), (Hole, 3), (End, 4)]
+        );
+        assert_eq!(
+            points(SparseIter::from(&f).ending_at(20 * UNIT)),
+            points(SparseIter::from(&f))
+        );
+        assert_eq!(
+            points(SparseIter::window(&f, 3 * UNIT..3 * UNIT)),
+            vec![(End, 3)]
+        );
+        #[allow(clippy::reversed_empty_ranges)]
+        for iter in &[
+            SparseIter::window(&f, 4 * UNIT..2 * UNIT),
+            SparseIter::window(&f, 4 * UNIT..2 * UNIT).read_scan(4096),
+        ] {
+            assert_eq!(points(iter.clone()), vec![], "{}", dir.display());
+        }
+
+        // shards put back together are the whole file
+        let mut shards = Vec::new();
+        for i in 0..4 {
+            let window = 2 * i * UNIT..2 * (i + 1) * UNIT;
+            shards.extend(
+                SparseRangeIter::from(SparseIter::window(&f, window)).map(|r| {
+                    let SparseRangeItem { kind, start, end } = r.unwrap();
+                    (kind, start / UNIT, end / UNIT)
+                }),
+            );
+        }
+        shards.dedup_by(|b, a| {
+            if a.0 == b.0 && a.2 == b.1 {
+                a.2 = b.2;
+                true
+            } else {
+                false
+            }
+        });
+        assert_eq!(shards, ranges(&f), "{}", dir.display());
+    }
+}
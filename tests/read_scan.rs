@@ -0,0 +1,152 @@
+mod common;
+
+use common::{dirs, sparse_file, write_all_at, UNIT};
+use fs_sparse::{ItemKind, SparseIter, SparseRangeIter};
+
+use ItemKind::{Data, Hole};
+
+fn ranges(iter: SparseIter<&std::fs::File>) -> Vec<(ItemKind, u64, u64)> {
+    SparseRangeIter::from(iter)
+        .map(|r| r.unwrap())
+        .map(|r| (r.kind, r.start, r.end))
+        .collect()
+}
+
+#[test]
+fn sparse() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 7, &[1, 2, 5]);
+        assert_eq!(
+            ranges(SparseIter::from(&f).read_scan(4096)),
+            ranges(SparseIter::from(&f)),
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn dense_zeros() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 0, &[]);
+        let mut buf = vec![0u8; 10000];
+        buf[0] = 1;
+        buf[8200] = 1;
+        write_all_at(&f, &buf, 0);
+
+        assert_eq!(
+            ranges(SparseIter::from(&f).read_scan(4096)),
+            vec![(Data, 0, 4096), (Hole, 4096, 8192), (Data, 8192, 10000)],
+            "{}",
+            dir.display()
+        );
+        assert_eq!(
+            ranges(SparseIter::starting_at(&f, 100).read_scan(4096)),
+            vec![(Hole, 100, 8192), (Data, 8192, 10000)],
+        );
+        assert_eq!(
+            ranges(SparseIter::starting_at(&f, 10000).read_scan(4096)),
+            vec![]
+        );
+        assert_eq!(
+            ranges(SparseIter::from(&f).read_scan(3 * UNIT)),
+            vec![(Data, 0, 10000)]
+        );
+    }
+}
+
+#[test]
+fn size_hint() {
+    let (_t, f) = sparse_file(&std::env::temp_dir(), 5, &[1, 3]);
+
+    // no bound until it's known where the scan stops
+    let mut iter = SparseIter::from(&f).read_scan(4096);
+    iter.next().unwrap().unwrap();
+    assert_eq!(iter.size_hint().1, None);
+
+    let mut iter = SparseIter::starting_at(&f, 100)
+        .ending_at(2 * UNIT)
+        .read_scan(4096);
+    iter.next().unwrap().unwrap();
+    let (lower, upper) = iter.size_hint();
+    let upper = upper.unwrap();
+    let rest = iter.count();
+    assert!(lower <= rest && rest <= upper, "{:?}", (lower, rest, upper));
+    // a block at a time, then `End`
+    assert!(upper <= (2 * UNIT / 4096) as usize + 1, "{}", upper);
+}
+
+#[test]
+fn fallback_to_read_scan() {
+    // procfs doesn't support SEEK_DATA, and reports a length of 0 despite having contents
+    let f = match std::fs::File::open("/proc/self/status") {
+        Ok(f) => f,
+        Err(_) => return,
+    };
+
+    let r = ranges(SparseIter::from(&f).fallback_to_read_scan(4096));
+    assert_eq!(r.len(), 1);
+    assert_eq!(r[0].0, Data);
+    assert!(r[0].2 > 0);
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn mmap_scan() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 7, &[1, 2, 5]);
+        write_all_at(&f, &[0u8; 100], 7 * UNIT);
+        write_all_at(&f, &[1u8; 1], 7 * UNIT + 100);
+
+        assert_eq!(
+            ranges(SparseIter::from(&f).mmap_scan(4096)),
+            ranges(SparseIter::from(&f).read_scan(4096)),
+            "{}",
+            dir.display()
+        );
+        assert_eq!(
+            ranges(SparseIter::starting_at(&f, 100).mmap_scan(4096)),
+            ranges(SparseIter::starting_at(&f, 100).read_scan(4096)),
+            "{}",
+            dir.display()
+        );
+    }
+
+    let (_t, f) = sparse_file(&std::env::temp_dir(), 0, &[]);
+    assert_eq!(ranges(SparseIter::from(&f).mmap_scan(4096)), vec![]);
+}
+
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+#[test]
+fn uring_scan() {
+    for dir in dirs() {
+        // more blocks than fit in one batch, with a partial block at the end
+        let (_t, f) = sparse_file(&dir, 7, &[1, 2, 5]);
+        write_all_at(&f, &[1u8; 1], 7 * UNIT + 100);
+
+        let mut iter = SparseRangeIter::from(SparseIter::from(&f).uring_scan(4096));
+        match iter.next() {
+            Some(Err(ref e))
+                if matches!(e.raw_os_error(), Some(libc::ENOSYS) | Some(libc::EPERM)) =>
+            {
+                return
+            }
+            _ => {}
+        }
+        assert_eq!(
+            ranges(SparseIter::from(&f).uring_scan(4096)),
+            ranges(SparseIter::from(&f).read_scan(4096)),
+            "{}",
+            dir.display()
+        );
+        assert_eq!(
+            ranges(SparseIter::starting_at(&f, 100).uring_scan(3000)),
+            ranges(SparseIter::starting_at(&f, 100).read_scan(3000)),
+        );
+        // a window ending partway through a batch, and partway through a block
+        assert_eq!(
+            ranges(SparseIter::window(&f, 100..2 * UNIT + 1000).uring_scan(4096)),
+            ranges(SparseIter::window(&f, 100..2 * UNIT + 1000).read_scan(4096)),
+        );
+    }
+}
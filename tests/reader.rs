@@ -0,0 +1,81 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{DataExtents, SparseIter, SparseReader};
+use std::io::Read;
+
+#[test]
+fn reads_everything() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[1, 3]);
+        let mut got = Vec::new();
+        SparseReader::new(&f).read_to_end(&mut got).unwrap();
+
+        let mut want = vec![0u8; 4 * UNIT as usize];
+        want[UNIT as usize..2 * UNIT as usize].fill(0xff);
+        want[3 * UNIT as usize..].fill(0xff);
+        assert!(got == want, "{}", dir.display());
+    }
+}
+
+#[test]
+fn starting_at() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 3, &[0, 2]);
+        let mut r = SparseReader::from(SparseIter::starting_at(&f, UNIT / 2));
+        let mut got = Vec::new();
+        r.read_to_end(&mut got).unwrap();
+        assert_eq!(r.position(), 3 * UNIT);
+
+        assert_eq!(got.len(), 5 * UNIT as usize / 2, "{}", dir.display());
+        assert!(got[..UNIT as usize / 2].iter().all(|&b| b == 0xff));
+        assert!(got[UNIT as usize / 2..3 * UNIT as usize / 2]
+            .iter()
+            .all(|&b| b == 0));
+        assert!(got[3 * UNIT as usize / 2..].iter().all(|&b| b == 0xff));
+    }
+}
+
+#[test]
+fn data_extents() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 5, &[1, 3]);
+        let extents: Vec<_> = DataExtents::new(&f).map(|r| r.unwrap()).collect();
+        assert_eq!(
+            extents.iter().map(|(o, _)| *o).collect::<Vec<_>>(),
+            vec![UNIT, 3 * UNIT],
+            "{}",
+            dir.display()
+        );
+
+        // read them out of order
+        for (_, mut r) in extents.into_iter().rev() {
+            assert_eq!(r.remaining(), UNIT);
+            let mut got = Vec::new();
+            r.read_to_end(&mut got).unwrap();
+            assert_eq!(got.len(), UNIT as usize);
+            assert!(got.iter().all(|&b| b == 0xff));
+            assert_eq!(r.remaining(), 0);
+        }
+    }
+}
+
+#[test]
+fn with_data() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[1, 3]);
+        // the data comes from somewhere else: here, a buffer of 0x11 the length of the file
+        let data = vec![0x11u8; 4 * UNIT as usize];
+        let mut got = Vec::new();
+        SparseReader::with_data(SparseIter::from(&f), &data[..])
+            .read_to_end(&mut got)
+            .unwrap();
+
+        let mut want = vec![0u8; 4 * UNIT as usize];
+        want[UNIT as usize..2 * UNIT as usize].fill(0x11);
+        want[3 * UNIT as usize..].fill(0x11);
+        assert!(got == want, "{}", dir.display());
+    }
+}
@@ -0,0 +1,69 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{rscan, ItemKind, SparseIter, SparseRangeIter, SparseRangeIterExt};
+
+use ItemKind::{Data, Hole};
+
+fn forward(file: &std::fs::File) -> Vec<(ItemKind, u64, u64)> {
+    let mut r: Vec<_> = SparseRangeIter::from(SparseIter::from(file))
+        .map(|r| r.unwrap())
+        .map(|r| (r.kind, r.start, r.end))
+        .collect();
+    r.reverse();
+    r
+}
+
+fn backward(file: &std::fs::File) -> Vec<(ItemKind, u64, u64)> {
+    rscan(file)
+        .map(|r| r.unwrap())
+        .map(|r| (r.kind, r.start, r.end))
+        .collect()
+}
+
+#[test]
+fn same_as_forward() {
+    for dir in dirs() {
+        // long enough that several windows are needed, with ranges that cross between them
+        for data in &[
+            &[][..],
+            &[0],
+            &[9],
+            &[0, 1, 2],
+            &[1, 4, 5, 6],
+            &[0, 2, 3, 8, 9],
+        ] {
+            let (_t, f) = sparse_file(&dir, 10, data);
+            assert_eq!(backward(&f), forward(&f), "{:?} {}", data, dir.display());
+        }
+    }
+}
+
+#[test]
+fn last_data() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 40, &[1, 3]);
+        let last = rscan(&f).data_only().next().unwrap().unwrap();
+        assert_eq!(
+            (last.start, last.end),
+            (3 * UNIT, 4 * UNIT),
+            "{}",
+            dir.display()
+        );
+
+        let (_t, f) = sparse_file(&dir, 4, &[]);
+        assert!(rscan(&f).data_only().next().is_none());
+        assert_eq!(backward(&f), vec![(Hole, 0, 4 * UNIT)]);
+
+        let (_t, f) = sparse_file(&dir, 4, &[0, 1, 2, 3]);
+        assert_eq!(backward(&f), vec![(Data, 0, 4 * UNIT)]);
+    }
+}
+
+#[test]
+fn empty() {
+    let f = tempfile::tempfile().unwrap();
+    assert!(rscan(&f).next().is_none());
+}
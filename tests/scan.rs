@@ -0,0 +1,153 @@
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{ItemKind, ScanBackend, SparseIter, SparseRangeIter, SparseScan};
+
+#[cfg(unix)]
+use fs_sparse::HoleCheck;
+
+use ItemKind::{Data, Hole};
+
+fn ranges<I: Iterator<Item = std::io::Result<fs_sparse::SparseRangeItem>>>(
+    iter: I,
+) -> Vec<(ItemKind, u64, u64)> {
+    iter.map(|r| r.unwrap())
+        .map(|r| (r.kind, r.start / UNIT, r.end / UNIT))
+        .collect()
+}
+
+#[test]
+fn default_is_seek_hole() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 7, &[1, 2, 5]);
+        let scan = SparseScan::builder().build(&f).unwrap();
+        assert_eq!(scan.backend(), ScanBackend::SeekHole);
+        assert_eq!(
+            ranges(scan),
+            ranges(SparseRangeIter::from(SparseIter::from(&f))),
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn first_supported_backend() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[1]);
+        let scan = SparseScan::builder()
+            .backends(&[
+                ScanBackend::ReadScan { block_size: 4096 },
+                ScanBackend::SeekHole,
+            ])
+            .build(&f)
+            .unwrap();
+        assert_eq!(scan.backend(), ScanBackend::ReadScan { block_size: 4096 });
+        assert_eq!(
+            ranges(scan),
+            vec![(Hole, 0, 1), (Data, 1, 2), (Hole, 2, 4)],
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+// procfs is only mounted everywhere on linux
+#[cfg(target_os = "linux")]
+#[test]
+fn unsupported_is_skipped() {
+    // procfs supports neither SEEK_HOLE nor FIEMAP
+    let f = std::fs::File::open("/proc/self/status").unwrap();
+    let mut backends = vec![ScanBackend::SeekHole, ScanBackend::Fiemap];
+
+    let err = SparseScan::builder()
+        .backends(&backends)
+        .build(&f)
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+
+    backends.push(ScanBackend::ReadScan { block_size: 512 });
+    let scan = SparseScan::builder().backends(&backends).build(&f).unwrap();
+    assert_eq!(scan.backend(), ScanBackend::ReadScan { block_size: 512 });
+}
+
+#[test]
+fn options() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 7, &[1, 3, 6]);
+        let scan = SparseScan::builder()
+            .sync(true)
+            .start(UNIT)
+            .min_hole_size(2 * UNIT)
+            .build(&f)
+            .unwrap();
+        assert_eq!(
+            ranges(scan),
+            vec![(Data, 1, 4), (Hole, 4, 6), (Data, 6, 7)],
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn end_bound() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 7, &[1, 3, 6]);
+        let scan = SparseScan::builder()
+            .start(UNIT)
+            .end(4 * UNIT)
+            .build(&f)
+            .unwrap();
+        assert_eq!(
+            ranges(scan),
+            vec![(Data, 1, 2), (Hole, 2, 3), (Data, 3, 4)],
+            "{}",
+            dir.display()
+        );
+
+        let scan = SparseScan::builder().end(20 * UNIT).build(&f).unwrap();
+        assert_eq!(ranges(scan).last(), Some(&(Data, 6, 7)));
+    }
+}
+
+#[test]
+fn aligned() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 8, &[1, 4]);
+        let scan = SparseScan::builder().align(2 * UNIT).build(&f).unwrap();
+        assert_eq!(
+            ranges(scan),
+            vec![(Data, 0, 2), (Hole, 2, 4), (Data, 4, 6), (Hole, 6, 8)],
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn verified() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 7, &[1, 2, 5]);
+        for check in &[HoleCheck::Full, HoleCheck::Sample(UNIT / 2)] {
+            let scan = SparseScan::builder().verify(*check).build(&f).unwrap();
+            assert_eq!(
+                ranges(scan),
+                ranges(SparseRangeIter::from(SparseIter::from(&f))),
+                "{}",
+                dir.display()
+            );
+        }
+    }
+}
+
+#[test]
+#[should_panic]
+fn zero_block_size() {
+    // caught when the backends are given, before there's a file to scan
+    let _ = SparseScan::builder().backends(&[
+        ScanBackend::SeekHole,
+        ScanBackend::ReadScan { block_size: 0 },
+    ]);
+}
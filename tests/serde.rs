@@ -0,0 +1,73 @@
+#![cfg(all(unix, feature = "serde"))]
+
+mod common;
+
+use common::{sparse_file, UNIT};
+use fs_sparse::{ItemKind, ScanPosition, SparseItem, SparseMap, SparseRangeItem, SparseStats};
+
+#[test]
+fn items() {
+    let item = SparseItem {
+        kind: ItemKind::Hole,
+        offset: 4096,
+    };
+    let json = serde_json::to_string(&item).unwrap();
+    assert_eq!(json, r#"{"kind":"Hole","offset":4096}"#);
+    assert_eq!(serde_json::from_str::<SparseItem>(&json).unwrap(), item);
+
+    let range = SparseRangeItem {
+        kind: ItemKind::Data,
+        start: 0,
+        end: 10,
+    };
+    let json = serde_json::to_string(&range).unwrap();
+    assert_eq!(json, r#"{"kind":"Data","start":0,"end":10}"#);
+    assert_eq!(
+        serde_json::from_str::<SparseRangeItem>(&json).unwrap(),
+        range
+    );
+}
+
+#[test]
+fn map() {
+    let (_t, f) = sparse_file(&std::env::temp_dir(), 2, &[1]);
+    let map = SparseMap::from_file(&f).unwrap();
+
+    let json = serde_json::to_string(&map).unwrap();
+    assert_eq!(
+        json,
+        format!(
+            r#"[{{"kind":"Hole","start":0,"end":{}}},{{"kind":"Data","start":{},"end":{}}}]"#,
+            UNIT,
+            UNIT,
+            2 * UNIT
+        )
+    );
+    assert_eq!(serde_json::from_str::<SparseMap>(&json).unwrap(), map);
+}
+
+#[test]
+fn stats() {
+    let stats = SparseStats {
+        data_bytes: 4096,
+        hole_bytes: 8192,
+        allocated_bytes: None,
+    };
+    let json = serde_json::to_string(&stats).unwrap();
+    assert_eq!(
+        json,
+        r#"{"data_bytes":4096,"hole_bytes":8192,"allocated_bytes":null}"#
+    );
+    assert_eq!(serde_json::from_str::<SparseStats>(&json).unwrap(), stats);
+}
+
+#[test]
+fn position() {
+    let pos = ScanPosition {
+        offset: 4096,
+        finished: false,
+    };
+    let json = serde_json::to_string(&pos).unwrap();
+    assert_eq!(json, r#"{"offset":4096,"finished":false}"#);
+    assert_eq!(serde_json::from_str::<ScanPosition>(&json).unwrap(), pos);
+}
@@ -0,0 +1,31 @@
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{allocated_size, is_sparse};
+
+#[test]
+fn allocated() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 8, &[1]);
+        let s = allocated_size(&f).unwrap();
+        assert_eq!(s.logical, 8 * UNIT, "{}", dir.display());
+        assert!(s.allocated >= UNIT, "{}: {:?}", dir.display(), s);
+        assert!(s.allocated < 8 * UNIT, "{}: {:?}", dir.display(), s);
+
+        let (_t, f) = sparse_file(&dir, 2, &[0, 1]);
+        let s = allocated_size(&f).unwrap();
+        assert_eq!(s.logical, 2 * UNIT, "{}", dir.display());
+        assert!(s.allocated >= 2 * UNIT, "{}: {:?}", dir.display(), s);
+    }
+}
+
+#[test]
+fn sparse() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 8, &[1]);
+        assert!(is_sparse(&f).unwrap(), "{}", dir.display());
+
+        let (_t, f) = sparse_file(&dir, 2, &[0, 1]);
+        assert!(!is_sparse(&f).unwrap(), "{}", dir.display());
+    }
+}
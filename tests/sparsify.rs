@@ -0,0 +1,111 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{
+    sparsify, sparsify_dry_run, sparsify_path, Error, ItemKind, SparseIter, SparseMap,
+    SparseRangeIter,
+};
+use std::os::unix::fs::FileExt;
+
+#[test]
+fn digs_holes() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 5, &[0, 1, 2, 4]);
+        f.write_all_at(&vec![0u8; 2 * UNIT as usize], UNIT).unwrap();
+
+        let s = match sparsify(&f, 4096) {
+            Ok(s) => s,
+            Err(Error::Unsupported { .. }) => continue,
+            Err(e) => panic!("{}: {}", dir.display(), e),
+        };
+        assert_eq!(s.scanned, 4 * UNIT, "{}", dir.display());
+        assert_eq!(s.punched, 2 * UNIT, "{}", dir.display());
+        assert_eq!(f.metadata().unwrap().len(), 5 * UNIT);
+
+        let ranges: Vec<_> = SparseRangeIter::from(SparseIter::from(&f))
+            .map(|r| r.unwrap())
+            .map(|r| (r.kind, r.start, r.end))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![
+                (ItemKind::Data, 0, UNIT),
+                (ItemKind::Hole, UNIT, 4 * UNIT),
+                (ItemKind::Data, 4 * UNIT, 5 * UNIT)
+            ],
+            "{}",
+            dir.display()
+        );
+
+        let mut buf = vec![0u8; 5 * UNIT as usize];
+        f.read_exact_at(&mut buf, 0).unwrap();
+        assert!(buf[..UNIT as usize].iter().all(|&b| b == 0xff));
+        assert!(buf[UNIT as usize..4 * UNIT as usize]
+            .iter()
+            .all(|&b| b == 0));
+        assert!(buf[4 * UNIT as usize..].iter().all(|&b| b == 0xff));
+    }
+}
+
+#[test]
+fn by_path() {
+    for dir in dirs() {
+        let (t, f) = sparse_file(&dir, 3, &[0, 1, 2]);
+        f.write_all_at(&vec![0u8; UNIT as usize], UNIT).unwrap();
+
+        let s = match sparsify_path(t.path()) {
+            Ok(s) => s,
+            Err(Error::Unsupported { .. }) => continue,
+            Err(e) => panic!("{}: {}", dir.display(), e),
+        };
+        assert_eq!(
+            (s.scanned, s.punched),
+            (3 * UNIT, UNIT),
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn nothing_to_do() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 3, &[1]);
+        let s = sparsify(&f, 4096).unwrap();
+        assert_eq!((s.punched, s.reclaimed), (0, 0), "{}", dir.display());
+    }
+}
+
+#[test]
+fn dry_run() {
+    for dir in dirs() {
+        let (t, f) = sparse_file(&dir, 5, &[0, 1, 2, 4]);
+        f.write_all_at(&vec![0u8; 2 * UNIT as usize], UNIT).unwrap();
+        let before = SparseMap::from_file(&f).unwrap();
+
+        // only needs to read
+        let ro = std::fs::File::open(t.path()).unwrap();
+        let dry = sparsify_dry_run(&ro, 4096).unwrap();
+        assert_eq!(
+            SparseMap::from_file(&f).unwrap(),
+            before,
+            "{}",
+            dir.display()
+        );
+        assert_eq!(
+            (dry.scanned, dry.punched, dry.reclaimed),
+            (4 * UNIT, 2 * UNIT, 0),
+            "{}",
+            dir.display()
+        );
+
+        let s = match sparsify(&f, 4096) {
+            Ok(s) => s,
+            Err(Error::Unsupported { .. }) => continue,
+            Err(e) => panic!("{}: {}", dir.display(), e),
+        };
+        assert_eq!((s.scanned, s.punched), (dry.scanned, dry.punched));
+    }
+}
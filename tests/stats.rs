@@ -0,0 +1,67 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{stats, Fragmentation, SparseMap, SparseStats};
+
+#[test]
+fn from_file() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[1]);
+        let s = stats(&f).unwrap();
+        assert_eq!(s.data_bytes, UNIT, "{}", dir.display());
+        assert_eq!(s.hole_bytes, 3 * UNIT);
+        assert_eq!(s.apparent_bytes(), 4 * UNIT);
+        assert_eq!(s.ratio(), 0.75);
+
+        let allocated = s.allocated_bytes.unwrap();
+        assert!((UNIT..2 * UNIT).contains(&allocated), "{}", allocated);
+        assert_eq!(s.saved_bytes(), Some(4 * UNIT - allocated));
+    }
+}
+
+#[test]
+fn from_map() {
+    let (_t, f) = sparse_file(&std::env::temp_dir(), 4, &[0, 3]);
+    let s = SparseStats::from(&SparseMap::from_file(&f).unwrap());
+    assert_eq!(
+        s,
+        SparseStats {
+            data_bytes: 2 * UNIT,
+            hole_bytes: 2 * UNIT,
+            allocated_bytes: None,
+        }
+    );
+    assert_eq!(s.ratio(), 0.5);
+    assert_eq!(s.saved_bytes(), None);
+}
+
+#[test]
+fn empty() {
+    let (_t, f) = sparse_file(&std::env::temp_dir(), 0, &[]);
+    let s = stats(&f).unwrap();
+    assert_eq!((s.apparent_bytes(), s.ratio()), (0, 0.0));
+    assert_eq!(s.saved_bytes(), Some(0));
+}
+
+#[test]
+fn fragmentation() {
+    let (_t, f) = sparse_file(&std::env::temp_dir(), 8, &[0, 2, 3, 7]);
+    let frag = Fragmentation::from(&SparseMap::from_file(&f).unwrap());
+    assert_eq!(
+        frag,
+        Fragmentation {
+            extents: 3,
+            data_bytes: 4 * UNIT,
+            largest: 2 * UNIT,
+            smallest: UNIT,
+        }
+    );
+    assert_eq!(frag.average(), 4 * UNIT / 3);
+
+    let (_t, f) = sparse_file(&std::env::temp_dir(), 1, &[]);
+    let frag = Fragmentation::from(&SparseMap::from_file(&f).unwrap());
+    assert_eq!(frag, Fragmentation::default());
+    assert_eq!(frag.average(), 0);
+}
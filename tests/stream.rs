@@ -0,0 +1,42 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{contents, dirs, ranges, sparse_file, UNIT};
+use fs_sparse::{read_sparse_stream, write_sparse_stream};
+use std::io::ErrorKind;
+
+#[test]
+fn round_trip() {
+    for dir in dirs() {
+        let (_t, src) = sparse_file(&dir, 7, &[1, 2, 5]);
+        let mut stream = Vec::new();
+        assert_eq!(write_sparse_stream(&src, &mut stream).unwrap(), 3 * UNIT);
+        // only the data (and a little framing) is sent
+        assert!((stream.len() as u64) < 3 * UNIT + 1024);
+
+        // more follows the stream
+        stream.extend_from_slice(b"trailer");
+        let mut r = &stream[..];
+        let (_t, dst) = sparse_file(&dir, 9, &[0, 8]);
+        assert_eq!(read_sparse_stream(&mut r, &dst).unwrap(), 3 * UNIT);
+        assert_eq!(r, b"trailer");
+
+        assert_eq!(ranges(&dst), ranges(&src), "{}", dir.display());
+        assert_eq!(contents(&dst), contents(&src), "{}", dir.display());
+    }
+}
+
+#[test]
+fn errors() {
+    let (_t, dst) = sparse_file(&dirs()[0], 0, &[]);
+    let e = read_sparse_stream(&b"not a sparse stream"[..], &dst).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+    let (_t, src) = sparse_file(&dirs()[0], 2, &[1]);
+    let mut stream = Vec::new();
+    write_sparse_stream(&src, &mut stream).unwrap();
+    stream.truncate(stream.len() - 20);
+    let e = read_sparse_stream(&stream[..], &dst).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+}
@@ -0,0 +1,101 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::tar::{
+    map_block_1_0, parse_map_1_0, parse_pax_0_1, pax_records_0_1, pax_records_1_0,
+};
+use fs_sparse::SparseMap;
+use std::io::Read;
+
+fn borrow<'a>(records: &'a [(&'static str, String)]) -> Vec<(&'static str, &'a str)> {
+    records.iter().map(|(k, v)| (*k, v.as_str())).collect()
+}
+
+#[test]
+fn format_0_1() {
+    for dir in dirs() {
+        let (_t, file) = sparse_file(&dir, 6, &[1, 2, 4]);
+        let map = SparseMap::from_file(&file).unwrap();
+        if map.hole_len() == 0 {
+            continue;
+        }
+
+        let records = pax_records_0_1(&map);
+        let u = UNIT;
+        assert_eq!(
+            records,
+            vec![
+                ("GNU.sparse.size", (6 * u).to_string()),
+                ("GNU.sparse.numblocks", "3".to_string()),
+                (
+                    "GNU.sparse.map",
+                    format!("{},{},{},{},{},0", u, 2 * u, 4 * u, u, 6 * u)
+                ),
+            ],
+            "{}",
+            dir.display()
+        );
+        assert_eq!(parse_pax_0_1(borrow(&records)).unwrap(), map);
+    }
+}
+
+#[test]
+fn format_1_0() {
+    for dir in dirs() {
+        for &(len, data) in &[(4, &[0, 3][..]), (3, &[][..])] {
+            let (_t, file) = sparse_file(&dir, len, data);
+            let map = SparseMap::from_file(&file).unwrap();
+
+            let block = map_block_1_0(&map);
+            assert_eq!(block.len() % 512, 0);
+
+            let mut archive = block.clone();
+            archive.extend_from_slice(b"data");
+            let mut r = &archive[..];
+            let records = pax_records_1_0(&map);
+            assert_eq!(parse_map_1_0(borrow(&records), &mut r).unwrap(), map);
+
+            // left at the data
+            let mut rest = String::new();
+            r.read_to_string(&mut rest).unwrap();
+            assert_eq!(rest, "data");
+        }
+    }
+}
+
+#[test]
+fn format_1_0_spanning_blocks() {
+    // enough pairs that the map needs several blocks
+    let data: Vec<u64> = (0..200).map(|i| i * 2).collect();
+    let (_t, file) = sparse_file(&std::env::temp_dir(), 400, &data);
+    let map = SparseMap::from_file(&file).unwrap();
+
+    let block = map_block_1_0(&map);
+    assert!(block.len() > 512);
+    let records = pax_records_1_0(&map);
+    assert_eq!(parse_map_1_0(borrow(&records), &block[..]).unwrap(), map);
+}
+
+#[test]
+fn malformed() {
+    let records = [
+        ("GNU.sparse.size", "10"),
+        ("GNU.sparse.numblocks", "1"),
+        ("GNU.sparse.map", "5,6"),
+    ];
+    let e = parse_pax_0_1(records.iter().copied()).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+
+    let records = [
+        ("GNU.sparse.major", "1"),
+        ("GNU.sparse.minor", "0"),
+        ("GNU.sparse.realsize", "10"),
+    ];
+    let e = parse_map_1_0(records.iter().copied(), &b"2\n0\n"[..]).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::UnexpectedEof);
+
+    let e = parse_map_1_0(records[..2].iter().copied(), &[0u8; 512][..]).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,95 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{ItemKind, SparseItem, SparseIter, SparseRangeIter};
+use std::fs::File;
+use std::sync::Arc;
+
+fn assert_send<T: Send>() {}
+
+#[test]
+fn send() {
+    assert_send::<SparseIter<File>>();
+    assert_send::<SparseIter<&File>>();
+    assert_send::<SparseIter<Arc<File>>>();
+    assert_send::<SparseRangeIter<File>>();
+    assert_send::<SparseRangeIter<&File>>();
+    assert_send::<SparseRangeIter<Arc<File>>>();
+}
+
+/// The rest of `iter`, or `None` if the filesystem couldn't do what it was asked
+fn rest<I: Iterator<Item = std::io::Result<T>>, T>(iter: I) -> Option<Vec<T>> {
+    iter.collect::<Result<Vec<_>, _>>().ok()
+}
+
+fn backends(f: &File) -> Vec<SparseIter<&File>> {
+    #[allow(unused_mut)]
+    let mut b = vec![SparseIter::from(f), SparseIter::from(f).read_scan(4096)];
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        b.push(SparseIter::from(f).fiemap());
+        b.push(SparseIter::from(f).fibmap());
+    }
+    b
+}
+
+#[test]
+fn clone_mid_scan() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 7, &[1, 2, 5]);
+        f.sync_all().unwrap();
+        for mut iter in backends(&f) {
+            match iter.next() {
+                Some(Ok(_)) => {}
+                _ => continue,
+            }
+            let clone = iter.clone();
+            let a = rest(iter);
+            assert_eq!(a, rest(clone), "{}", dir.display());
+            if let Some(a) = a {
+                assert_eq!(a.last().unwrap().offset, 7 * UNIT);
+            }
+        }
+    }
+}
+
+#[test]
+fn clone_ranges() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 7, &[1, 2, 5]);
+        let mut iter = SparseRangeIter::from(SparseIter::from(&f));
+        let first = iter.next().unwrap().unwrap();
+        assert_eq!(
+            (first.kind, first.start, first.end),
+            (ItemKind::Hole, 0, UNIT)
+        );
+        let clone = iter.clone();
+        assert_eq!(rest(iter).unwrap(), rest(clone).unwrap());
+    }
+}
+
+#[test]
+fn split_across_threads() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 8, &[1, 2, 5]);
+        let f = Arc::new(f);
+        let whole = rest(SparseIter::from(f.clone())).unwrap();
+
+        let first = SparseIter::from(f.clone());
+        let mut second = first.clone();
+        second.skip_to(4 * UNIT);
+        let second = std::thread::spawn(move || rest(second).unwrap());
+        let first: Vec<SparseItem> = rest(first)
+            .unwrap()
+            .into_iter()
+            .take_while(|i| i.offset < 4 * UNIT)
+            .collect();
+        let second = second.join().unwrap();
+
+        let mut split = first;
+        split.extend(second.into_iter().skip(1));
+        assert_eq!(split, whole, "{}", dir.display());
+    }
+}
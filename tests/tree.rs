@@ -0,0 +1,56 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, UNIT};
+use fs_sparse::scan_tree;
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+/// Create `path`, `len` units long with data in the units in `data`
+fn create(path: &Path, len: u64, data: &[u64]) {
+    let f = File::create(path).unwrap();
+    f.set_len(len * UNIT).unwrap();
+    for d in data {
+        f.write_all_at(&vec![0xffu8; UNIT as usize], d * UNIT)
+            .unwrap();
+    }
+}
+
+#[test]
+fn totals() {
+    for dir in dirs() {
+        let tmp = tempfile::tempdir_in(&dir).unwrap();
+        let root = tmp.path();
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+        create(&root.join("dense"), 3, &[0, 1, 2]);
+        create(&root.join("a/sparse"), 4, &[1]);
+        create(&root.join("a/b/empty"), 0, &[]);
+        std::os::unix::fs::symlink(root.join("dense"), root.join("a/link")).unwrap();
+
+        let stats = scan_tree(root).unwrap();
+        assert!(stats.errors.is_empty(), "{:?}", stats.errors);
+        assert_eq!(stats.files, 3);
+        assert_eq!(stats.apparent, 7 * UNIT);
+        assert_eq!(stats.holes, 3 * UNIT, "{}", dir.display());
+        assert_eq!(stats.data(), 4 * UNIT);
+        assert!(stats.allocated >= 4 * UNIT);
+
+        let largest: Vec<_> = stats.largest.iter().map(|f| f.path.clone()).collect();
+        assert_eq!(
+            largest,
+            vec![
+                root.join("dense"),
+                root.join("a/sparse"),
+                root.join("a/b/empty")
+            ]
+        );
+
+        // a single file works too
+        let stats = scan_tree(root.join("a/sparse")).unwrap();
+        assert_eq!((stats.files, stats.holes), (1, 3 * UNIT));
+    }
+
+    assert!(scan_tree("/nonexistent").is_err());
+}
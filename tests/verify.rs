@@ -0,0 +1,38 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{verify_holes, HoleCheck};
+
+#[test]
+fn holes_are_zero() {
+    for dir in dirs() {
+        for data in &[&[][..], &[0], &[1, 3], &[0, 1, 2, 3, 4]] {
+            let (_t, f) = sparse_file(&dir, 5, data);
+            assert_eq!(verify_holes(&f, HoleCheck::Full).unwrap(), vec![]);
+            assert_eq!(
+                verify_holes(&f, HoleCheck::Sample(UNIT / 3)).unwrap(),
+                vec![]
+            );
+            assert_eq!(verify_holes(&f, HoleCheck::Sample(1)).unwrap(), vec![]);
+            assert_eq!(
+                verify_holes(&f, HoleCheck::Sample(u64::MAX)).unwrap(),
+                vec![]
+            );
+        }
+    }
+}
+
+#[test]
+fn empty() {
+    let f = tempfile::tempfile().unwrap();
+    assert_eq!(verify_holes(&f, HoleCheck::Full).unwrap(), vec![]);
+}
+
+#[test]
+#[should_panic]
+fn zero_spacing() {
+    let (_t, f) = sparse_file(&std::env::temp_dir(), 2, &[]);
+    let _ = verify_holes(&f, HoleCheck::Sample(0));
+}
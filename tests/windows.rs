@@ -0,0 +1,95 @@
+#![cfg(windows)]
+
+mod common;
+
+use common::{ranges, sparse_file, write_all_at, UNIT};
+use fs_sparse::{
+    allocated_size, block_size, is_marked_sparse, is_sparse, map_path, min_hole_size,
+    next_data_from, next_hole_from, preallocate, punch_hole, set_sparse, ItemKind,
+};
+
+#[test]
+fn unmarked_file_is_all_data() {
+    let f = tempfile::tempfile().unwrap();
+    f.set_len(4 * UNIT).unwrap();
+    assert!(!is_marked_sparse(&f).unwrap());
+    assert!(!is_sparse(&f).unwrap());
+    assert_eq!(ranges(&f), vec![(ItemKind::Data, 0, 4)]);
+}
+
+#[test]
+fn marked_file_has_holes() {
+    let (_t, f) = sparse_file(&std::env::temp_dir(), 7, &[1, 2, 5]);
+    assert!(is_marked_sparse(&f).unwrap());
+    assert_eq!(
+        ranges(&f),
+        vec![
+            (ItemKind::Hole, 0, 1),
+            (ItemKind::Data, 1, 3),
+            (ItemKind::Hole, 3, 5),
+            (ItemKind::Data, 5, 6),
+            (ItemKind::Hole, 6, 7),
+        ]
+    );
+    assert_eq!(next_data_from(&f, 0).unwrap(), Some(UNIT));
+    assert_eq!(next_hole_from(&f, UNIT).unwrap(), Some(3 * UNIT));
+    assert_eq!(next_data_from(&f, 6 * UNIT).unwrap(), None);
+    assert_eq!(next_hole_from(&f, 7 * UNIT).unwrap(), None);
+
+    let size = allocated_size(&f).unwrap();
+    assert_eq!(size.logical, 7 * UNIT);
+    assert!(size.allocated < size.logical);
+}
+
+#[test]
+fn map_while_open_for_writing() {
+    let (t, _f) = sparse_file(&std::env::temp_dir(), 3, &[1]);
+    let map = map_path(t.path()).unwrap();
+    assert_eq!(map.data_len(), UNIT);
+    assert_eq!(map.file_len(), 3 * UNIT);
+}
+
+#[test]
+fn unmark() {
+    let f = tempfile::tempfile().unwrap();
+    set_sparse(&f, true).unwrap();
+    assert!(is_marked_sparse(&f).unwrap());
+    set_sparse(&f, false).unwrap();
+    assert!(!is_marked_sparse(&f).unwrap());
+}
+
+#[test]
+fn punch_marks_sparse() {
+    let f = tempfile::tempfile().unwrap();
+    write_all_at(&f, &vec![0xff; 4 * UNIT as usize], 0);
+    assert!(!is_marked_sparse(&f).unwrap());
+
+    punch_hole(&f, UNIT, 2 * UNIT).unwrap();
+    assert!(is_marked_sparse(&f).unwrap());
+    assert_eq!(
+        ranges(&f),
+        vec![
+            (ItemKind::Data, 0, 1),
+            (ItemKind::Hole, 1, 3),
+            (ItemKind::Data, 3, 4),
+        ]
+    );
+}
+
+#[test]
+fn preallocate_extends() {
+    let f = tempfile::tempfile().unwrap();
+    preallocate(&f, 2 * UNIT).unwrap();
+    let size = allocated_size(&f).unwrap();
+    assert_eq!(size.logical, 2 * UNIT);
+    assert!(size.allocated >= 2 * UNIT);
+}
+
+#[test]
+fn hole_size() {
+    let f = tempfile::tempfile().unwrap();
+    let block = block_size(&f).unwrap();
+    assert!(block.is_power_of_two());
+    // NTFS and ReFS both support sparse files
+    assert_eq!(min_hole_size(&f).unwrap(), Some(block));
+}
@@ -0,0 +1,56 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, ranges, sparse_file, UNIT};
+use fs_sparse::{ItemKind, SparseWriter};
+use std::io::Write;
+use std::os::unix::fs::FileExt;
+
+#[test]
+fn skips_zeros() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 0, &[]);
+        let mut w = SparseWriter::new(&f).unwrap();
+        // odd sized writes, so blocks are assembled from pieces
+        let mut data = vec![0xffu8; UNIT as usize];
+        data.extend(vec![0u8; 2 * UNIT as usize]);
+        data.extend(vec![0xffu8; UNIT as usize]);
+        data.extend(vec![0u8; UNIT as usize]);
+        for chunk in data.chunks(1000) {
+            w.write_all(chunk).unwrap();
+        }
+        assert_eq!(w.position(), 5 * UNIT);
+        w.finish().unwrap();
+
+        assert_eq!(f.metadata().unwrap().len(), 5 * UNIT, "{}", dir.display());
+        assert_eq!(
+            ranges(&f),
+            vec![
+                (ItemKind::Data, 0, 1),
+                (ItemKind::Hole, 1, 3),
+                (ItemKind::Data, 3, 4),
+                (ItemKind::Hole, 4, 5)
+            ],
+            "{}",
+            dir.display()
+        );
+    }
+}
+
+#[test]
+fn overwrites_with_zeros() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 3, &[0, 1, 2]);
+        let mut w = SparseWriter::starting_at(&f, 10).unwrap().block_size(512);
+        w.write_all(&vec![0u8; UNIT as usize]).unwrap();
+        drop(w);
+
+        let mut buf = vec![0u8; 3 * UNIT as usize];
+        f.read_exact_at(&mut buf, 0).unwrap();
+        assert!(buf[..10].iter().all(|&b| b == 0xff));
+        assert!(buf[10..UNIT as usize + 10].iter().all(|&b| b == 0));
+        assert!(buf[UNIT as usize + 10..].iter().all(|&b| b == 0xff));
+        assert_eq!(f.metadata().unwrap().len(), 3 * UNIT, "{}", dir.display());
+    }
+}
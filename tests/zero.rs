@@ -0,0 +1,82 @@
+use fs_sparse::{is_zero, ItemKind, Segmenter};
+
+use ItemKind::{Data, Hole};
+
+#[test]
+fn zero() {
+    // cover every alignment and every length around the SIMD step sizes
+    let buf = vec![0u8; 1024];
+    for start in 0..64 {
+        for len in 0..300 {
+            assert!(is_zero(&buf[start..start + len]));
+        }
+    }
+
+    let mut buf = buf;
+    for start in 0..64 {
+        for len in 1..300 {
+            for pos in [0, len / 2, len - 1] {
+                buf[start + pos] = 0x80;
+                assert!(
+                    !is_zero(&buf[start..start + len]),
+                    "{} {} {}",
+                    start,
+                    len,
+                    pos
+                );
+                buf[start + pos] = 0;
+            }
+        }
+    }
+}
+
+fn runs(r: Vec<fs_sparse::SparseRangeItem>) -> Vec<(ItemKind, u64, u64)> {
+    r.into_iter().map(|r| (r.kind, r.start, r.end)).collect()
+}
+
+#[test]
+fn segmenter() {
+    let mut s = Segmenter::new(4);
+    assert_eq!(runs(s.feed(&[1, 2, 0, 0, 0, 0])), vec![]);
+    assert_eq!(runs(s.feed(&[0, 0, 5])), vec![(Data, 0, 4)]);
+    assert_eq!(s.offset(), 9);
+    assert_eq!(runs(s.finish()), vec![(Hole, 4, 8), (Data, 8, 9)]);
+
+    assert_eq!(runs(Segmenter::new(4).finish()), vec![]);
+    assert_eq!(runs(Segmenter::new(4).feed(&[])), vec![]);
+
+    // a short zero block at the end is still a hole
+    let mut s = Segmenter::new(4);
+    assert_eq!(runs(s.feed(&[9; 4])), vec![]);
+    assert_eq!(runs(s.feed(&[0; 2])), vec![]);
+    assert_eq!(runs(s.finish()), vec![(Data, 0, 4), (Hole, 4, 6)]);
+}
+
+#[test]
+fn segmenter_any_pieces() {
+    // 3 blocks of data, 5 of zeros, 1 of data, 2 of zeros, and a partial block of data
+    let block = 512;
+    let mut stream = vec![0u8; 11 * block + 100];
+    for &b in &[0, 1, 2, 8] {
+        stream[b * block + 7] = 1;
+    }
+    stream[11 * block + 99] = 1;
+    let b = block as u64;
+    let expected = vec![
+        (Data, 0, 3 * b),
+        (Hole, 3 * b, 8 * b),
+        (Data, 8 * b, 9 * b),
+        (Hole, 9 * b, 11 * b),
+        (Data, 11 * b, 11 * b + 100),
+    ];
+
+    for piece in &[1, 7, 511, 512, 513, 4096, stream.len()] {
+        let mut s = Segmenter::new(b);
+        let mut r = Vec::new();
+        for p in stream.chunks(*piece) {
+            r.extend(runs(s.feed(p)));
+        }
+        r.extend(runs(s.finish()));
+        assert_eq!(r, expected, "{}", piece);
+    }
+}
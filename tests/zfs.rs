@@ -0,0 +1,39 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{dirs, sparse_file, UNIT};
+use fs_sparse::{is_zfs, ItemKind, SparseIter, SparseRangeIter};
+use std::path::Path;
+
+use ItemKind::{Data, Hole};
+
+#[test]
+fn tmpfs_is_not_zfs() {
+    let shm = Path::new("/dev/shm");
+    if !shm.is_dir() {
+        return;
+    }
+    let (_t, f) = sparse_file(shm, 1, &[0]);
+    assert!(!is_zfs(&f).unwrap());
+}
+
+#[test]
+fn sync_on_zfs() {
+    for dir in dirs() {
+        let (_t, f) = sparse_file(&dir, 4, &[1]);
+        let mut iter = SparseRangeIter::from(SparseIter::from(&f).sync_on_zfs());
+        let ranges: Vec<_> = iter
+            .by_ref()
+            .map(|r| r.unwrap())
+            .map(|r| (r.kind, r.start / UNIT, r.end / UNIT))
+            .collect();
+        assert_eq!(
+            ranges,
+            vec![(Hole, 0, 1), (Data, 1, 2), (Hole, 2, 4)],
+            "{}",
+            dir.display()
+        );
+        assert_eq!(iter.warning(), None, "{}", dir.display());
+    }
+}